@@ -0,0 +1,7 @@
+//! Shared helpers for the criterion benches
+
+/// Decode a hex fixture from `tests/fixtures`
+pub fn load_fixture(name: &str) -> Vec<u8> {
+    let hex_content = std::fs::read_to_string(format!("tests/fixtures/{name}")).unwrap();
+    hex::decode(hex_content.trim().trim_start_matches("0x")).unwrap()
+}
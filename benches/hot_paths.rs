@@ -0,0 +1,52 @@
+//! Baseline timings of the per-contract hot paths on real pool code: protocol matching,
+//! selector extraction and TLSH fingerprinting
+//!
+//! Run with `cargo bench --bench hot_paths`.
+
+mod common;
+
+use common::load_fixture;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use which_dex::selector_fingerprint::extract_selectors;
+use which_dex::{identify_protocol, BytecodeFingerprint};
+
+/// An 11 KB UniswapV2 pair and a 22 KB UniswapV3 pool, near the 24 KB code size limit
+/// (not the largest fixture: `story_storyhunt_pool.hex` is slightly bigger)
+const FIXTURES: [(&str, &str); 2] = [
+    ("univ2_11kb", "univ2_uni_eth.hex"),
+    ("univ3_22kb", "univ3_usdc_eth.hex"),
+];
+
+fn hot_paths(c: &mut Criterion) {
+    let fixtures: Vec<_> = FIXTURES
+        .iter()
+        .map(|(label, file)| (*label, load_fixture(file)))
+        .collect();
+
+    let mut group = c.benchmark_group("identify_protocol");
+    for (label, bytecode) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(label), bytecode, |b, code| {
+            b.iter(|| identify_protocol(black_box(code)))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("extract_selectors");
+    for (label, bytecode) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(label), bytecode, |b, code| {
+            b.iter(|| extract_selectors(black_box(code)))
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("fingerprint_from_bytecode");
+    for (label, bytecode) in &fixtures {
+        group.bench_with_input(BenchmarkId::from_parameter(label), bytecode, |b, code| {
+            b.iter(|| BytecodeFingerprint::from_bytecode(black_box(code)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hot_paths);
+criterion_main!(benches);
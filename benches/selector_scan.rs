@@ -0,0 +1,38 @@
+//! Selector lookups: one window scan per selector vs. a single-pass `SelectorSet`
+//!
+//! Run with `cargo bench --bench selector_scan`.
+
+mod common;
+
+use common::load_fixture;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use which_dex::selector_fingerprint::selectors;
+use which_dex::{identify_protocols, SelectorSet};
+
+fn selector_scan(c: &mut Criterion) {
+    let bytecode = load_fixture("univ3_usdc_eth.hex");
+
+    c.bench_function("exists_in per selector", |b| {
+        b.iter(|| {
+            selectors::NAMES
+                .iter()
+                .filter(|(selector, _)| selector.exists_in(black_box(&bytecode)))
+                .count()
+        })
+    });
+    c.bench_function("selector set build + lookups", |b| {
+        b.iter(|| {
+            let set = SelectorSet::from_bytecode(black_box(&bytecode));
+            selectors::NAMES
+                .iter()
+                .filter(|(selector, _)| set.contains(selector))
+                .count()
+        })
+    });
+    c.bench_function("identify_protocols", |b| {
+        b.iter(|| identify_protocols(black_box(&bytecode)))
+    });
+}
+
+criterion_group!(benches, selector_scan);
+criterion_main!(benches);
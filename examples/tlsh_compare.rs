@@ -7,17 +7,35 @@
 //!
 //! Or with files:
 //!   `cargo run --example tlsh_compare -- --file code1.hex code2.hex`
+//!
+//! Or pairwise across every `.hex` file in a directory (matrix + clusters):
+//!   `cargo run --example tlsh_compare -- --dir tests/fixtures [max_distance]`
 
 use std::env;
 use std::fs;
+use std::path::Path;
+use which_dex::bytecode_fingerprint::{cluster, fingerprint_hex_dir, similarity_matrix};
 use which_dex::{BytecodeFingerprint, Similarity};
 
+/// Default clustering threshold: the upper bound of `Similarity::SameFamily`
+const DEFAULT_MAX_DISTANCE: i32 = 100;
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
+    if args.len() >= 3 && args[1] == "--dir" {
+        let max_distance = args
+            .get(3)
+            .map(|d| d.parse().expect("max_distance must be an integer"))
+            .unwrap_or(DEFAULT_MAX_DISTANCE);
+        compare_dir(Path::new(&args[2]), max_distance);
+        return;
+    }
+
     if args.len() < 3 {
         eprintln!("Usage: {} <hex1> <hex2>", args[0]);
         eprintln!("   or: {} --file <file1> <file2>", args[0]);
+        eprintln!("   or: {} --dir <path> [max_distance]", args[0]);
         std::process::exit(1);
     }
 
@@ -71,3 +89,30 @@ fn main() {
         Similarity::Different => println!("\n✗ Different contracts"),
     }
 }
+
+fn compare_dir(dir: &Path, max_distance: i32) {
+    let dir_fps = fingerprint_hex_dir(dir).expect("Failed to read directory");
+    for (name, reason) in &dir_fps.skipped {
+        eprintln!("warning: skipping {name}: {reason}");
+    }
+
+    let (names, fps): (Vec<String>, Vec<BytecodeFingerprint>) =
+        dir_fps.fingerprints.into_iter().unzip();
+    let matrix = similarity_matrix(&fps);
+
+    println!("Distance matrix ({} files):", names.len());
+    for (i, row) in matrix.iter().enumerate() {
+        let cells: Vec<String> = row.iter().map(|d| format!("{d:>4}")).collect();
+        println!("  [{i:>2}] {} {}", cells.join(""), names[i]);
+    }
+
+    let clusters = cluster(&matrix, max_distance);
+    println!(
+        "\nClusters (distance <= {max_distance}): {}",
+        clusters.len()
+    );
+    for (i, members) in clusters.iter().enumerate() {
+        let members: Vec<&str> = members.iter().map(|m| names[*m].as_str()).collect();
+        println!("  {}: {}", i + 1, members.join(", "));
+    }
+}
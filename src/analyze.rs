@@ -1,16 +1,59 @@
-use alloy::primitives::Address;
+use std::collections::HashSet;
+#[cfg(feature = "rpc")]
+use std::future::Future;
+use std::sync::Arc;
+#[cfg(feature = "rpc")]
+use std::time::Duration;
+
+#[cfg(feature = "rpc")]
+use alloy::eips::{BlockId, BlockNumberOrTag};
+#[cfg(feature = "rpc")]
+use alloy::network::TransactionBuilder;
+#[cfg(feature = "rpc")]
 use alloy::providers::{Provider, ProviderBuilder};
+#[cfg(feature = "rpc")]
+use alloy::rpc::client::RpcClient;
+#[cfg(feature = "rpc")]
+use alloy::rpc::types::state::StateOverride;
+#[cfg(feature = "rpc")]
+use alloy::rpc::types::TransactionRequest;
+#[cfg(feature = "rpc")]
 use alloy::transports::http::reqwest::Url as AlloyUrl;
+#[cfg(feature = "rpc")]
+use alloy::transports::http::ReqwestTransport;
+#[cfg(feature = "rpc")]
+use alloy::transports::{RpcError as AlloyRpcError, TransportError, TransportErrorKind};
+use core::str::FromStr;
+
+#[cfg(feature = "rpc")]
+use alloy_primitives::U256;
+use alloy_primitives::{Address, B256};
+use base64::Engine;
 use serde::Serialize;
 use thiserror::Error;
-use tracing::debug;
+use tracing::{debug, warn};
+#[cfg(feature = "rpc")]
 use url::Url;
 
-use crate::bytecode_fingerprint::{extract_eip1167_impl, is_eip1167_proxy, BytecodeFingerprint};
-use crate::selector_fingerprint::selectors;
-use crate::selector_fingerprint::{identify_protocols, DexProtocol};
+use crate::bytecode_fingerprint::{
+    disassemble, extract_cwia_impl, extract_eip1167_impl, forwarding_suspected, is_eip1167_proxy,
+    opcode_histogram, runtime_code, BytecodeFingerprint, FingerprintDb, FingerprintError,
+    Similarity,
+};
+use crate::chain::{Chain, ChainProfile};
+use crate::classify::{classify_with, DetectionMethod};
+use crate::factories::{factory_dex, v2_fork_family, FactoryAllowlist};
+use crate::metadata::{parse_metadata, SolcMetadata};
+#[cfg(feature = "rpc")]
+use crate::selector_fingerprint::token_risk_flags;
+use crate::selector_fingerprint::{
+    balancer_pool_subtype, dispatcher_region, eip7702_delegation_target, fingerprints,
+    identify_contract_kind, identify_protocols_verbose_with, identify_protocols_with,
+    is_safe_proxy, protocol_fingerprint, selector_name, selectors, ContractKind, DexProtocol,
+    Selector, SelectorScan,
+};
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum AnalyzeError {
     #[error("invalid rpc url")]
     InvalidRpcUrl,
@@ -18,24 +61,183 @@ pub enum AnalyzeError {
     #[error("invalid address (expected 20-byte 0x-prefixed hex)")]
     InvalidAddress,
 
-    #[error("address has no deployed bytecode (EOA or not deployed)")]
+    #[error("address has no deployed bytecode (EOA, not deployed, or self-destructed)")]
     NoDeployedBytecode,
 
     #[error("rpc error: {0}")]
-    Rpc(String),
+    Rpc(#[from] RpcError),
+
+    #[error("cannot read addresses file: {0}")]
+    AddressesFile(String),
+
+    #[error("analysis exceeded deadline of {0} ms")]
+    Deadline(u64),
+
+    #[error("cannot read bytecode input: {0}")]
+    BytecodeInput(String),
+
+    #[error("invalid bytecode input: {0}")]
+    InvalidBytecode(String),
+
+    #[error("invalid transaction hash (expected 32-byte 0x-prefixed hex)")]
+    InvalidTxHash,
+
+    #[error("invalid block `{0}` (expected latest, finalized, safe, earliest, pending, a block number or a 32-byte 0x-prefixed block hash)")]
+    InvalidBlock(String),
+
+    #[error("no receipt for transaction {0} (unknown or still pending)")]
+    TransactionNotFound(String),
+
+    /// The receipt has no `contractAddress`: a call or transfer, not a creation
+    #[error("transaction {0} did not create a contract")]
+    NotContractCreation(String),
+
+    /// Code that has to be compared can't be fingerprinted (e.g. too small for TLSH)
+    #[error("cannot fingerprint {address}: {source}")]
+    Fingerprint {
+        address: String,
+        source: FingerprintError,
+    },
+}
+
+/// Why an RPC request failed
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum RpcError {
+    /// Connection, TLS or HTTP-level failure
+    #[error("transport error: {0}")]
+    Transport(String),
+
+    /// The endpoint throttled the request (HTTP 429 or a provider rate-limit error)
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+
+    /// The response could not be decoded
+    #[error("invalid response: {0}")]
+    Decode(String),
+
+    /// The request timed out before the endpoint answered
+    #[error("request timed out: {0}")]
+    Timeout(String),
+
+    /// An `eth_call` reverted
+    #[error("contract reverted: {0}")]
+    ContractRevert(String),
+
+    /// Any other JSON-RPC error object returned by the endpoint
+    #[error("server error {code}: {message}")]
+    Server { code: i64, message: String },
+}
+
+#[cfg(feature = "rpc")]
+impl From<TransportError> for RpcError {
+    fn from(err: TransportError) -> Self {
+        match err {
+            AlloyRpcError::ErrorResp(payload) => {
+                let message = truncate_message(payload.message.to_string());
+                let lower = message.to_lowercase();
+                // 3 is the geth code for reverts carrying data; bare reverts come as -32000.
+                if payload.code == 3 || lower.contains("revert") {
+                    Self::ContractRevert(message)
+                } else if matches!(payload.code, 429 | -32005)
+                    || [
+                        "rate limit",
+                        "too many requests",
+                        "request limit",
+                        "credits limited",
+                    ]
+                    .iter()
+                    .any(|needle| lower.contains(needle))
+                {
+                    Self::RateLimited(message)
+                } else {
+                    Self::Server {
+                        code: payload.code,
+                        message,
+                    }
+                }
+            }
+            AlloyRpcError::NullResp
+            | AlloyRpcError::SerError(_)
+            | AlloyRpcError::DeserError { .. } => Self::Decode(truncate_message(err.to_string())),
+            AlloyRpcError::Transport(TransportErrorKind::HttpError(http))
+                if http.is_rate_limit_err() =>
+            {
+                Self::RateLimited(truncate_message(http.to_string()))
+            }
+            AlloyRpcError::Transport(TransportErrorKind::Custom(custom))
+                if is_timeout(custom.as_ref()) =>
+            {
+                Self::Timeout(truncate_message(custom.to_string()))
+            }
+            other => Self::Transport(truncate_message(other.to_string())),
+        }
+    }
+}
+
+/// Longest provider error message kept in an [`RpcError`], in characters
+#[cfg(feature = "rpc")]
+const MAX_ERROR_MESSAGE_CHARS: usize = 500;
+
+/// Cut a provider message (e.g. an HTML error page in an HTTP error body) to
+/// [`MAX_ERROR_MESSAGE_CHARS`] on a char boundary, marking the cut
+#[cfg(feature = "rpc")]
+fn truncate_message(mut message: String) -> String {
+    if let Some((cut, _)) = message.char_indices().nth(MAX_ERROR_MESSAGE_CHARS) {
+        let dropped = message.len() - cut;
+        message.truncate(cut);
+        message.push_str(&format!("… ({dropped} more bytes)"));
+    }
+    message
+}
+
+/// Whether a transport's custom error (usually `reqwest::Error`) is a timeout
+#[cfg(feature = "rpc")]
+fn is_timeout(err: &(dyn std::error::Error + 'static)) -> bool {
+    if let Some(reqwest) = err.downcast_ref::<alloy::transports::http::reqwest::Error>() {
+        return reqwest.is_timeout();
+    }
+    if let Some(io) = err.downcast_ref::<std::io::Error>() {
+        return io.kind() == std::io::ErrorKind::TimedOut;
+    }
+    err.source().is_some_and(is_timeout)
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct ProtocolCandidate {
+    /// Name of `protocol_enum`, restyled by [`BytecodeAnalysis::apply_name_style`]
     pub protocol: String,
+    /// The candidate protocol itself, for matching without re-parsing `protocol`
+    /// (always serialized canonically, e.g. `"UniswapV3"`)
+    pub protocol_enum: DexProtocol,
     pub confidence: u32,
 }
 
+impl ProtocolCandidate {
+    pub fn new(protocol: DexProtocol, confidence: u32) -> Self {
+        Self {
+            protocol: dex_protocol_name(protocol).to_string(),
+            protocol_enum: protocol,
+            confidence,
+        }
+    }
+
+    /// `confidence` as a percentage of the most its fingerprint can score
+    /// (matched selectors / required + optional ones)
+    pub fn confidence_percent(&self) -> Option<u32> {
+        protocol_fingerprint(self.protocol_enum).map(|fp| fp.confidence_percent(self.confidence))
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct FingerprintReport {
     pub hash_hex: String,
+    /// Keccak-256 of the normalized bytecode (see [`BytecodeFingerprint::exact_hash`])
+    pub exact_hash: String,
     pub original_size: usize,
     pub normalized_size: usize,
+    /// Metadata stripping removed a suspiciously large part of the code
+    /// (see [`BytecodeFingerprint::metadata_strip_suspect`])
+    pub metadata_strip_suspect: bool,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -46,13 +248,87 @@ pub struct BytecodeAnalysis {
     pub protocol: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub protocol_candidates: Option<Vec<ProtocolCandidate>>,
+    /// Pool math refinement of `protocol` (Balancer V2: Weighted / Stable / ComposableStable)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_subtype: Option<String>,
+    /// DEX that deployed a pool of a forked protocol (e.g. SushiSwap for UniswapV2,
+    /// BaseSwapV3 for UniswapV3); `protocol` stays the forked one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fork_family: Option<String>,
+    /// Swap invariant of a V2-shaped pool: `constant_product` (x*y=k) or `stable`
+    /// (Solidly x³y+y³x). Solidly pools hold it in a `stable()` storage flag, so theirs
+    /// is only known with `resolve_onchain`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub curve_type: Option<String>,
+    /// Pool factory, read via `factory()` (only with `resolve_onchain`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub factory: Option<String>,
+    /// Whether `factory` is listed in the factory allowlist for this chain and
+    /// `protocol`; a verified pool reports the fingerprint's highest `pool_confidence`
+    /// (only with `resolve_onchain` and a `factory_allowlist`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub factory_verified: Option<bool>,
+    /// Non-AMM category (e.g. `PerpVault`); such contracts are never `is_pool_likely`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_kind: Option<String>,
+    /// Human-readable refinement of `contract_kind` (e.g. `UniswapV3 factory`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contract_hint: Option<String>,
+    /// Well-known contract whose exact normalized code this is (e.g. `UniswapV3Pool`;
+    /// only with `known_contracts`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub known_contract: Option<String>,
+    /// Confidence of the reported `protocol` (0 when Unknown)
+    pub pool_confidence: u32,
+    /// What decided `protocol`: `selector`, `selector+tlsh_agree`, `tlsh_nearest` or
+    /// `tlsh_fallback` (the TLSH ones only with a fingerprint database), or
+    /// `dynamic_probe` (only with `dynamic`)
+    pub detection_method: String,
+    /// Blended selector + TLSH confidence from [`classify`](crate::classify::classify), in `0.0..=1.0` (only with a
+    /// fingerprint database)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub combined_confidence: Option<f64>,
+    /// Zero-argument views that returned data when called (only with `dynamic`, for
+    /// verdicts that were `Unknown` from bytecode)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dynamic_probes: Option<Vec<String>>,
+    /// Why the verdict is `Unknown`: the closest reference by TLSH (with a fingerprint
+    /// database) and the selectors keeping near-miss protocols from matching (only with
+    /// `explain`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<String>,
 
     pub is_pool_likely: bool,
 
+    /// Advisory: the code looks like a thin wrapper forwarding calls to another contract
+    /// (`DELEGATECALL`/`STATICCALL`-heavy, little logic per selector). Its protocol match
+    /// may come from the wrapped pool's interface; `is_pool_likely` is not affected.
+    pub forwarding_suspected: bool,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fingerprint: Option<FingerprintReport>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fingerprint_error: Option<String>,
+
+    /// IPFS CIDv0 of the solc metadata JSON, for looking up verified sources (e.g. Sourcify)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata_ipfs: Option<String>,
+    /// Hex Swarm hash of the metadata JSON (`bzzr0`/`bzzr1`, solc < 0.6)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata_swarm: Option<String>,
+
+    /// Count of each opcode 0x00..=0xff, PUSH data skipped (only with `opcode_histogram`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub opcode_histogram: Option<Vec<u32>>,
+
+    /// Instructions of the dispatcher region, at most [`DISASSEMBLY_MAX_BYTES`] (only with
+    /// `include_disassembly`, `Unknown` verdicts)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disassembly: Option<Vec<String>>,
+
+    /// 0x-prefixed runtime bytecode (only with `include_bytecode`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytecode_hex: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -61,14 +337,298 @@ pub struct AnalyzeReport {
     pub address: String,
 
     pub is_eip1167_proxy: bool,
+    /// Shape of the account's code when it forwards calls elsewhere
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_kind: Option<ProxyKind>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub implementation_address: Option<String>,
+    /// Bytes appended after a clones-with-immutable-args runtime (2-byte length suffix included)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub immutable_args_len: Option<usize>,
+    /// The appended bytes themselves, hex-encoded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub immutable_args: Option<String>,
+    /// Storage slot the implementation was read from (`proxy_kind: custom_slot`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impl_slot: Option<String>,
 
     pub analysis: BytecodeAnalysis,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_analysis: Option<BytecodeAnalysis>,
+    /// The proxy's own code is a likely pool too (`proxy_analysis.is_pool_likely`), as in
+    /// diamond-ish setups whose proxy carries pool selectors besides its `DELEGATECALL`
+    pub proxy_is_also_pool: bool,
+
+    /// Owner controls found on the pool's tokens (`AnalyzeOptions::scan_tokens`), as
+    /// `"token0 <address>: <signature>"`; a rug-screening hint apart from the verdict
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub token_risk_flags: Vec<String>,
+
+    /// TLSH hash of the analyzed code, mirroring `analysis.fingerprint.hash_hex`
+    /// (the implementation's for EIP-1167 proxies)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub implementation_fingerprint_hash: Option<String>,
+}
+
+/// One flat verdict for a report, proxied or not (see [`AnalyzeReport::merge_proxy`])
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EffectiveVerdict {
+    /// Address whose code the verdict is about: the implementation behind a resolved
+    /// proxy, the analyzed address otherwise
+    pub address: String,
+    pub protocol: String,
+    pub code_size: usize,
+    pub is_pool_likely: bool,
+    /// The verdict comes from an implementation reached through a proxy
+    pub via_proxy: bool,
+}
+
+/// How a proxy's code forwards to its implementation, as reported in `proxy_kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProxyKind {
+    /// Canonical 45-byte EIP-1167 minimal proxy
+    Eip1167,
+    /// Minimal proxy with immutable args appended to its code (wighawag, 0xSplits, Solady)
+    ClonesWithImmutableArgs,
+    /// Safe proxy; the singleton is read through `masterCopy()`
+    Safe,
+    /// EIP-7702 delegation designator
+    Eip7702Delegation,
+    /// Delegating code whose implementation was read from a user-given storage slot
+    /// (`AnalyzeOptions::impl_slot`); never detected from the code alone
+    CustomSlot,
+}
+
+impl ProxyKind {
+    /// Kind of proxy `bytecode` is, if any
+    pub fn of(bytecode: &[u8]) -> Option<Self> {
+        if eip7702_delegation_target(bytecode).is_some() {
+            Some(Self::Eip7702Delegation)
+        } else if is_eip1167_proxy(bytecode) {
+            Some(Self::Eip1167)
+        } else if extract_cwia_impl(bytecode).is_some() {
+            Some(Self::ClonesWithImmutableArgs)
+        } else if is_safe_proxy(bytecode) {
+            Some(Self::Safe)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Eip1167 => "eip1167",
+            Self::ClonesWithImmutableArgs => "clones_with_immutable_args",
+            Self::Safe => "safe",
+            Self::Eip7702Delegation => "eip7702_delegation",
+            Self::CustomSlot => "custom_slot",
+        }
+    }
 }
 
+impl BytecodeAnalysis {
+    /// Rename `protocol` and the candidates' protocols (canonical names) to `style`
+    pub fn apply_name_style(&mut self, style: NameStyle) {
+        self.protocol = style.apply(&self.protocol);
+        for candidate in self.protocol_candidates.iter_mut().flatten() {
+            candidate.protocol = style.apply(&candidate.protocol);
+        }
+    }
+}
+
+impl AnalyzeReport {
+    /// [`BytecodeAnalysis::apply_name_style`] on every analysis in the report
+    pub fn apply_name_style(&mut self, style: NameStyle) {
+        self.analysis.apply_name_style(style);
+        if let Some(proxy) = &mut self.proxy_analysis {
+            proxy.apply_name_style(style);
+        }
+    }
+
+    /// The verdict that counts for this address, whether it was reached through a
+    /// proxy or analyzed directly
+    ///
+    /// `analysis` already holds the implementation's analysis for a resolved proxy;
+    /// this pairs it with the address that code lives at.
+    pub fn merge_proxy(&self) -> EffectiveVerdict {
+        let via_proxy = self.proxy_analysis.is_some();
+        let address = match &self.implementation_address {
+            Some(implementation) if via_proxy => implementation.clone(),
+            _ => self.address.clone(),
+        };
+        EffectiveVerdict {
+            address,
+            protocol: self.analysis.protocol.clone(),
+            code_size: self.analysis.code_size,
+            is_pool_likely: self.analysis.is_pool_likely,
+            via_proxy,
+        }
+    }
+}
+
+/// Default number of `eth_getCode` calls grouped into one JSON-RPC batch
+pub const DEFAULT_BATCH_SIZE: usize = 50;
+
+/// Number of batches a batch run keeps in flight, as given by `--concurrency`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Concurrency {
+    /// Exactly this many batches at once (0 counts as 1)
+    Fixed(usize),
+    /// Adaptive (AIMD): start with one batch, add one per window of healthy batches,
+    /// halve on rate limits and timeouts; rate-limited addresses are retried
+    Auto,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Self::Fixed(1)
+    }
+}
+
+impl FromStr for Concurrency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Self::Auto);
+        }
+        match s.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Self::Fixed(n)),
+            _ => Err(format!(
+                "invalid concurrency `{s}` (expected a positive number or auto)"
+            )),
+        }
+    }
+}
+
+/// Knobs for a single analysis run (library and CLI share these)
+#[derive(Debug, Clone)]
+pub struct AnalyzeOptions {
+    /// Number of `eth_getCode` calls sent per JSON-RPC batch (batch analysis only)
+    pub batch_size: usize,
+    /// Batches in flight at once (batch analysis only)
+    pub concurrency: Concurrency,
+    /// Wall-clock bound of each address's analysis (batch analysis only): its batch's
+    /// code fetches plus its own `eth_call`s. Addresses past it fail with
+    /// [`AnalyzeError::Deadline`]; the rest of the run carries on.
+    #[cfg(feature = "rpc")]
+    pub deadline: Option<Duration>,
+    /// Soft per-chain hint used only to break exact confidence ties (chain priors)
+    pub chain_hint: Option<Chain>,
+    /// Bytecode layout of the chain (selects the selector-scan strategy)
+    pub chain_profile: ChainProfile,
+    /// Minimum `pool_confidence` for `is_pool_likely` (0: any identified protocol)
+    pub pool_min_confidence: u32,
+    /// Require required selectors to be PUSH operands, not arbitrary byte matches
+    /// (evm profile only; the zksync profile already scans constant-pool words)
+    pub strict_push4: bool,
+    /// Embed the analyzed bytecode (`bytecode_hex`) in every analysis of the report
+    pub include_bytecode: bool,
+    /// Report each analysis's `opcode_histogram`
+    pub opcode_histogram: bool,
+    /// Disassemble the dispatcher of `Unknown` verdicts (`disassembly`) for manual triage
+    pub include_disassembly: bool,
+    /// Analyze only the runtime segment of creation bytecode (offline input including
+    /// init code, see [`runtime_code`]); other code is analyzed whole
+    pub runtime_only: bool,
+    /// Read pool state via `eth_call` (currently `factory()`) to refine the verdict
+    pub resolve_onchain: bool,
+    /// Classify `Unknown` verdicts by calling each fingerprint's zero-argument views
+    /// (`dynamic_probe`); slow, one `eth_call` per view
+    pub dynamic: bool,
+    /// Resolve a likely pool's `token0()`/`token1()` and flag owner controls in their
+    /// code (`token_risk_flags`); two `eth_call`s and two code fetches per pool
+    pub scan_tokens: bool,
+    /// Explain `Unknown` verdicts (`explanation`), naming the closest `fingerprint_db`
+    /// reference if there is a database
+    pub explain: bool,
+    /// Decide verdicts with [`classify`](crate::classify::classify) against
+    /// `fingerprint_db` (the `combined` classifier)
+    pub combined: bool,
+    /// Reference fingerprints for `combined` and `explain`
+    pub fingerprint_db: Option<Arc<FingerprintDb>>,
+    /// Curated factories checked against the `factory()` read by `resolve_onchain`
+    pub factory_allowlist: Option<Arc<FactoryAllowlist>>,
+    /// `eth_call`-style state override to analyze a hypothetical chain state
+    /// ([`analyze_address_with_options`] only; batch analysis ignores it)
+    ///
+    /// Account `code` overrides replace the fetched bytecode of the analyzed address
+    /// and of proxy implementations. The whole map is also forwarded with every
+    /// `eth_call` (`masterCopy()`, `factory()`, dynamic probes), so storage overrides
+    /// can e.g. repoint a Safe proxy; endpoints without state override support
+    /// ignore or reject it there.
+    #[cfg(feature = "rpc")]
+    pub state_override: Option<Arc<StateOverride>>,
+    /// Storage slot holding the implementation of a proxy no built-in shape recognizes
+    /// ([`analyze_address_with_options`] only): read when the code has a
+    /// `DELEGATECALL` but no known proxy layout, and reported as `custom_slot`
+    pub impl_slot: Option<B256>,
+    /// Name the code when its exact normalized hash is a well-known contract's
+    /// (`known_contract`, see [`known_contract`](crate::bytecode_fingerprint::known_contract))
+    pub known_contracts: bool,
+    /// Addresses analyzed as they are, never followed to an implementation
+    ///
+    /// Takes precedence over every automatic resolution (EIP-1167, clones with
+    /// immutable args, Safe, EIP-7702) and over `impl_slot`, e.g. to classify the
+    /// wrapper around a shared beacon.
+    pub skip_proxy_resolution: HashSet<Address>,
+    /// Block every read of the analysis is made at (code, proxy storage, `eth_call`s),
+    /// e.g. `finalized` to stay clear of reorgs (see [`parse_block_id`])
+    #[cfg(feature = "rpc")]
+    pub block: BlockId,
+}
+
+impl Default for AnalyzeOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            concurrency: Concurrency::default(),
+            #[cfg(feature = "rpc")]
+            deadline: None,
+            chain_hint: None,
+            chain_profile: ChainProfile::default(),
+            pool_min_confidence: 0,
+            strict_push4: false,
+            include_bytecode: false,
+            opcode_histogram: false,
+            include_disassembly: false,
+            runtime_only: false,
+            resolve_onchain: false,
+            dynamic: false,
+            scan_tokens: false,
+            explain: false,
+            combined: false,
+            fingerprint_db: None,
+            factory_allowlist: None,
+            #[cfg(feature = "rpc")]
+            state_override: None,
+            impl_slot: None,
+            known_contracts: false,
+            skip_proxy_resolution: HashSet::new(),
+            #[cfg(feature = "rpc")]
+            block: BlockId::latest(),
+        }
+    }
+}
+
+impl AnalyzeOptions {
+    /// Whether a proxy at `address` is followed to its implementation
+    #[cfg(feature = "rpc")]
+    pub(crate) fn resolves_proxy(&self, address: Address) -> bool {
+        !self.skip_proxy_resolution.contains(&address)
+    }
+
+    /// Selector-scan strategy implied by the chain profile and `strict_push4`
+    pub fn selector_scan(&self) -> SelectorScan {
+        match self.chain_profile.selector_scan() {
+            SelectorScan::Anywhere if self.strict_push4 => SelectorScan::Push4,
+            scan => scan,
+        }
+    }
+}
+
+#[cfg(feature = "rpc")]
 pub fn validate_rpc_url(rpc_url: &str) -> Result<(), AnalyzeError> {
     if rpc_url.trim().is_empty() {
         return Err(AnalyzeError::InvalidRpcUrl);
@@ -83,53 +643,461 @@ pub fn parse_address_hex(address: &str) -> Result<Address, AnalyzeError> {
         .map_err(|_| AnalyzeError::InvalidAddress)
 }
 
+/// Parse a 0x-prefixed 32-byte transaction hash
+pub fn parse_tx_hash(tx_hash: &str) -> Result<B256, AnalyzeError> {
+    tx_hash
+        .parse::<B256>()
+        .map_err(|_| AnalyzeError::InvalidTxHash)
+}
+
+/// Parse a block for [`AnalyzeOptions::block`]: a tag (`latest`, `finalized`, `safe`,
+/// `earliest`, `pending`), a number (decimal or 0x-prefixed hex) or a 32-byte block hash
+#[cfg(feature = "rpc")]
+pub fn parse_block_id(block: &str) -> Result<BlockId, AnalyzeError> {
+    let invalid = || AnalyzeError::InvalidBlock(block.to_string());
+    let tag = match block.to_ascii_lowercase().as_str() {
+        "latest" => Some(BlockNumberOrTag::Latest),
+        "finalized" => Some(BlockNumberOrTag::Finalized),
+        "safe" => Some(BlockNumberOrTag::Safe),
+        "earliest" => Some(BlockNumberOrTag::Earliest),
+        "pending" => Some(BlockNumberOrTag::Pending),
+        _ => None,
+    };
+    if let Some(tag) = tag {
+        return Ok(BlockId::Number(tag));
+    }
+    match block.strip_prefix("0x") {
+        Some(digits) if digits.len() == 64 => block
+            .parse::<B256>()
+            .map(BlockId::from)
+            .map_err(|_| invalid()),
+        Some(digits) if !digits.is_empty() && digits.len() <= 16 => u64::from_str_radix(digits, 16)
+            .map(BlockId::number)
+            .map_err(|_| invalid()),
+        Some(_) => Err(invalid()),
+        None => block
+            .parse::<u64>()
+            .map(BlockId::number)
+            .map_err(|_| invalid()),
+    }
+}
+
+/// How offline bytecode input is encoded, as given by `--input-encoding`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputEncoding {
+    /// Hex text, `0x` prefix optional
+    #[default]
+    Hex,
+    /// Standard (padded) base64 text
+    Base64,
+    /// The bytecode bytes themselves
+    Raw,
+}
+
+impl FromStr for InputEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hex" => Ok(Self::Hex),
+            "base64" | "b64" => Ok(Self::Base64),
+            "raw" | "binary" => Ok(Self::Raw),
+            _ => Err(format!(
+                "unknown input encoding `{s}` (expected hex, base64 or raw)"
+            )),
+        }
+    }
+}
+
+/// Decode bytecode read from a file or stdin
+///
+/// Surrounding whitespace is ignored for the text encodings (and line breaks
+/// inside base64, as wrapped by `base64` tools). Empty input is an error.
+pub fn decode_bytecode(input: &[u8], encoding: InputEncoding) -> Result<Vec<u8>, AnalyzeError> {
+    let text = || {
+        core::str::from_utf8(input).map_err(|_| {
+            AnalyzeError::InvalidBytecode(
+                "not UTF-8 text (binary input needs the raw encoding)".to_string(),
+            )
+        })
+    };
+
+    let bytecode = match encoding {
+        InputEncoding::Hex => {
+            let hex_str = text()?.trim();
+            let hex_str = hex_str.strip_prefix("0x").unwrap_or(hex_str);
+            hex::decode(hex_str)
+                .map_err(|e| AnalyzeError::InvalidBytecode(format!("invalid hex: {e}")))?
+        }
+        InputEncoding::Base64 => {
+            let b64: String = text()?.split_whitespace().collect();
+            base64::engine::general_purpose::STANDARD
+                .decode(b64)
+                .map_err(|e| AnalyzeError::InvalidBytecode(format!("invalid base64: {e}")))?
+        }
+        InputEncoding::Raw => input.to_vec(),
+    };
+
+    if bytecode.is_empty() {
+        return Err(AnalyzeError::InvalidBytecode("empty input".to_string()));
+    }
+    Ok(bytecode)
+}
+
+/// Canonical name of `p` (see [`DexProtocol::name`])
 pub fn dex_protocol_name(p: DexProtocol) -> &'static str {
-    match p {
-        DexProtocol::UniswapV2 => "UniswapV2",
-        DexProtocol::UniswapV3 => "UniswapV3",
-        DexProtocol::Solidly => "Solidly",
-        DexProtocol::AlgebraLegacyV1 => "AlgebraLegacyV1",
-        DexProtocol::AlgebraLegacyV1_9Plus => "AlgebraLegacyV1_9Plus",
-        DexProtocol::AlgebraIntegral => "AlgebraIntegral",
-        DexProtocol::Unknown => "Unknown",
-    }
-}
-
-fn decide_protocol(bytecode: &[u8]) -> (DexProtocol, Option<Vec<ProtocolCandidate>>) {
-    let mut matches = identify_protocols(bytecode);
-    matches.sort_by(|a, b| {
-        b.1.cmp(&a.1)
-            .then_with(|| dex_protocol_name(a.0).cmp(dex_protocol_name(b.0)))
-    });
+    p.name()
+}
+
+/// Alternative protocol names accepted by [`parse_dex_protocol`], in normalized form
+/// (lowercase ASCII letters and digits only)
+pub static PROTOCOL_ALIASES: &[(&str, DexProtocol)] = &[
+    ("univ2", DexProtocol::UniswapV2),
+    ("uni2", DexProtocol::UniswapV2),
+    ("univ3", DexProtocol::UniswapV3),
+    ("uni3", DexProtocol::UniswapV3),
+    ("velodrome", DexProtocol::Solidly),
+    ("aerodrome", DexProtocol::Solidly),
+    ("algebrav1", DexProtocol::AlgebraLegacyV1),
+    ("algebrav19", DexProtocol::AlgebraLegacyV1_9Plus),
+    ("balancerv2", DexProtocol::BalancerV2Pool),
+    ("woofipmm", DexProtocol::WooFi),
+];
+
+/// Parse a protocol name in any [`NameStyle`], or one of `aliases`
+///
+/// Matching ignores case and every character that is not an ASCII letter or digit,
+/// so `UniswapV3`, `uniswap-v3` and `UNISWAP_V3` are the same name. Pass
+/// [`PROTOCOL_ALIASES`] (what [`FromStr`] uses), or an extended table of your own.
+pub fn parse_dex_protocol(s: &str, aliases: &[(&str, DexProtocol)]) -> Result<DexProtocol, String> {
+    fn normalize(name: &str) -> String {
+        name.chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    }
+
+    let known = || {
+        fingerprints()
+            .iter()
+            .map(|fp| fp.protocol)
+            .chain([DexProtocol::Unknown])
+    };
+    let wanted = normalize(s);
+    known()
+        .find(|p| normalize(dex_protocol_name(*p)) == wanted)
+        .or_else(|| {
+            aliases
+                .iter()
+                .find(|(alias, _)| normalize(alias) == wanted)
+                .map(|(_, p)| *p)
+        })
+        .ok_or_else(|| {
+            let names: Vec<&str> = known().map(dex_protocol_name).collect();
+            format!(
+                "unknown protocol `{s}` (expected one of {})",
+                names.join(", ")
+            )
+        })
+}
+
+/// Inverse of [`dex_protocol_name`] in any [`NameStyle`], plus [`PROTOCOL_ALIASES`]
+impl FromStr for DexProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_dex_protocol(s, PROTOCOL_ALIASES)
+    }
+}
+
+/// Serialized as [`dex_protocol_name`], like the `protocol` strings of reports
+impl Serialize for DexProtocol {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(dex_protocol_name(*self))
+    }
+}
+
+/// How protocol names are rendered in reports, as given by `--name-style`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NameStyle {
+    /// As [`dex_protocol_name`] spells them: `UniswapV3`
+    #[default]
+    Canonical,
+    /// `uniswapv3`
+    Lowercase,
+    /// `uniswap-v3`
+    Kebab,
+}
+
+impl NameStyle {
+    /// Render a canonical protocol name in this style
+    pub fn apply(self, canonical: &str) -> String {
+        match self {
+            Self::Canonical => canonical.to_string(),
+            Self::Lowercase => canonical
+                .chars()
+                .filter(char::is_ascii_alphanumeric)
+                .map(|c| c.to_ascii_lowercase())
+                .collect(),
+            Self::Kebab => {
+                let chars: Vec<char> = canonical.chars().collect();
+                let mut out = String::with_capacity(canonical.len() + 4);
+                for (i, &c) in chars.iter().enumerate() {
+                    if !c.is_ascii_alphanumeric() {
+                        if !out.is_empty() && !out.ends_with('-') {
+                            out.push('-');
+                        }
+                        continue;
+                    }
+                    // A word starts at an uppercase letter after a lowercase letter or
+                    // digit, or at the last capital of an acronym (`CLPool` -> `cl-pool`).
+                    let prev = i.checked_sub(1).map(|j| chars[j]);
+                    let next = chars.get(i + 1);
+                    let boundary = c.is_ascii_uppercase()
+                        && prev.is_some_and(|p| {
+                            p.is_ascii_lowercase()
+                                || p.is_ascii_digit()
+                                || (p.is_ascii_uppercase()
+                                    && next.is_some_and(char::is_ascii_lowercase))
+                        });
+                    if boundary && !out.ends_with('-') {
+                        out.push('-');
+                    }
+                    out.push(c.to_ascii_lowercase());
+                }
+                out
+            }
+        }
+    }
+}
+
+impl FromStr for NameStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "canonical" => Ok(Self::Canonical),
+            "lowercase" | "lower" => Ok(Self::Lowercase),
+            "kebab" | "kebab-case" => Ok(Self::Kebab),
+            _ => Err(format!(
+                "unknown name style `{s}` (expected canonical, lowercase or kebab)"
+            )),
+        }
+    }
+}
+
+fn decide_protocol(
+    bytecode: &[u8],
+    options: &AnalyzeOptions,
+) -> (DexProtocol, u32, Option<Vec<ProtocolCandidate>>) {
+    let matches = identify_protocols_with(bytecode, options.selector_scan());
 
     debug!(matches = ?matches.iter().map(|(p,c)| (dex_protocol_name(*p), *c)).collect::<Vec<_>>(), "selector_fingerprint_matches");
 
     match matches.len() {
-        1 => (matches[0].0, None),
-        0 => (DexProtocol::Unknown, None),
+        1 => (matches[0].0, matches[0].1, None),
+        0 => (DexProtocol::Unknown, 0, None),
         _ => {
+            // A chain hint only settles exact ties; candidates stay visible either way.
+            let tie_break = options.chain_hint.and_then(|hint| hint.break_tie(&matches));
+            if let Some(p) = tie_break {
+                debug!(protocol = dex_protocol_name(p), "chain_hint_tie_break");
+            }
+
+            let confidence = tie_break.map_or(0, |_| matches[0].1);
             let candidates = matches
                 .into_iter()
-                .map(|(p, confidence)| ProtocolCandidate {
-                    protocol: dex_protocol_name(p).to_string(),
-                    confidence,
-                })
+                .map(|(p, confidence)| ProtocolCandidate::new(p, confidence))
                 .collect();
-            (DexProtocol::Unknown, Some(candidates))
+            (
+                tie_break.unwrap_or(DexProtocol::Unknown),
+                confidence,
+                Some(candidates),
+            )
+        }
+    }
+}
+
+fn contract_hint(kind: ContractKind) -> Option<String> {
+    match kind {
+        ContractKind::Factory { pool_protocol } => {
+            Some(format!("{} factory", dex_protocol_name(pool_protocol)))
         }
+        ContractKind::Precompile { name } => Some(format!("{name} precompile (no bytecode)")),
+        ContractKind::ZeroAddress => Some("zero address (burn or unset, no bytecode)".to_string()),
+        ContractKind::Eip7702Delegation { target } => Some(format!(
+            "EIP-7702 delegation to {:#x}",
+            Address::from(target)
+        )),
+        ContractKind::PerpVault
+        | ContractKind::Pmm
+        | ContractKind::Safe
+        | ContractKind::WrappedNative
+        | ContractKind::Permit2
+        | ContractKind::PositionManager
+        | ContractKind::LpToken
+        | ContractKind::Pool => None,
+    }
+}
+
+/// Addresses without bytecode by design: the zero address and the precompiles
+///
+/// Analyzing them reports this kind instead of [`AnalyzeError::NoDeployedBytecode`].
+pub fn system_address_kind(address: Address) -> Option<ContractKind> {
+    if address.is_zero() {
+        return Some(ContractKind::ZeroAddress);
+    }
+    precompile_name(address).map(|name| ContractKind::Precompile { name })
+}
+
+/// Name of the precompile at `address` (0x01..=0x0a, as of Cancun)
+pub fn precompile_name(address: Address) -> Option<&'static str> {
+    const PRECOMPILES: [&str; 10] = [
+        "ecrecover",
+        "sha256",
+        "ripemd160",
+        "identity",
+        "modexp",
+        "ecAdd",
+        "ecMul",
+        "ecPairing",
+        "blake2f",
+        "pointEvaluation",
+    ];
+
+    let (high, low) = address.as_slice().split_at(19);
+    if high.iter().any(|b| *b != 0) {
+        return None;
     }
+    let index = usize::from(low[0]).checked_sub(1)?;
+    PRECOMPILES.get(index).copied()
+}
+
+/// Report for a [`system_address_kind`] address, built without fetching code
+#[cfg(feature = "rpc")]
+pub(crate) fn system_address_report(
+    rpc_url: &str,
+    address: Address,
+    options: &AnalyzeOptions,
+) -> Option<AnalyzeReport> {
+    let kind = system_address_kind(address)?;
+    let mut analysis = analyze_bytecode_with_options(address, &[], options);
+    // Empty code is expected here, not a fingerprinting failure.
+    analysis.fingerprint_error = None;
+    analysis.contract_kind = Some(kind.name().to_string());
+    analysis.contract_hint = contract_hint(kind);
+
+    Some(AnalyzeReport {
+        rpc_url: rpc_url.to_string(),
+        address: format!("{address:#x}"),
+        is_eip1167_proxy: false,
+        proxy_kind: None,
+        implementation_address: None,
+        immutable_args_len: None,
+        immutable_args: None,
+        impl_slot: None,
+        implementation_fingerprint_hash: None,
+        analysis,
+        proxy_analysis: None,
+        proxy_is_also_pool: false,
+        token_risk_flags: Vec::new(),
+    })
 }
 
 pub fn analyze_bytecode(address: Address, bytecode: &[u8]) -> BytecodeAnalysis {
-    let (protocol, candidates) = decide_protocol(bytecode);
-    let is_pool_likely = protocol != DexProtocol::Unknown;
+    analyze_bytecode_with_options(address, bytecode, &AnalyzeOptions::default())
+}
+
+/// Analyze bytecode offline; with `options.combined` the verdict comes from
+/// [`classify`](crate::classify::classify) (see [`analyze_bytecode_with_db`])
+pub fn analyze_bytecode_with_options(
+    address: Address,
+    bytecode: &[u8],
+    options: &AnalyzeOptions,
+) -> BytecodeAnalysis {
+    let db = options
+        .fingerprint_db
+        .as_deref()
+        .filter(|_| options.combined);
+    analyze(address, bytecode, options, db)
+}
+
+fn analyze(
+    address: Address,
+    bytecode: &[u8],
+    options: &AnalyzeOptions,
+    db: Option<&FingerprintDb>,
+) -> BytecodeAnalysis {
+    // Constructor code can reference other contracts' selectors; only the runtime counts.
+    let bytecode = if options.runtime_only {
+        runtime_code(bytecode).unwrap_or(bytecode)
+    } else {
+        bytecode
+    };
+    let (mut protocol, mut pool_confidence, mut candidates) = decide_protocol(bytecode, options);
+    // An Unknown classification leaves the selector verdict (and its chain-hint tie
+    // break) alone. Any other one is confirmed, so the selector candidates go.
+    let classification = db.map(|db| classify_with(bytecode, options.selector_scan(), db));
+    if let Some(c) = classification
+        .as_ref()
+        .filter(|c| c.protocol != DexProtocol::Unknown)
+    {
+        protocol = c.protocol;
+        pool_confidence = c.selector_confidence;
+        candidates = None;
+    }
+    let contract_kind = identify_contract_kind(bytecode);
+    // Proxy and delegation stubs carry no code of their own to classify or fingerprint.
+    let stub = is_eip1167_proxy(bytecode)
+        || extract_cwia_impl(bytecode).is_some()
+        || matches!(contract_kind, Some(ContractKind::Eip7702Delegation { .. }));
+    let metadata = parse_metadata(bytecode);
+    let explanation = (options.explain && protocol == DexProtocol::Unknown && !stub).then(|| {
+        let reference_db = db.or(options.fingerprint_db.as_deref());
+        explain_unknown(bytecode, options.selector_scan(), reference_db)
+    });
+    let is_pool_likely = protocol != DexProtocol::Unknown
+        && pool_confidence >= options.pool_min_confidence
+        && contract_kind.is_none_or(|k| k.is_pool());
+    let pool_subtype = match protocol {
+        DexProtocol::BalancerV2Pool => balancer_pool_subtype(bytecode).map(str::to_string),
+        _ => None,
+    };
+    let fork_family = match protocol {
+        DexProtocol::UniswapV2 => v2_fork_family(bytecode, None).map(str::to_string),
+        _ => None,
+    };
+    let curve_type = v2_curve_type(bytecode, protocol).map(str::to_string);
 
-    let (fingerprint, fingerprint_error) = match BytecodeFingerprint::from_bytecode(bytecode) {
+    // EIP-1167 proxies (45 bytes) and EIP-7702 designators (23 bytes) are always below
+    // the TLSH minimum; the implementation carries the fingerprint, so the stub reports
+    // neither a fingerprint nor an error.
+    let fp = BytecodeFingerprint::from_bytecode(bytecode);
+    let known_contract = fp
+        .as_ref()
+        .ok()
+        .filter(|_| options.known_contracts)
+        .and_then(BytecodeFingerprint::known_contract)
+        .map(str::to_string);
+    if let Ok(fp) = &fp {
+        if fp.metadata_strip_suspect() {
+            warn!(
+                address = %format!("{address:#x}"),
+                original_size = fp.original_size(),
+                normalized_size = fp.normalized_size(),
+                "metadata_strip_suspect"
+            );
+        }
+    }
+    let (fingerprint, fingerprint_error) = match fp {
+        Err(FingerprintError::BytecodeTooSmall(_)) if stub => (None, None),
         Ok(fp) => (
             Some(FingerprintReport {
                 hash_hex: fp.hash_hex(),
+                exact_hash: fp.exact_hash_hex(),
                 original_size: fp.original_size(),
                 normalized_size: fp.normalized_size(),
+                metadata_strip_suspect: fp.metadata_strip_suspect(),
             }),
             None,
         ),
@@ -141,13 +1109,195 @@ pub fn analyze_bytecode(address: Address, bytecode: &[u8]) -> BytecodeAnalysis {
         code_size: bytecode.len(),
         protocol: dex_protocol_name(protocol).to_string(),
         protocol_candidates: candidates,
+        pool_subtype,
+        fork_family,
+        curve_type,
+        factory: None,
+        factory_verified: None,
+        contract_kind: contract_kind.map(|k| k.name().to_string()),
+        contract_hint: contract_kind.and_then(contract_hint),
+        known_contract,
+        pool_confidence,
+        detection_method: classification
+            .as_ref()
+            .map_or(DetectionMethod::Selector, |c| c.method)
+            .as_str()
+            .to_string(),
+        combined_confidence: classification.map(|c| c.confidence),
+        dynamic_probes: None,
+        explanation,
         is_pool_likely,
+        forwarding_suspected: forwarding_suspected(bytecode),
         fingerprint,
         fingerprint_error,
+        metadata_ipfs: metadata.as_ref().and_then(SolcMetadata::ipfs_cid),
+        metadata_swarm: metadata.as_ref().and_then(SolcMetadata::swarm_hash),
+        opcode_histogram: options
+            .opcode_histogram
+            .then(|| opcode_histogram(bytecode).to_vec()),
+        disassembly: (options.include_disassembly && protocol == DexProtocol::Unknown)
+            .then(|| disassemble(bytecode, disassembly_len(bytecode))),
+        bytecode_hex: options
+            .include_bytecode
+            .then(|| format!("0x{}", hex::encode(bytecode))),
+    }
+}
+
+/// Most bytes of code disassembled into [`BytecodeAnalysis::disassembly`]
+pub const DISASSEMBLY_MAX_BYTES: usize = 256;
+
+/// The dispatcher region, or the start of the code when no dispatcher is found, capped
+/// at [`DISASSEMBLY_MAX_BYTES`]
+fn disassembly_len(bytecode: &[u8]) -> usize {
+    match dispatcher_region(bytecode).end {
+        0 => DISASSEMBLY_MAX_BYTES,
+        end => end.min(DISASSEMBLY_MAX_BYTES),
+    }
+}
+
+/// [`analyze_bytecode_with_options`] with the verdict taken from
+/// [`classify`](crate::classify::classify) against `db` (whatever `options` hold)
+pub fn analyze_bytecode_with_db(
+    address: Address,
+    bytecode: &[u8],
+    options: &AnalyzeOptions,
+    db: &FingerprintDb,
+) -> BytecodeAnalysis {
+    analyze(address, bytecode, options, Some(db))
+}
+
+/// Triage note for an `Unknown` verdict, e.g. `Closest family: UniswapV3 (TLSH diff 62,
+/// same family); missing required selector fee().`
+///
+/// Combines the nearest reference in `db` (if any) with the selector breakdown of
+/// [`identify_protocols_verbose_with`]: what the closest family lacks or has in excess,
+/// and which other protocols are one required selector short.
+fn explain_unknown(bytecode: &[u8], scan: SelectorScan, db: Option<&FingerprintDb>) -> String {
+    let details = identify_protocols_verbose_with(bytecode, scan);
+    let closest = db.and_then(|db| {
+        let fp = BytecodeFingerprint::from_bytecode(bytecode).ok()?;
+        db.nearest(&fp)
+    });
+
+    let mut parts = Vec::new();
+    if let Some((protocol, distance)) = closest {
+        parts.push(format!(
+            "Closest family: {} (TLSH diff {distance}, {})",
+            dex_protocol_name(protocol),
+            similarity_label(Similarity::from_diff(distance))
+        ));
+        if let Some(detail) = details.iter().find(|d| d.protocol == protocol) {
+            if !detail.missing_required.is_empty() {
+                parts.push(format!(
+                    "missing required {}",
+                    selector_list(&detail.missing_required)
+                ));
+            }
+            if !detail.forbidden_present.is_empty() {
+                parts.push(format!(
+                    "has forbidden {}",
+                    selector_list(&detail.forbidden_present)
+                ));
+            }
+        }
+    }
+    for detail in details.iter().filter(|d| d.is_near_miss()) {
+        if closest.is_some_and(|(p, _)| p == detail.protocol) {
+            continue;
+        }
+        parts.push(format!(
+            "{} is missing required {}",
+            dex_protocol_name(detail.protocol),
+            selector_list(&detail.missing_required)
+        ));
+    }
+
+    if parts.is_empty() {
+        "No known protocol is within one required selector of matching.".to_string()
+    } else {
+        format!("{}.", parts.join("; "))
+    }
+}
+
+/// [`Similarity::as_str`] spelled for prose (`same family`)
+fn similarity_label(similarity: Similarity) -> String {
+    similarity.as_str().replace('_', " ")
+}
+
+/// `selector fee()` / `selectors fee(), slot0()`, unknown selectors as hex
+fn selector_list(selectors: &[Selector]) -> String {
+    let names: Vec<String> = selectors
+        .iter()
+        .map(|s| selector_name(s).map_or_else(|| s.to_string(), str::to_string))
+        .collect();
+    let noun = if names.len() == 1 {
+        "selector"
+    } else {
+        "selectors"
+    };
+    format!("{noun} {}", names.join(", "))
+}
+
+const CURVE_CONSTANT_PRODUCT: &str = "constant_product";
+const CURVE_STABLE: &str = "stable";
+
+/// Curve of a V2-shaped pool (`token0`/`token1`/`getReserves`), whichever fingerprint
+/// matched: `stable()` next to that core marks the stable invariant
+///
+/// Solidly code serves `stable()` for both curves (it's a storage flag), so Solidly
+/// pools only get a curve from [`apply_stable_flag`].
+fn v2_curve_type(bytecode: &[u8], protocol: DexProtocol) -> Option<&'static str> {
+    if matches!(protocol, DexProtocol::Solidly | DexProtocol::SolidlyCL) {
+        return None;
+    }
+    let v2_core = [
+        selectors::TOKEN0,
+        selectors::TOKEN1,
+        selectors::GET_RESERVES,
+    ]
+    .iter()
+    .all(|s| s.exists_in(bytecode));
+    v2_core.then(|| {
+        if selectors::STABLE.exists_in(bytecode) {
+            CURVE_STABLE
+        } else {
+            CURVE_CONSTANT_PRODUCT
+        }
+    })
+}
+
+/// Record a Solidly pool's resolved `stable()` flag as its `curve_type`
+pub fn apply_stable_flag(analysis: &mut BytecodeAnalysis, stable: bool) {
+    let curve = if stable {
+        CURVE_STABLE
+    } else {
+        CURVE_CONSTANT_PRODUCT
+    };
+    analysis.curve_type = Some(curve.to_string());
+}
+
+/// Record a resolved `factory()` and refine the fork family from the known-factory map
+pub fn apply_factory(analysis: &mut BytecodeAnalysis, factory: Address) {
+    analysis.factory = Some(format!("{factory:#x}"));
+    let dex = analysis
+        .protocol
+        .parse()
+        .ok()
+        .and_then(|protocol| factory_dex(factory, protocol));
+    if let Some(dex) = dex {
+        analysis.fork_family = Some(dex.to_string());
     }
 }
 
+/// Implementation of an EIP-1167 or clones-with-immutable-args proxy, or the target of
+/// an EIP-7702 delegation
 pub fn proxy_implementation_address(bytecode: &[u8]) -> Option<Address> {
+    if let Some(target) = eip7702_delegation_target(bytecode) {
+        return Some(Address::from(target));
+    }
+    if let Some((implementation, _)) = extract_cwia_impl(bytecode) {
+        return Some(Address::from(implementation));
+    }
     if !is_eip1167_proxy(bytecode) {
         return None;
     }
@@ -155,80 +1305,766 @@ pub fn proxy_implementation_address(bytecode: &[u8]) -> Option<Address> {
     Some(Address::from(impl_bytes))
 }
 
-async fn fetch_code(rpc_url: &str, address: Address) -> Result<Vec<u8>, AnalyzeError> {
-    let url: AlloyUrl = rpc_url.parse().map_err(|_| AnalyzeError::InvalidRpcUrl)?;
-    let provider = ProviderBuilder::new().on_http(url);
+/// Implementation code fetched for `bytecode`'s [`proxy_implementation_address`]
+///
+/// An EIP-7702 delegation may point at an address without code (the account then runs
+/// nothing); it is reported on its own instead of failing with
+/// [`AnalyzeError::NoDeployedBytecode`].
+#[cfg(feature = "rpc")]
+pub(crate) fn proxy_implementation(
+    bytecode: &[u8],
+    impl_address: Address,
+    impl_code: Result<Vec<u8>, AnalyzeError>,
+) -> Result<Option<(Address, Vec<u8>)>, AnalyzeError> {
+    match impl_code {
+        Ok(code) => Ok(Some((impl_address, code))),
+        Err(AnalyzeError::NoDeployedBytecode) if eip7702_delegation_target(bytecode).is_some() => {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(feature = "rpc")]
+pub(crate) fn rpc_url(rpc_url: &str) -> Result<AlloyUrl, AnalyzeError> {
+    rpc_url.parse().map_err(|_| AnalyzeError::InvalidRpcUrl)
+}
+
+/// JSON-RPC client of one endpoint, built once per run and shared by all its calls
+/// (addresses, proxy hops, on-chain reads)
+///
+/// Its HTTP client keeps connections alive, so calls reuse one connection instead of
+/// connecting (and setting up TLS) again each time.
+#[cfg(feature = "rpc")]
+pub(crate) struct Rpc {
+    url: String,
+    client: RpcClient<ReqwestTransport>,
+    /// Block every read is made at
+    block: BlockId,
+}
+
+#[cfg(feature = "rpc")]
+impl Rpc {
+    /// Client for `rpc_url`, validated like [`validate_rpc_url`]
+    pub(crate) fn new(rpc_url: &str) -> Result<Self, AnalyzeError> {
+        validate_rpc_url(rpc_url)?;
+        Ok(Self {
+            url: rpc_url.to_string(),
+            client: RpcClient::new_http(self::rpc_url(rpc_url)?),
+            block: BlockId::latest(),
+        })
+    }
+
+    /// Make every read at `block` instead of the latest one
+    pub(crate) fn at(self, block: BlockId) -> Self {
+        Self { block, ..self }
+    }
+
+    /// The endpoint, as given
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub(crate) fn client(&self) -> &RpcClient<ReqwestTransport> {
+        &self.client
+    }
+
+    pub(crate) fn block(&self) -> BlockId {
+        self.block
+    }
+}
 
-    let bytes = provider
-        .get_code_at(address)
+/// Fetch runtime bytecode; an address without code is [`AnalyzeError::NoDeployedBytecode`]
+#[cfg(feature = "rpc")]
+pub(crate) async fn fetch_code(rpc: &Rpc, address: Address) -> Result<Vec<u8>, AnalyzeError> {
+    let result: Option<String> = rpc
+        .client
+        .request("eth_getCode", (address, rpc.block))
         .await
-        .map_err(|e| AnalyzeError::Rpc(e.to_string()))?;
+        .map_err(RpcError::from)?;
 
+    let bytes = decode_code(result)?;
     debug!(address = %format!("{address:#x}"), code_size = bytes.len(), "fetched_code");
+    Ok(bytes)
+}
+
+/// [`fetch_code`], unless `overrides` sets the account's code
+#[cfg(feature = "rpc")]
+pub(crate) async fn fetch_code_with(
+    rpc: &Rpc,
+    address: Address,
+    overrides: Option<&StateOverride>,
+) -> Result<Vec<u8>, AnalyzeError> {
+    let code = overrides
+        .and_then(|overrides| overrides.get(&address))
+        .and_then(|account| account.code.as_ref());
+    match code {
+        Some(code) if code.is_empty() => Err(AnalyzeError::NoDeployedBytecode),
+        Some(code) => {
+            debug!(address = %format!("{address:#x}"), code_size = code.len(), "overridden_code");
+            Ok(code.to_vec())
+        }
+        None => fetch_code(rpc, address).await,
+    }
+}
+
+/// Decode an `eth_getCode` result
+///
+/// Endpoints disagree on how to say "no code" (`null`, `""`, `"0x"`, `"0x0"`); all of
+/// them map to [`AnalyzeError::NoDeployedBytecode`]. Zero bytes (`"0x00"`) are
+/// deployed code, all `STOP`s, and decode as such.
+#[cfg(feature = "rpc")]
+pub(crate) fn decode_code(result: Option<String>) -> Result<Vec<u8>, AnalyzeError> {
+    let hex_code = result.unwrap_or_default();
+    if matches!(hex_code.as_str(), "" | "0x" | "0x0") {
+        return Err(AnalyzeError::NoDeployedBytecode);
+    }
+
+    let digits = hex_code.strip_prefix("0x").unwrap_or(&hex_code);
+    hex::decode(digits)
+        .map_err(|e| RpcError::Decode(format!("invalid eth_getCode result: {e}")).into())
+}
+
+/// `eth_call` against `to` at the run's block, with an optional state override
+#[cfg(feature = "rpc")]
+pub(crate) async fn eth_call(
+    rpc: &Rpc,
+    to: Address,
+    calldata: &[u8],
+    overrides: Option<&StateOverride>,
+) -> Result<Vec<u8>, AnalyzeError> {
+    let provider = ProviderBuilder::new().on_client(rpc.client.clone());
+    let tx = TransactionRequest::default()
+        .with_to(to)
+        .with_input(calldata.to_vec());
+
+    let mut call = provider.call(&tx).block(rpc.block);
+    if let Some(overrides) = overrides {
+        call = call.overrides(overrides);
+    }
+    let bytes = call.await.map_err(RpcError::from)?;
     Ok(bytes.to_vec())
 }
 
+/// Record whether the resolved factory is allowlisted; a verified pool gets its
+/// fingerprint's highest confidence
+pub fn apply_factory_verification(analysis: &mut BytecodeAnalysis, verified: bool) {
+    analysis.factory_verified = Some(verified);
+    if !verified {
+        return;
+    }
+    let fingerprint = analysis
+        .protocol
+        .parse()
+        .ok()
+        .and_then(protocol_fingerprint);
+    if let Some(fp) = fingerprint {
+        analysis.pool_confidence = fp.max_confidence();
+    }
+}
+
+/// Chain id of the endpoint (`eth_chainId`)
+#[cfg(feature = "rpc")]
+pub(crate) async fn chain_id(rpc: &Rpc) -> Result<u64, AnalyzeError> {
+    let id: alloy::primitives::U64 = rpc
+        .client
+        .request_noparams("eth_chainId")
+        .await
+        .map_err(RpcError::from)?;
+    Ok(id.to())
+}
+
+/// `options.factory_allowlist` with the endpoint's chain id, when `resolve_onchain`
+/// will check factories against it
+///
+/// A failed chain id read is logged and leaves factories unverified.
+#[cfg(feature = "rpc")]
+pub(crate) async fn factory_allowlist_for_chain<'a>(
+    rpc: &Rpc,
+    options: &'a AnalyzeOptions,
+) -> Option<(&'a FactoryAllowlist, u64)> {
+    let allowlist = options
+        .factory_allowlist
+        .as_deref()
+        .filter(|_| options.resolve_onchain)?;
+    match chain_id(rpc).await {
+        Ok(id) => Some((allowlist, id)),
+        Err(e) => {
+            debug!(error = %e, "chain_id_failed");
+            None
+        }
+    }
+}
+
+/// Refine a report with on-chain reads; failed reads are logged and skipped
+///
+/// With an `allowlist` (and the chain id it is checked for), a resolved factory is
+/// also verified against it.
+#[cfg(feature = "rpc")]
+pub(crate) async fn resolve_onchain(
+    rpc: &Rpc,
+    report: &mut AnalyzeReport,
+    allowlist: Option<(&FactoryAllowlist, u64)>,
+    overrides: Option<&StateOverride>,
+) {
+    if !report.analysis.is_pool_likely {
+        return;
+    }
+    // Pool state (and thus `factory`) lives at the called address, also for proxies.
+    let Ok(address) = parse_address_hex(&report.address) else {
+        return;
+    };
+
+    match eth_call(rpc, address, selectors::FACTORY.as_bytes(), overrides).await {
+        Ok(ret) if ret.len() == 32 => {
+            let factory = Address::from_slice(&ret[12..]);
+            apply_factory(&mut report.analysis, factory);
+            if let Some((allowlist, chain_id)) = allowlist {
+                let verified = report
+                    .analysis
+                    .protocol
+                    .parse()
+                    .is_ok_and(|protocol| allowlist.contains(chain_id, factory, protocol));
+                apply_factory_verification(&mut report.analysis, verified);
+            }
+        }
+        Ok(ret) => debug!(len = ret.len(), "factory_call_unexpected_return"),
+        Err(e) => debug!(error = %e, "factory_call_failed"),
+    }
+
+    if report.analysis.protocol == dex_protocol_name(DexProtocol::Solidly) {
+        match eth_call(rpc, address, selectors::STABLE.as_bytes(), overrides).await {
+            Ok(ret) if ret.len() == 32 && ret[..31].iter().all(|b| *b == 0) && ret[31] <= 1 => {
+                apply_stable_flag(&mut report.analysis, ret[31] == 1);
+            }
+            Ok(ret) => debug!(len = ret.len(), "stable_call_unexpected_return"),
+            Err(e) => debug!(error = %e, "stable_call_failed"),
+        }
+    }
+}
+
+/// Flag owner controls (blacklists, transfer caps) in the code of a likely pool's
+/// tokens, behind their proxies unless `options` skip them; failed reads are logged
+/// and skipped
+#[cfg(feature = "rpc")]
+pub(crate) async fn scan_tokens(
+    rpc: &Rpc,
+    report: &mut AnalyzeReport,
+    options: &AnalyzeOptions,
+    overrides: Option<&StateOverride>,
+) {
+    if !report.analysis.is_pool_likely {
+        return;
+    }
+    let Ok(pool) = parse_address_hex(&report.address) else {
+        return;
+    };
+
+    for (label, getter) in [("token0", selectors::TOKEN0), ("token1", selectors::TOKEN1)] {
+        let token = match eth_call(rpc, pool, getter.as_bytes(), overrides).await {
+            Ok(ret) if ret.len() == 32 => Address::from_slice(&ret[12..]),
+            Ok(ret) => {
+                debug!(
+                    token = label,
+                    len = ret.len(),
+                    "token_call_unexpected_return"
+                );
+                continue;
+            }
+            Err(e) => {
+                debug!(token = label, error = %e, "token_call_failed");
+                continue;
+            }
+        };
+        let code = match fetch_code_with(rpc, token, overrides).await {
+            Ok(code) if options.skip_proxy_resolution.contains(&token) => Ok(code),
+            Ok(code) => implementation_code(rpc, token, code, overrides)
+                .await
+                .map(|(_, code)| code),
+            Err(e) => Err(e),
+        };
+        match code {
+            Ok(code) => report.token_risk_flags.extend(
+                token_risk_flags(&code)
+                    .into_iter()
+                    .map(|signature| format!("{label} {token:#x}: {signature}")),
+            ),
+            Err(e) => debug!(token = label, error = %e, "token_code_fetch_failed"),
+        }
+    }
+}
+
+/// Zero-argument views of every fingerprint (required, forbidden and optional), in
+/// fingerprint order: the calls [`probe_dynamic`] can make without arguments
+pub fn dynamic_probe_selectors() -> Vec<Selector> {
+    let mut probes: Vec<Selector> = Vec::new();
+    for fp in fingerprints() {
+        for selector in fp.required.iter().chain(fp.forbidden).chain(fp.optional) {
+            let zero_args = selector_name(selector).is_some_and(|name| name.ends_with("()"));
+            if zero_args && !probes.contains(selector) {
+                probes.push(*selector);
+            }
+        }
+    }
+    probes
+}
+
+/// Selector no fingerprint uses: a contract answering it answers everything
+#[cfg(feature = "rpc")]
+const CONTROL_PROBE: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+
+/// Classify an `Unknown` report by which views return data when called
+///
+/// A view counts as present when the call returns at least one word, and absent when it
+/// reverts or returns less. The present views are then matched against the
+/// fingerprints like bytecode selectors; only a single match becomes the verdict
+/// (`detection_method: dynamic_probe`). Contracts that answer a selector nobody uses
+/// (catch-all fallbacks) and calls failing for other reasons than a revert leave the
+/// report untouched.
+#[cfg(feature = "rpc")]
+pub(crate) async fn probe_dynamic(
+    rpc: &Rpc,
+    report: &mut AnalyzeReport,
+    options: &AnalyzeOptions,
+    overrides: Option<&StateOverride>,
+) {
+    if !wants_dynamic_probe(report) {
+        return;
+    }
+    let Ok(address) = parse_address_hex(&report.address) else {
+        return;
+    };
+
+    match eth_call(rpc, address, &CONTROL_PROBE, overrides).await {
+        Ok(ret) if ret.len() >= 32 => {
+            debug!("dynamic_probe_catch_all_fallback");
+            return;
+        }
+        Ok(_) | Err(AnalyzeError::Rpc(RpcError::ContractRevert(_))) => {}
+        Err(e) => {
+            debug!(error = %e, "dynamic_probe_failed");
+            return;
+        }
+    }
+
+    let mut present = Vec::new();
+    for selector in dynamic_probe_selectors() {
+        match eth_call(rpc, address, selector.as_bytes(), overrides).await {
+            Ok(ret) if ret.len() >= 32 => present.push(selector),
+            Ok(_) | Err(AnalyzeError::Rpc(RpcError::ContractRevert(_))) => {}
+            Err(e) => {
+                debug!(selector = %selector, error = %e, "dynamic_probe_failed");
+                return;
+            }
+        }
+    }
+    apply_dynamic_probes(&mut report.analysis, &present, options);
+}
+
+/// Whether [`probe_dynamic`] may decide `report`: the verdict it reports
+/// ([`AnalyzeReport::merge_proxy`], implementation and combined classifier included) is
+/// `Unknown`, and calls to the address reach the code that verdict was made for
+///
+/// A proxy left unresolved is reported as itself, but calls to it would answer for its
+/// implementation, so it is not probed.
+#[cfg(feature = "rpc")]
+fn wants_dynamic_probe(report: &AnalyzeReport) -> bool {
+    let unresolved_proxy = report.proxy_kind.is_some() && report.implementation_address.is_none();
+    report.merge_proxy().protocol.parse() == Ok(DexProtocol::Unknown) && !unresolved_proxy
+}
+
+/// Record the views that answered and, if they match exactly one fingerprint, the verdict
+pub fn apply_dynamic_probes(
+    analysis: &mut BytecodeAnalysis,
+    present: &[Selector],
+    options: &AnalyzeOptions,
+) {
+    analysis.dynamic_probes = Some(
+        present
+            .iter()
+            .map(|s| selector_name(s).map_or_else(|| s.to_string(), str::to_string))
+            .collect(),
+    );
+
+    // One zero-padded word per selector: aligned-word scanning matches them exactly.
+    let words: Vec<u8> = present
+        .iter()
+        .flat_map(|s| core::iter::repeat_n(0u8, 28).chain(s.as_bytes().iter().copied()))
+        .collect();
+    let [(protocol, confidence)] = identify_protocols_with(&words, SelectorScan::AlignedWords)[..]
+    else {
+        return;
+    };
+
+    analysis.protocol = dex_protocol_name(protocol).to_string();
+    analysis.protocol_candidates = None;
+    analysis.pool_confidence = confidence;
+    analysis.is_pool_likely = confidence >= options.pool_min_confidence
+        && analysis
+            .contract_kind
+            .as_deref()
+            .is_none_or(|k| k == ContractKind::Pool.name());
+    analysis.detection_method = "dynamic_probe".to_string();
+    analysis.explanation = None;
+    if protocol == DexProtocol::UniswapV2 {
+        analysis.curve_type = Some(CURVE_CONSTANT_PRODUCT.to_string());
+    }
+}
+
+/// Whether the code has a `DELEGATECALL` instruction (push data excluded)
+#[cfg(feature = "rpc")]
+fn delegatecalls(bytecode: &[u8]) -> bool {
+    const DELEGATECALL: usize = 0xf4;
+    opcode_histogram(bytecode)[DELEGATECALL] > 0
+}
+
+/// Implementation whose address `proxy` keeps in storage `slot` (low 20 bytes)
+///
+/// An empty slot yields `None`, leaving the proxy to be analyzed by itself; an
+/// implementation without code is [`AnalyzeError::NoDeployedBytecode`].
+#[cfg(feature = "rpc")]
+async fn slot_implementation(
+    rpc: &Rpc,
+    proxy: Address,
+    slot: B256,
+    overrides: Option<&StateOverride>,
+) -> Result<Option<(Address, Vec<u8>)>, AnalyzeError> {
+    let word: U256 = rpc
+        .client
+        .request("eth_getStorageAt", (proxy, slot, rpc.block))
+        .await
+        .map_err(RpcError::from)?;
+    let implementation = Address::from_word(word.into());
+    if implementation.is_zero() {
+        debug!(slot = %format!("{slot:#x}"), "impl_slot_empty");
+        return Ok(None);
+    }
+
+    let code = fetch_code_with(rpc, implementation, overrides).await?;
+    Ok(Some((implementation, code)))
+}
+
+/// Singleton behind a Safe proxy, read through the proxy's own `masterCopy()`
+///
+/// Failed reads are logged and yield `None`: the proxy is still reported as a Safe
+/// from its layout, just without the singleton's confirmation.
+#[cfg(feature = "rpc")]
+pub(crate) async fn safe_singleton(
+    rpc: &Rpc,
+    proxy: Address,
+    overrides: Option<&StateOverride>,
+) -> Option<(Address, Vec<u8>)> {
+    let singleton = match eth_call(rpc, proxy, selectors::MASTER_COPY.as_bytes(), overrides).await {
+        Ok(ret) if ret.len() == 32 => Address::from_slice(&ret[12..]),
+        Ok(ret) => {
+            debug!(len = ret.len(), "master_copy_call_unexpected_return");
+            return None;
+        }
+        Err(e) => {
+            debug!(error = %e, "master_copy_call_failed");
+            return None;
+        }
+    };
+
+    match fetch_code_with(rpc, singleton, overrides).await {
+        Ok(code) => Some((singleton, code)),
+        Err(e) => {
+            debug!(singleton = %format!("{singleton:#x}"), error = %e, "safe_singleton_fetch_failed");
+            None
+        }
+    }
+}
+
+/// Runtime bytecode of `address` or, with `resolve_proxy`, of the EIP-1167, Safe or
+/// EIP-7702 implementation it points at
+///
+/// Returns the address the code was read from. A Safe proxy whose singleton can't be
+/// read, or a delegation to an address without code, yields the account's own code, as
+/// in [`analyze_address`].
+#[cfg(feature = "rpc")]
+pub async fn fetch_resolved_code(
+    rpc_url: &str,
+    address: Address,
+    resolve_proxy: bool,
+) -> Result<(Address, Vec<u8>), AnalyzeError> {
+    resolved_code(&Rpc::new(rpc_url)?, address, resolve_proxy).await
+}
+
+/// [`fetch_resolved_code`] over a shared client
+#[cfg(feature = "rpc")]
+pub(crate) async fn resolved_code(
+    rpc: &Rpc,
+    address: Address,
+    resolve_proxy: bool,
+) -> Result<(Address, Vec<u8>), AnalyzeError> {
+    let bytecode = fetch_code(rpc, address).await?;
+    if !resolve_proxy {
+        return Ok((address, bytecode));
+    }
+    implementation_code(rpc, address, bytecode, None).await
+}
+
+/// Implementation behind `bytecode` (`address`'s code) if it is an EIP-1167 or Safe
+/// proxy, else `address` and `bytecode` themselves (read under `overrides`, if any)
+#[cfg(feature = "rpc")]
+async fn implementation_code(
+    rpc: &Rpc,
+    address: Address,
+    bytecode: Vec<u8>,
+    overrides: Option<&StateOverride>,
+) -> Result<(Address, Vec<u8>), AnalyzeError> {
+    match proxy_implementation_address(&bytecode) {
+        Some(impl_address) => {
+            let impl_code = fetch_code_with(rpc, impl_address, overrides).await;
+            Ok(proxy_implementation(&bytecode, impl_address, impl_code)?
+                .unwrap_or((address, bytecode)))
+        }
+        None if is_safe_proxy(&bytecode) => Ok(safe_singleton(rpc, address, overrides)
+            .await
+            .unwrap_or((address, bytecode))),
+        None => Ok((address, bytecode)),
+    }
+}
+
+/// Address of the contract created by `tx_hash`, from its receipt's `contractAddress`
+///
+/// The receipt is read as plain JSON, so chains with extra or missing receipt fields
+/// work as long as `contractAddress` is there.
+#[cfg(feature = "rpc")]
+pub async fn created_contract_address(
+    rpc_url: &str,
+    tx_hash: B256,
+) -> Result<Address, AnalyzeError> {
+    let receipt: Option<serde_json::Value> = Rpc::new(rpc_url)?
+        .client
+        .request("eth_getTransactionReceipt", (tx_hash,))
+        .await
+        .map_err(RpcError::from)?;
+    let receipt =
+        receipt.ok_or_else(|| AnalyzeError::TransactionNotFound(format!("{tx_hash:#x}")))?;
+
+    match receipt.get("contractAddress") {
+        None | Some(serde_json::Value::Null) => {
+            Err(AnalyzeError::NotContractCreation(format!("{tx_hash:#x}")))
+        }
+        Some(address) => address
+            .as_str()
+            .and_then(|a| a.parse().ok())
+            .ok_or_else(|| {
+                RpcError::Decode(format!(
+                    "receipt contractAddress is not an address: {address}"
+                ))
+                .into()
+            }),
+    }
+}
+
+#[cfg(feature = "rpc")]
 pub async fn analyze_address(
     rpc_url: &str,
     address: Address,
 ) -> Result<AnalyzeReport, AnalyzeError> {
-    validate_rpc_url(rpc_url)?;
+    analyze_address_with_options(rpc_url, address, &AnalyzeOptions::default()).await
+}
+
+#[cfg(feature = "rpc")]
+pub async fn analyze_address_with_options(
+    rpc_url: &str,
+    address: Address,
+    options: &AnalyzeOptions,
+) -> Result<AnalyzeReport, AnalyzeError> {
+    let rpc = Rpc::new(rpc_url)?.at(options.block);
+    if let Some(report) = system_address_report(rpc_url, address, options) {
+        return Ok(report);
+    }
+
+    let overrides = options.state_override.as_deref();
+    let bytecode = fetch_code_with(&rpc, address, overrides).await?;
+    let mut custom_slot = None;
+    let implementation = match proxy_implementation_address(&bytecode) {
+        _ if !options.resolves_proxy(address) => None,
+        Some(impl_address) => {
+            let impl_code = fetch_code_with(&rpc, impl_address, overrides).await;
+            proxy_implementation(&bytecode, impl_address, impl_code)?
+        }
+        None if is_safe_proxy(&bytecode) => safe_singleton(&rpc, address, overrides).await,
+        None => match options.impl_slot.filter(|_| delegatecalls(&bytecode)) {
+            Some(slot) => {
+                let implementation = slot_implementation(&rpc, address, slot, overrides).await?;
+                custom_slot = implementation.is_some().then_some(slot);
+                implementation
+            }
+            None => None,
+        },
+    };
+
+    let mut report = build_report(
+        rpc_url,
+        address,
+        &bytecode,
+        implementation
+            .as_ref()
+            .map(|(a, code)| (*a, code.as_slice())),
+        options,
+    )?;
+    if let Some(slot) = custom_slot {
+        report.proxy_kind = Some(ProxyKind::CustomSlot);
+        report.impl_slot = Some(format!("{slot:#x}"));
+    }
+    if options.dynamic {
+        probe_dynamic(&rpc, &mut report, options, overrides).await;
+    }
+    if options.resolve_onchain {
+        let allowlist = factory_allowlist_for_chain(&rpc, options).await;
+        resolve_onchain(&rpc, &mut report, allowlist, overrides).await;
+    }
+    if options.scan_tokens {
+        scan_tokens(&rpc, &mut report, options, overrides).await;
+    }
+    Ok(report)
+}
+
+/// [`analyze_address`] bounded by a wall-clock deadline covering every RPC call, proxy hops included
+#[cfg(feature = "rpc")]
+pub async fn analyze_address_with_deadline(
+    rpc_url: &str,
+    address: Address,
+    deadline: Duration,
+) -> Result<AnalyzeReport, AnalyzeError> {
+    with_deadline(deadline, analyze_address(rpc_url, address)).await
+}
 
-    let bytecode = fetch_code(rpc_url, address).await?;
+/// Run an analysis future, failing with [`AnalyzeError::Deadline`] once `deadline` elapses
+#[cfg(feature = "rpc")]
+pub async fn with_deadline<T>(
+    deadline: Duration,
+    analysis: impl Future<Output = Result<T, AnalyzeError>>,
+) -> Result<T, AnalyzeError> {
+    tokio::time::timeout(deadline, analysis)
+        .await
+        .map_err(|_| AnalyzeError::Deadline(deadline.as_millis() as u64))?
+}
+
+/// Report for a proxy whose implementation was already analyzed (`analysis`)
+#[cfg(feature = "rpc")]
+pub(crate) fn proxy_report(
+    rpc_url: &str,
+    address: Address,
+    bytecode: &[u8],
+    impl_address: Address,
+    mut analysis: BytecodeAnalysis,
+    options: &AnalyzeOptions,
+) -> AnalyzeReport {
+    let proxy_analysis = analyze_bytecode_with_options(address, bytecode, options);
+    // A delegating EOA runs the target's code but is still an account, not a pool.
+    if let Some(kind @ ContractKind::Eip7702Delegation { .. }) = identify_contract_kind(bytecode) {
+        analysis.contract_kind = Some(kind.name().to_string());
+        analysis.contract_hint = contract_hint(kind);
+        analysis.is_pool_likely = false;
+    }
+
+    AnalyzeReport {
+        rpc_url: rpc_url.to_string(),
+        address: format!("{address:#x}"),
+        is_eip1167_proxy: is_eip1167_proxy(bytecode),
+        proxy_kind: ProxyKind::of(bytecode),
+        implementation_address: Some(format!("{impl_address:#x}")),
+        immutable_args_len: extract_cwia_impl(bytecode).map(|(_, len)| len),
+        immutable_args: immutable_args(bytecode),
+        impl_slot: None,
+        implementation_fingerprint_hash: fingerprint_hash(&analysis),
+        analysis,
+        proxy_is_also_pool: proxy_analysis.is_pool_likely,
+        token_risk_flags: Vec::new(),
+        proxy_analysis: Some(proxy_analysis),
+    }
+}
+
+/// Assemble a report from already-fetched bytecode.
+///
+/// `implementation` must be given (address + code) when `bytecode` is an EIP-1167 or
+/// clones-with-immutable-args proxy,
+/// and may be given for a Safe proxy (its singleton).
+#[cfg(feature = "rpc")]
+pub(crate) fn build_report(
+    rpc_url: &str,
+    address: Address,
+    bytecode: &[u8],
+    implementation: Option<(Address, &[u8])>,
+    options: &AnalyzeOptions,
+) -> Result<AnalyzeReport, AnalyzeError> {
     if bytecode.is_empty() {
         return Err(AnalyzeError::NoDeployedBytecode);
     }
 
-    let proxy_impl = proxy_implementation_address(&bytecode);
-    if let Some(impl_address) = proxy_impl {
+    if let Some((impl_address, impl_bytecode)) = implementation {
         debug!(
             proxy = %format!("{address:#x}"),
             implementation = %format!("{impl_address:#x}"),
-            "eip1167_proxy_resolved"
+            "proxy_resolved"
         );
-        let impl_bytecode = fetch_code(rpc_url, impl_address).await?;
         if impl_bytecode.is_empty() {
             return Err(AnalyzeError::NoDeployedBytecode);
         }
 
-        let analysis = analyze_bytecode(impl_address, &impl_bytecode);
-        let proxy_analysis = analyze_bytecode(address, &bytecode);
-
-        return Ok(AnalyzeReport {
-            rpc_url: rpc_url.to_string(),
-            address: format!("{address:#x}"),
-            is_eip1167_proxy: true,
-            implementation_address: Some(format!("{impl_address:#x}")),
+        let analysis = analyze_bytecode_with_options(impl_address, impl_bytecode, options);
+        return Ok(proxy_report(
+            rpc_url,
+            address,
+            bytecode,
+            impl_address,
             analysis,
-            proxy_analysis: Some(proxy_analysis),
-        });
+            options,
+        ));
     }
 
     debug!(
-        token0 = selectors::TOKEN0.exists_in(&bytecode),
-        token1 = selectors::TOKEN1.exists_in(&bytecode),
-        globalState = selectors::GLOBAL_STATE.exists_in(&bytecode),
-        plugin = selectors::PLUGIN.exists_in(&bytecode),
-        fee = selectors::FEE.exists_in(&bytecode),
-        slot0 = selectors::SLOT0.exists_in(&bytecode),
-        safelyGetStateOfAMM = selectors::SAFELY_GET_STATE_OF_AMM.exists_in(&bytecode),
+        token0 = selectors::TOKEN0.exists_in(bytecode),
+        token1 = selectors::TOKEN1.exists_in(bytecode),
+        globalState = selectors::GLOBAL_STATE.exists_in(bytecode),
+        plugin = selectors::PLUGIN.exists_in(bytecode),
+        fee = selectors::FEE.exists_in(bytecode),
+        slot0 = selectors::SLOT0.exists_in(bytecode),
+        safelyGetStateOfAMM = selectors::SAFELY_GET_STATE_OF_AMM.exists_in(bytecode),
         "key_selector_presence"
     );
 
+    let analysis = analyze_bytecode_with_options(address, bytecode, options);
     Ok(AnalyzeReport {
         rpc_url: rpc_url.to_string(),
         address: format!("{address:#x}"),
-        is_eip1167_proxy: false,
+        // An unresolved proxy (`skip_proxy_resolution`) still reports its shape.
+        is_eip1167_proxy: is_eip1167_proxy(bytecode),
+        proxy_kind: ProxyKind::of(bytecode),
         implementation_address: None,
-        analysis: analyze_bytecode(address, &bytecode),
+        immutable_args_len: extract_cwia_impl(bytecode).map(|(_, len)| len),
+        immutable_args: immutable_args(bytecode),
+        impl_slot: None,
+        implementation_fingerprint_hash: fingerprint_hash(&analysis),
+        analysis,
         proxy_analysis: None,
+        proxy_is_also_pool: false,
+        token_risk_flags: Vec::new(),
     })
 }
 
+#[cfg(feature = "rpc")]
+fn fingerprint_hash(analysis: &BytecodeAnalysis) -> Option<String> {
+    analysis.fingerprint.as_ref().map(|fp| fp.hash_hex.clone())
+}
+
+/// Hex of the args appended after a clones-with-immutable-args runtime
+#[cfg(feature = "rpc")]
+fn immutable_args(bytecode: &[u8]) -> Option<String> {
+    extract_cwia_impl(bytecode)
+        .map(|(_, len)| format!("0x{}", hex::encode(&bytecode[bytecode.len() - len..])))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[cfg(feature = "rpc")]
     #[test]
     fn test_validate_rpc_url() {
         assert!(validate_rpc_url("https://example.com").is_ok());
@@ -236,6 +2072,95 @@ mod tests {
         assert!(validate_rpc_url("not-a-url").is_err());
     }
 
+    #[cfg(feature = "rpc")]
+    #[test]
+    fn test_rpc_error_from_alloy() {
+        let resp = |code: i64, message: &str| {
+            let payload = serde_json::json!({ "code": code, "message": message });
+            RpcError::from(TransportError::ErrorResp(
+                serde_json::from_value(payload).unwrap(),
+            ))
+        };
+        assert!(matches!(
+            resp(3, "execution reverted: !auth"),
+            RpcError::ContractRevert(_)
+        ));
+        assert!(matches!(
+            resp(-32000, "execution reverted"),
+            RpcError::ContractRevert(_)
+        ));
+        assert!(matches!(
+            resp(-32005, "limit exceeded"),
+            RpcError::RateLimited(_)
+        ));
+        assert!(matches!(
+            resp(-32016, "over rate limit"),
+            RpcError::RateLimited(_)
+        ));
+        assert_eq!(
+            resp(-32601, "method not found"),
+            RpcError::Server {
+                code: -32601,
+                message: "method not found".into()
+            }
+        );
+
+        let http = |status| RpcError::from(TransportErrorKind::http_error(status, String::new()));
+        assert!(matches!(http(429), RpcError::RateLimited(_)));
+        assert!(matches!(http(502), RpcError::Transport(_)));
+
+        let timed_out = std::io::Error::new(std::io::ErrorKind::TimedOut, "deadline elapsed");
+        assert!(matches!(
+            RpcError::from(TransportErrorKind::custom(timed_out)),
+            RpcError::Timeout(_)
+        ));
+        assert!(matches!(
+            RpcError::from(TransportErrorKind::custom_str("connection refused")),
+            RpcError::Transport(_)
+        ));
+
+        let err = serde_json::from_str::<String>("0x").unwrap_err();
+        assert!(matches!(
+            RpcError::from(TransportError::deser_err(err, "0x")),
+            RpcError::Decode(_)
+        ));
+        assert!(matches!(
+            RpcError::from(TransportError::NullResp),
+            RpcError::Decode(_)
+        ));
+    }
+
+    #[cfg(feature = "rpc")]
+    #[test]
+    fn test_rpc_error_truncates_long_messages() {
+        // An HTML error page with multi-byte characters straddling the cut
+        let page = format!("<html>{}</html>", "Fehler: Überlastung — ".repeat(200));
+        assert!(page.len() > 4_000);
+
+        let RpcError::Transport(message) =
+            RpcError::from(TransportErrorKind::http_error(502, page.clone()))
+        else {
+            panic!("expected a transport error");
+        };
+        let (kept, marker) = message.split_once('…').unwrap();
+        assert_eq!(kept.chars().count(), MAX_ERROR_MESSAGE_CHARS);
+        assert!(page.contains(&kept["HTTP error 502 with body: ".len()..]));
+        assert!(marker.ends_with("more bytes)"), "{marker}");
+
+        let short = RpcError::from(TransportErrorKind::http_error(502, "bad gateway".into()));
+        assert_eq!(
+            short,
+            RpcError::Transport("HTTP error 502 with body: bad gateway".into())
+        );
+    }
+
+    #[cfg(feature = "rpc")]
+    #[test]
+    fn test_rpc_error_display() {
+        let err = AnalyzeError::from(RpcError::RateLimited("HTTP error 429".into()));
+        assert_eq!(err.to_string(), "rpc error: rate limited: HTTP error 429");
+    }
+
     #[test]
     fn test_parse_address_hex() {
         let addr = parse_address_hex("0x0000000000000000000000000000000000000001").unwrap();
@@ -247,6 +2172,144 @@ mod tests {
         assert!(parse_address_hex("0x1234").is_err());
     }
 
+    #[test]
+    fn test_decode_bytecode_encodings() {
+        let code = [0x60, 0x80, 0x60, 0x40, 0x52];
+        assert_eq!(
+            decode_bytecode(b" 0x6080604052\n", InputEncoding::Hex).unwrap(),
+            code
+        );
+        assert_eq!(
+            decode_bytecode(b"YIBg\nQFI=\n", InputEncoding::Base64).unwrap(),
+            code
+        );
+        assert_eq!(decode_bytecode(&code, InputEncoding::Raw).unwrap(), code);
+
+        let err =
+            |input: &[u8], encoding| decode_bytecode(input, encoding).unwrap_err().to_string();
+        assert!(err(b"0x60zz", InputEncoding::Hex).contains("invalid hex"));
+        assert!(err(b"YIBg*FI=", InputEncoding::Base64).contains("invalid base64"));
+        assert!(err(&[0xff, 0xfe], InputEncoding::Base64).contains("not UTF-8"));
+        assert!(err(b"\n", InputEncoding::Hex).contains("empty input"));
+        assert!(err(b"", InputEncoding::Raw).contains("empty input"));
+
+        assert_eq!("B64".parse::<InputEncoding>(), Ok(InputEncoding::Base64));
+        assert!("utf8".parse::<InputEncoding>().is_err());
+    }
+
+    #[test]
+    fn test_candidate_confidence_percent() {
+        let candidate = ProtocolCandidate::new;
+        assert_eq!(
+            candidate(DexProtocol::UniswapV2, 6).confidence_percent(),
+            Some(85)
+        );
+        assert_eq!(
+            candidate(DexProtocol::Unknown, 6).confidence_percent(),
+            None
+        );
+
+        // Styled names don't get in the way.
+        let mut analysis = analyze_bytecode(Address::ZERO, &[]);
+        analysis.protocol_candidates = Some(vec![candidate(DexProtocol::UniswapV2, 6)]);
+        analysis.apply_name_style(NameStyle::Kebab);
+        let styled = &analysis.protocol_candidates.unwrap()[0];
+        assert_eq!(styled.protocol, "uniswap-v2");
+        assert_eq!(styled.confidence_percent(), Some(85));
+    }
+
+    #[test]
+    fn test_dex_protocol_from_str() {
+        for fp in fingerprints() {
+            let name = dex_protocol_name(fp.protocol);
+            assert_eq!(name.parse::<DexProtocol>(), Ok(fp.protocol));
+        }
+        assert_eq!(
+            "algebraintegral".parse::<DexProtocol>(),
+            Ok(DexProtocol::AlgebraIntegral)
+        );
+        assert_eq!("Unknown".parse::<DexProtocol>(), Ok(DexProtocol::Unknown));
+
+        let err = "Curve".parse::<DexProtocol>().unwrap_err();
+        assert!(
+            err.starts_with("unknown protocol `Curve` (expected one of "),
+            "{err}"
+        );
+        assert!(err.contains("AlgebraIntegral"), "{err}");
+    }
+
+    #[test]
+    fn test_name_styles() {
+        let styled = |name, style: NameStyle| style.apply(name);
+        assert_eq!(styled("UniswapV3", NameStyle::Canonical), "UniswapV3");
+        assert_eq!(styled("UniswapV3", NameStyle::Lowercase), "uniswapv3");
+        assert_eq!(styled("UniswapV3", NameStyle::Kebab), "uniswap-v3");
+        assert_eq!(
+            styled("AlgebraLegacyV1_9Plus", NameStyle::Kebab),
+            "algebra-legacy-v1-9-plus"
+        );
+        assert_eq!(styled("SolidlyCL", NameStyle::Kebab), "solidly-cl");
+        assert_eq!(
+            styled("BalancerV2Pool", NameStyle::Kebab),
+            "balancer-v2-pool"
+        );
+
+        // Every style parses back to the protocol it was rendered from.
+        let protocols = fingerprints().iter().map(|fp| fp.protocol);
+        for protocol in protocols.chain([DexProtocol::Unknown]) {
+            for style in [NameStyle::Canonical, NameStyle::Lowercase, NameStyle::Kebab] {
+                let name = style.apply(dex_protocol_name(protocol));
+                assert_eq!(name.parse::<DexProtocol>(), Ok(protocol), "{name}");
+            }
+        }
+        assert_eq!("Kebab".parse::<NameStyle>(), Ok(NameStyle::Kebab));
+        assert!("snake".parse::<NameStyle>().is_err());
+    }
+
+    #[test]
+    fn test_protocol_aliases() {
+        for name in ["Uni V3", "uniswap-v3", "UNISWAP_V3", "univ3"] {
+            assert_eq!(
+                name.parse::<DexProtocol>(),
+                Ok(DexProtocol::UniswapV3),
+                "{name}"
+            );
+        }
+        assert_eq!("Velodrome".parse::<DexProtocol>(), Ok(DexProtocol::Solidly));
+        assert!("joe".parse::<DexProtocol>().is_err());
+
+        let aliases = [("trader-joe", DexProtocol::UniswapV2)];
+        assert_eq!(
+            parse_dex_protocol("TraderJoe", &aliases),
+            Ok(DexProtocol::UniswapV2)
+        );
+        assert_eq!(
+            parse_dex_protocol("WooFi", &aliases),
+            Ok(DexProtocol::WooFi)
+        );
+    }
+
+    #[test]
+    fn test_system_address_kind() {
+        assert_eq!(
+            system_address_kind(Address::ZERO),
+            Some(ContractKind::ZeroAddress)
+        );
+        assert_eq!(
+            system_address_kind(Address::with_last_byte(0x01)),
+            Some(ContractKind::Precompile { name: "ecrecover" })
+        );
+        assert_eq!(
+            precompile_name(Address::with_last_byte(0x0a)),
+            Some("pointEvaluation")
+        );
+        assert_eq!(system_address_kind(Address::with_last_byte(0x0b)), None);
+
+        let mut high_byte_set = Address::with_last_byte(0x01);
+        high_byte_set.0[0] = 0x01;
+        assert_eq!(system_address_kind(high_byte_set), None);
+    }
+
     #[test]
     fn test_proxy_implementation_address() {
         // EIP-1167 runtime code with impl=0x95885af5492195f0754be71ad1545fe81364e531
@@ -0,0 +1,838 @@
+//! Batch analysis over many addresses
+//!
+//! Bytecode is fetched with JSON-RPC batch requests: up to `batch_size`
+//! `eth_getCode` calls share a single HTTP round trip. Endpoints that reject
+//! batches are transparently retried with one request per address. Up to
+//! `concurrency` batches are in flight at once; `Concurrency::Auto` tunes that
+//! number to the endpoint (see [`AdaptiveLimit`]).
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use alloy::primitives::{keccak256, Address};
+use alloy::transports::{RpcError as AlloyRpcError, TransportErrorKind};
+use futures::stream::{FuturesUnordered, StreamExt};
+use serde::Serialize;
+use tracing::debug;
+
+use crate::analyze::{
+    analyze_bytecode_with_options, build_report, decode_code, factory_allowlist_for_chain,
+    fetch_code, parse_address_hex, probe_dynamic, proxy_implementation,
+    proxy_implementation_address, proxy_report, resolve_onchain, resolved_code, safe_singleton,
+    scan_tokens, system_address_kind, system_address_report, AnalyzeError, AnalyzeOptions,
+    AnalyzeReport, BytecodeAnalysis, Concurrency, Rpc, RpcError,
+};
+use crate::bytecode_fingerprint::{BytecodeFingerprint, Similarity};
+use crate::factories::FactoryAllowlist;
+use crate::metrics::{AnalyzerMetrics, NoopMetrics};
+use crate::selector_fingerprint::{eip7702_delegation_target, is_safe_proxy};
+
+pub use crate::analyze::DEFAULT_BATCH_SIZE;
+
+/// Ceiling of the [`Concurrency::Auto`] limit on batches in flight
+pub const MAX_AUTO_CONCURRENCY: usize = 32;
+
+/// Attempts per address under [`Concurrency::Auto`] before a rate limit is reported
+const MAX_RATE_LIMITED_ATTEMPTS: u32 = 4;
+
+/// Wait before the first retry of rate-limited addresses; doubles with each attempt
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// AIMD limit on batches in flight, for [`Concurrency::Auto`]
+///
+/// Starts at 1 and grows by one after a window of `limit` healthy batches in a row;
+/// a batch hitting a rate limit or timeout halves it. A batch over twice as slow as the
+/// fastest one seen is not healthy, so a slowing endpoint stops the ramp before it
+/// starts throttling.
+#[derive(Debug)]
+pub struct AdaptiveLimit {
+    limit: usize,
+    healthy: usize,
+    fastest: Option<Duration>,
+}
+
+impl Default for AdaptiveLimit {
+    fn default() -> Self {
+        Self {
+            limit: 1,
+            healthy: 0,
+            fastest: None,
+        }
+    }
+}
+
+impl AdaptiveLimit {
+    /// Batches currently allowed in flight
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Record a finished batch; returns the new limit if it changed
+    pub fn on_batch(&mut self, elapsed: Duration, congested: bool) -> Option<usize> {
+        if congested {
+            self.healthy = 0;
+            let halved = (self.limit / 2).max(1);
+            return (halved != self.limit).then(|| {
+                self.limit = halved;
+                halved
+            });
+        }
+
+        let fastest = *self
+            .fastest
+            .insert(self.fastest.map_or(elapsed, |f| f.min(elapsed)));
+        if elapsed > 2 * fastest {
+            return None;
+        }
+        self.healthy += 1;
+        if self.healthy < self.limit || self.limit >= MAX_AUTO_CONCURRENCY {
+            return None;
+        }
+        self.healthy = 0;
+        self.limit += 1;
+        Some(self.limit)
+    }
+}
+
+/// Parse an address list: one address per line, blank lines and `#` comments are skipped
+pub fn parse_address_list(contents: &str) -> Result<Vec<Address>, AnalyzeError> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(parse_address_hex)
+        .collect()
+}
+
+/// Fetch runtime bytecode for every address, `batch_size` addresses per JSON-RPC batch
+///
+/// The result has one entry per input address, in input order.
+pub async fn fetch_codes(
+    rpc_url: &str,
+    addresses: &[Address],
+    batch_size: usize,
+) -> Result<Vec<Result<Vec<u8>, AnalyzeError>>, AnalyzeError> {
+    fetch_code_batches(&Rpc::new(rpc_url)?, addresses, batch_size).await
+}
+
+/// [`fetch_codes`] over a shared client
+async fn fetch_code_batches(
+    rpc: &Rpc,
+    addresses: &[Address],
+    batch_size: usize,
+) -> Result<Vec<Result<Vec<u8>, AnalyzeError>>, AnalyzeError> {
+    let mut codes = Vec::with_capacity(addresses.len());
+    for chunk in addresses.chunks(batch_size.max(1)) {
+        match fetch_chunk(rpc, chunk).await {
+            Some(chunk_codes) => codes.extend(chunk_codes),
+            None => {
+                debug!(size = chunk.len(), "batch_rejected_falling_back");
+                for address in chunk {
+                    codes.push(fetch_code(rpc, *address).await);
+                }
+            }
+        }
+    }
+
+    Ok(codes)
+}
+
+/// Send one `eth_getCode` batch. Returns `None` if the endpoint rejected the batch as a whole.
+async fn fetch_chunk(rpc: &Rpc, chunk: &[Address]) -> Option<Vec<Result<Vec<u8>, AnalyzeError>>> {
+    let mut batch = rpc.client().new_batch();
+    let mut waiters = Vec::with_capacity(chunk.len());
+    for address in chunk {
+        let waiter = batch
+            .add_call::<_, Option<String>>("eth_getCode", &(address, rpc.block()))
+            .ok()?;
+        waiters.push(waiter);
+    }
+
+    if let Err(e) = batch.send().await {
+        debug!(error = %e, "batch_send_failed");
+        return None;
+    }
+
+    let mut codes = Vec::with_capacity(chunk.len());
+    for (address, waiter) in chunk.iter().zip(waiters) {
+        match waiter.await {
+            Ok(result) => {
+                let code = decode_code(result);
+                if let Ok(bytes) = &code {
+                    debug!(address = %format!("{address:#x}"), code_size = bytes.len(), "fetched_code");
+                }
+                codes.push(code);
+            }
+            // Endpoints without batch support answer with a single error object,
+            // which leaves every call in the batch without a response.
+            Err(AlloyRpcError::Transport(TransportErrorKind::MissingBatchResponse(_))) => {
+                return None
+            }
+            Err(e) => codes.push(Err(RpcError::from(e).into())),
+        }
+    }
+    Some(codes)
+}
+
+/// Analyze many addresses, resolving EIP-1167 and Safe proxies like [`crate::analyze::analyze_address`]
+///
+/// Both the addresses and the proxy implementations are fetched in batches of
+/// `options.batch_size`, `options.concurrency` batches at a time. Per-address failures
+/// are returned alongside the address, in input order, instead of aborting the run.
+pub async fn analyze_addresses(
+    rpc_url: &str,
+    addresses: &[Address],
+    options: &AnalyzeOptions,
+) -> Result<Vec<(Address, Result<AnalyzeReport, AnalyzeError>)>, AnalyzeError> {
+    analyze_addresses_with_metrics(rpc_url, addresses, options, &NoopMetrics).await
+}
+
+/// [`analyze_addresses`], reporting every outcome to `metrics`
+///
+/// The latency reported for an address is the wall time of its batch (code fetch,
+/// proxy hop and analysis). Events of an address wait for every earlier address, so
+/// they arrive in input order whatever order the batches finish in.
+pub async fn analyze_addresses_with_metrics(
+    rpc_url: &str,
+    addresses: &[Address],
+    options: &AnalyzeOptions,
+    metrics: &dyn AnalyzerMetrics,
+) -> Result<Vec<(Address, Result<AnalyzeReport, AnalyzeError>)>, AnalyzeError> {
+    let rpc = Rpc::new(rpc_url)?.at(options.block);
+
+    let batch_size = options.batch_size.max(1);
+    let mut pending: VecDeque<Batch> = (0..addresses.len())
+        .step_by(batch_size)
+        .map(|start| Batch {
+            indices: (start..addresses.len().min(start + batch_size)).collect(),
+            attempt: 0,
+        })
+        .collect();
+    let (fixed_limit, mut adaptive) = match options.concurrency {
+        Concurrency::Fixed(n) => (n.max(1), None),
+        Concurrency::Auto => (1, Some(AdaptiveLimit::default())),
+    };
+
+    let mut done: Vec<Option<(Result<AnalyzeReport, AnalyzeError>, Duration)>> =
+        addresses.iter().map(|_| None).collect();
+    let mut reported = 0;
+    let allowlist = factory_allowlist_for_chain(&rpc, options).await;
+    let mut in_flight = FuturesUnordered::new();
+    loop {
+        let limit = adaptive.as_ref().map_or(fixed_limit, AdaptiveLimit::limit);
+        while in_flight.len() < limit {
+            let Some(batch) = pending.pop_front() else {
+                break;
+            };
+            in_flight.push(run_batch(
+                &rpc, addresses, batch, options, allowlist, metrics,
+            ));
+        }
+        let Some((batch, outcome, elapsed)) = in_flight.next().await else {
+            break;
+        };
+
+        let mut congested = false;
+        let mut retry = Vec::new();
+        for (index, result) in batch.indices.iter().zip(outcome?) {
+            match &result {
+                Err(AnalyzeError::Rpc(RpcError::RateLimited(_))) => {
+                    congested = true;
+                    if adaptive.is_some() && batch.attempt + 1 < MAX_RATE_LIMITED_ATTEMPTS {
+                        retry.push(*index);
+                        continue;
+                    }
+                }
+                Err(AnalyzeError::Rpc(RpcError::Timeout(_))) => congested = true,
+                _ => {}
+            }
+            done[*index] = Some((result, elapsed));
+        }
+        if let Some(limit) = adaptive
+            .as_mut()
+            .and_then(|a| a.on_batch(elapsed, congested))
+        {
+            debug!(limit, "concurrency_changed");
+            metrics.on_concurrency_changed(limit);
+        }
+        if !retry.is_empty() {
+            pending.push_front(Batch {
+                indices: retry,
+                attempt: batch.attempt + 1,
+            });
+        }
+
+        while let Some(Some((result, elapsed))) = done.get(reported) {
+            let address = addresses[reported];
+            match result {
+                Ok(report) => metrics.on_analyzed(report, *elapsed),
+                Err(e @ AnalyzeError::Rpc(_)) => metrics.on_rpc_error(address, e),
+                Err(_) => {}
+            }
+            metrics.on_completed(address, result);
+            reported += 1;
+        }
+    }
+
+    Ok(addresses
+        .iter()
+        .zip(done)
+        .map(|(address, result)| {
+            let (result, _) = result.expect("every batch ran to completion");
+            (*address, result)
+        })
+        .collect())
+}
+
+/// Addresses (as indices into the input) fetched together, and how many times their
+/// fetch was already rate limited
+struct Batch {
+    indices: Vec<usize>,
+    attempt: u32,
+}
+
+/// Analyze one batch, backing off first if it is a rate-limit retry
+async fn run_batch(
+    rpc: &Rpc,
+    addresses: &[Address],
+    batch: Batch,
+    options: &AnalyzeOptions,
+    allowlist: Option<(&FactoryAllowlist, u64)>,
+    metrics: &dyn AnalyzerMetrics,
+) -> (
+    Batch,
+    Result<Vec<Result<AnalyzeReport, AnalyzeError>>, AnalyzeError>,
+    Duration,
+) {
+    if let Some(retry) = batch.attempt.checked_sub(1) {
+        tokio::time::sleep(RATE_LIMIT_BACKOFF * 2u32.pow(retry)).await;
+    }
+    let started = Instant::now();
+    let chunk: Vec<Address> = batch.indices.iter().map(|i| addresses[*i]).collect();
+    let outcome = async {
+        let results = match before_deadline(
+            options,
+            started,
+            analyze_chunk(rpc, &chunk, options, metrics),
+        )
+        .await
+        {
+            // Every address of the batch was still waiting on its code.
+            Err(e @ AnalyzeError::Deadline(_)) => return Ok(vec![Err(e); chunk.len()]),
+            results => results?,
+        };
+        let mut reports = Vec::with_capacity(results.len());
+        for (_, result) in results {
+            let Ok(mut report) = result else {
+                reports.push(result);
+                continue;
+            };
+            let follow_ups = async {
+                if options.dynamic {
+                    probe_dynamic(rpc, &mut report, options, None).await;
+                }
+                if options.resolve_onchain {
+                    resolve_onchain(rpc, &mut report, allowlist, None).await;
+                }
+                if options.scan_tokens {
+                    scan_tokens(rpc, &mut report, options, None).await;
+                }
+                Ok(())
+            };
+            let finished = before_deadline(options, started, follow_ups).await;
+            reports.push(finished.map(|()| report));
+        }
+        Ok(reports)
+    }
+    .await;
+    (batch, outcome, started.elapsed())
+}
+
+/// Run `step`, failing with [`AnalyzeError::Deadline`] once `options.deadline` has
+/// passed since `started`
+async fn before_deadline<T>(
+    options: &AnalyzeOptions,
+    started: Instant,
+    step: impl Future<Output = Result<T, AnalyzeError>>,
+) -> Result<T, AnalyzeError> {
+    let Some(deadline) = options.deadline else {
+        return step.await;
+    };
+    tokio::time::timeout_at((started + deadline).into(), step)
+        .await
+        .map_err(|_| AnalyzeError::Deadline(deadline.as_millis() as u64))?
+}
+
+/// Fetch and analyze one batch of addresses (codes in one round trip, implementations in another)
+async fn analyze_chunk(
+    rpc: &Rpc,
+    chunk: &[Address],
+    options: &AnalyzeOptions,
+    metrics: &dyn AnalyzerMetrics,
+) -> Result<Vec<(Address, Result<AnalyzeReport, AnalyzeError>)>, AnalyzeError> {
+    // System addresses are reported without their code: keep them out of the batch.
+    let fetched: Vec<Address> = chunk
+        .iter()
+        .copied()
+        .filter(|address| system_address_kind(*address).is_none())
+        .collect();
+    let codes = fetch_code_batches(rpc, &fetched, chunk.len()).await?;
+
+    let impl_addresses: Vec<Address> = fetched
+        .iter()
+        .zip(&codes)
+        .filter(|(address, _)| options.resolves_proxy(**address))
+        .filter_map(|(_, code)| code.as_ref().ok())
+        .filter_map(|code| proxy_implementation_address(code))
+        .collect();
+    let mut impl_codes = fetch_code_batches(rpc, &impl_addresses, chunk.len())
+        .await?
+        .into_iter();
+
+    let mut codes = codes.into_iter();
+    let mut results = Vec::with_capacity(chunk.len());
+    for address in chunk {
+        if let Some(report) = system_address_report(rpc.url(), *address, options) {
+            results.push((*address, Ok(report)));
+            continue;
+        }
+        let code = codes.next().expect("one code fetch per non-system address");
+        let bytecode = match code {
+            Ok(bytecode) => bytecode,
+            Err(e) => {
+                results.push((*address, Err(e)));
+                continue;
+            }
+        };
+        let implementation = match proxy_implementation_address(&bytecode) {
+            _ if !options.resolves_proxy(*address) => None,
+            Some(impl_address) => {
+                let impl_code = impl_codes
+                    .next()
+                    .expect("one implementation fetch per proxy");
+                match proxy_implementation(&bytecode, impl_address, impl_code) {
+                    Ok(implementation) => implementation,
+                    Err(e) => {
+                        results.push((*address, Err(e)));
+                        continue;
+                    }
+                }
+            }
+            // Safe proxies are rare in pool lists; their singletons are read one by one.
+            None if is_safe_proxy(&bytecode) => safe_singleton(rpc, *address, None).await,
+            None => None,
+        };
+        if let Some((impl_address, _)) = &implementation {
+            metrics.on_proxy_resolved(*address, *impl_address);
+        }
+
+        let result = build_report(
+            rpc.url(),
+            *address,
+            &bytecode,
+            implementation
+                .as_ref()
+                .map(|(a, code)| (*a, code.as_slice())),
+            options,
+        );
+        results.push((*address, result));
+    }
+
+    Ok(results)
+}
+
+/// [`analyze_addresses`] for clone-heavy lists: each distinct implementation is fetched
+/// and analyzed once, and its analysis is shared by every proxy pointing at it
+///
+/// All codes are fetched first (`options.batch_size` per JSON-RPC batch, up to
+/// `options.concurrency` batches in flight, `auto` counting as one), then the distinct
+/// implementations behind them. A factory's clones thus cost one implementation fetch
+/// instead of one per pool. Every address is still reported on its own, with its own
+/// `proxy_analysis`, in input order. Safe singletons are read per proxy as usual.
+/// Unlike [`analyze_addresses`], rate-limited fetches are not retried.
+pub async fn analyze_addresses_grouped_by_implementation(
+    rpc_url: &str,
+    addresses: &[Address],
+    options: &AnalyzeOptions,
+) -> Result<Vec<(Address, Result<AnalyzeReport, AnalyzeError>)>, AnalyzeError> {
+    let rpc = Rpc::new(rpc_url)?.at(options.block);
+    let concurrency = match options.concurrency {
+        Concurrency::Fixed(n) => n,
+        Concurrency::Auto => 1,
+    };
+    let codes = fetch_codes_buffered(&rpc, addresses, options.batch_size, concurrency).await;
+
+    let mut seen = HashSet::new();
+    let impl_addresses: Vec<Address> = addresses
+        .iter()
+        .zip(&codes)
+        .filter(|(address, _)| options.resolves_proxy(**address))
+        .filter_map(|(_, code)| code.as_ref().ok())
+        .filter_map(|code| proxy_implementation_address(code))
+        .filter(|impl_address| seen.insert(*impl_address))
+        .collect();
+    let impl_codes =
+        fetch_codes_buffered(&rpc, &impl_addresses, options.batch_size, concurrency).await;
+    let implementations: HashMap<Address, Result<BytecodeAnalysis, AnalyzeError>> = impl_addresses
+        .into_iter()
+        .zip(impl_codes)
+        .map(|(impl_address, code)| {
+            let analysis =
+                code.map(|code| analyze_bytecode_with_options(impl_address, &code, options));
+            (impl_address, analysis)
+        })
+        .collect();
+    debug!(
+        addresses = addresses.len(),
+        implementations = implementations.len(),
+        "implementations_deduplicated"
+    );
+
+    let allowlist = factory_allowlist_for_chain(&rpc, options).await;
+    let mut results = Vec::with_capacity(addresses.len());
+    for (address, code) in addresses.iter().zip(codes) {
+        let mut result =
+            report_with_shared_implementation(&rpc, *address, code, &implementations, options)
+                .await;
+        if let Ok(report) = &mut result {
+            if options.dynamic {
+                probe_dynamic(&rpc, report, options, None).await;
+            }
+            if options.resolve_onchain {
+                resolve_onchain(&rpc, report, allowlist, None).await;
+            }
+            if options.scan_tokens {
+                scan_tokens(&rpc, report, options, None).await;
+            }
+        }
+        results.push((*address, result));
+    }
+    Ok(results)
+}
+
+/// Report for one address of [`analyze_addresses_grouped_by_implementation`], taking
+/// a proxy's implementation analysis from `implementations`
+async fn report_with_shared_implementation(
+    rpc: &Rpc,
+    address: Address,
+    code: Result<Vec<u8>, AnalyzeError>,
+    implementations: &HashMap<Address, Result<BytecodeAnalysis, AnalyzeError>>,
+    options: &AnalyzeOptions,
+) -> Result<AnalyzeReport, AnalyzeError> {
+    if let Some(report) = system_address_report(rpc.url(), address, options) {
+        return Ok(report);
+    }
+    let bytecode = code?;
+    let resolves_proxy = options.resolves_proxy(address);
+    let Some(impl_address) = proxy_implementation_address(&bytecode).filter(|_| resolves_proxy)
+    else {
+        let implementation = if resolves_proxy && is_safe_proxy(&bytecode) {
+            safe_singleton(rpc, address, None).await
+        } else {
+            None
+        };
+        return build_report(
+            rpc.url(),
+            address,
+            &bytecode,
+            implementation
+                .as_ref()
+                .map(|(a, code)| (*a, code.as_slice())),
+            options,
+        );
+    };
+
+    match &implementations[&impl_address] {
+        Ok(analysis) => Ok(proxy_report(
+            rpc.url(),
+            address,
+            &bytecode,
+            impl_address,
+            analysis.clone(),
+            options,
+        )),
+        // Delegation to an undeployed target: the account is analyzed by itself.
+        Err(AnalyzeError::NoDeployedBytecode) if eip7702_delegation_target(&bytecode).is_some() => {
+            build_report(rpc.url(), address, &bytecode, None, options)
+        }
+        Err(e) => Err(e.clone()),
+    }
+}
+
+/// Reports sharing one implementation, as emitted by `--dedupe-by-fingerprint`
+#[derive(Debug, Clone, Serialize)]
+pub struct FingerprintGroup {
+    /// Representative report (the first address of the group)
+    #[serde(flatten)]
+    pub report: AnalyzeReport,
+    /// Every address in the group, representative included, in input order
+    pub addresses: Vec<String>,
+}
+
+/// Collapse successful reports whose analyzed code has the same exact normalized hash
+/// and verdict
+///
+/// Reports without a fingerprint stay in groups of their own. Groups are ordered by
+/// the first occurrence of their representative; failed addresses are skipped.
+pub fn group_by_fingerprint<'a>(
+    reports: impl IntoIterator<Item = &'a AnalyzeReport>,
+) -> Vec<FingerprintGroup> {
+    let mut groups: Vec<FingerprintGroup> = Vec::new();
+    let mut index: HashMap<(String, String), usize> = HashMap::new();
+
+    for report in reports {
+        let key = report
+            .analysis
+            .fingerprint
+            .as_ref()
+            .map(|fp| (fp.exact_hash.clone(), report.analysis.protocol.clone()));
+
+        if let Some(&i) = key.as_ref().and_then(|k| index.get(k)) {
+            groups[i].addresses.push(report.address.clone());
+            continue;
+        }
+        if let Some(key) = key {
+            index.insert(key, groups.len());
+        }
+        groups.push(FingerprintGroup {
+            report: report.clone(),
+            addresses: vec![report.address.clone()],
+        });
+    }
+
+    groups
+}
+
+/// One candidate of [`compare_many`]
+#[derive(Debug, Clone, Serialize)]
+pub struct CandidateMatch {
+    pub address: String,
+    /// TLSH diff to the suspect's code (0 is identical)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<Similarity>,
+    /// Why the candidate could not be compared (fetch or fingerprint failure)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Rank `candidates` by TLSH diff to `suspect`'s code, closest first
+///
+/// Tells which known template a suspicious clone was derived from. Proxies are compared
+/// by their implementation's code. Candidates are fetched `batch_size` per JSON-RPC
+/// batch with up to `concurrency` batches in flight; those that can't be fetched or
+/// fingerprinted come last, in input order, with their error. Fails only when the
+/// suspect itself can't be fetched or fingerprinted.
+pub async fn compare_many(
+    rpc_url: &str,
+    suspect: Address,
+    candidates: &[Address],
+    batch_size: usize,
+    concurrency: usize,
+) -> Result<Vec<CandidateMatch>, AnalyzeError> {
+    let rpc = Rpc::new(rpc_url)?;
+    let (_, code) = resolved_code(&rpc, suspect, true).await?;
+    let suspect_fp =
+        BytecodeFingerprint::from_bytecode(&code).map_err(|source| AnalyzeError::Fingerprint {
+            address: format!("{suspect:#x}"),
+            source,
+        })?;
+
+    let fetches = candidates
+        .chunks(batch_size.max(1))
+        .map(|chunk| (chunk.len(), resolved_codes(&rpc, chunk)));
+    let codes = collect_chunks(fetches, concurrency, candidates.len()).await;
+    let mut matches: Vec<CandidateMatch> = candidates
+        .iter()
+        .zip(codes)
+        .map(|(address, code)| {
+            let compared = code.and_then(|code| {
+                BytecodeFingerprint::from_bytecode(&code).map_err(|source| {
+                    AnalyzeError::Fingerprint {
+                        address: format!("{address:#x}"),
+                        source,
+                    }
+                })
+            });
+            let diff = compared.as_ref().ok().map(|fp| suspect_fp.distance(fp));
+            CandidateMatch {
+                address: format!("{address:#x}"),
+                diff,
+                similarity: diff.map(Similarity::from_diff),
+                error: compared.err().map(|e| e.to_string()),
+            }
+        })
+        .collect();
+    matches.sort_by_key(|m| (m.diff.is_none(), m.diff));
+    Ok(matches)
+}
+
+/// Result of [`compare_implementations`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ImplementationComparison {
+    pub address_a: String,
+    /// Where `address_a`'s code was read from (itself when it is not a proxy)
+    pub implementation_a: String,
+    pub address_b: String,
+    pub implementation_b: String,
+    /// The two implementations have byte-for-byte identical code
+    pub exact_match: bool,
+    /// TLSH diff between the two implementations (0 is identical)
+    pub diff: i32,
+    pub similarity: Similarity,
+}
+
+/// Resolve `a` and `b` to their implementations and compare the code behind them
+///
+/// Answers whether two proxies (or a proxy and a contract) run the same code: exactly
+/// (`exact_match`), up to immutables or as a fork (`similarity`), or not at all.
+pub async fn compare_implementations(
+    rpc_url: &str,
+    a: Address,
+    b: Address,
+) -> Result<ImplementationComparison, AnalyzeError> {
+    let rpc = &Rpc::new(rpc_url)?;
+    let fingerprint = |address: Address| async move {
+        let (implementation, code) = resolved_code(rpc, address, true).await?;
+        let fp = BytecodeFingerprint::from_bytecode(&code).map_err(|source| {
+            AnalyzeError::Fingerprint {
+                address: format!("{implementation:#x}"),
+                source,
+            }
+        })?;
+        Ok::<_, AnalyzeError>((implementation, keccak256(&code), fp))
+    };
+    let ((impl_a, hash_a, fp_a), (impl_b, hash_b, fp_b)) =
+        futures::try_join!(fingerprint(a), fingerprint(b))?;
+
+    let diff = fp_a.distance(&fp_b);
+    Ok(ImplementationComparison {
+        address_a: format!("{a:#x}"),
+        implementation_a: format!("{impl_a:#x}"),
+        address_b: format!("{b:#x}"),
+        implementation_b: format!("{impl_b:#x}"),
+        exact_match: hash_a == hash_b,
+        diff,
+        similarity: Similarity::from_diff(diff),
+    })
+}
+
+/// [`fetch_codes`] with up to `concurrency` batches in flight
+async fn fetch_codes_buffered(
+    rpc: &Rpc,
+    addresses: &[Address],
+    batch_size: usize,
+    concurrency: usize,
+) -> Vec<Result<Vec<u8>, AnalyzeError>> {
+    let fetches = addresses
+        .chunks(batch_size.max(1))
+        .map(|chunk| (chunk.len(), fetch_code_batches(rpc, chunk, chunk.len())));
+    collect_chunks(fetches, concurrency, addresses.len()).await
+}
+
+/// Run per-chunk fetches (each with its chunk's length), up to `concurrency` at a time,
+/// and concatenate their codes in order
+///
+/// A chunk failing as a whole fails each of its addresses, not the run.
+async fn collect_chunks<F>(
+    fetches: impl Iterator<Item = (usize, F)>,
+    concurrency: usize,
+    len: usize,
+) -> Vec<Result<Vec<u8>, AnalyzeError>>
+where
+    F: Future<Output = Result<Vec<Result<Vec<u8>, AnalyzeError>>, AnalyzeError>>,
+{
+    let chunks: Vec<_> = futures::stream::iter(fetches)
+        .map(|(chunk_len, fetch)| async move { (chunk_len, fetch.await) })
+        .buffered(concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut codes = Vec::with_capacity(len);
+    for (chunk_len, chunk) in chunks {
+        match chunk {
+            Ok(chunk) => codes.extend(chunk),
+            Err(e) => {
+                debug!(size = chunk_len, error = %e, "chunk_fetch_failed");
+                codes.extend(std::iter::repeat_n(Err(e), chunk_len));
+            }
+        }
+    }
+    codes
+}
+
+/// Code of each address in one JSON-RPC batch, proxies replaced by their implementation
+async fn resolved_codes(
+    rpc: &Rpc,
+    chunk: &[Address],
+) -> Result<Vec<Result<Vec<u8>, AnalyzeError>>, AnalyzeError> {
+    let codes = fetch_code_batches(rpc, chunk, chunk.len()).await?;
+    let impl_addresses: Vec<Address> = codes
+        .iter()
+        .filter_map(|code| code.as_ref().ok())
+        .filter_map(|code| proxy_implementation_address(code))
+        .collect();
+    let mut impl_codes = fetch_code_batches(rpc, &impl_addresses, chunk.len())
+        .await?
+        .into_iter();
+
+    Ok(codes
+        .into_iter()
+        .map(|code| {
+            let code = code?;
+            match proxy_implementation_address(&code) {
+                Some(impl_address) => {
+                    let impl_code = impl_codes
+                        .next()
+                        .expect("one implementation fetch per proxy");
+                    Ok(proxy_implementation(&code, impl_address, impl_code)?
+                        .map_or(code, |(_, impl_code)| impl_code))
+                }
+                None => Ok(code),
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address_list() {
+        let list = "\
+# pools
+0x0000000000000000000000000000000000000001
+
+  0x0000000000000000000000000000000000000002
+";
+        let addresses = parse_address_list(list).unwrap();
+        assert_eq!(addresses.len(), 2);
+        assert_eq!(
+            format!("{:#x}", addresses[1]),
+            "0x0000000000000000000000000000000000000002"
+        );
+
+        assert!(parse_address_list("0x1234").is_err());
+    }
+
+    #[test]
+    fn test_adaptive_limit_aimd() {
+        let fast = Duration::from_millis(10);
+        let mut limit = AdaptiveLimit::default();
+
+        // +1 per window of `limit` healthy batches
+        assert_eq!(limit.on_batch(fast, false), Some(2));
+        assert_eq!(limit.on_batch(fast, false), None);
+        assert_eq!(limit.on_batch(fast, false), Some(3));
+        // Slow batches hold the limit without resetting the window.
+        assert_eq!(limit.on_batch(fast * 3, false), None);
+        assert_eq!(limit.limit(), 3);
+
+        assert_eq!(limit.on_batch(fast, true), Some(1));
+        assert_eq!(limit.on_batch(fast, true), None);
+        assert_eq!(limit.limit(), 1);
+    }
+}
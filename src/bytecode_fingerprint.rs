@@ -3,11 +3,24 @@
 //! This module provides functionality to compare EVM bytecode and determine
 //! if two contracts are from the same protocol family.
 
+use alloy_primitives::{hex, keccak256, Address};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tlsh2::{TlshDefault, TlshDefaultBuilder};
 
+use crate::metadata::trailing_metadata_len;
+use crate::selector_fingerprint::{
+    dispatcher_region, extract_selectors, push_data_len, DexProtocol,
+};
+
 /// Similarity classification based on TLSH diff score
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Serializes as its [`as_str`](Self::as_str) label (`"same_family"`, ...). Ordered
+/// like the diff it comes from: more similar is *lower* (`Identical` < `SameContract`
+/// < `SameFamily` < `PossiblyRelated` < `Different`), so `sim <= Similarity::SameFamily`
+/// reads "at least same family".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Similarity {
     /// Identical bytecode (diff = 0)
     Identical,
@@ -22,28 +35,72 @@ pub enum Similarity {
 }
 
 impl Similarity {
-    /// Create from TLSH diff score
+    /// Create from TLSH diff score, with the default [`SimilarityThresholds`]
     pub fn from_diff(diff: i32) -> Self {
-        match diff {
-            0 => Self::Identical,
-            1..=30 => Self::SameContract,
-            31..=100 => Self::SameFamily,
-            101..=150 => Self::PossiblyRelated,
-            _ => Self::Different,
+        SimilarityThresholds::default().classify(diff)
+    }
+
+    /// Label used in JSON output (`identical`, `same_contract`, `same_family`,
+    /// `possibly_related`, `different`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Identical => "identical",
+            Self::SameContract => "same_contract",
+            Self::SameFamily => "same_family",
+            Self::PossiblyRelated => "possibly_related",
+            Self::Different => "different",
         }
     }
 
+    /// Position in the ordering: 0 for `Identical` up to 4 for `Different` (lower is
+    /// more similar)
+    pub fn rank(self) -> u8 {
+        self as u8
+    }
+
     /// Check if contracts are from the same protocol family
     pub fn is_same_family(&self) -> bool {
-        matches!(
-            self,
-            Self::Identical | Self::SameContract | Self::SameFamily
-        )
+        *self <= Self::SameFamily
+    }
+}
+
+/// Upper TLSH diff bound (inclusive) of each [`Similarity`] above `Different`
+///
+/// Only a diff of 0 is `Identical`. Fields missing from a deserialized config keep their
+/// defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimilarityThresholds {
+    pub same_contract: i32,
+    pub same_family: i32,
+    pub possibly_related: i32,
+}
+
+impl Default for SimilarityThresholds {
+    fn default() -> Self {
+        Self {
+            same_contract: 30,
+            same_family: 100,
+            possibly_related: 150,
+        }
+    }
+}
+
+impl SimilarityThresholds {
+    /// Classify a TLSH diff score
+    pub fn classify(&self, diff: i32) -> Similarity {
+        match diff {
+            0 => Similarity::Identical,
+            d @ 1.. if d <= self.same_contract => Similarity::SameContract,
+            d @ 1.. if d <= self.same_family => Similarity::SameFamily,
+            d @ 1.. if d <= self.possibly_related => Similarity::PossiblyRelated,
+            _ => Similarity::Different,
+        }
     }
 }
 
 /// Errors that can occur during fingerprinting
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum FingerprintError {
     #[error("bytecode too small for TLSH (need at least 50 bytes, got {0})")]
     BytecodeTooSmall(usize),
@@ -52,20 +109,55 @@ pub enum FingerprintError {
     InvalidBytecode,
 }
 
+/// Which normalization steps [`BytecodeFingerprint::from_bytecode_with_options`] applies
+///
+/// The defaults are what [`BytecodeFingerprint::from_bytecode`] does: a "family"
+/// fingerprint, alike for every deployment of one contract. Turning `normalize_push`
+/// off gives a "strict identity" fingerprint that tells deployments apart by their
+/// immutables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FingerprintOptions {
+    /// Drop the CBOR metadata trailer (and vyper immutables after it)
+    pub strip_metadata: bool,
+    /// Zero PUSH data (immutables, addresses, constants)
+    pub normalize_push: bool,
+    /// Fingerprint only the runtime deployed by creation code (see [`runtime_code`]);
+    /// other code is fingerprinted whole
+    pub strip_constructor: bool,
+}
+
+impl Default for FingerprintOptions {
+    fn default() -> Self {
+        Self {
+            strip_metadata: true,
+            normalize_push: true,
+            strip_constructor: false,
+        }
+    }
+}
+
 /// Fuzzy fingerprint for bytecode similarity comparison
 ///
 /// Uses TLSH (Trend Micro Locality Sensitive Hash) to enable detection of
 /// similar-but-not-identical contracts (e.g., same protocol, different immutables).
 pub struct BytecodeFingerprint {
     tlsh: TlshDefault,
+    exact_hash: [u8; 32],
     original_size: usize,
     normalized_size: usize,
+    /// Size of the code metadata stripping cut from, and how much it cut
+    pre_strip_size: usize,
+    metadata_len: usize,
 }
 
+/// Alternate name for [`BytecodeFingerprint`]; both refer to the same type
+pub type Fingerprint = BytecodeFingerprint;
+
 impl std::fmt::Debug for BytecodeFingerprint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BytecodeFingerprint")
             .field("hash", &self.hash_hex())
+            .field("exact_hash", &self.exact_hash_hex())
             .field("original_size", &self.original_size)
             .field("normalized_size", &self.normalized_size)
             .finish()
@@ -75,12 +167,22 @@ impl std::fmt::Debug for BytecodeFingerprint {
 impl BytecodeFingerprint {
     /// Create a fingerprint from raw bytecode
     pub fn from_bytecode(bytecode: &[u8]) -> Result<Self, FingerprintError> {
+        Self::from_bytecode_with_options(bytecode, &FingerprintOptions::default())
+    }
+
+    /// Create a fingerprint from raw bytecode, normalized as `options` say
+    ///
+    /// `exact_hash` and the TLSH hash both cover the normalized code.
+    pub fn from_bytecode_with_options(
+        bytecode: &[u8],
+        options: &FingerprintOptions,
+    ) -> Result<Self, FingerprintError> {
         if bytecode.len() < 50 {
             return Err(FingerprintError::BytecodeTooSmall(bytecode.len()));
         }
 
-        let stripped = strip_metadata(bytecode);
-        let normalized = normalize_push_data(stripped);
+        let (code, stripped) = strip_for_fingerprint(bytecode, options);
+        let normalized = normalize_stripped(stripped, options);
 
         let mut builder = TlshDefaultBuilder::new();
         builder.update(&normalized);
@@ -89,8 +191,11 @@ impl BytecodeFingerprint {
 
         Ok(Self {
             tlsh,
+            exact_hash: keccak256(&normalized).0,
             original_size: bytecode.len(),
             normalized_size: normalized.len(),
+            pre_strip_size: code.len(),
+            metadata_len: code.len() - stripped.len(),
         })
     }
 
@@ -104,6 +209,25 @@ impl BytecodeFingerprint {
         self.tlsh.hash()
     }
 
+    /// Keccak-256 of the normalized bytecode
+    ///
+    /// Equal for contracts that differ only in PUSH data (immutables, addresses)
+    /// or metadata, e.g. every pool deployed by one factory.
+    pub fn exact_hash(&self) -> [u8; 32] {
+        self.exact_hash
+    }
+
+    /// Get the exact hash as hex string
+    pub fn exact_hash_hex(&self) -> String {
+        hex::encode(self.exact_hash)
+    }
+
+    /// Name of the well-known contract with this exact normalized code, if any
+    /// (see [`known_contract`])
+    pub fn known_contract(&self) -> Option<&'static str> {
+        known_contract(&self.exact_hash)
+    }
+
     /// Original bytecode size
     pub fn original_size(&self) -> usize {
         self.original_size
@@ -114,6 +238,15 @@ impl BytecodeFingerprint {
         self.normalized_size
     }
 
+    /// Whether metadata stripping cut more than a quarter of the code
+    ///
+    /// Real trailers are tens of bytes (plus a few immutable words for vyper), so a
+    /// cut this large suggests marker-like bytes inside the code were taken for a
+    /// trailer and the fingerprint covers only part of the contract.
+    pub fn metadata_strip_suspect(&self) -> bool {
+        self.metadata_len * 4 > self.pre_strip_size
+    }
+
     /// Calculate distance score between two fingerprints
     ///
     /// Lower score = more similar (0 = identical)
@@ -126,26 +259,282 @@ impl BytecodeFingerprint {
         self.tlsh.diff(&other.tlsh, true)
     }
 
+    /// [`distance`](Self::distance) with TLSH's length term scaled by the relative size
+    /// difference of the normalized code
+    ///
+    /// TLSH scores length by logarithmic buckets (1 point for a one-bucket gap, 24 for
+    /// two), so two builds of one template whose sizes straddle bucket edges (different
+    /// compiler patch or optimizer runs across chains) pay for the edge rather than for
+    /// the size difference. Here the length term is weighted by `|a - b| / max(a, b)`,
+    /// reaching full weight at a 10% difference; the rest of the score (bucket
+    /// distribution, quartile ratios, checksum) is unchanged, so structural divergence
+    /// still counts in full. Never larger than `distance`.
+    ///
+    /// Prefer it for cross-chain "same pool template" checks; use `distance` to stay
+    /// comparable with other TLSH tooling.
+    pub fn distance_normalized(&self, other: &Self) -> i32 {
+        const FULL_LENGTH_WEIGHT_AT: f64 = 0.1;
+
+        let body = self.tlsh.diff(&other.tlsh, false);
+        let length_term = self.distance(other) - body;
+        let (a, b) = (self.normalized_size, other.normalized_size);
+        let relative = a.abs_diff(b) as f64 / a.max(b) as f64;
+        let weight = (relative / FULL_LENGTH_WEIGHT_AT).min(1.0);
+        body + (f64::from(length_term) * weight).round() as i32
+    }
+
+    /// [`compare`](Self::compare) over [`distance_normalized`](Self::distance_normalized)
+    pub fn compare_normalized(&self, other: &Self) -> Similarity {
+        Similarity::from_diff(self.distance_normalized(other))
+    }
+
     /// Compare and return similarity classification
     pub fn compare(&self, other: &Self) -> Similarity {
         Similarity::from_diff(self.distance(other))
     }
+
+    /// Old name of [`BytecodeFingerprint::distance`]
+    #[deprecated(note = "use `distance`")]
+    pub fn diff(&self, other: &Self) -> i32 {
+        self.distance(other)
+    }
+
+    /// Old name of [`BytecodeFingerprint::compare`]
+    #[deprecated(note = "use `compare`")]
+    pub fn similarity(&self, other: &Self) -> Similarity {
+        self.compare(other)
+    }
 }
 
-/// Strip CBOR metadata from bytecode (starts with 0xa264 or 0xa165)
-fn strip_metadata(bytecode: &[u8]) -> &[u8] {
-    // CBOR metadata markers for different solc versions
-    const MARKERS: [[u8; 2]; 2] = [
-        [0xa2, 0x64], // solc >= 0.6.0
-        [0xa1, 0x65], // older solc
-    ];
+/// Pairwise [`BytecodeFingerprint::distance`] of every fingerprint, rows computed in parallel
+///
+/// `matrix[i][j]` is the distance between `fingerprints[i]` and `fingerprints[j]`.
+pub fn similarity_matrix(fingerprints: &[BytecodeFingerprint]) -> Vec<Vec<i32>> {
+    let n = fingerprints.len();
+    let threads = std::thread::available_parallelism().map_or(1, |p| p.get());
+    let rows_per_thread = n.div_ceil(threads).max(1);
+
+    let mut matrix = vec![Vec::new(); n];
+    std::thread::scope(|scope| {
+        for (chunk_index, rows) in matrix.chunks_mut(rows_per_thread).enumerate() {
+            scope.spawn(move || {
+                for (offset, row) in rows.iter_mut().enumerate() {
+                    let fp = &fingerprints[chunk_index * rows_per_thread + offset];
+                    *row = fingerprints
+                        .iter()
+                        .map(|other| fp.distance(other))
+                        .collect();
+                }
+            });
+        }
+    });
+    matrix
+}
+
+/// Single-linkage clusters: indices joined whenever their distance is `<= max_distance`
+///
+/// Clusters and their members are ordered by first index.
+pub fn cluster(matrix: &[Vec<i32>], max_distance: i32) -> Vec<Vec<usize>> {
+    let n = matrix.len();
+    let mut parent: Vec<usize> = (0..n).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+
+    for (i, row) in matrix.iter().enumerate() {
+        for (j, distance) in row.iter().enumerate().skip(i + 1) {
+            if *distance <= max_distance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    let mut cluster_of_root = vec![usize::MAX; n];
+    for i in 0..n {
+        let r = root(&mut parent, i);
+        if cluster_of_root[r] == usize::MAX {
+            cluster_of_root[r] = clusters.len();
+            clusters.push(Vec::new());
+        }
+        clusters[cluster_of_root[r]].push(i);
+    }
+    clusters
+}
+
+/// Exact normalized hashes ([`BytecodeFingerprint::exact_hash`]) of well-known contracts
+///
+/// Normalization zeroes immutables, so every pool of one of these templates shares its
+/// entry (e.g. all UniswapV3 fee tiers).
+const KNOWN_CONTRACTS: &[([u8; 32], &str)] = &[
+    (
+        hex!("b26776091ef104b2214bb5dc348d21950c98e3f8498acfc92491127059c5bb64"),
+        "UniswapV2Pair",
+    ),
+    (
+        hex!("6bb4808cc945e54bcb97cb50d47fd6a9c996c410b570c93e5fc1053f08ae64c1"),
+        "UniswapV3Pool",
+    ),
+    // Algebra V1 pool on Polygon
+    (
+        hex!("554171d6dbd1dd3595c5e0e19288a5249fabaae2e938036fa977c088bb7739ae"),
+        "AlgebraPool",
+    ),
+    // Velodrome pool implementation (solc 0.8.19)
+    (
+        hex!("3b08b36635cdf25b3b82013f5fb15cd62676ae9f4a789c21bba63ec31b84f1fa"),
+        "VelodromePool",
+    ),
+];
+
+/// Name of the well-known contract whose exact normalized hash is `exact_hash`
+///
+/// A match is the strongest identification there is: the code is that contract,
+/// immutables aside.
+pub fn known_contract(exact_hash: &[u8; 32]) -> Option<&'static str> {
+    KNOWN_CONTRACTS
+        .iter()
+        .find(|(hash, _)| hash == exact_hash)
+        .map(|(_, name)| *name)
+}
+
+/// Reference fingerprints labeled with the protocol they belong to
+///
+/// Answers nearest-neighbour queries by [`BytecodeFingerprint::distance`], so code whose
+/// selectors are inconclusive can still be matched to a known template.
+#[derive(Debug, Default)]
+pub struct FingerprintDb {
+    entries: Vec<(DexProtocol, BytecodeFingerprint)>,
+}
+
+impl FingerprintDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a reference fingerprint for `protocol`
+    pub fn insert(&mut self, protocol: DexProtocol, fingerprint: BytecodeFingerprint) {
+        self.entries.push((protocol, fingerprint));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Protocol and distance of the closest reference (the first one on ties)
+    pub fn nearest(&self, fingerprint: &BytecodeFingerprint) -> Option<(DexProtocol, i32)> {
+        self.entries
+            .iter()
+            .map(|(protocol, reference)| (*protocol, fingerprint.distance(reference)))
+            .min_by_key(|(_, distance)| *distance)
+    }
+
+    /// Distance to the closest reference of `protocol` (`None` without any)
+    pub fn nearest_to(
+        &self,
+        fingerprint: &BytecodeFingerprint,
+        protocol: DexProtocol,
+    ) -> Option<i32> {
+        self.entries
+            .iter()
+            .filter(|(p, _)| *p == protocol)
+            .map(|(_, reference)| fingerprint.distance(reference))
+            .min()
+    }
+}
+
+/// Fingerprints of every `.hex` file in a directory (see [`fingerprint_hex_dir`])
+#[derive(Debug, Default)]
+pub struct DirFingerprints {
+    /// File name and fingerprint, sorted by file name
+    pub fingerprints: Vec<(String, BytecodeFingerprint)>,
+    /// File name and reason for files that could not be fingerprinted
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Fingerprint every `.hex` file (`0x`-prefixed or bare hex) in `dir`
+///
+/// Files that are not valid hex or too small for TLSH are reported in `skipped`
+/// instead of failing the whole directory.
+pub fn fingerprint_hex_dir(dir: &std::path::Path) -> std::io::Result<DirFingerprints> {
+    let mut paths: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|p| p.extension().is_some_and(|ext| ext == "hex"));
+    paths.sort();
 
-    for marker in MARKERS {
-        if let Some(pos) = bytecode.windows(2).rposition(|w| w == marker) {
-            return &bytecode[..pos];
+    let mut out = DirFingerprints::default();
+    for path in paths {
+        let name = path
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+        let contents = std::fs::read_to_string(&path)?;
+        let fingerprint = hex::decode(contents.trim().trim_start_matches("0x"))
+            .map_err(|e| format!("invalid hex: {e}"))
+            .and_then(|code| BytecodeFingerprint::from_bytecode(&code).map_err(|e| e.to_string()));
+        match fingerprint {
+            Ok(fp) => out.fingerprints.push((name, fp)),
+            Err(reason) => out.skipped.push((name, reason)),
         }
     }
-    bytecode
+    Ok(out)
+}
+
+/// The exact bytes [`BytecodeFingerprint::from_bytecode_with_options`] hashes
+///
+/// Fingerprints keep only their hashes; dump this for two contracts and diff the dumps
+/// to see where their fingerprints diverge.
+pub fn normalize_for_fingerprint(bytecode: &[u8], options: &FingerprintOptions) -> Vec<u8> {
+    normalize_stripped(strip_for_fingerprint(bytecode, options).1, options)
+}
+
+/// The code `options` keep, before and after its metadata is stripped
+fn strip_for_fingerprint<'a>(
+    bytecode: &'a [u8],
+    options: &FingerprintOptions,
+) -> (&'a [u8], &'a [u8]) {
+    let code = if options.strip_constructor {
+        runtime_code(bytecode).unwrap_or(bytecode)
+    } else {
+        bytecode
+    };
+    let stripped = if options.strip_metadata {
+        strip_metadata(code)
+    } else {
+        code
+    };
+    (code, stripped)
+}
+
+fn normalize_stripped(code: &[u8], options: &FingerprintOptions) -> Vec<u8> {
+    if options.normalize_push {
+        normalize_push_data(code)
+    } else {
+        code.to_vec()
+    }
+}
+
+/// Strip the CBOR metadata trailer, located through its length suffix, or a vyper
+/// signature together with the immutables deployed after it
+///
+/// Code without a well-formed trailer is kept whole: the same pool deployed with and
+/// without metadata (or with a different hash) still normalizes alike, while marker
+/// bytes inside the code never cut it short. Dropping vyper immutables lets pools of
+/// one Curve template (different coins, `A`, fees) normalize alike.
+fn strip_metadata(bytecode: &[u8]) -> &[u8] {
+    match trailing_metadata_len(bytecode) {
+        Some(len) => &bytecode[..bytecode.len() - len],
+        None => bytecode,
+    }
 }
 
 /// Normalize bytecode by zeroing out PUSH data (immutables, addresses, etc.)
@@ -158,17 +547,238 @@ fn normalize_push_data(bytecode: &[u8]) -> Vec<u8> {
         result.push(op);
         i += 1;
 
-        // PUSH1 (0x60) to PUSH32 (0x7f)
+        // Replace pushed data with zeros to ignore immutables/addresses
+        let push_size = push_data_len(op);
+        let zeros_to_add = push_size.min(bytecode.len().saturating_sub(i));
+        result.extend(std::iter::repeat_n(0u8, zeros_to_add));
+        i += push_size;
+    }
+
+    result
+}
+
+/// Count of every opcode in the code (metadata trailer excluded), PUSH data skipped
+///
+/// A cheap clustering signal next to TLSH: compilers and code shapes leave distinct
+/// opcode mixes. Compare histograms with [`cosine_distance`].
+pub fn opcode_histogram(bytecode: &[u8]) -> [u32; 256] {
+    let code = strip_metadata(bytecode);
+    let mut histogram = [0u32; 256];
+    let mut i = 0;
+    while i < code.len() {
+        let op = code[i];
+        histogram[op as usize] += 1;
+        i += push_data_len(op) + 1;
+    }
+    histogram
+}
+
+/// Whether the code looks like a thin wrapper forwarding its functions to another
+/// contract (advisory)
+///
+/// Flags code with at least 4 dispatched selectors, at least one `DELEGATECALL` or
+/// `STATICCALL` per two selectors, and fewer than 4 `JUMPDEST`s per selector. Real
+/// pools branch far more (UniswapV2 pairs: ~10 `JUMPDEST`s per selector), while a
+/// wrapper's functions do little besides the call. Wrappers that expose pool selectors
+/// match pool fingerprints without holding the pool's logic.
+pub fn forwarding_suspected(bytecode: &[u8]) -> bool {
+    const DELEGATECALL: usize = 0xf4;
+    const STATICCALL: usize = 0xfa;
+    const JUMPDEST: usize = 0x5b;
+
+    let selectors = extract_selectors(&bytecode[dispatcher_region(bytecode)]).len();
+    let histogram = opcode_histogram(bytecode);
+    let forwarding_calls = (histogram[DELEGATECALL] + histogram[STATICCALL]) as usize;
+    let jumpdests = histogram[JUMPDEST] as usize;
+
+    selectors >= 4 && forwarding_calls * 2 >= selectors && jumpdests < selectors * 4
+}
+
+/// Runtime code deployed by creation (init) code; `None` for code that doesn't look
+/// like a deployer
+///
+/// Recognized by the deployer tail solc and vyper emit: `PUSH <size> [DUP1]
+/// PUSH <offset> <zero> CODECOPY [PUSH <size>] <zero> RETURN` (`<zero>` being `PUSH0`
+/// or `PUSH1 0`), copying a segment that lies after that `RETURN`. Constructor
+/// arguments appended after the runtime are left out.
+pub fn runtime_code(bytecode: &[u8]) -> Option<&[u8]> {
+    const RETURN: u8 = 0xf3;
+
+    // Instructions so far: opcode and, for pushes that fit a usize, the pushed value
+    let mut instructions: Vec<(u8, Option<usize>)> = Vec::new();
+    let mut i = 0;
+    while i < bytecode.len() {
+        let op = bytecode[i];
+        let data_end = (i + 1 + push_data_len(op)).min(bytecode.len());
+        if op == RETURN {
+            let segment = copied_segment(&instructions)
+                .filter(|(offset, size)| *offset > i && *size > 0)
+                .and_then(|(offset, size)| bytecode.get(offset..offset.checked_add(size)?));
+            if segment.is_some() {
+                return segment;
+            }
+        }
+        let value = (0x5f..=0x7f)
+            .contains(&op)
+            .then(|| &bytecode[i + 1..data_end])
+            .filter(|data| data.len() <= 8)
+            .map(|data| data.iter().fold(0usize, |v, b| (v << 8) | usize::from(*b)));
+        instructions.push((op, value));
+        i = data_end;
+    }
+    None
+}
+
+/// `(offset, size)` copied and returned by a deployer tail ending just before `RETURN`
+fn copied_segment(instructions: &[(u8, Option<usize>)]) -> Option<(usize, usize)> {
+    const DUP1: u8 = 0x80;
+    const CODECOPY: u8 = 0x39;
+
+    match instructions {
+        // solc: PUSH size DUP1 PUSH offset 0 CODECOPY 0 RETURN
+        [.., (_, Some(size)), (DUP1, _), (_, Some(offset)), (_, Some(0)), (CODECOPY, _), (_, Some(0))] => {
+            Some((*offset, *size))
+        }
+        // vyper: PUSH size PUSH offset 0 CODECOPY PUSH size 0 RETURN
+        [.., (_, Some(size)), (_, Some(offset)), (_, Some(0)), (CODECOPY, _), (_, Some(returned)), (_, Some(0))]
+            if size == returned =>
+        {
+            Some((*offset, *size))
+        }
+        _ => None,
+    }
+}
+
+/// One line per instruction starting in the first `max_bytes` of the code:
+/// `"<offset>: <mnemonic> [0x<immediate>]"`, offsets in hex
+///
+/// PUSH immediates are read whole even past `max_bytes`; a PUSH cut short by the end of
+/// the code shows the bytes that are there. Bytes that aren't opcodes render as
+/// `UNKNOWN(0x..)`.
+pub fn disassemble(bytecode: &[u8], max_bytes: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut i = 0;
+    while i < bytecode.len().min(max_bytes) {
+        let op = bytecode[i];
+        let data_end = (i + 1 + push_data_len(op)).min(bytecode.len());
+        let mut line = format!("{i:04x}: {}", mnemonic(op));
         if (0x60..=0x7f).contains(&op) {
-            let push_size = (op - 0x5f) as usize;
-            // Replace pushed data with zeros to ignore immutables/addresses
-            let zeros_to_add = push_size.min(bytecode.len().saturating_sub(i));
-            result.extend(std::iter::repeat(0u8).take(zeros_to_add));
-            i += push_size;
+            line.push_str(&format!(" 0x{}", hex::encode(&bytecode[i + 1..data_end])));
         }
+        lines.push(line);
+        i += push_data_len(op) + 1;
     }
+    lines
+}
 
-    result
+/// Mnemonic of an opcode (Cancun)
+fn mnemonic(op: u8) -> String {
+    let name = match op {
+        0x00 => "STOP",
+        0x01 => "ADD",
+        0x02 => "MUL",
+        0x03 => "SUB",
+        0x04 => "DIV",
+        0x05 => "SDIV",
+        0x06 => "MOD",
+        0x07 => "SMOD",
+        0x08 => "ADDMOD",
+        0x09 => "MULMOD",
+        0x0a => "EXP",
+        0x0b => "SIGNEXTEND",
+        0x10 => "LT",
+        0x11 => "GT",
+        0x12 => "SLT",
+        0x13 => "SGT",
+        0x14 => "EQ",
+        0x15 => "ISZERO",
+        0x16 => "AND",
+        0x17 => "OR",
+        0x18 => "XOR",
+        0x19 => "NOT",
+        0x1a => "BYTE",
+        0x1b => "SHL",
+        0x1c => "SHR",
+        0x1d => "SAR",
+        0x20 => "KECCAK256",
+        0x30 => "ADDRESS",
+        0x31 => "BALANCE",
+        0x32 => "ORIGIN",
+        0x33 => "CALLER",
+        0x34 => "CALLVALUE",
+        0x35 => "CALLDATALOAD",
+        0x36 => "CALLDATASIZE",
+        0x37 => "CALLDATACOPY",
+        0x38 => "CODESIZE",
+        0x39 => "CODECOPY",
+        0x3a => "GASPRICE",
+        0x3b => "EXTCODESIZE",
+        0x3c => "EXTCODECOPY",
+        0x3d => "RETURNDATASIZE",
+        0x3e => "RETURNDATACOPY",
+        0x3f => "EXTCODEHASH",
+        0x40 => "BLOCKHASH",
+        0x41 => "COINBASE",
+        0x42 => "TIMESTAMP",
+        0x43 => "NUMBER",
+        0x44 => "PREVRANDAO",
+        0x45 => "GASLIMIT",
+        0x46 => "CHAINID",
+        0x47 => "SELFBALANCE",
+        0x48 => "BASEFEE",
+        0x49 => "BLOBHASH",
+        0x4a => "BLOBBASEFEE",
+        0x50 => "POP",
+        0x51 => "MLOAD",
+        0x52 => "MSTORE",
+        0x53 => "MSTORE8",
+        0x54 => "SLOAD",
+        0x55 => "SSTORE",
+        0x56 => "JUMP",
+        0x57 => "JUMPI",
+        0x58 => "PC",
+        0x59 => "MSIZE",
+        0x5a => "GAS",
+        0x5b => "JUMPDEST",
+        0x5c => "TLOAD",
+        0x5d => "TSTORE",
+        0x5e => "MCOPY",
+        0x5f => "PUSH0",
+        0x60..=0x7f => return format!("PUSH{}", op - 0x5f),
+        0x80..=0x8f => return format!("DUP{}", op - 0x7f),
+        0x90..=0x9f => return format!("SWAP{}", op - 0x8f),
+        0xa0..=0xa4 => return format!("LOG{}", op - 0xa0),
+        0xf0 => "CREATE",
+        0xf1 => "CALL",
+        0xf2 => "CALLCODE",
+        0xf3 => "RETURN",
+        0xf4 => "DELEGATECALL",
+        0xf5 => "CREATE2",
+        0xfa => "STATICCALL",
+        0xfd => "REVERT",
+        0xfe => "INVALID",
+        0xff => "SELFDESTRUCT",
+        _ => return format!("UNKNOWN(0x{op:02x})"),
+    };
+    name.to_string()
+}
+
+/// Cosine distance (`1 - cosine similarity`) between two opcode histograms
+///
+/// 0.0 for identical opcode mixes, up to 1.0 for disjoint ones; 1.0 when either
+/// histogram is empty.
+pub fn cosine_distance(a: &[u32; 256], b: &[u32; 256]) -> f64 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (f64::from(*x), f64::from(*y));
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+    (1.0 - dot / (norm_a.sqrt() * norm_b.sqrt())).max(0.0)
 }
 
 /// Check if bytecode is an EIP-1167 minimal proxy
@@ -184,21 +794,116 @@ pub fn extract_eip1167_impl(bytecode: &[u8]) -> Option<[u8; 20]> {
         return None;
     }
 
-    let mut addr = [0u8; 20];
-    addr.copy_from_slice(&bytecode[10..30]);
-    Some(addr)
+    extract_embedded_address(bytecode).map(|addr| addr.0 .0)
+}
+
+/// Implementation and immutable-args length of a clones-with-immutable-args proxy
+///
+/// The CWIA runtime (wighawag, 0xSplits, Solady) is a minimal proxy that appends data
+/// after its own code and forwards it (`CODECOPY`) behind the calldata, so its length
+/// varies and [`is_eip1167_proxy`] rejects it. Recognized by shape: a prologue with
+/// `CODECOPY`, `PUSH20 <implementation> GAS DELEGATECALL`, the minimal-proxy epilogue
+/// ending in `JUMPI REVERT JUMPDEST RETURN` (with the jump to that `JUMPDEST`), then
+/// the args. Their length counts every byte after the runtime, including the 2-byte
+/// length suffix these libraries append.
+pub fn extract_cwia_impl(bytecode: &[u8]) -> Option<([u8; 20], usize)> {
+    const CODECOPY: u8 = 0x39;
+    const PUSH20: u8 = 0x73;
+    // GAS DELEGATECALL RETURNDATASIZE RETURNDATASIZE SWAP4 DUP1 RETURNDATACOPY PUSH1
+    const EPILOGUE: [u8; 8] = [0x5a, 0xf4, 0x3d, 0x3d, 0x93, 0x80, 0x3e, 0x60];
+    // JUMPI REVERT JUMPDEST RETURN
+    const TAIL: [u8; 4] = [0x57, 0xfd, 0x5b, 0xf3];
+
+    let mut codecopy = false;
+    let mut i = 0;
+    while i < bytecode.len() {
+        let op = bytecode[i];
+        if op == PUSH20 {
+            let end = i + 21 + EPILOGUE.len() + 1 + TAIL.len();
+            let epilogue = bytecode.get(i + 21..end)?;
+            let jump_dest = usize::from(epilogue[EPILOGUE.len()]);
+            let shaped = codecopy
+                && epilogue.starts_with(&EPILOGUE)
+                && epilogue.ends_with(&TAIL)
+                && jump_dest == end - 2;
+            if !shaped || bytecode.len() == end {
+                return None;
+            }
+            let mut implementation = [0u8; 20];
+            implementation.copy_from_slice(&bytecode[i + 1..i + 21]);
+            return Some((implementation, bytecode.len() - end));
+        }
+        codecopy |= op == CODECOPY;
+        i += push_data_len(op) + 1;
+    }
+    None
+}
+
+/// Find the delegation target of a minimal-proxy-shaped code: a `PUSH20 <address>`
+/// immediately followed by `GAS DELEGATECALL`
+///
+/// Independent of length and prologue, so it covers the canonical EIP-1167 layout as
+/// well as optimized variants (e.g. 0age's 44-byte proxy, the `PUSH0` proxy of EIP-7511).
+/// Opcodes are walked properly, so bytes inside other PUSH data never match.
+pub fn extract_embedded_address(bytecode: &[u8]) -> Option<Address> {
+    const PUSH20: u8 = 0x73;
+    const GAS_DELEGATECALL: [u8; 2] = [0x5a, 0xf4];
+
+    let mut i = 0;
+    while i < bytecode.len() {
+        let op = bytecode[i];
+        if op == PUSH20 && bytecode.get(i + 21..i + 23) == Some(&GAS_DELEGATECALL[..]) {
+            return Some(Address::from_slice(&bytecode[i + 1..i + 21]));
+        }
+        i += push_data_len(op) + 1;
+    }
+    None
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// solc 0.8.19 trailer, `{"ipfs": <34 bytes>, "solc": 0.8.19}` + length 0x0033,
+    /// with `ipfs_tail` as the last 4 hash bytes
+    fn solc_trailer(ipfs_tail: &str) -> Vec<u8> {
+        hex::decode(format!(
+            "a2646970667358221220{}{ipfs_tail}64736f6c63430008130033",
+            "11".repeat(28)
+        ))
+        .unwrap()
+    }
+
     #[test]
     fn test_strip_metadata() {
-        // Bytecode ending with 0xa264... metadata
-        let bytecode = vec![0x60, 0x80, 0x60, 0x40, 0xa2, 0x64, 0x69, 0x70];
-        let stripped = strip_metadata(&bytecode);
-        assert_eq!(stripped, &[0x60, 0x80, 0x60, 0x40]);
+        let code = [0x60, 0x80, 0x60, 0x40];
+        let bytecode = [&code[..], &solc_trailer("22222222")].concat();
+        assert_eq!(strip_metadata(&bytecode), code);
+    }
+
+    #[test]
+    fn test_strip_metadata_ignores_markers_outside_the_trailer() {
+        // PUSH2 0xa264 in the code: a last-marker search would cut the code there.
+        let code = [0x61, 0xa2, 0x64, 0x50, 0x60, 0x40];
+        assert_eq!(strip_metadata(&code), code);
+        let bytecode = [&code[..], &solc_trailer("22222222")].concat();
+        assert_eq!(strip_metadata(&bytecode), code);
+
+        // Marker bytes inside the IPFS hash: a last-marker search would leave most of
+        // the trailer in place.
+        let bytecode = [&code[..], &solc_trailer("a2643333")].concat();
+        assert_eq!(strip_metadata(&bytecode), code);
+
+        // Older `a165` trailer ({"bzzr0": <32 bytes>}, length 0x0029) after an `a264`
+        // in the code: a marker-priority search would cut at the code's `a264`.
+        let bzzr0 = hex::decode(format!("a165627a7a72305820{}0029", "44".repeat(32))).unwrap();
+        let bytecode = [&code[..], &bzzr0].concat();
+        assert_eq!(strip_metadata(&bytecode), code);
+
+        // Wrong length suffix: not a trailer, nothing is stripped.
+        let mut bytecode = [&code[..], &solc_trailer("22222222")].concat();
+        *bytecode.last_mut().unwrap() = 0x34;
+        assert_eq!(strip_metadata(&bytecode), bytecode);
     }
 
     #[test]
@@ -208,6 +913,70 @@ mod tests {
         let normalized = normalize_push_data(&bytecode);
         // Push values should be zeroed
         assert_eq!(normalized, vec![0x60, 0x00, 0x60, 0x00]);
+
+        // PUSH0 has no immediate: the PUSH1 after it keeps its opcode
+        let bytecode = vec![0x5f, 0x60, 0x80, 0x5f, 0x5f];
+        assert_eq!(
+            normalize_push_data(&bytecode),
+            vec![0x5f, 0x60, 0x00, 0x5f, 0x5f]
+        );
+    }
+
+    #[test]
+    fn test_runtime_code() {
+        let runtime = hex::decode("6080604052348015600f57600080fd5b50").unwrap();
+        // solc: PUSH1 0x11 DUP1 PUSH1 0x15 PUSH1 0 CODECOPY PUSH1 0 RETURN INVALID,
+        // then the runtime and a 32-byte constructor argument
+        let solc = hex::decode(format!(
+            "608060405234600a5760118060156000396000f3fe{}{}",
+            hex::encode(&runtime),
+            "00".repeat(32)
+        ))
+        .unwrap();
+        assert_eq!(runtime_code(&solc), Some(runtime.as_slice()));
+
+        // vyper: PUSH2 0x0011 PUSH2 0x000d PUSH0 CODECOPY PUSH2 0x0011 PUSH0 RETURN
+        let vyper = hex::decode(format!(
+            "61001161000d5f396100115ff3{}",
+            hex::encode(&runtime)
+        ))
+        .unwrap();
+        assert_eq!(runtime_code(&vyper), Some(runtime.as_slice()));
+
+        // Runtime code, and a deployer copying past the end of the input
+        assert_eq!(runtime_code(&runtime), None);
+        assert_eq!(runtime_code(&vyper[..vyper.len() - 1]), None);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        // PUSH1 0x80 PUSH1 0x40 MSTORE PUSH4 0x0902f1ac DUP2 EQ PUSH2 0x0123 JUMPI 0x0c PUSH32 ..
+        let code = hex::decode(format!(
+            "6080604052630902f1ac8114610123570c7f{}",
+            "ab".repeat(31)
+        ))
+        .unwrap();
+        let lines = disassemble(&code, 256);
+        assert_eq!(
+            lines,
+            [
+                "0000: PUSH1 0x80",
+                "0002: PUSH1 0x40",
+                "0004: MSTORE",
+                "0005: PUSH4 0x0902f1ac",
+                "000a: DUP2",
+                "000b: EQ",
+                "000c: PUSH2 0x0123",
+                "000f: JUMPI",
+                "0010: UNKNOWN(0x0c)",
+                // cut short by the end of the code: 31 of 32 bytes
+                &format!("0011: PUSH32 0x{}", "ab".repeat(31)),
+            ]
+        );
+
+        // Instructions starting before the limit are shown whole.
+        assert_eq!(disassemble(&code, 6), &lines[..4]);
+        assert!(disassemble(&[], 256).is_empty());
     }
 
     #[test]
@@ -234,6 +1003,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extract_embedded_address_layouts() {
+        let target = "95885af5492195f0754be71ad1545fe81364e531";
+        let layouts = [
+            // canonical EIP-1167 (45 bytes)
+            format!("363d3d373d3d3d363d73{target}5af43d82803e903d91602b57fd5bf3"),
+            // 0age's optimized minimal proxy (44 bytes)
+            format!("3d3d3d3d363d3d37363d73{target}5af43d3d93803e602a57fd5bf3"),
+            // EIP-7511 PUSH0 minimal proxy (44 bytes)
+            format!("365f5f375f5f365f73{target}5af43d5f5f3e5f3d91602a57fd5bf3"),
+        ];
+
+        for layout in layouts {
+            let code = hex::decode(&layout).unwrap();
+            let addr = extract_embedded_address(&code).unwrap_or_else(|| panic!("{layout}"));
+            assert_eq!(hex::encode(addr), target);
+        }
+    }
+
+    #[test]
+    fn test_extract_cwia_impl() {
+        let target = "95885af5492195f0754be71ad1545fe81364e531";
+        // Solady / 0xSplits layout: 55-byte runtime, 4 bytes of args, 2-byte length.
+        let cwia = hex::decode(format!(
+            "3d3d3d3d363d3d376100066037363936610006013d73{target}\
+             5af43d3d93803e603557fd5bf3deadbeef0006"
+        ))
+        .unwrap();
+        let (implementation, args_len) = extract_cwia_impl(&cwia).unwrap();
+        assert_eq!(hex::encode(implementation), target);
+        assert_eq!(args_len, 6);
+        assert!(!is_eip1167_proxy(&cwia));
+
+        // The same runtime without args, and 0age's proxy (same epilogue, no
+        // CODECOPY), are plain minimal proxies.
+        assert_eq!(extract_cwia_impl(&cwia[..55]), None);
+        let optimized = hex::decode(format!(
+            "3d3d3d3d363d3d37363d73{target}5af43d3d93803e602a57fd5bf3deadbeef"
+        ))
+        .unwrap();
+        assert_eq!(extract_cwia_impl(&optimized), None);
+    }
+
+    #[test]
+    fn test_extract_embedded_address_ignores_push_data() {
+        // PUSH32 whose data contains `73 <20 bytes> 5af4`: not an instruction.
+        let target = "95885af5492195f0754be71ad1545fe81364e531";
+        let code = hex::decode(format!("7f73{target}5af4{}00", "00".repeat(9))).unwrap();
+        assert_eq!(extract_embedded_address(&code), None);
+
+        // Only the canonical layout counts as EIP-1167.
+        let optimized = hex::decode(format!(
+            "3d3d3d3d363d3d37363d73{target}5af43d3d93803e602a57fd5bf3"
+        ))
+        .unwrap();
+        assert_eq!(extract_eip1167_impl(&optimized), None);
+    }
+
     #[test]
     fn test_similarity_from_diff() {
         assert_eq!(Similarity::from_diff(0), Similarity::Identical);
@@ -242,4 +1069,28 @@ mod tests {
         assert_eq!(Similarity::from_diff(120), Similarity::PossiblyRelated);
         assert_eq!(Similarity::from_diff(200), Similarity::Different);
     }
+
+    #[test]
+    fn test_similarity_orders_by_closeness() {
+        let closest_first = [
+            Similarity::Identical,
+            Similarity::SameContract,
+            Similarity::SameFamily,
+            Similarity::PossiblyRelated,
+            Similarity::Different,
+        ];
+        for (rank, pair) in closest_first.windows(2).enumerate() {
+            assert!(pair[0] < pair[1], "{pair:?}");
+            assert_eq!(pair[0].rank() as usize, rank);
+        }
+        assert_eq!(Similarity::Different.rank(), 4);
+
+        // A smaller diff never classifies as less similar.
+        for diff in 0..300 {
+            assert!(Similarity::from_diff(diff) <= Similarity::from_diff(diff + 1));
+        }
+        for sim in closest_first {
+            assert_eq!(sim.is_same_family(), sim <= Similarity::SameFamily);
+        }
+    }
 }
@@ -0,0 +1,238 @@
+//! Chains, chain hints and profiles
+//!
+//! A [`Chain`] used as a hint carries soft, per-chain deployment knowledge (which
+//! protocols are common there), used only to break ties between equally-confident
+//! selector matches. A hint never overrides a verdict that the selectors already
+//! decide on their own.
+//!
+//! A [`ChainProfile`] describes how a chain's bytecode is laid out (which
+//! compiler produced it), and thus how selectors are scanned for.
+
+use std::str::FromStr;
+
+use crate::selector_fingerprint::{DexProtocol, SelectorScan};
+
+/// Bytecode layout of a chain, as given by `--chain`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ChainProfile {
+    /// EVM chains compiled with solc/vyper (Ethereum, most L2s)
+    #[default]
+    Evm,
+    /// ZK Stack chains running EraVM code compiled by zksolc (zkSync Era, Abstract, ...)
+    ZkSync,
+}
+
+impl ChainProfile {
+    pub fn selector_scan(&self) -> SelectorScan {
+        match self {
+            Self::Evm => SelectorScan::Anywhere,
+            Self::ZkSync => SelectorScan::AlignedWords,
+        }
+    }
+}
+
+impl FromStr for ChainProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "evm" | "default" => Ok(Self::Evm),
+            "zksync" | "zksync-era" | "abstract" => Ok(Self::ZkSync),
+            _ => Err(format!(
+                "unknown chain profile `{s}` (expected evm or zksync)"
+            )),
+        }
+    }
+}
+
+/// Chain the analyzed contract is deployed on, as given by `--chain-hint` (or a
+/// named `--chain`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Chain {
+    Ethereum,
+    Optimism,
+    Polygon,
+    Bsc,
+    Arbitrum,
+    Base,
+}
+
+impl Chain {
+    /// Protocols that can tie, in order of prevalence on this chain (most common first)
+    ///
+    /// Only Balancer and WOOFi pools can match at equal confidence: every other pair
+    /// of fingerprints is mutually exclusive (one forbids a selector the other
+    /// requires), so ranking them would never change a verdict. WOOFi is far more
+    /// common on BSC and Arbitrum, Balancer everywhere else.
+    pub fn protocol_priors(&self) -> &'static [DexProtocol] {
+        match self {
+            Self::Bsc | Self::Arbitrum => &[DexProtocol::WooFi, DexProtocol::BalancerV2Pool],
+            Self::Ethereum | Self::Optimism | Self::Polygon | Self::Base => {
+                &[DexProtocol::BalancerV2Pool, DexProtocol::WooFi]
+            }
+        }
+    }
+
+    /// Pick a protocol among equally-confident matches
+    ///
+    /// `matches` must be sorted by descending confidence. Returns `None` unless the
+    /// top confidence is shared by several matches and one of them is preferred on
+    /// this chain by [`protocol_priors`](Self::protocol_priors).
+    ///
+    /// Algebra variants never tie: each forbids a selector the others require.
+    pub fn break_tie(&self, matches: &[(DexProtocol, u32)]) -> Option<DexProtocol> {
+        let top = matches.first()?.1;
+        let tied: Vec<DexProtocol> = matches
+            .iter()
+            .take_while(|(_, c)| *c == top)
+            .map(|(p, _)| *p)
+            .collect();
+
+        if tied.len() < 2 {
+            return None;
+        }
+
+        self.protocol_priors()
+            .iter()
+            .copied()
+            .find(|p| tied.contains(p))
+    }
+}
+
+impl FromStr for Chain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ethereum" | "eth" | "mainnet" => Ok(Self::Ethereum),
+            "optimism" | "op" => Ok(Self::Optimism),
+            "polygon" | "matic" => Ok(Self::Polygon),
+            "bsc" | "bnb" => Ok(Self::Bsc),
+            "arbitrum" | "arb" => Ok(Self::Arbitrum),
+            "base" => Ok(Self::Base),
+            _ => Err(format!(
+                "unknown chain `{s}` (expected ethereum, optimism, polygon, bsc, arbitrum or base)"
+            )),
+        }
+    }
+}
+
+/// Value of `--chain`: a bytecode profile, or a named chain (the `evm` profile plus
+/// that chain's priors as hint)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChainPreset {
+    Profile(ChainProfile),
+    Chain(Chain),
+}
+
+impl ChainPreset {
+    pub fn profile(&self) -> ChainProfile {
+        match self {
+            Self::Profile(profile) => *profile,
+            Self::Chain(_) => ChainProfile::Evm,
+        }
+    }
+
+    /// The named chain, used as hint unless one is given explicitly
+    pub fn chain(&self) -> Option<Chain> {
+        match self {
+            Self::Profile(_) => None,
+            Self::Chain(chain) => Some(*chain),
+        }
+    }
+}
+
+impl Default for ChainPreset {
+    fn default() -> Self {
+        Self::Profile(ChainProfile::default())
+    }
+}
+
+impl FromStr for ChainPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse()
+            .map(Self::Profile)
+            .or_else(|_| s.parse().map(Self::Chain))
+            .map_err(|_| {
+                format!(
+                    "unknown chain `{s}` (expected evm, zksync, ethereum, optimism, polygon, bsc, arbitrum or base)"
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selector_fingerprint::{identify_protocols, selectors};
+
+    #[test]
+    fn test_chain_from_str() {
+        assert_eq!("Polygon".parse::<Chain>(), Ok(Chain::Polygon));
+        assert_eq!("bnb".parse::<Chain>(), Ok(Chain::Bsc));
+        assert!("solana".parse::<Chain>().is_err());
+    }
+
+    #[test]
+    fn test_chain_profile_from_str() {
+        assert_eq!(
+            "zkSync-Era".parse::<ChainProfile>(),
+            Ok(ChainProfile::ZkSync)
+        );
+        assert_eq!("evm".parse::<ChainProfile>(), Ok(ChainProfile::default()));
+        assert!("solana".parse::<ChainProfile>().is_err());
+    }
+
+    /// Balancer's three required views against WOOFi's two plus an (unweighted) optional
+    /// one: a tie the fingerprints can produce, since neither forbids the other's views
+    fn balancer_woofi_tie() -> Vec<u8> {
+        [
+            selectors::GET_POOL_ID,
+            selectors::GET_VAULT,
+            selectors::GET_SWAP_FEE_PERCENTAGE,
+            selectors::QUERY_SWAP,
+            selectors::TRY_QUERY,
+            selectors::SELL_BASE,
+        ]
+        .iter()
+        .flat_map(|s| *s.as_bytes())
+        .collect()
+    }
+
+    #[test]
+    fn test_break_tie_ignores_clear_winner() {
+        let mut code = balancer_woofi_tie();
+        code.extend(selectors::GET_AMPLIFICATION_PARAMETER.as_bytes());
+        let matches = identify_protocols(&code);
+        assert_eq!(matches[0].0, DexProtocol::BalancerV2Pool);
+        assert!(matches[0].1 > matches[1].1, "{matches:?}");
+        // WOOFi leads the priors on BSC, but Balancer already won.
+        assert_eq!(Chain::Bsc.break_tie(&matches), None);
+    }
+
+    #[test]
+    fn test_break_tie_uses_protocol_priors() {
+        let matches = identify_protocols(&balancer_woofi_tie());
+        assert_eq!(matches.len(), 2, "{matches:?}");
+        assert_eq!(matches[0].1, matches[1].1, "{matches:?}");
+        assert_eq!(
+            Chain::Ethereum.break_tie(&matches),
+            Some(DexProtocol::BalancerV2Pool)
+        );
+        assert_eq!(Chain::Bsc.break_tie(&matches), Some(DexProtocol::WooFi));
+    }
+
+    #[test]
+    fn test_chain_preset_from_str() {
+        assert_eq!(
+            "zksync".parse::<ChainPreset>(),
+            Ok(ChainPreset::Profile(ChainProfile::ZkSync))
+        );
+        let optimism = "Optimism".parse::<ChainPreset>().unwrap();
+        assert_eq!(optimism, ChainPreset::Chain(Chain::Optimism));
+        assert_eq!(optimism.profile(), ChainProfile::Evm);
+        assert!("solana".parse::<ChainPreset>().is_err());
+    }
+}
@@ -0,0 +1,207 @@
+//! Combined selector + TLSH classification
+//!
+//! Selectors propose candidate protocols; the nearest reference fingerprint of each
+//! candidate in a [`FingerprintDb`] confirms a lone candidate or breaks a tie between
+//! several. Selectors keep a veto: a reference whose protocol the selectors ruled out
+//! never wins, however close its code shape. Only code matching no selector set at all
+//! falls back to the nearest reference overall.
+
+use std::io;
+use std::path::Path;
+
+use crate::bytecode_fingerprint::{
+    fingerprint_hex_dir, BytecodeFingerprint, FingerprintDb, Similarity,
+};
+use crate::selector_fingerprint::{
+    identify_protocols_with, protocol_fingerprint, DexProtocol, SelectorScan,
+};
+
+/// Weight of the selector score in [`Classification::confidence`]; TLSH gets the rest
+pub const SELECTOR_WEIGHT: f64 = 0.6;
+
+/// TLSH distance at which the TLSH score drops to 0
+const MAX_SCORED_DISTANCE: f64 = 300.0;
+
+/// Which signal decided a [`Classification`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionMethod {
+    /// Selectors alone (no reference of the protocol in the same family)
+    Selector,
+    /// The only selector candidate, confirmed by a same-family reference
+    SelectorTlshAgree,
+    /// Several selector candidates; the one with the nearest same-family reference won
+    TlshNearest,
+    /// No selector candidate; the nearest reference overall is in the same family
+    TlshFallback,
+}
+
+impl DetectionMethod {
+    /// Name used in reports (`detection_method`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Selector => "selector",
+            Self::SelectorTlshAgree => "selector+tlsh_agree",
+            Self::TlshNearest => "tlsh_nearest",
+            Self::TlshFallback => "tlsh_fallback",
+        }
+    }
+}
+
+/// Verdict of [`classify`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Classification {
+    pub protocol: DexProtocol,
+    /// Selector confidence of `protocol` (0 for `Unknown` and TLSH fallbacks)
+    pub selector_confidence: u32,
+    /// TLSH distance to the nearest reference of `protocol`, if there is one
+    pub distance: Option<i32>,
+    /// Blend of the selector score (share of the fingerprint's selectors present) and
+    /// the TLSH score (`1 - distance / 300`, floored at 0), in `0.0..=1.0`
+    ///
+    /// A protocol without any reference is scored on selectors alone.
+    pub confidence: f64,
+    pub method: DetectionMethod,
+}
+
+impl Classification {
+    fn unknown() -> Self {
+        Self {
+            protocol: DexProtocol::Unknown,
+            selector_confidence: 0,
+            distance: None,
+            confidence: 0.0,
+            method: DetectionMethod::Selector,
+        }
+    }
+}
+
+/// Classify bytecode with selectors and the references in `db`
+pub fn classify(bytecode: &[u8], db: &FingerprintDb) -> Classification {
+    classify_with(bytecode, SelectorScan::Anywhere, db)
+}
+
+/// [`classify`] with an explicit selector-scan strategy
+pub fn classify_with(bytecode: &[u8], scan: SelectorScan, db: &FingerprintDb) -> Classification {
+    // Best selector match first
+    let candidates = identify_protocols_with(bytecode, scan);
+
+    let fingerprint = BytecodeFingerprint::from_bytecode(bytecode).ok();
+    let nearest_to = |protocol| {
+        fingerprint
+            .as_ref()
+            .and_then(|fp| db.nearest_to(fp, protocol))
+    };
+
+    match candidates.as_slice() {
+        [] => match fingerprint.as_ref().and_then(|fp| db.nearest(fp)) {
+            Some((protocol, distance)) if same_family(distance) => Classification {
+                protocol,
+                selector_confidence: 0,
+                distance: Some(distance),
+                confidence: (1.0 - SELECTOR_WEIGHT) * tlsh_score(distance),
+                method: DetectionMethod::TlshFallback,
+            },
+            _ => Classification::unknown(),
+        },
+        [(protocol, selector_confidence)] => {
+            let distance = nearest_to(*protocol);
+            Classification {
+                protocol: *protocol,
+                selector_confidence: *selector_confidence,
+                distance,
+                confidence: blend(*protocol, *selector_confidence, distance),
+                method: if distance.is_some_and(same_family) {
+                    DetectionMethod::SelectorTlshAgree
+                } else {
+                    DetectionMethod::Selector
+                },
+            }
+        }
+        _ => {
+            // min_by_key keeps the first minimum, i.e. the better selector match.
+            let best = candidates
+                .iter()
+                .filter_map(|(p, c)| {
+                    nearest_to(*p)
+                        .filter(|d| same_family(*d))
+                        .map(|d| (*p, *c, d))
+                })
+                .min_by_key(|(_, _, distance)| *distance);
+            match best {
+                Some((protocol, selector_confidence, distance)) => Classification {
+                    protocol,
+                    selector_confidence,
+                    distance: Some(distance),
+                    confidence: blend(protocol, selector_confidence, Some(distance)),
+                    method: DetectionMethod::TlshNearest,
+                },
+                None => Classification::unknown(),
+            }
+        }
+    }
+}
+
+/// Load reference fingerprints laid out as `<dir>/<Protocol>/*.hex`
+///
+/// Each subdirectory is named after a protocol, in any spelling `DexProtocol::from_str`
+/// accepts (`UniswapV3`, `uniswap-v3`, ...). Returns the database and the entries left
+/// out (`Protocol/file` or a subdirectory name, with the reason), as
+/// [`fingerprint_hex_dir`] does for single files.
+pub fn load_fingerprint_db(dir: &Path) -> io::Result<(FingerprintDb, Vec<(String, String)>)> {
+    let mut subdirs: Vec<_> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    subdirs.retain(|p| p.is_dir());
+    subdirs.sort();
+
+    let mut db = FingerprintDb::new();
+    let mut skipped = Vec::new();
+    for subdir in subdirs {
+        let name = subdir
+            .file_name()
+            .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+        let protocol = match name.parse::<DexProtocol>() {
+            Ok(DexProtocol::Unknown) => {
+                skipped.push((name, "Unknown is not a reference protocol".to_string()));
+                continue;
+            }
+            Ok(protocol) => protocol,
+            Err(e) => {
+                skipped.push((name, e));
+                continue;
+            }
+        };
+
+        let fingerprints = fingerprint_hex_dir(&subdir)?;
+        for (_, fingerprint) in fingerprints.fingerprints {
+            db.insert(protocol, fingerprint);
+        }
+        skipped.extend(
+            fingerprints
+                .skipped
+                .into_iter()
+                .map(|(file, reason)| (format!("{name}/{file}"), reason)),
+        );
+    }
+    Ok((db, skipped))
+}
+
+fn same_family(distance: i32) -> bool {
+    Similarity::from_diff(distance).is_same_family()
+}
+
+fn tlsh_score(distance: i32) -> f64 {
+    (1.0 - f64::from(distance) / MAX_SCORED_DISTANCE).max(0.0)
+}
+
+fn blend(protocol: DexProtocol, selector_confidence: u32, distance: Option<i32>) -> f64 {
+    let selector_score = protocol_fingerprint(protocol).map_or(0.0, |fp| {
+        f64::from(fp.confidence_percent(selector_confidence)) / 100.0
+    });
+    match distance {
+        Some(distance) => {
+            SELECTOR_WEIGHT * selector_score + (1.0 - SELECTOR_WEIGHT) * tlsh_score(distance)
+        }
+        None => selector_score,
+    }
+}
@@ -0,0 +1,312 @@
+//! Known factory deployments
+//!
+//! Many V2 forks deploy byte-for-byte UniswapV2 pairs, and many V3 forks (e.g. on
+//! Base) byte-for-byte UniswapV3 pools, so selectors alone cannot tell them apart.
+//! The factory a pool reports via `factory()` can.
+
+use alloy_primitives::{address, Address};
+
+use crate::selector_fingerprint::{selectors, DexProtocol};
+
+/// A factory whose pools belong to a known DEX
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KnownFactory {
+    pub chain_id: u64,
+    pub address: Address,
+    /// Protocol the factory's pools are detected as
+    pub protocol: DexProtocol,
+    /// DEX (fork family) that deployed the factory
+    pub dex: &'static str,
+}
+
+/// Seeded factory map (public deployment addresses)
+pub static KNOWN_FACTORIES: &[KnownFactory] = &[
+    KnownFactory {
+        chain_id: 1,
+        address: address!("5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "UniswapV2",
+    },
+    KnownFactory {
+        chain_id: 1,
+        address: address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "SushiSwap",
+    },
+    // SushiSwap uses one factory address on most other chains (Polygon, Arbitrum, BSC, ...)
+    KnownFactory {
+        chain_id: 137,
+        address: address!("c35DADB65012eC5796536bD9864eD8773aBc74C4"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "SushiSwap",
+    },
+    KnownFactory {
+        chain_id: 137,
+        address: address!("5757371414417b8C6CAad45bAeF941aBc7d3Ab32"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "QuickSwap",
+    },
+    KnownFactory {
+        chain_id: 56,
+        address: address!("0841BD0B734E4F5853f0dD8d7Ea041c241fb0Da6"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "ApeSwap",
+    },
+    KnownFactory {
+        chain_id: 56,
+        address: address!("cA143Ce32Fe78f1f7019d7d551a6402fC5350c73"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "PancakeSwapV2",
+    },
+    KnownFactory {
+        chain_id: 42161,
+        address: address!("6EcCab422D763aC031210895C81787E87B43A652"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "CamelotV2",
+    },
+    // Trader Joe V1 (Avalanche). JoePair exposes exactly the UniswapV2Pair selectors
+    // (its fee split lives in the factory's feeTo), so only the factory identifies it.
+    KnownFactory {
+        chain_id: 43114,
+        address: address!("9Ad6C38BE94206cA50bb0d90783181662f0Cfa10"),
+        protocol: DexProtocol::UniswapV2,
+        dex: "TraderJoeV1",
+    },
+    // Base V3 forks: all deploy UniswapV3 pools, only their factories differ.
+    KnownFactory {
+        chain_id: 8453,
+        address: address!("33128a8fC17869897dcE68Ed026d694621f6FDfD"),
+        protocol: DexProtocol::UniswapV3,
+        dex: "UniswapV3",
+    },
+    KnownFactory {
+        chain_id: 8453,
+        address: address!("38015D05f4fEC8AFe15D7cc0386a126574e8077B"),
+        protocol: DexProtocol::UniswapV3,
+        dex: "BaseSwapV3",
+    },
+    KnownFactory {
+        chain_id: 8453,
+        address: address!("0Fd83557b2be93617c9C1C1B6fd549401C74558C"),
+        protocol: DexProtocol::UniswapV3,
+        dex: "AlienBaseV3",
+    },
+    KnownFactory {
+        chain_id: 8453,
+        address: address!("0BFbCF9fa4f9C56B0F40a671Ad40E0805A091865"),
+        protocol: DexProtocol::UniswapV3,
+        dex: "PancakeSwapV3",
+    },
+    KnownFactory {
+        chain_id: 8453,
+        address: address!("3D237AC6D2f425D2E890Cc99198818cc1FA48870"),
+        protocol: DexProtocol::UniswapV3,
+        dex: "DackieSwapV3",
+    },
+    // The address of SushiSwap's V2 factory on Polygon and other chains.
+    KnownFactory {
+        chain_id: 8453,
+        address: address!("c35DADB65012eC5796536bD9864eD8773aBc74C4"),
+        protocol: DexProtocol::UniswapV3,
+        dex: "SushiSwapV3",
+    },
+];
+
+/// Look up a factory by address
+///
+/// An address names one DEX per protocol, but may be listed once per protocol
+/// (SushiSwap's V2 factory elsewhere is its V3 factory on Base); this returns the
+/// first entry, [`factory_dex`] the one for a given protocol.
+pub fn known_factory(factory: Address) -> Option<&'static KnownFactory> {
+    KNOWN_FACTORIES.iter().find(|f| f.address == factory)
+}
+
+/// DEX that deployed a pool detected as `protocol` through `factory`
+pub fn factory_dex(factory: Address, protocol: DexProtocol) -> Option<&'static str> {
+    KNOWN_FACTORIES
+        .iter()
+        .find(|f| f.address == factory && f.protocol == protocol)
+        .map(|known| known.dex)
+}
+
+/// Curated factories per chain and protocol, as given by `--factory-allowlist`
+///
+/// A pool whose `factory()` is listed for its chain and detected protocol is
+/// `factory_verified`; selector look-alikes deployed by some other factory are not.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FactoryAllowlist {
+    entries: Vec<(u64, Address, DexProtocol)>,
+}
+
+impl FactoryAllowlist {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse CSV lines of `chain_id,factory,protocol`
+    ///
+    /// Blank lines, `#` comments and a `chain_id,...` header are skipped; protocols
+    /// take any spelling `DexProtocol::from_str` accepts. A malformed line fails the
+    /// whole list.
+    #[cfg(feature = "analysis")]
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut allowlist = Self::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("chain_id") {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [chain_id, factory, protocol] = fields[..] else {
+                return Err(format!(
+                    "line {}: expected chain_id,factory,protocol",
+                    i + 1
+                ));
+            };
+            let chain_id = chain_id
+                .parse()
+                .map_err(|_| format!("line {}: invalid chain id `{chain_id}`", i + 1))?;
+            let factory = factory
+                .parse()
+                .map_err(|_| format!("line {}: invalid factory address `{factory}`", i + 1))?;
+            let protocol = protocol
+                .parse()
+                .map_err(|e| format!("line {}: {e}", i + 1))?;
+            allowlist.insert(chain_id, factory, protocol);
+        }
+        Ok(allowlist)
+    }
+
+    pub fn insert(&mut self, chain_id: u64, factory: Address, protocol: DexProtocol) {
+        self.entries.push((chain_id, factory, protocol));
+    }
+
+    /// Whether `factory` is listed for `protocol` on `chain_id`
+    pub fn contains(&self, chain_id: u64, factory: Address, protocol: DexProtocol) -> bool {
+        self.entries.contains(&(chain_id, factory, protocol))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Fork family of a UniswapV2-shaped pool
+///
+/// The factory (when resolved on-chain) wins; otherwise only forks with their own
+/// pair selectors can be recognized (Camelot V2 pairs add per-token fee getters).
+pub fn v2_fork_family(bytecode: &[u8], factory: Option<Address>) -> Option<&'static str> {
+    if let Some(dex) = factory.and_then(|f| factory_dex(f, DexProtocol::UniswapV2)) {
+        return Some(dex);
+    }
+
+    if selectors::TOKEN0_FEE_PERCENT.exists_in(bytecode) {
+        return Some("CamelotV2");
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distinct_factories_resolve_to_distinct_families() {
+        let sushi = address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac");
+        let quickswap = address!("5757371414417b8C6CAad45bAeF941aBc7d3Ab32");
+
+        assert_eq!(v2_fork_family(&[], Some(sushi)), Some("SushiSwap"));
+        assert_eq!(v2_fork_family(&[], Some(quickswap)), Some("QuickSwap"));
+        let joe = address!("9Ad6C38BE94206cA50bb0d90783181662f0Cfa10");
+        assert_eq!(v2_fork_family(&[], Some(joe)), Some("TraderJoeV1"));
+        assert_eq!(v2_fork_family(&[], Some(Address::ZERO)), None);
+    }
+
+    #[test]
+    fn test_base_v3_factories_name_their_dex() {
+        let baseswap = address!("38015D05f4fEC8AFe15D7cc0386a126574e8077B");
+        let alien_base = address!("0Fd83557b2be93617c9C1C1B6fd549401C74558C");
+        assert_eq!(
+            factory_dex(baseswap, DexProtocol::UniswapV3),
+            Some("BaseSwapV3")
+        );
+        assert_eq!(
+            factory_dex(alien_base, DexProtocol::UniswapV3),
+            Some("AlienBaseV3")
+        );
+        // A V3 factory never names a V2 pool's fork.
+        assert_eq!(v2_fork_family(&[], Some(baseswap)), None);
+
+        // One address, a V2 factory on Polygon and SushiSwap's V3 factory on Base
+        let sushi = address!("c35DADB65012eC5796536bD9864eD8773aBc74C4");
+        assert_eq!(
+            factory_dex(sushi, DexProtocol::UniswapV3),
+            Some("SushiSwapV3")
+        );
+        assert_eq!(v2_fork_family(&[], Some(sushi)), Some("SushiSwap"));
+        let dackie = address!("3D237AC6D2f425D2E890Cc99198818cc1FA48870");
+        assert_eq!(
+            factory_dex(dackie, DexProtocol::UniswapV3),
+            Some("DackieSwapV3")
+        );
+
+        let base_v3 = KNOWN_FACTORIES
+            .iter()
+            .filter(|f| f.chain_id == 8453 && f.protocol == DexProtocol::UniswapV3)
+            .count();
+        assert_eq!(base_v3, 6);
+    }
+
+    #[test]
+    fn test_camelot_recognized_by_selectors() {
+        let bytecode = selectors::TOKEN0_FEE_PERCENT.as_bytes().to_vec();
+        assert_eq!(v2_fork_family(&bytecode, None), Some("CamelotV2"));
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_parse_factory_allowlist() {
+        let sushi = address!("C0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac");
+        let allowlist = FactoryAllowlist::parse(
+            "chain_id,factory,protocol\n\
+             # mainnet\n\
+             1, 0xc0aee478e3658e2610c5f7a4a2e1777ce9e4f2ac, uniswap-v2\n\
+             \n",
+        )
+        .unwrap();
+        assert_eq!(allowlist.len(), 1);
+        assert!(allowlist.contains(1, sushi, DexProtocol::UniswapV2));
+        assert!(!allowlist.contains(137, sushi, DexProtocol::UniswapV2));
+        assert!(!allowlist.contains(1, sushi, DexProtocol::UniswapV3));
+
+        let err = FactoryAllowlist::parse("1,0xc0aee478e3658e2610c5f7a4a2e1777ce9e4f2ac\n");
+        assert_eq!(
+            err.unwrap_err(),
+            "line 1: expected chain_id,factory,protocol"
+        );
+        assert!(FactoryAllowlist::parse("1,0x12,UniswapV2").is_err());
+        assert!(
+            FactoryAllowlist::parse("1,0xc0aee478e3658e2610c5f7a4a2e1777ce9e4f2ac,Nope").is_err()
+        );
+    }
+
+    #[test]
+    fn test_known_factory_addresses_are_unique_per_protocol() {
+        for (i, a) in KNOWN_FACTORIES.iter().enumerate() {
+            for b in &KNOWN_FACTORIES[i + 1..] {
+                assert_ne!(
+                    (a.address, a.protocol),
+                    (b.address, b.protocol),
+                    "{} / {}",
+                    a.dex,
+                    b.dex
+                );
+            }
+        }
+    }
+}
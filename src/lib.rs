@@ -1,10 +1,65 @@
+//! Identify DEX pools and their protocol from EVM bytecode.
+//!
+//! With `default-features = false` only [`selector_fingerprint`] and [`metadata`]
+//! are built, as `no_std + alloc`. The `fingerprint` feature adds TLSH comparison,
+//! `analysis` adds offline report building, `rpc` (default) adds the fetch/analyze
+//! pipeline and `wasm` adds wasm-bindgen entrypoints.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "analysis")]
 pub mod analyze;
+#[cfg(feature = "rpc")]
+pub mod batch;
+#[cfg(feature = "fingerprint")]
 pub mod bytecode_fingerprint;
+#[cfg(feature = "std")]
+pub mod chain;
+#[cfg(feature = "analysis")]
+pub mod classify;
+#[cfg(feature = "std")]
+pub mod factories;
+pub mod metadata;
+#[cfg(feature = "rpc")]
+pub mod metrics;
 pub mod selector_fingerprint;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
+#[cfg(feature = "analysis")]
+pub use analyze::{
+    analyze_bytecode, analyze_bytecode_with_db, analyze_bytecode_with_options, decode_bytecode,
+    dex_protocol_name, parse_address_hex, parse_dex_protocol, parse_tx_hash,
+    proxy_implementation_address, AnalyzeError, AnalyzeOptions, AnalyzeReport, BytecodeAnalysis,
+    Concurrency, EffectiveVerdict, InputEncoding, NameStyle, ProxyKind, RpcError,
+};
+#[cfg(feature = "rpc")]
 pub use analyze::{
-    analyze_bytecode, dex_protocol_name, parse_address_hex, proxy_implementation_address,
-    validate_rpc_url, AnalyzeError, AnalyzeReport, BytecodeAnalysis,
+    created_contract_address, fetch_resolved_code, parse_block_id, validate_rpc_url, with_deadline,
+};
+#[cfg(feature = "rpc")]
+pub use batch::{
+    analyze_addresses, analyze_addresses_grouped_by_implementation, analyze_addresses_with_metrics,
+    compare_implementations, compare_many, group_by_fingerprint, parse_address_list,
+    CandidateMatch, FingerprintGroup, ImplementationComparison, DEFAULT_BATCH_SIZE,
+    MAX_AUTO_CONCURRENCY,
+};
+#[cfg(feature = "fingerprint")]
+pub use bytecode_fingerprint::{
+    known_contract, normalize_for_fingerprint, runtime_code, BytecodeFingerprint, Fingerprint,
+    FingerprintDb, FingerprintError, FingerprintOptions, Similarity, SimilarityThresholds,
+};
+#[cfg(feature = "std")]
+pub use chain::{Chain, ChainPreset, ChainProfile};
+#[cfg(feature = "analysis")]
+pub use classify::{classify, load_fingerprint_db, Classification, DetectionMethod};
+#[cfg(feature = "std")]
+pub use factories::FactoryAllowlist;
+#[cfg(feature = "rpc")]
+pub use metrics::{AnalyzerMetrics, InMemoryMetrics, MetricsSnapshot, NoopMetrics};
+pub use selector_fingerprint::{
+    identify_protocol, identify_protocols, ContractKind, DexProtocol, Selector, SelectorParseError,
+    SelectorSet,
 };
-pub use bytecode_fingerprint::{BytecodeFingerprint, FingerprintError, Similarity};
-pub use selector_fingerprint::{identify_protocol, identify_protocols, DexProtocol, Selector};
@@ -1,45 +1,517 @@
-use clap::{Parser, Subcommand};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, B256, U256};
+use clap::{ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use thiserror::Error;
 use tracing_subscriber::EnvFilter;
-use which_dex::analyze::{analyze_address, parse_address_hex, AnalyzeError, AnalyzeReport};
-use which_dex::validate_rpc_url;
+use which_dex::analyze::{
+    analyze_address_with_options, parse_address_hex, parse_block_id, AnalyzeError, AnalyzeReport,
+};
+use which_dex::selector_fingerprint::{fingerprint_specs, FingerprintSpec, SpecSelector};
+use which_dex::{
+    analyze_addresses_with_metrics, analyze_bytecode_with_options, compare_implementations,
+    compare_many, created_contract_address, decode_bytecode, dex_protocol_name,
+    fetch_resolved_code, group_by_fingerprint, load_fingerprint_db, normalize_for_fingerprint,
+    parse_address_list, parse_tx_hash, validate_rpc_url, with_deadline, AnalyzeOptions,
+    AnalyzerMetrics, BytecodeAnalysis, Chain, ChainPreset, Concurrency, DexProtocol,
+    FactoryAllowlist, FingerprintDb, FingerprintOptions, InMemoryMetrics, InputEncoding,
+    MetricsSnapshot, NameStyle, DEFAULT_BATCH_SIZE,
+};
 
 #[derive(Debug, Parser)]
 #[command(name = "which-dex", about = "DEX pool identifier", version)]
 struct Cli {
+    /// TOML file of flag defaults (e.g. `rpc-url = "https://..."`); flags given on the
+    /// command line win
+    #[arg(long, global = true)]
+    config: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+/// `--config` file: defaults for valued flags, keyed by their long name
+///
+/// A key applies to every subcommand taking that flag and is ignored by the others.
+/// Values are checked by the flags' own parsers, so a bad value fails like it would on
+/// the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct Config {
+    rpc_url: Option<String>,
+    batch_size: Option<usize>,
+    /// A number, or `"auto"` (`analyze` only)
+    concurrency: Option<ConfigValue>,
+    deadline_ms: Option<u64>,
+    pool_min_confidence: Option<u32>,
+    chain: Option<String>,
+    chain_hint: Option<String>,
+    name_style: Option<String>,
+    fingerprint_db: Option<String>,
+    factory_allowlist: Option<String>,
+    block: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ConfigValue {
+    Number(u64),
+    Text(String),
+}
+
+impl Config {
+    fn load(path: &str) -> Result<Self, CliError> {
+        let config_error = |reason: String| CliError::Config {
+            path: path.to_string(),
+            reason,
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| config_error(e.to_string()))?;
+        toml::from_str(&contents).map_err(|e| config_error(e.to_string()))
+    }
+
+    /// `(arg id, value)` for every key set in the file
+    fn defaults(&self) -> Vec<(&'static str, String)> {
+        let concurrency = self.concurrency.as_ref().map(|value| match value {
+            ConfigValue::Number(n) => n.to_string(),
+            ConfigValue::Text(text) => text.clone(),
+        });
+        [
+            ("rpc_url", self.rpc_url.clone()),
+            ("batch_size", self.batch_size.map(|n| n.to_string())),
+            ("concurrency", concurrency),
+            ("deadline_ms", self.deadline_ms.map(|n| n.to_string())),
+            (
+                "pool_min_confidence",
+                self.pool_min_confidence.map(|n| n.to_string()),
+            ),
+            ("chain", self.chain.clone()),
+            ("chain_hint", self.chain_hint.clone()),
+            ("name_style", self.name_style.clone()),
+            ("fingerprint_db", self.fingerprint_db.clone()),
+            ("factory_allowlist", self.factory_allowlist.clone()),
+            ("block", self.block.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(id, value)| Some((id, value?)))
+        .collect()
+    }
+
+    /// Make the file's values the defaults of `command`'s subcommands
+    fn apply(&self, mut command: clap::Command) -> clap::Command {
+        let defaults = self.defaults();
+        let names: Vec<String> = command
+            .get_subcommands()
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+        for name in names {
+            command = command.mut_subcommand(name, |mut sub| {
+                for (id, value) in &defaults {
+                    if sub.get_arguments().any(|arg| arg.get_id() == id) {
+                        sub = sub
+                            .mut_arg(*id, |arg| arg.default_value(value.clone()).required(false));
+                    }
+                }
+                sub
+            });
+        }
+        command
+    }
+}
+
+/// Parse a storage slot: 0x-prefixed hex of up to 32 bytes, left-padded
+fn parse_slot(s: &str) -> Result<B256, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .ok_or_else(|| format!("invalid slot `{s}` (expected 0x-prefixed hex)"))?;
+    U256::from_str_radix(digits, 16)
+        .map(B256::from)
+        .map_err(|e| format!("invalid slot `{s}`: {e}"))
+}
+
+/// Parse the command line over the defaults of its `--config` file, if any
+fn parse_cli() -> Result<Cli, CliError> {
+    let args: Vec<String> = std::env::args().collect();
+    let mut command = Cli::command();
+    if let Some(path) = config_path(&args) {
+        command = Config::load(path)?.apply(command);
+    }
+    let matches = command.get_matches_from(args);
+    Ok(Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit()))
+}
+
+/// The `--config` value, read ahead of parsing since it changes the flags' defaults
+fn config_path(args: &[String]) -> Option<&str> {
+    args.iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.strip_prefix("--config") {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(rest) => rest.strip_prefix('='),
+            None => None,
+        })
+}
+
+#[derive(Debug, Error)]
+enum CliError {
+    #[error(transparent)]
+    Analyze(#[from] AnalyzeError),
+
+    /// `--fail-on-unknown` tripped; holds the offending addresses
+    #[error("{} address(es) resolved to Unknown:\n  {}", .0.len(), .0.join("\n  "))]
+    Unknown(Vec<String>),
+
+    /// Writing reports to stdout or `--output` failed
+    #[error("cannot write output: {0}")]
+    Output(#[from] io::Error),
+
+    /// `--fingerprint-db` could not be read
+    #[error("cannot load fingerprint db {path}: {source}")]
+    FingerprintDb { path: String, source: io::Error },
+
+    /// `--factory-allowlist` could not be read or has a malformed line
+    #[error("cannot load factory allowlist {path}: {reason}")]
+    FactoryAllowlist { path: String, reason: String },
+
+    /// `--config` could not be read, is not TOML, or has an unknown key
+    #[error("cannot load config {path}: {reason}")]
+    Config { path: String, reason: String },
+}
+
 #[derive(Debug, Subcommand)]
 enum Commands {
     /// Fetch bytecode via RPC and identify whether the address looks like a DEX pool + which protocol.
-    Analyze {
-        /// RPC URL (e.g. https://...)
-        #[arg(long)]
-        rpc_url: String,
-        /// Contract address (0x-prefixed hex)
-        #[arg(long)]
-        address: String,
-        /// Emit JSON to stdout (human-readable output goes to stderr)
-        #[arg(long)]
-        json: bool,
-        /// Enable verbose debug logs (tracing)
-        #[arg(long)]
-        verbose: bool,
-    },
+    Analyze(Box<AnalyzeArgs>),
+    /// Identify bytecode read from a file or stdin, without RPC.
+    AnalyzeCode(AnalyzeCodeArgs),
+    /// Print the runtime bytecode of an address as hex, without analysis.
+    Code(CodeArgs),
+    /// Analyze the contract created by a deployment transaction.
+    AnalyzeTx(AnalyzeTxArgs),
+    /// Rank known addresses by how closely their code (TLSH) resembles a suspect's.
+    /// With `--compare-implementations`, compare the code behind two proxies instead.
+    CompareMany(CompareManyArgs),
+    /// List the protocols this binary can detect and the selectors each one matches on.
+    Protocols(ProtocolsArgs),
+    /// Dump every protocol fingerprint as JSON (same as `protocols --json`).
+    #[command(hide = true)]
+    Fingerprints,
+}
+
+#[derive(Debug, Args)]
+struct ProtocolsArgs {
+    /// Emit JSON to stdout
+    #[arg(long)]
+    json: bool,
+}
+
+/// `--sort` key for batch output
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum SortKey {
+    /// Detected protocol name
+    Protocol,
+    /// Address, as a 20-byte number
+    Address,
+    /// Runtime code size of the analyzed code, smallest first
+    #[value(name = "code_size")]
+    CodeSize,
+}
+
+/// `--classifier`: what decides the reported protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Classifier {
+    /// Selector fingerprints only
+    Selector,
+    /// Selectors propose candidates, `--fingerprint-db` references confirm or break ties
+    Combined,
+}
+
+#[derive(Debug, Args)]
+struct AnalyzeCodeArgs {
+    /// File holding the runtime bytecode (stdin if omitted or `-`)
+    #[arg(long)]
+    file: Option<String>,
+    /// The input is creation bytecode: analyze only the runtime code it deploys, so
+    /// selectors in the constructor don't count (input that isn't is analyzed whole)
+    #[arg(long)]
+    runtime_only: bool,
+    /// Encoding of the input (hex, base64, raw)
+    #[arg(long, default_value = "hex")]
+    input_encoding: InputEncoding,
+    /// Address to label the analysis with
+    #[arg(long)]
+    address: Option<String>,
+    /// Explain `Unknown` verdicts: closest `--fingerprint-db` family and the selectors
+    /// keeping near-miss protocols from matching
+    #[arg(long)]
+    explain: bool,
+    /// Add a `disassembly` of the dispatcher (first 256 bytes at most) to `Unknown` verdicts
+    #[arg(long)]
+    include_disassembly: bool,
+    /// Name code that is exactly a well-known contract's, immutables aside
+    /// (`known_contract`, e.g. UniswapV3Pool)
+    #[arg(long)]
+    known_contracts: bool,
+    /// Write the normalized bytecode the fingerprint hashes (metadata stripped, PUSH
+    /// data zeroed) to this file as hex; diff two dumps to see why fingerprints differ
+    #[arg(long, value_name = "FILE")]
+    dump_normalized: Option<String>,
+    /// What decides the protocol (combined needs --fingerprint-db)
+    #[arg(
+        long,
+        default_value = "selector",
+        requires_if("combined", "fingerprint_db")
+    )]
+    classifier: Classifier,
+    /// Reference bytecodes for `--classifier combined` and `--explain`, as
+    /// `<dir>/<Protocol>/*.hex`
+    #[arg(long)]
+    fingerprint_db: Option<String>,
+    /// Spelling of protocol names (canonical: UniswapV3, lowercase: uniswapv3,
+    /// kebab: uniswap-v3)
+    #[arg(long, default_value = "canonical")]
+    name_style: NameStyle,
+    /// Emit JSON to stdout
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct CodeArgs {
+    /// RPC URL (e.g. https://...)
+    #[arg(long)]
+    rpc_url: String,
+    /// Contract address (0x-prefixed hex)
+    #[arg(long)]
+    address: String,
+    /// Print the implementation's code when the address is an EIP-1167 or Safe proxy
+    #[arg(long)]
+    resolve_proxy: bool,
+}
+
+#[derive(Debug, Args)]
+struct CompareManyArgs {
+    /// RPC URL (e.g. https://...)
+    #[arg(long)]
+    rpc_url: String,
+    /// Suspect contract address (0x-prefixed hex)
+    #[arg(long)]
+    address: String,
+    /// File with one candidate address per line (`#` comments allowed)
+    #[arg(long, required_unless_present = "compare_implementations")]
+    candidates: Option<String>,
+    /// Resolve `--address` and this address to their implementations and compare
+    /// the two, instead of ranking `--candidates`
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "candidates")]
+    compare_implementations: Option<String>,
+    /// Number of `eth_getCode` calls sent per JSON-RPC batch
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE)]
+    batch_size: usize,
+    /// Candidate batches fetched at once
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+    /// Emit the ranked candidates as a JSON array
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+struct AnalyzeTxArgs {
+    /// RPC URL (e.g. https://...)
+    #[arg(long)]
+    rpc_url: String,
+    /// Hash of the contract-creation transaction (0x-prefixed hex)
+    #[arg(long)]
+    tx_hash: String,
+    /// Spelling of protocol names (canonical: UniswapV3, lowercase: uniswapv3,
+    /// kebab: uniswap-v3)
+    #[arg(long, default_value = "canonical")]
+    name_style: NameStyle,
+    /// Emit JSON to stdout
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("batch").args(["addresses_file", "stdin"])))]
+struct AnalyzeArgs {
+    /// RPC URL (e.g. https://...)
+    #[arg(long)]
+    rpc_url: String,
+    /// Contract address (0x-prefixed hex)
+    #[arg(long, required_unless_present = "batch")]
+    address: Option<String>,
+    /// Analyze every address in a file (one per line, `#` comments allowed)
+    #[arg(long, conflicts_with = "address")]
+    addresses_file: Option<String>,
+    /// Analyze every address read from stdin, as laid out for `--addresses-file`
+    #[arg(long, conflicts_with = "address")]
+    stdin: bool,
+    /// Analyze only the first N addresses of `--addresses-file` or `--stdin`
+    #[arg(long, visible_alias = "head", value_name = "N", requires = "batch")]
+    limit: Option<usize>,
+    /// Number of `eth_getCode` calls sent per JSON-RPC batch (batch mode)
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE)]
+    batch_size: usize,
+    /// Batches in flight at once (batch mode): a number, or `auto` to ramp up while the
+    /// endpoint stays fast and back off (retrying) when it rate-limits
+    #[arg(long, default_value = "1", requires = "batch")]
+    concurrency: Concurrency,
+    /// Wall-clock limit in milliseconds for the whole analysis, proxy hops included
+    /// (in batch mode: for each address, which alone fails when it runs over)
+    #[arg(long)]
+    deadline_ms: Option<u64>,
+    /// Soft chain hint (ethereum, optimism, polygon, bsc, arbitrum, base): only breaks
+    /// exact confidence ties in favor of protocols common on that chain, never overrides
+    /// a selector-based verdict; no effect on Algebra variants, which never tie
+    /// (defaults to a chain named by `--chain`)
+    #[arg(long)]
+    chain_hint: Option<Chain>,
+    /// Batch mode: emit one representative report per exact normalized bytecode hash,
+    /// with the list of addresses sharing it
+    #[arg(long, requires = "batch")]
+    dedupe_by_fingerprint: bool,
+    /// Minimum confidence of the identified protocol for `is_pool_likely`
+    /// (default 0: any identified protocol)
+    #[arg(long, default_value_t = 0)]
+    pool_min_confidence: u32,
+    /// Only count required selectors that appear as PUSH operands (dispatcher comparisons),
+    /// ignoring coincidental byte matches in data sections
+    #[arg(long)]
+    strict_push4: bool,
+    /// Bytecode layout profile (evm, zksync): zksync scans EraVM constant-pool words for
+    /// selectors. A chain name (e.g. optimism) selects the evm profile and that chain's
+    /// priors as `--chain-hint`
+    #[arg(long, default_value = "evm")]
+    chain: ChainPreset,
+    /// Embed the fetched bytecode (proxy and implementation) as `bytecode_hex` in JSON reports
+    #[arg(long)]
+    include_bytecode: bool,
+    /// Add an `opcode_histogram` (256 opcode counts, PUSH data skipped) to every analysis
+    #[arg(long)]
+    opcode_histogram: bool,
+    /// Add a `disassembly` of the dispatcher (first 256 bytes at most) to `Unknown` verdicts
+    #[arg(long)]
+    include_disassembly: bool,
+    /// Name code that is exactly a well-known contract's, immutables aside
+    /// (`known_contract`, e.g. UniswapV3Pool)
+    #[arg(long)]
+    known_contracts: bool,
+    /// Batch mode: only emit addresses whose detected protocol is this one (repeatable,
+    /// case-insensitive, e.g. AlgebraIntegral)
+    #[arg(long, requires = "batch")]
+    only_protocol: Vec<DexProtocol>,
+    /// Batch mode: order the reports by this key once every address is done
+    /// (default: input order; failed addresses go last)
+    #[arg(long, requires = "batch")]
+    sort: Option<SortKey>,
+    /// Storage slot (0x-prefixed hex) holding the implementation of a delegating proxy
+    /// no built-in shape recognizes; read only when automatic proxy detection fails
+    #[arg(long, value_parser = parse_slot, conflicts_with = "batch")]
+    impl_slot: Option<B256>,
+    /// Block to read code, proxy storage and calls at: latest, finalized, safe, earliest,
+    /// pending, a block number or a block hash
+    #[arg(long, default_value = "latest", value_parser = parse_block_id)]
+    block: BlockId,
+    /// Analyze these addresses' own code instead of following them to an implementation
+    /// (repeatable or comma-separated; wins over automatic proxy resolution and
+    /// `--impl-slot`)
+    #[arg(long, value_name = "ADDRESS", value_delimiter = ',', value_parser = parse_address_hex)]
+    exclude_proxy_resolution_for: Vec<Address>,
+    /// Read pool state on-chain (`factory()`) to identify the fork family of V2-style pools
+    #[arg(long)]
+    resolve_onchain: bool,
+    /// CSV of curated factories (`chain_id,factory,protocol`): pools whose resolved
+    /// `factory()` is listed for the endpoint's chain are `factory_verified`
+    #[arg(long, requires = "resolve_onchain")]
+    factory_allowlist: Option<String>,
+    /// When bytecode is inconclusive, classify by which protocol views answer an
+    /// `eth_call` (slow: about 30 calls per Unknown address)
+    #[arg(long)]
+    dynamic: bool,
+    /// Resolve each likely pool's token0/token1 and flag owner controls in their code
+    /// (blacklists, whitelists, max-tx caps) as `token_risk_flags`; a rug-screening
+    /// hint, separate from protocol detection
+    #[arg(long)]
+    scan_tokens: bool,
+    /// Explain `Unknown` verdicts: closest `--fingerprint-db` family and the selectors
+    /// keeping near-miss protocols from matching
+    #[arg(long)]
+    explain: bool,
+    /// What decides the protocol (combined needs --fingerprint-db)
+    #[arg(
+        long,
+        default_value = "selector",
+        requires_if("combined", "fingerprint_db")
+    )]
+    classifier: Classifier,
+    /// Reference bytecodes for `--classifier combined` and `--explain`, as
+    /// `<dir>/<Protocol>/*.hex`
+    #[arg(long)]
+    fingerprint_db: Option<String>,
+    /// Exit non-zero (after analyzing every address) if any address resolves to `Unknown`
+    #[arg(long)]
+    fail_on_unknown: bool,
+    /// Emit JSON to stdout (logs go to stderr)
+    #[arg(long)]
+    json: bool,
+    /// Spelling of protocol names in reports (canonical: UniswapV3, lowercase: uniswapv3,
+    /// kebab: uniswap-v3)
+    #[arg(long, default_value = "canonical")]
+    name_style: NameStyle,
+    /// Write reports (in the chosen format) to this file instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+    /// Emit compact JSON Lines only: one object per address, nothing on stderr unless
+    /// the run fails
+    #[arg(long, conflicts_with = "verbose")]
+    ndjson: bool,
+    /// Enable verbose debug logs (tracing, on stderr)
+    #[arg(long)]
+    verbose: bool,
+    /// Batch mode: after the reports, tally protocols, proxies, errors and `Unknown`
+    /// (a final `{"summary": ...}` JSON object, or one line on stderr)
+    #[arg(long, requires = "batch")]
+    summary: bool,
+    /// No batch progress or `--output` note on stderr (errors and `--summary` are still
+    /// reported)
+    #[arg(long)]
+    quiet: bool,
 }
 
 #[tokio::main]
 async fn main() {
-    let cli = Cli::parse();
+    let cli = match parse_cli() {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("error: {e}");
+            std::process::exit(1);
+        }
+    };
 
     let result = match cli.command {
-        Commands::Analyze {
-            rpc_url,
-            address,
-            json,
-            verbose,
-        } => run_analyze(&rpc_url, &address, json, verbose).await,
+        Commands::Analyze(args) if args.addresses_file.is_some() || args.stdin => {
+            run_batch(&args).await
+        }
+        Commands::Analyze(args) => run_analyze(&args).await,
+        Commands::AnalyzeCode(args) => run_analyze_code(&args),
+        Commands::Code(args) => run_code(&args).await,
+        Commands::AnalyzeTx(args) => run_analyze_tx(&args).await,
+        Commands::CompareMany(args) => run_compare_many(&args).await,
+        Commands::Protocols(args) => {
+            run_protocols(&args);
+            Ok(())
+        }
+        Commands::Fingerprints => {
+            run_protocols(&ProtocolsArgs { json: true });
+            Ok(())
+        }
     };
 
     if let Err(e) = result {
@@ -48,73 +520,831 @@ async fn main() {
     }
 }
 
-async fn run_analyze(
-    rpc_url: &str,
-    address: &str,
-    json: bool,
-    verbose: bool,
-) -> Result<(), AnalyzeError> {
-    init_tracing(verbose);
-    validate_rpc_url(rpc_url)?;
+impl AnalyzeArgs {
+    fn json_output(&self) -> bool {
+        self.json || self.ndjson
+    }
+
+    /// Buffered report sink: the `--output` file, or stdout
+    fn output(&self) -> io::Result<Box<dyn Write>> {
+        Ok(match &self.output {
+            Some(path) => {
+                let file = File::create(path)
+                    .map_err(|e| io::Error::new(e.kind(), format!("{path}: {e}")))?;
+                Box::new(BufWriter::new(file))
+            }
+            None => Box::new(BufWriter::new(io::stdout())),
+        })
+    }
+
+    /// Flush the report sink and, for `--output`, note on stderr where `count` reports went
+    fn finish_output(&self, mut out: Box<dyn Write>, count: usize) -> io::Result<()> {
+        out.flush()?;
+        if let Some(path) = &self.output {
+            if !self.ndjson && !self.quiet {
+                eprintln!("wrote {count} report(s) to {path}");
+            }
+        }
+        Ok(())
+    }
+
+    /// `--fail-on-unknown`: error out listing every report whose protocol is `Unknown`
+    fn check_unknown<'a>(
+        &self,
+        reports: impl IntoIterator<Item = &'a AnalyzeReport>,
+    ) -> Result<(), CliError> {
+        if !self.fail_on_unknown {
+            return Ok(());
+        }
+        let unknown: Vec<String> = reports
+            .into_iter()
+            .filter(|report| report.analysis.protocol == "Unknown")
+            .map(|report| report.address.clone())
+            .collect();
+        if unknown.is_empty() {
+            Ok(())
+        } else {
+            Err(CliError::Unknown(unknown))
+        }
+    }
+
+    /// `--only-protocol`: keep the reports whose protocol was asked for (failed addresses
+    /// are dropped too), noting on stderr how many addresses were skipped
+    fn filter_protocols(
+        &self,
+        results: Vec<(Address, Result<AnalyzeReport, AnalyzeError>)>,
+    ) -> Vec<(Address, Result<AnalyzeReport, AnalyzeError>)> {
+        if self.only_protocol.is_empty() {
+            return results;
+        }
+        let total = results.len();
+        let kept: Vec<_> = results
+            .into_iter()
+            .filter(|(_, result)| {
+                result.as_ref().is_ok_and(|report| {
+                    self.only_protocol
+                        .iter()
+                        .any(|p| dex_protocol_name(*p) == report.analysis.protocol)
+                })
+            })
+            .collect();
+        if !self.ndjson && !self.quiet {
+            eprintln!(
+                "skipped {} address(es) not matching --only-protocol",
+                total - kept.len()
+            );
+        }
+        kept
+    }
+
+    fn options(&self) -> Result<AnalyzeOptions, CliError> {
+        let quiet = self.ndjson || self.quiet;
+        Ok(AnalyzeOptions {
+            batch_size: self.batch_size,
+            concurrency: self.concurrency,
+            deadline: self.deadline_ms.map(Duration::from_millis),
+            chain_hint: self.chain_hint.or(self.chain.chain()),
+            chain_profile: self.chain.profile(),
+            pool_min_confidence: self.pool_min_confidence,
+            strict_push4: self.strict_push4,
+            include_bytecode: self.include_bytecode,
+            opcode_histogram: self.opcode_histogram,
+            include_disassembly: self.include_disassembly,
+            // Fetched code is runtime code already.
+            runtime_only: false,
+            impl_slot: self.impl_slot,
+            known_contracts: self.known_contracts,
+            skip_proxy_resolution: self.exclude_proxy_resolution_for.iter().copied().collect(),
+            resolve_onchain: self.resolve_onchain,
+            dynamic: self.dynamic,
+            scan_tokens: self.scan_tokens,
+            explain: self.explain,
+            combined: self.classifier == Classifier::Combined,
+            fingerprint_db: fingerprint_db(
+                self.fingerprint_db.as_deref(),
+                self.classifier == Classifier::Combined || self.explain,
+                quiet,
+            )?,
+            factory_allowlist: self
+                .factory_allowlist
+                .as_deref()
+                .map(factory_allowlist)
+                .transpose()?,
+            state_override: None,
+            block: self.block,
+        })
+    }
+}
+
+/// Batch progress on stderr: `[done/total] <address> (errors: n)` per completed address
+///
+/// Reports are only written once the run is over, so progress never interleaves with
+/// them. On a terminal the line is redrawn in place; otherwise one line per address.
+struct Progress {
+    total: usize,
+    done: AtomicUsize,
+    errors: AtomicUsize,
+    redraw: bool,
+}
+
+impl Progress {
+    fn new(total: usize) -> Self {
+        Self {
+            total,
+            done: AtomicUsize::new(0),
+            errors: AtomicUsize::new(0),
+            redraw: io::stderr().is_terminal(),
+        }
+    }
+}
+
+impl AnalyzerMetrics for Progress {
+    fn on_completed(&self, address: Address, result: &Result<AnalyzeReport, AnalyzeError>) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let errors = match result {
+            Ok(_) => self.errors.load(Ordering::Relaxed),
+            Err(_) => self.errors.fetch_add(1, Ordering::Relaxed) + 1,
+        };
+
+        let line = format!("[{done}/{}] {address:#x} (errors: {errors})", self.total);
+        let mut err = io::stderr().lock();
+        let _ = if !self.redraw {
+            writeln!(err, "{line}")
+        } else if done == self.total {
+            writeln!(err, "\r\x1b[2K{line}")
+        } else {
+            write!(err, "\r\x1b[2K{line}")
+        };
+    }
+}
+
+/// Fans batch events out to every sink it holds
+struct Sinks<'a>(Vec<&'a dyn AnalyzerMetrics>);
+
+impl AnalyzerMetrics for Sinks<'_> {
+    fn on_analyzed(&self, report: &AnalyzeReport, elapsed: Duration) {
+        self.0.iter().for_each(|s| s.on_analyzed(report, elapsed));
+    }
+
+    fn on_rpc_error(&self, address: Address, error: &AnalyzeError) {
+        self.0.iter().for_each(|s| s.on_rpc_error(address, error));
+    }
+
+    fn on_proxy_resolved(&self, proxy: Address, implementation: Address) {
+        self.0
+            .iter()
+            .for_each(|s| s.on_proxy_resolved(proxy, implementation));
+    }
+
+    fn on_completed(&self, address: Address, result: &Result<AnalyzeReport, AnalyzeError>) {
+        self.0.iter().for_each(|s| s.on_completed(address, result));
+    }
+
+    fn on_concurrency_changed(&self, limit: usize) {
+        self.0.iter().for_each(|s| s.on_concurrency_changed(limit));
+    }
+}
+
+/// Apply `--deadline-ms` (if given) to an analysis future
+async fn bounded<T>(
+    args: &AnalyzeArgs,
+    analysis: impl Future<Output = Result<T, AnalyzeError>>,
+) -> Result<T, AnalyzeError> {
+    match args.deadline_ms {
+        Some(ms) => with_deadline(Duration::from_millis(ms), analysis).await,
+        None => analysis.await,
+    }
+}
+
+async fn run_analyze(args: &AnalyzeArgs) -> Result<(), CliError> {
+    init_tracing(args);
+    validate_rpc_url(&args.rpc_url)?;
+    let address = args
+        .address
+        .as_deref()
+        .expect("clap requires --address or --addresses-file");
     let addr = parse_address_hex(address)?;
 
-    let report = analyze_address(rpc_url, addr).await?;
+    let mut report = bounded(
+        args,
+        analyze_address_with_options(&args.rpc_url, addr, &args.options()?),
+    )
+    .await?;
+    let unknown_check = args.check_unknown([&report]);
+    report.apply_name_style(args.name_style);
 
-    if json {
-        println!(
+    let mut out = args.output()?;
+    if args.json_output() {
+        writeln!(
+            out,
             "{}",
             serde_json::to_string(&report).expect("serialize report")
-        );
+        )?;
     } else {
-        print_human(&report);
+        write_human(&mut out, &report);
     }
+    args.finish_output(out, 1)?;
 
-    Ok(())
+    unknown_check
 }
 
-fn init_tracing(verbose: bool) {
-    let level = if verbose { "debug" } else { "info" };
-    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
-    let _ = tracing_subscriber::fmt().with_env_filter(filter).try_init();
+async fn run_batch(args: &AnalyzeArgs) -> Result<(), CliError> {
+    init_tracing(args);
+    validate_rpc_url(&args.rpc_url)?;
+    let contents = match &args.addresses_file {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|e| AnalyzeError::AddressesFile(format!("{path}: {e}")))?,
+        None => io::read_to_string(io::stdin())
+            .map_err(|e| AnalyzeError::AddressesFile(format!("stdin: {e}")))?,
+    };
+    let mut addresses = parse_address_list(&contents)?;
+    if let Some(limit) = args.limit {
+        addresses.truncate(limit);
+    }
+
+    let progress = Progress::new(addresses.len());
+    let summary = InMemoryMetrics::new();
+    let mut sinks = Sinks(Vec::new());
+    if !args.ndjson && !args.quiet {
+        sinks.0.push(&progress);
+    }
+    if args.summary {
+        sinks.0.push(&summary);
+    }
+    let results =
+        analyze_addresses_with_metrics(&args.rpc_url, &addresses, &args.options()?, &sinks).await?;
+
+    // `--fail-on-unknown` looks at every address, filtered out or not.
+    let unknown_check = args.check_unknown(results.iter().filter_map(|(_, r)| r.as_ref().ok()));
+    let mut results = args.filter_protocols(results);
+    if let Some(key) = args.sort {
+        sort_results(&mut results, key);
+    }
+    for (_, result) in &mut results {
+        if let Ok(report) = result {
+            report.apply_name_style(args.name_style);
+        }
+    }
+
+    let mut out = args.output()?;
+    let count = if args.dedupe_by_fingerprint {
+        write_groups(args, &mut out, &results)?
+    } else {
+        write_reports(args, &mut out, &results)?
+    };
+    if args.summary && args.json_output() {
+        writeln!(
+            out,
+            "{}",
+            summary_json(&summary.snapshot(), args.name_style)
+        )?;
+    }
+    args.finish_output(out, count)?;
+    if args.summary && !args.json_output() {
+        eprintln!("{}", summary_line(&summary.snapshot(), args.name_style));
+    }
+
+    unknown_check
 }
 
-fn print_human(report: &AnalyzeReport) {
-    write_human(&mut std::io::stdout(), report);
+/// One entry per address: the report, or its error
+fn write_reports(
+    args: &AnalyzeArgs,
+    out: &mut dyn Write,
+    results: &[(Address, Result<AnalyzeReport, AnalyzeError>)],
+) -> io::Result<usize> {
+    for (i, (address, result)) in results.iter().enumerate() {
+        if args.json_output() {
+            // JSON Lines: one object per address, errors included.
+            let line = match result {
+                Ok(report) => serde_json::to_string(report).expect("serialize report"),
+                Err(e) => serde_json::json!({
+                    "address": format!("{address:#x}"),
+                    "error": e.to_string(),
+                })
+                .to_string(),
+            };
+            writeln!(out, "{line}")?;
+            continue;
+        }
+
+        if i > 0 {
+            writeln!(out)?;
+        }
+        match result {
+            Ok(report) => write_human(out, report),
+            Err(e) => {
+                writeln!(out, "address: {address:#x}")?;
+                writeln!(out, "error: {e}")?;
+            }
+        }
+    }
+    Ok(results.len())
 }
 
-fn write_human<W: std::io::Write>(out: &mut W, report: &AnalyzeReport) {
-    let _ = writeln!(out, "address: {}", report.address);
+/// `--summary` as the final JSON Lines object
+fn summary_json(snapshot: &MetricsSnapshot, style: NameStyle) -> serde_json::Value {
+    let protocols: serde_json::Map<String, serde_json::Value> = snapshot
+        .protocols
+        .iter()
+        .map(|(protocol, count)| (style.apply(protocol), (*count).into()))
+        .collect();
+    serde_json::json!({
+        "summary": {
+            "total": snapshot.analyzed_total + snapshot.failed_total,
+            "protocols": protocols,
+            "proxies": snapshot.proxies_resolved,
+            "errors": snapshot.failed_total,
+            "unknown": snapshot.protocols.get("Unknown").copied().unwrap_or(0),
+        }
+    })
+}
+
+/// `--summary` as a one-line tally for stderr
+fn summary_line(snapshot: &MetricsSnapshot, style: NameStyle) -> String {
+    let protocols: Vec<String> = snapshot
+        .protocols
+        .iter()
+        .map(|(protocol, count)| format!("{} {count}", style.apply(protocol)))
+        .collect();
+    format!(
+        "summary: {} address(es) | {} | proxies {} | errors {} | unknown {}",
+        snapshot.analyzed_total + snapshot.failed_total,
+        protocols.join(", "),
+        snapshot.proxies_resolved,
+        snapshot.failed_total,
+        snapshot.protocols.get("Unknown").copied().unwrap_or(0),
+    )
+}
 
-    if report.is_eip1167_proxy {
-        let _ = writeln!(out, "eip1167_proxy: true");
-        if let Some(impl_addr) = &report.implementation_address {
-            let _ = writeln!(out, "implementation_address: {impl_addr}");
+fn run_analyze_code(args: &AnalyzeCodeArgs) -> Result<(), CliError> {
+    let address = match &args.address {
+        Some(address) => parse_address_hex(address)?,
+        None => Address::ZERO,
+    };
+    let input = match args.file.as_deref() {
+        None | Some("-") => {
+            let mut input = Vec::new();
+            io::stdin()
+                .read_to_end(&mut input)
+                .map_err(|e| AnalyzeError::BytecodeInput(format!("stdin: {e}")))?;
+            input
         }
+        Some(path) => {
+            std::fs::read(path).map_err(|e| AnalyzeError::BytecodeInput(format!("{path}: {e}")))?
+        }
+    };
+    let bytecode = decode_bytecode(&input, args.input_encoding)?;
+
+    let options = AnalyzeOptions {
+        explain: args.explain,
+        include_disassembly: args.include_disassembly,
+        known_contracts: args.known_contracts,
+        runtime_only: args.runtime_only,
+        combined: args.classifier == Classifier::Combined,
+        fingerprint_db: fingerprint_db(
+            args.fingerprint_db.as_deref(),
+            args.classifier == Classifier::Combined || args.explain,
+            false,
+        )?,
+        ..AnalyzeOptions::default()
+    };
+    if let Some(path) = &args.dump_normalized {
+        let fingerprint_options = FingerprintOptions {
+            strip_constructor: args.runtime_only,
+            ..FingerprintOptions::default()
+        };
+        let normalized = normalize_for_fingerprint(&bytecode, &fingerprint_options);
+        std::fs::write(path, format!("0x{}\n", alloy::hex::encode(normalized)))?;
+    }
+    let mut analysis = analyze_bytecode_with_options(address, &bytecode, &options);
+    analysis.apply_name_style(args.name_style);
+    let mut out = io::stdout().lock();
+    if args.json {
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&analysis).expect("serialize analysis")
+        )?;
     } else {
-        let _ = writeln!(out, "eip1167_proxy: false");
+        write_analysis(&mut out, &analysis);
     }
+    Ok(())
+}
+
+async fn run_code(args: &CodeArgs) -> Result<(), CliError> {
+    let address = parse_address_hex(&args.address)?;
+    let (_, bytecode) = fetch_resolved_code(&args.rpc_url, address, args.resolve_proxy).await?;
+    writeln!(io::stdout().lock(), "0x{}", alloy::hex::encode(bytecode))?;
+    Ok(())
+}
 
-    let _ = writeln!(out, "");
-    let _ = writeln!(out, "code_size: {}", report.analysis.code_size);
-    let _ = writeln!(out, "protocol: {}", report.analysis.protocol);
-    let _ = writeln!(out, "is_pool_likely: {}", report.analysis.is_pool_likely);
+async fn run_analyze_tx(args: &AnalyzeTxArgs) -> Result<(), CliError> {
+    let tx_hash = parse_tx_hash(&args.tx_hash)?;
+    let address = created_contract_address(&args.rpc_url, tx_hash).await?;
+    let mut report =
+        analyze_address_with_options(&args.rpc_url, address, &AnalyzeOptions::default()).await?;
+    report.apply_name_style(args.name_style);
 
-    if report.analysis.protocol == "Unknown" {
-        if let Some(cands) = &report.analysis.protocol_candidates {
-            if !cands.is_empty() {
-                let _ = writeln!(out, "protocol_candidates:");
-                for c in cands {
-                    let _ = writeln!(out, "  - {} (confidence {})", c.protocol, c.confidence);
+    let mut out = io::stdout().lock();
+    if args.json {
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&report).expect("serialize report")
+        )?;
+    } else {
+        writeln!(out, "created_by: {tx_hash:#x}")?;
+        write_human(&mut out, &report);
+    }
+    Ok(())
+}
+
+async fn run_compare_many(args: &CompareManyArgs) -> Result<(), CliError> {
+    validate_rpc_url(&args.rpc_url)?;
+    let suspect = parse_address_hex(&args.address)?;
+    let Some(candidates) = &args.candidates else {
+        let other = args
+            .compare_implementations
+            .as_deref()
+            .expect("clap requires --candidates or --compare-implementations");
+        return run_compare_implementations(args, suspect, parse_address_hex(other)?).await;
+    };
+    let contents = std::fs::read_to_string(candidates)
+        .map_err(|e| AnalyzeError::AddressesFile(format!("{candidates}: {e}")))?;
+    let candidates = parse_address_list(&contents)?;
+
+    let matches = compare_many(
+        &args.rpc_url,
+        suspect,
+        &candidates,
+        args.batch_size,
+        args.concurrency,
+    )
+    .await?;
+
+    let mut out = io::stdout().lock();
+    if args.json {
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&matches).expect("serialize matches")
+        )?;
+        return Ok(());
+    }
+    writeln!(out, "suspect: {suspect:#x}")?;
+    for m in &matches {
+        match (m.diff, m.similarity, &m.error) {
+            (Some(diff), Some(similarity), _) => writeln!(
+                out,
+                "{}  diff {diff:>4}  {}",
+                m.address,
+                similarity.as_str()
+            )?,
+            (_, _, error) => writeln!(
+                out,
+                "{}  error: {}",
+                m.address,
+                error.as_deref().unwrap_or("not compared")
+            )?,
+        }
+    }
+    Ok(())
+}
+
+async fn run_compare_implementations(
+    args: &CompareManyArgs,
+    a: Address,
+    b: Address,
+) -> Result<(), CliError> {
+    let comparison = compare_implementations(&args.rpc_url, a, b).await?;
+
+    let mut out = io::stdout().lock();
+    if args.json {
+        writeln!(
+            out,
+            "{}",
+            serde_json::to_string(&comparison).expect("serialize comparison")
+        )?;
+        return Ok(());
+    }
+    writeln!(
+        out,
+        "{} -> {}",
+        comparison.address_a, comparison.implementation_a
+    )?;
+    writeln!(
+        out,
+        "{} -> {}",
+        comparison.address_b, comparison.implementation_b
+    )?;
+    writeln!(out, "exact_match: {}", comparison.exact_match)?;
+    writeln!(
+        out,
+        "diff: {} ({})",
+        comparison.diff,
+        comparison.similarity.as_str()
+    )?;
+    Ok(())
+}
+
+/// Load `--fingerprint-db` if something `needed` it, noting skipped entries on stderr
+fn fingerprint_db(
+    path: Option<&str>,
+    needed: bool,
+    quiet: bool,
+) -> Result<Option<Arc<FingerprintDb>>, CliError> {
+    let Some(path) = path.filter(|_| needed) else {
+        return Ok(None);
+    };
+    let (db, skipped) =
+        load_fingerprint_db(Path::new(path)).map_err(|source| CliError::FingerprintDb {
+            path: path.to_string(),
+            source,
+        })?;
+    if !quiet {
+        for (entry, reason) in skipped {
+            eprintln!("skipped fingerprint db entry {entry}: {reason}");
+        }
+    }
+    Ok(Some(Arc::new(db)))
+}
+
+fn factory_allowlist(path: &str) -> Result<Arc<FactoryAllowlist>, CliError> {
+    let error = |reason: String| CliError::FactoryAllowlist {
+        path: path.to_string(),
+        reason,
+    };
+    let contents = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    FactoryAllowlist::parse(&contents)
+        .map(Arc::new)
+        .map_err(error)
+}
+
+/// `--sort`: stable sort, so ties keep their input order; failed addresses sort after
+/// every report (except by address, which they all have)
+fn sort_results(results: &mut [(Address, Result<AnalyzeReport, AnalyzeError>)], key: SortKey) {
+    match key {
+        SortKey::Address => results.sort_by_key(|(address, _)| *address),
+        SortKey::Protocol => {
+            results.sort_by(|(_, a), (_, b)| protocol_key(a).cmp(&protocol_key(b)))
+        }
+        SortKey::CodeSize => results.sort_by_key(|(_, result)| match result {
+            Ok(report) => (false, report.analysis.code_size),
+            Err(_) => (true, 0),
+        }),
+    }
+}
+
+/// `--sort protocol` key: reports by protocol name, failed addresses last
+fn protocol_key(result: &Result<AnalyzeReport, AnalyzeError>) -> (bool, &str) {
+    match result {
+        Ok(report) => (false, report.analysis.protocol.as_str()),
+        Err(_) => (true, ""),
+    }
+}
+
+/// `--dedupe-by-fingerprint` output: one block per group, then failed addresses
+///
+/// Returns the number of entries written.
+fn write_groups(
+    args: &AnalyzeArgs,
+    out: &mut dyn Write,
+    results: &[(Address, Result<AnalyzeReport, AnalyzeError>)],
+) -> io::Result<usize> {
+    let groups = group_by_fingerprint(results.iter().filter_map(|(_, r)| r.as_ref().ok()));
+    let errors: Vec<_> = results
+        .iter()
+        .filter_map(|(address, r)| r.as_ref().err().map(|e| (address, e)))
+        .collect();
+    let count = groups.len() + errors.len();
+
+    if args.json_output() {
+        for group in &groups {
+            writeln!(
+                out,
+                "{}",
+                serde_json::to_string(group).expect("serialize group")
+            )?;
+        }
+        for (address, e) in errors {
+            let line = serde_json::json!({
+                "address": format!("{address:#x}"),
+                "error": e.to_string(),
+            });
+            writeln!(out, "{line}")?;
+        }
+        return Ok(count);
+    }
+
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write_human(out, &group.report);
+        writeln!(out, "shared_by: {} addresses", group.addresses.len())?;
+        for address in &group.addresses {
+            writeln!(out, "  - {address}")?;
+        }
+    }
+    for (address, e) in errors {
+        writeln!(out)?;
+        writeln!(out, "address: {address:#x}")?;
+        writeln!(out, "error: {e}")?;
+    }
+    Ok(count)
+}
+
+fn run_protocols(args: &ProtocolsArgs) {
+    fn groups(spec: &FingerprintSpec) -> [(&'static str, &[SpecSelector]); 3] {
+        [
+            ("required", &spec.required),
+            ("forbidden", &spec.forbidden),
+            ("optional", &spec.optional),
+        ]
+    }
+    let specs: Vec<FingerprintSpec> = fingerprint_specs().collect();
+
+    if args.json {
+        let protocols: Vec<serde_json::Value> = specs
+            .iter()
+            .map(|spec| {
+                let mut entry = serde_json::json!({ "protocol": dex_protocol_name(spec.protocol) });
+                for (group, selectors) in groups(spec) {
+                    entry[group] = selectors
+                        .iter()
+                        .map(|s| {
+                            let mut selector = serde_json::json!({ "selector": s.selector.to_string(), "name": s.name });
+                            if group == "optional" {
+                                selector["weight"] = s.weight.into();
+                            }
+                            selector
+                        })
+                        .collect();
+                }
+                entry
+            })
+            .collect();
+        let out = serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocols": protocols,
+        });
+        println!("{out}");
+        return;
+    }
+
+    println!("which-dex {}", env!("CARGO_PKG_VERSION"));
+    for spec in &specs {
+        println!();
+        println!("{}", dex_protocol_name(spec.protocol));
+        for (group, selectors) in groups(spec) {
+            if selectors.is_empty() {
+                continue;
+            }
+            println!("  {group}:");
+            for s in selectors {
+                let (selector, name) = (s.selector, s.name.unwrap_or("?"));
+                match s.weight {
+                    weight if group == "optional" && weight != 1 => {
+                        println!("    {selector} {name} (weight {weight})")
+                    }
+                    _ => println!("    {selector} {name}"),
                 }
             }
         }
     }
+}
+
+fn init_tracing(args: &AnalyzeArgs) {
+    if args.ndjson {
+        return;
+    }
+    let level = if args.verbose { "debug" } else { "info" };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init();
+}
+
+fn write_human<W: Write + ?Sized>(out: &mut W, report: &AnalyzeReport) {
+    let _ = writeln!(out, "address: {}", report.address);
+
+    let _ = writeln!(out, "eip1167_proxy: {}", report.is_eip1167_proxy);
+    if let Some(kind) = report.proxy_kind {
+        let _ = writeln!(out, "proxy_kind: {}", kind.as_str());
+    }
+    // Also set for Safe proxies and EIP-7702 delegations
+    if let Some(impl_addr) = &report.implementation_address {
+        let _ = writeln!(out, "implementation_address: {impl_addr}");
+    }
+    if let Some(len) = report.immutable_args_len {
+        let _ = writeln!(out, "immutable_args_len: {len}");
+    }
+    if let Some(args) = &report.immutable_args {
+        let _ = writeln!(out, "immutable_args: {args}");
+    }
+    if let Some(slot) = &report.impl_slot {
+        let _ = writeln!(out, "impl_slot: {slot}");
+    }
+
+    let _ = writeln!(out);
+    write_analysis(out, &report.analysis);
+    if !report.token_risk_flags.is_empty() {
+        let _ = writeln!(out, "token_risk_flags:");
+        for flag in &report.token_risk_flags {
+            let _ = writeln!(out, "  {flag}");
+        }
+    }
 
     if let Some(proxy) = &report.proxy_analysis {
-        let _ = writeln!(out, "");
+        let _ = writeln!(out);
         let _ = writeln!(out, "proxy_bytecode_analysis:");
         let _ = writeln!(out, "  address: {}", proxy.address);
-        let _ = writeln!(out, "  code_size: {}", proxy.code_size);
-        let _ = writeln!(out, "  protocol: {}", proxy.protocol);
+        if !report.proxy_is_also_pool {
+            let _ = writeln!(out, "  code_size: {}", proxy.code_size);
+            let _ = writeln!(out, "  protocol: {}", proxy.protocol);
+            return;
+        }
+        // A proxy that is a pool in its own right gets the full analysis.
+        let _ = writeln!(out, "  proxy_is_also_pool: true");
+        let mut analysis = Vec::new();
+        write_analysis(&mut analysis, proxy);
+        for line in String::from_utf8_lossy(&analysis).lines() {
+            let _ = writeln!(out, "  {line}");
+        }
+    }
+}
+
+fn write_analysis<W: Write + ?Sized>(out: &mut W, analysis: &BytecodeAnalysis) {
+    let _ = writeln!(out, "code_size: {}", analysis.code_size);
+    let _ = writeln!(out, "protocol: {}", analysis.protocol);
+    if let Some(name) = &analysis.known_contract {
+        let _ = writeln!(out, "known_contract: {name}");
+    }
+    if let Some(subtype) = &analysis.pool_subtype {
+        let _ = writeln!(out, "pool_subtype: {subtype}");
+    }
+    if let Some(family) = &analysis.fork_family {
+        let _ = writeln!(out, "fork_family: {family}");
+    }
+    if let Some(curve) = &analysis.curve_type {
+        let _ = writeln!(out, "curve_type: {curve}");
+    }
+    if let Some(factory) = &analysis.factory {
+        let _ = writeln!(out, "factory: {factory}");
+    }
+    if let Some(verified) = analysis.factory_verified {
+        let _ = writeln!(out, "factory_verified: {verified}");
+    }
+    if let Some(kind) = &analysis.contract_kind {
+        let _ = writeln!(out, "contract_kind: {kind}");
+    }
+    if let Some(hint) = &analysis.contract_hint {
+        let _ = writeln!(out, "contract_hint: {hint}");
+    }
+    let _ = writeln!(out, "pool_confidence: {}", analysis.pool_confidence);
+    if let Some(confidence) = analysis.combined_confidence {
+        let _ = writeln!(out, "combined_confidence: {confidence:.2}");
+    }
+    if analysis.detection_method != "selector" {
+        let _ = writeln!(out, "detection_method: {}", analysis.detection_method);
+    }
+    let _ = writeln!(out, "is_pool_likely: {}", analysis.is_pool_likely);
+    if analysis.forwarding_suspected {
+        let _ = writeln!(
+            out,
+            "forwarding_suspected: true (advisory: looks like a thin wrapper forwarding to another contract)"
+        );
+    }
+    if let Some(probes) = &analysis.dynamic_probes {
+        let _ = writeln!(out, "dynamic_probes: {}", probes.join(", "));
+    }
+    if let Some(explanation) = &analysis.explanation {
+        let _ = writeln!(out, "explanation: {explanation}");
+    }
+    if let Some(lines) = &analysis.disassembly {
+        let _ = writeln!(out, "disassembly:");
+        for line in lines {
+            let _ = writeln!(out, "  {line}");
+        }
+    }
+
+    if analysis.protocol.parse() == Ok(DexProtocol::Unknown) {
+        if let Some(cands) = &analysis.protocol_candidates {
+            if !cands.is_empty() {
+                let _ = writeln!(out, "protocol_candidates:");
+                for c in cands {
+                    let _ = match c.confidence_percent() {
+                        Some(pct) => writeln!(
+                            out,
+                            "  - {} (confidence {}, {pct}%)",
+                            c.protocol, c.confidence
+                        ),
+                        None => writeln!(out, "  - {} (confidence {})", c.protocol, c.confidence),
+                    };
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,343 @@
+//! Solidity metadata trailer parsing
+//!
+//! solc appends a CBOR map to the runtime code, followed by its length as a
+//! big-endian `u16`. The map points at the contract's metadata JSON (by IPFS
+//! or Swarm hash), which source-verification services such as Sourcify index.
+//!
+//! vyper 0.3.x appends a CBOR signature too, but immutables are stored after the
+//! runtime code, so in deployed code the signature need not come last; see
+//! [`vyper_signature_offset`].
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// Decoded solc metadata trailer
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SolcMetadata {
+    /// IPFS multihash of the metadata JSON (`ipfs` key, solc >= 0.6.0)
+    pub ipfs: Option<Vec<u8>>,
+    /// Swarm hash (`bzzr0` key, solc 0.4.x - 0.5.8)
+    pub bzzr0: Option<Vec<u8>>,
+    /// Swarm hash (`bzzr1` key, solc 0.5.9 - 0.5.x)
+    pub bzzr1: Option<Vec<u8>>,
+    /// Compiler version (`solc` key), e.g. `0.8.19`
+    pub solc: Option<String>,
+    /// Compiled with experimental features
+    pub experimental: bool,
+}
+
+impl SolcMetadata {
+    /// IPFS CIDv0 (`Qm...`) of the metadata JSON
+    pub fn ipfs_cid(&self) -> Option<String> {
+        self.ipfs.as_deref().map(base58_encode)
+    }
+
+    /// Hex Swarm hash of the metadata JSON (`bzzr1` preferred over `bzzr0`)
+    pub fn swarm_hash(&self) -> Option<String> {
+        self.bzzr1
+            .as_deref()
+            .or(self.bzzr0.as_deref())
+            .map(hex::encode)
+    }
+}
+
+/// Parse the metadata trailer at the end of runtime bytecode
+///
+/// Returns `None` when the code has no well-formed CBOR map trailer (vyper >= 0.3.10,
+/// stripped metadata, non-solc compilers).
+pub fn parse_metadata(bytecode: &[u8]) -> Option<SolcMetadata> {
+    metadata_trailer(bytecode).map(|(_, metadata)| metadata)
+}
+
+/// Byte length of the metadata trailer (CBOR map plus its 2-byte length suffix), if
+/// the code ends in a well-formed one
+///
+/// The suffix must point at a CBOR map that spans exactly up to it, so marker-like
+/// bytes (`0xa264...`) elsewhere in the code or inside the map never move the cut.
+pub fn metadata_trailer_len(bytecode: &[u8]) -> Option<usize> {
+    metadata_trailer(bytecode).map(|(len, _)| len)
+}
+
+/// Byte length of the compiler metadata to drop from the end of the code: the solc
+/// trailer ([`metadata_trailer_len`]), or a vyper signature and the immutables after it
+pub fn trailing_metadata_len(bytecode: &[u8]) -> Option<usize> {
+    metadata_trailer_len(bytecode)
+        .or_else(|| vyper_signature_offset(bytecode).map(|at| bytecode.len() - at))
+}
+
+/// Offset of the vyper 0.3.x signature (`{"vyper": [major, minor, patch]}` plus its
+/// length `0x000b`) that ends the runtime code proper
+///
+/// vyper deploys immutables as whole 32-byte words after the runtime code, so the
+/// signature is looked for only at word boundaries from the end. vyper >= 0.3.10
+/// appends its metadata to the initcode only, leaving nothing to find here.
+pub fn vyper_signature_offset(bytecode: &[u8]) -> Option<usize> {
+    const PREFIX: &[u8] = b"\xa1\x65vyper\x83";
+    const SIGNATURE_LEN: usize = PREFIX.len() + 5;
+
+    let last = bytecode.len().checked_sub(SIGNATURE_LEN)?;
+    (0..=last).rev().step_by(32).find(|&at| {
+        let signature = &bytecode[at..at + SIGNATURE_LEN];
+        let (version, len) = signature[PREFIX.len()..].split_at(3);
+        // Version parts are small CBOR uints, encoded in their initial byte
+        signature.starts_with(PREFIX) && version.iter().all(|b| *b < 24) && len == [0x00, 0x0b]
+    })
+}
+
+fn metadata_trailer(bytecode: &[u8]) -> Option<(usize, SolcMetadata)> {
+    let len_at = bytecode.len().checked_sub(2)?;
+    let len = u16::from_be_bytes([bytecode[len_at], bytecode[len_at + 1]]) as usize;
+    let cbor = &bytecode[len_at.checked_sub(len)?..len_at];
+
+    let mut reader = Cbor { data: cbor, pos: 0 };
+    let (major, entries) = reader.head()?;
+    if major != MAJOR_MAP {
+        return None;
+    }
+
+    let mut metadata = SolcMetadata::default();
+    for _ in 0..entries {
+        let key = reader.text()?;
+        match reader.value()? {
+            Value::Bytes(bytes) => match key {
+                "ipfs" => metadata.ipfs = Some(bytes.to_vec()),
+                "bzzr0" => metadata.bzzr0 = Some(bytes.to_vec()),
+                "bzzr1" => metadata.bzzr1 = Some(bytes.to_vec()),
+                // Release builds encode the version as 3 bytes: major, minor, patch
+                "solc" if bytes.len() == 3 => {
+                    metadata.solc = Some(alloc::format!("{}.{}.{}", bytes[0], bytes[1], bytes[2]));
+                }
+                _ => {}
+            },
+            // Prerelease builds encode the full version string
+            Value::Text(text) if key == "solc" => metadata.solc = Some(String::from(text)),
+            Value::Bool(flag) if key == "experimental" => metadata.experimental = flag,
+            _ => {}
+        }
+    }
+
+    // The whole trailer must be the map; anything else means we misread the length.
+    (reader.pos == cbor.len()).then_some((len + 2, metadata))
+}
+
+const MAJOR_UINT: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_SIMPLE: u8 = 7;
+
+/// Deepest array/map nesting accepted inside the trailer map (solc and vyper use 1)
+const MAX_NESTING: usize = 4;
+
+enum Value<'a> {
+    Bytes(&'a [u8]),
+    Text(&'a str),
+    Bool(bool),
+    Other,
+}
+
+/// Just enough CBOR for solc trailers: definite-length maps of text keys
+struct Cbor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cbor<'a> {
+    /// Major type and argument of the next item
+    fn head(&mut self) -> Option<(u8, u64)> {
+        let initial = *self.data.get(self.pos)?;
+        self.pos += 1;
+        let (major, info) = (initial >> 5, initial & 0x1f);
+
+        let arg = match info {
+            0..=23 => u64::from(info),
+            24 => u64::from(*self.take(1)?.first()?),
+            25 => u64::from(u16::from_be_bytes(self.take(2)?.try_into().ok()?)),
+            26 => u64::from(u32::from_be_bytes(self.take(4)?.try_into().ok()?)),
+            27 => u64::from_be_bytes(self.take(8)?.try_into().ok()?),
+            _ => return None,
+        };
+        Some((major, arg))
+    }
+
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(n)?)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn text(&mut self) -> Option<&'a str> {
+        match self.value()? {
+            Value::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    fn value(&mut self) -> Option<Value<'a>> {
+        self.nested_value(0)
+    }
+
+    /// Next item, `depth` arrays/maps deep; anything nested past [`MAX_NESTING`] is
+    /// rejected so crafted trailers can't recurse without bound
+    fn nested_value(&mut self, depth: usize) -> Option<Value<'a>> {
+        let (major, arg) = self.head()?;
+        match major {
+            MAJOR_UINT => Some(Value::Other),
+            MAJOR_BYTES => Some(Value::Bytes(self.take(usize::try_from(arg).ok()?)?)),
+            MAJOR_TEXT => {
+                let bytes = self.take(usize::try_from(arg).ok()?)?;
+                core::str::from_utf8(bytes).ok().map(Value::Text)
+            }
+            // vyper 0.3.x versions: `{"vyper": [0, 3, 7]}`
+            MAJOR_ARRAY | MAJOR_MAP if depth < MAX_NESTING => {
+                let items = if major == MAJOR_MAP {
+                    arg.checked_mul(2)?
+                } else {
+                    arg
+                };
+                for _ in 0..items {
+                    self.nested_value(depth + 1)?;
+                }
+                Some(Value::Other)
+            }
+            MAJOR_SIMPLE => match arg {
+                20 => Some(Value::Bool(false)),
+                21 => Some(Value::Bool(true)),
+                _ => Some(Value::Other),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Bitcoin-alphabet base58, as used by IPFS CIDv0
+fn base58_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    // Little-endian base-58 digits, updated byte by byte.
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in bytes {
+        let mut carry = u32::from(byte);
+        for digit in digits.iter_mut() {
+            carry += u32::from(*digit) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    let zeros = bytes.iter().take_while(|b| **b == 0).count();
+    core::iter::repeat_n(b'1', zeros)
+        .chain(digits.iter().rev().map(|d| ALPHABET[*d as usize]))
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base58_encode() {
+        assert_eq!(base58_encode(b""), "");
+        assert_eq!(base58_encode(&[0, 0, 1]), "112");
+        assert_eq!(base58_encode(b"hello world"), "StV1DL6CwTryKyV");
+    }
+
+    #[test]
+    fn test_parse_metadata_versions() {
+        // solc 0.8.19 trailer: {"ipfs": <34 bytes>, "solc": 0x000813}, length 0x0033
+        let code = hex::decode(concat!(
+            "6080",
+            "a2646970667358221220",
+            "76e4352151e43f398acac398c2ecc194fc99a560bee567db1d6078f7934cabf7",
+            "64736f6c6343000813",
+            "0033",
+        ))
+        .unwrap();
+
+        let metadata = parse_metadata(&code).unwrap();
+        assert_eq!(metadata.solc.as_deref(), Some("0.8.19"));
+        assert_eq!(
+            metadata.ipfs_cid().as_deref(),
+            Some("QmWLkeQeobHkwM4JomB3Eh9JkFDKcWSCMcApTXpMX2yfjC")
+        );
+        assert!(!metadata.experimental);
+    }
+
+    #[test]
+    fn test_parse_metadata_prerelease_and_experimental() {
+        // {"solc": "0.8.20-nightly", "experimental": true}, length 0x0023
+        let code = hex::decode(concat!(
+            "a2",
+            "64736f6c63",
+            "6e302e382e32302d6e696768746c79",
+            "6c6578706572696d656e74616c",
+            "f5",
+            "0023",
+        ))
+        .unwrap();
+
+        let metadata = parse_metadata(&code).unwrap();
+        assert_eq!(metadata.solc.as_deref(), Some("0.8.20-nightly"));
+        assert!(metadata.experimental);
+        assert_eq!(metadata.ipfs_cid(), None);
+    }
+
+    #[test]
+    fn test_parse_metadata_rejects_garbage() {
+        assert_eq!(parse_metadata(&[]), None);
+        assert_eq!(parse_metadata(&[0x60, 0x80, 0x60, 0x40]), None);
+        // Length points past the start of the code.
+        assert_eq!(parse_metadata(&[0xa1, 0x00, 0x33]), None);
+    }
+
+    #[test]
+    fn test_parse_metadata_rejects_deep_nesting() {
+        // {"vyper": [[[...[0]...]]]}, nested far deeper than any compiler emits
+        let mut cbor = hex::decode("a1657679706572").unwrap();
+        cbor.extend(core::iter::repeat_n(0x81, 60_000));
+        cbor.push(0x00);
+        let mut code = hex::decode("6080").unwrap();
+        code.extend_from_slice(&cbor);
+        code.extend_from_slice(&(cbor.len() as u16).to_be_bytes());
+        assert_eq!(parse_metadata(&code), None);
+
+        // Shallow nesting still parses: {"vyper": [[0, 3], {"a": 7}]}
+        let cbor = hex::decode("a165767970657282820003a1616107").unwrap();
+        let mut code = hex::decode("6080").unwrap();
+        code.extend_from_slice(&cbor);
+        code.extend_from_slice(&(cbor.len() as u16).to_be_bytes());
+        assert!(parse_metadata(&code).is_some());
+    }
+
+    #[test]
+    fn test_metadata_trailer_len_vyper() {
+        // vyper 0.3.7: {"vyper": [0, 3, 7]}, length 0x000b
+        let code = hex::decode("6080a165767970657283000307000b").unwrap();
+        assert_eq!(metadata_trailer_len(&code), Some(13));
+        assert_eq!(parse_metadata(&code).unwrap().solc, None);
+    }
+
+    #[test]
+    fn test_vyper_signature_before_immutables() {
+        let code = hex::decode("6080a165767970657283000307000b").unwrap();
+        assert_eq!(vyper_signature_offset(&code), Some(2));
+        assert_eq!(trailing_metadata_len(&code), Some(13));
+
+        // Two immutables after the signature: the CBOR trailer is gone from the end.
+        let deployed = [&code[..], &[0x11; 64]].concat();
+        assert_eq!(metadata_trailer_len(&deployed), None);
+        assert_eq!(vyper_signature_offset(&deployed), Some(2));
+        assert_eq!(trailing_metadata_len(&deployed), Some(77));
+
+        // Not a whole number of words after it: data that only looks like a signature.
+        let misaligned = [&code[..], &[0x11; 33]].concat();
+        assert_eq!(vyper_signature_offset(&misaligned), None);
+        assert_eq!(trailing_metadata_len(&misaligned), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Metrics hook for batch runs
+//!
+//! [`analyze_addresses_with_metrics`](crate::batch::analyze_addresses_with_metrics)
+//! reports every outcome to an [`AnalyzerMetrics`] sink, so a service can feed
+//! its own metrics stack without this crate depending on one.
+//! [`InMemoryMetrics`] is a ready-made sink that can be dumped as JSON.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use alloy::primitives::Address;
+use serde::Serialize;
+
+use crate::analyze::{AnalyzeError, AnalyzeReport};
+
+/// Sink for analysis events; every method defaults to a no-op
+pub trait AnalyzerMetrics: Send + Sync {
+    /// An address was analyzed; `elapsed` is the wall time of the batch it was fetched in
+    fn on_analyzed(&self, _report: &AnalyzeReport, _elapsed: Duration) {}
+
+    /// An RPC call for an address failed
+    fn on_rpc_error(&self, _address: Address, _error: &AnalyzeError) {}
+
+    /// An EIP-1167 proxy was resolved to its implementation (or a Safe proxy to its singleton)
+    fn on_proxy_resolved(&self, _proxy: Address, _implementation: Address) {}
+
+    /// An address is done, successfully or not; called once per input address, in
+    /// input order, after the more specific events above
+    fn on_completed(&self, _address: Address, _result: &Result<AnalyzeReport, AnalyzeError>) {}
+
+    /// `Concurrency::Auto` moved its limit on batches in flight to `limit`
+    fn on_concurrency_changed(&self, _limit: usize) {}
+}
+
+/// Metrics sink that drops every event
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopMetrics;
+
+impl AnalyzerMetrics for NoopMetrics {}
+
+/// Upper bounds (inclusive, milliseconds) of the latency histogram buckets
+pub const LATENCY_BUCKETS_MS: &[u64] = &[10, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// Counters collected by [`InMemoryMetrics`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MetricsSnapshot {
+    pub analyzed_total: u64,
+    /// Analyzed addresses per reported `protocol`
+    pub protocols: BTreeMap<String, u64>,
+    pub proxies_resolved: u64,
+    pub rpc_errors: u64,
+    /// Addresses that failed for any reason (RPC errors, no bytecode, ...)
+    pub failed_total: u64,
+    pub latency_ms: LatencyHistogram,
+}
+
+/// Prometheus-style histogram: cumulative bucket counts plus sum and count
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LatencyHistogram {
+    /// `(le, count)` pairs, one per [`LATENCY_BUCKETS_MS`] bound; `count` is cumulative
+    pub buckets: Vec<(u64, u64)>,
+    pub sum: u64,
+    pub count: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: LATENCY_BUCKETS_MS.iter().map(|le| (*le, 0)).collect(),
+            sum: 0,
+            count: 0,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        for (le, count) in &mut self.buckets {
+            if ms <= *le {
+                *count += 1;
+            }
+        }
+        self.sum = self.sum.saturating_add(ms);
+        self.count += 1;
+    }
+}
+
+/// Thread-safe in-memory metrics sink
+#[derive(Debug, Default)]
+pub struct InMemoryMetrics {
+    inner: Mutex<MetricsSnapshot>,
+}
+
+impl InMemoryMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy of the current counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.lock().clone()
+    }
+
+    /// Current counters as a JSON object
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.snapshot()).expect("serialize metrics")
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MetricsSnapshot> {
+        // Counters stay meaningful even if a panicking thread held the lock.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl AnalyzerMetrics for InMemoryMetrics {
+    fn on_analyzed(&self, report: &AnalyzeReport, elapsed: Duration) {
+        let mut m = self.lock();
+        m.analyzed_total += 1;
+        *m.protocols
+            .entry(report.analysis.protocol.clone())
+            .or_default() += 1;
+        m.latency_ms.observe(elapsed);
+    }
+
+    fn on_rpc_error(&self, _address: Address, _error: &AnalyzeError) {
+        self.lock().rpc_errors += 1;
+    }
+
+    fn on_proxy_resolved(&self, _proxy: Address, _implementation: Address) {
+        self.lock().proxies_resolved += 1;
+    }
+
+    fn on_completed(&self, _address: Address, result: &Result<AnalyzeReport, AnalyzeError>) {
+        if result.is_err() {
+            self.lock().failed_total += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_histogram_is_cumulative() {
+        let mut h = LatencyHistogram::default();
+        h.observe(Duration::from_millis(7));
+        h.observe(Duration::from_millis(300));
+        h.observe(Duration::from_secs(60));
+
+        assert_eq!(h.count, 3);
+        assert_eq!(h.sum, 60_307);
+        assert_eq!(h.buckets[0], (10, 1));
+        assert_eq!(h.buckets[3], (250, 1));
+        assert_eq!(h.buckets[4], (500, 2));
+        assert_eq!(*h.buckets.last().unwrap(), (10_000, 2));
+    }
+}
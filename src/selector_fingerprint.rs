@@ -3,7 +3,12 @@
 //! This module identifies DEX protocols by checking which function selectors
 //! are present in contract bytecode. Each protocol has a unique "selector signature".
 
-use alloy::primitives::keccak256;
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Range;
+use core::str::FromStr;
+
+use alloy_primitives::keccak256;
 
 /// DEX protocol type identified by interface
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -14,12 +19,20 @@ pub enum DexProtocol {
     UniswapV3,
     /// Solidly / Velodrome / Aerodrome
     Solidly,
+    /// Solidly-derived concentrated liquidity: V3-style `slot0()` pools that keep the
+    /// legacy Solidly `stable()` (and often `getReserves()`) selectors
+    SolidlyCL,
     /// Algebra (legacy CLAMM, pre-plugin era; e.g., early Algebra v1.x)
     AlgebraLegacyV1,
     /// Algebra (legacy CLAMM with plugin(); broadly v1.9+ and similar deployments)
     AlgebraLegacyV1_9Plus,
     /// Algebra Integral (aka AMM v4 / "V4" in Algebra docs; plugin + getFee())
     AlgebraIntegral,
+    /// Balancer V2 pool (tokens and balances live in the Vault; see [`balancer_pool_subtype`])
+    BalancerV2Pool,
+    /// WOOFi synthetic proactive market maker: oracle-priced, no reserves to read
+    /// (always reported with [`ContractKind::Pmm`])
+    WooFi,
     /// Unknown protocol
     Unknown,
 }
@@ -35,11 +48,33 @@ impl DexProtocol {
         matches!(
             self,
             Self::UniswapV3
+                | Self::SolidlyCL
                 | Self::AlgebraLegacyV1
                 | Self::AlgebraLegacyV1_9Plus
                 | Self::AlgebraIntegral
         )
     }
+
+    /// Check if this is a proactive market maker (quotes from an oracle, not reserves)
+    pub fn is_pmm(&self) -> bool {
+        matches!(self, Self::WooFi)
+    }
+
+    /// Canonical name, e.g. `UniswapV3`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::UniswapV2 => "UniswapV2",
+            Self::UniswapV3 => "UniswapV3",
+            Self::Solidly => "Solidly",
+            Self::SolidlyCL => "SolidlyCL",
+            Self::AlgebraLegacyV1 => "AlgebraLegacyV1",
+            Self::AlgebraLegacyV1_9Plus => "AlgebraLegacyV1_9Plus",
+            Self::AlgebraIntegral => "AlgebraIntegral",
+            Self::BalancerV2Pool => "BalancerV2Pool",
+            Self::WooFi => "WooFi",
+            Self::Unknown => "Unknown",
+        }
+    }
 }
 
 /// Function selector (first 4 bytes of keccak256(signature))
@@ -58,6 +93,17 @@ impl Selector {
         Self([hash[0], hash[1], hash[2], hash[3]])
     }
 
+    /// Parse a selector from hex (`0x` prefix optional), the inverse of its `Display`
+    pub fn from_hex(s: &str) -> Result<Self, SelectorParseError> {
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        let mut bytes = [0u8; 4];
+        if digits.len() != 2 * bytes.len() {
+            return Err(SelectorParseError::InvalidLength(digits.len()));
+        }
+        hex::decode_to_slice(digits, &mut bytes).map_err(|_| SelectorParseError::InvalidHex)?;
+        Ok(Self(bytes))
+    }
+
     /// Get the selector bytes
     pub fn as_bytes(&self) -> &[u8; 4] {
         &self.0
@@ -67,14 +113,115 @@ impl Selector {
     pub fn exists_in(&self, bytecode: &[u8]) -> bool {
         bytecode.windows(4).any(|w| w == self.0)
     }
+
+    /// Byte offsets of every occurrence of this selector in bytecode (overlaps included)
+    ///
+    /// Pair with [`dispatcher_region`] to tell dispatcher comparisons from
+    /// coincidental matches elsewhere in the code.
+    pub fn positions_in(&self, bytecode: &[u8]) -> Vec<usize> {
+        bytecode
+            .windows(4)
+            .enumerate()
+            .filter(|(_, w)| *w == self.0)
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+/// Every distinct 4-byte sequence of some bytecode, collected in one pass
+///
+/// Checking many selectors against one contract with [`Selector::exists_in`] rescans the
+/// code per selector; build a set once and each check becomes a binary search. (A
+/// sorted `Vec` rather than a `HashSet`, which the `no_std` core doesn't have.)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectorSet(Vec<[u8; 4]>);
+
+impl SelectorSet {
+    /// Every 4-byte window of `bytecode`: `contains` agrees with [`Selector::exists_in`]
+    pub fn from_bytecode(bytecode: &[u8]) -> Self {
+        bytecode
+            .windows(4)
+            .map(|w| Selector([w[0], w[1], w[2], w[3]]))
+            .collect()
+    }
+
+    pub fn contains(&self, selector: &Selector) -> bool {
+        self.0.binary_search(&selector.0).is_ok()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
 }
 
-impl std::fmt::Display for Selector {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl FromIterator<Selector> for SelectorSet {
+    fn from_iter<I: IntoIterator<Item = Selector>>(iter: I) -> Self {
+        let mut selectors: Vec<[u8; 4]> = iter.into_iter().map(|s| s.0).collect();
+        selectors.sort_unstable();
+        selectors.dedup();
+        Self(selectors)
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "0x{}", hex::encode(self.0))
     }
 }
 
+impl From<[u8; 4]> for Selector {
+    fn from(bytes: [u8; 4]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Selector> for [u8; 4] {
+    fn from(selector: Selector) -> Self {
+        selector.0
+    }
+}
+
+impl AsRef<[u8]> for Selector {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FromStr for Selector {
+    type Err = SelectorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+/// Error parsing a [`Selector`] from hex
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectorParseError {
+    /// Expected exactly 8 hex digits; holds the number found
+    InvalidLength(usize),
+    /// Input contains a non-hex character
+    InvalidHex,
+}
+
+impl fmt::Display for SelectorParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidLength(len) => {
+                write!(f, "selector must be 8 hex digits (4 bytes), got {len}")
+            }
+            Self::InvalidHex => write!(f, "selector contains non-hex characters"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SelectorParseError {}
+
 /// Well-known function selectors for DEX protocols
 pub mod selectors {
     use super::Selector;
@@ -84,12 +231,22 @@ pub mod selectors {
     pub const TOKEN1: Selector = Selector::from_bytes([0xd2, 0x12, 0x20, 0xa7]); // token1()
     pub const FACTORY: Selector = Selector::from_bytes([0xc4, 0x5a, 0x01, 0x55]); // factory()
 
+    // ERC-20 (V2-style pairs are their own LP token) and the pool swap entry points
+    pub const TRANSFER: Selector = Selector::from_bytes([0xa9, 0x05, 0x9c, 0xbb]); // transfer(address,uint256)
+    pub const BALANCE_OF: Selector = Selector::from_bytes([0x70, 0xa0, 0x82, 0x31]); // balanceOf(address)
+    pub const TOTAL_SUPPLY: Selector = Selector::from_bytes([0x18, 0x16, 0x0d, 0xdd]); // totalSupply()
+    pub const SWAP_V2: Selector = Selector::from_bytes([0x02, 0x2c, 0x0d, 0x9f]); // swap(uint256,uint256,address,bytes)
+    pub const SWAP_V3: Selector = Selector::from_bytes([0x12, 0x8a, 0xcb, 0x08]); // swap(address,bool,int256,uint160,bytes)
+
     // UniswapV2-style (many forks share the exact same interface)
     pub const GET_RESERVES: Selector = Selector::from_bytes([0x09, 0x02, 0xf1, 0xac]); // getReserves()
     pub const K_LAST: Selector = Selector::from_bytes([0x74, 0x64, 0xfc, 0x3d]); // kLast()
     pub const PRICE0_CUMULATIVE_LAST: Selector = Selector::from_bytes([0x59, 0x09, 0xc0, 0xd5]); // price0CumulativeLast()
     pub const PRICE1_CUMULATIVE_LAST: Selector = Selector::from_bytes([0x5a, 0x3d, 0x54, 0x93]); // price1CumulativeLast()
 
+    // UniswapV2 fork-specific pair selectors
+    pub const TOKEN0_FEE_PERCENT: Selector = Selector::from_bytes([0x62, 0xec, 0xec, 0x03]); // token0FeePercent() (Camelot V2)
+
     // UniswapV3-style concentrated liquidity
     pub const SLOT0: Selector = Selector::from_bytes([0x38, 0x50, 0xc7, 0xbd]); // slot0()
     pub const FEE: Selector = Selector::from_bytes([0xdd, 0xca, 0x3f, 0x43]); // fee()
@@ -100,6 +257,7 @@ pub mod selectors {
 
     // Solidly / Velodrome / Aerodrome
     pub const STABLE: Selector = Selector::from_bytes([0x22, 0xbe, 0x3d, 0xe1]); // stable()
+    pub const GET_AMOUNT_OUT: Selector = Selector::from_bytes([0xf1, 0x40, 0xa3, 0x5a]); // getAmountOut(uint256,address)
     pub const CLAIM_FEES: Selector = Selector::from_bytes([0xd2, 0x94, 0xf0, 0x93]); // claimFees()
     pub const CURRENT_CUMULATIVE_PRICES: Selector = Selector::from_bytes([0x1d, 0xf8, 0xc7, 0x17]); // currentCumulativePrices()
 
@@ -124,38 +282,331 @@ pub mod selectors {
     pub const PLUGIN_CONFIG: Selector = Selector::from_bytes([0x58, 0x1a, 0x75, 0x99]); // pluginConfig()
     pub const IS_UNLOCKED: Selector = Selector::from_bytes([0x83, 0x80, 0xed, 0xb7]);
     // isUnlocked()
+
+    // Balancer V2 pools (all pool types)
+    pub const GET_POOL_ID: Selector = Selector::from_bytes([0x38, 0xff, 0xf2, 0xd0]); // getPoolId()
+    pub const GET_VAULT: Selector = Selector::from_bytes([0x8d, 0x92, 0x8a, 0xf8]); // getVault()
+    pub const GET_SWAP_FEE_PERCENTAGE: Selector = Selector::from_bytes([0x55, 0xc6, 0x76, 0x28]); // getSwapFeePercentage()
+
+    // Balancer V2 pool math refinements
+    pub const GET_NORMALIZED_WEIGHTS: Selector = Selector::from_bytes([0xf8, 0x9f, 0x27, 0xed]); // getNormalizedWeights()
+    pub const GET_AMPLIFICATION_PARAMETER: Selector =
+        Selector::from_bytes([0x6d, 0xac, 0xcf, 0xfa]); // getAmplificationParameter()
+    pub const GET_BPT_INDEX: Selector = Selector::from_bytes([0x82, 0x68, 0x7a, 0x56]);
+    // getBptIndex()
+
+    // GMX-style perp vaults (not AMMs)
+    pub const GET_MIN_PRICE: Selector = Selector::from_bytes([0x81, 0xa6, 0x12, 0xd6]); // getMinPrice(address)
+    pub const GET_MAX_PRICE: Selector = Selector::from_bytes([0xe1, 0x24, 0xe6, 0xd2]); // getMaxPrice(address)
+    pub const USDG: Selector = Selector::from_bytes([0xf5, 0xb9, 0x1b, 0x7b]); // usdg()
+
+    // WOOFi PMM (not an AMM). WooPPV2 is a single pool per chain holding every listed
+    // token, deployed at one address across BSC, Avalanche, Polygon, Arbitrum,
+    // Optimism, Base and other chains. Legacy WooPP V1 pools (one per quote token,
+    // sellBase/sellQuote with querySellBase/querySellQuote) lack querySwap.
+    pub const QUERY_SWAP: Selector = Selector::from_bytes([0xe9, 0x48, 0x03, 0xf4]); // querySwap(address,address,uint256)
+    pub const TRY_QUERY: Selector = Selector::from_bytes([0xce, 0x82, 0x4f, 0x19]); // tryQuery(address,address,uint256)
+    pub const SELL_BASE: Selector = Selector::from_bytes([0x68, 0x46, 0xfb, 0x50]); // sellBase(address,uint256,uint256,address,address)
+    pub const SELL_QUOTE: Selector = Selector::from_bytes([0xf3, 0x28, 0x7c, 0x2f]); // sellQuote(address,uint256,uint256,address,address)
+
+    // Safe (Gnosis Safe) multisig (not a pool). Safes are deployed as small proxies
+    // that answer masterCopy() themselves and delegate everything else to a singleton.
+    pub const GET_THRESHOLD: Selector = Selector::from_bytes([0xe7, 0x52, 0x35, 0xb8]); // getThreshold()
+    pub const GET_OWNERS: Selector = Selector::from_bytes([0xa0, 0xe6, 0x7e, 0x2b]); // getOwners()
+    pub const IS_OWNER: Selector = Selector::from_bytes([0x2f, 0x54, 0xbf, 0x6e]); // isOwner(address)
+    pub const MASTER_COPY: Selector = Selector::from_bytes([0xa6, 0x19, 0x48, 0x6e]); // masterCopy()
+
+    // Pool factories (not pools; V3 factories embed the full pool creation code)
+    pub const ALL_PAIRS_LENGTH: Selector = Selector::from_bytes([0x57, 0x4f, 0x2b, 0xa3]); // allPairsLength()
+    pub const GET_PAIR: Selector = Selector::from_bytes([0xe6, 0xa4, 0x39, 0x05]); // getPair(address,address)
+    pub const CREATE_PAIR: Selector = Selector::from_bytes([0xc9, 0xc6, 0x53, 0x96]); // createPair(address,address)
+    pub const GET_POOL_V3: Selector = Selector::from_bytes([0x16, 0x98, 0xee, 0x82]); // getPool(address,address,uint24)
+    pub const CREATE_POOL_V3: Selector = Selector::from_bytes([0xa1, 0x67, 0x12, 0x95]); // createPool(address,address,uint24)
+    pub const GET_POOL_SOLIDLY: Selector = Selector::from_bytes([0x79, 0xbc, 0x57, 0xd5]); // getPool(address,address,bool)
+    pub const CREATE_POOL_SOLIDLY: Selector = Selector::from_bytes([0x36, 0xbf, 0x95, 0xa0]); // createPool(address,address,bool)
+
+    // Owner controls over who may transfer and how much (tokens, not pools); common in
+    // honeypot tokens whose pairs can be bought into but not sold out of
+    pub const BLACKLIST: Selector = Selector::from_bytes([0xf9, 0xf9, 0x2b, 0xe4]); // blacklist(address)
+    pub const ADD_TO_BLACKLIST: Selector = Selector::from_bytes([0x44, 0x33, 0x7e, 0xa1]); // addToBlacklist(address)
+    pub const IS_BLACKLISTED: Selector = Selector::from_bytes([0xfe, 0x57, 0x5a, 0x87]); // isBlacklisted(address)
+    pub const WHITELIST: Selector = Selector::from_bytes([0x9b, 0x19, 0x25, 0x1a]); // whitelist(address)
+    pub const SET_WHITELIST: Selector = Selector::from_bytes([0x53, 0xd6, 0xfd, 0x59]); // setWhitelist(address,bool)
+    pub const SET_MAX_TX_AMOUNT: Selector = Selector::from_bytes([0xec, 0x28, 0x43, 0x8a]); // setMaxTxAmount(uint256)
+
+    // Routing infrastructure that shows up next to pools: wrapped native tokens (WETH9
+    // and its copies on other chains) and Uniswap's Permit2
+    pub const ALLOWANCE: Selector = Selector::from_bytes([0xdd, 0x62, 0xed, 0x3e]); // allowance(address,address)
+    pub const DEPOSIT: Selector = Selector::from_bytes([0xd0, 0xe3, 0x0d, 0xb0]); // deposit()
+    pub const WITHDRAW: Selector = Selector::from_bytes([0x2e, 0x1a, 0x7d, 0x4d]); // withdraw(uint256)
+    pub const PERMIT_TRANSFER_FROM: Selector = Selector::from_bytes([0x30, 0xf2, 0x8b, 0x7a]); // permitTransferFrom(((address,uint256),uint256,uint256),(address,uint256),address,bytes)
+    pub const PERMIT2_ALLOWANCE: Selector = Selector::from_bytes([0x92, 0x7d, 0xa1, 0x05]); // allowance(address,address,address)
+
+    // UniswapV3 NonfungiblePositionManager: LP positions as ERC-721 tokens
+    pub const POSITIONS_NFT: Selector = Selector::from_bytes([0x99, 0xfb, 0xab, 0x88]); // positions(uint256)
+    pub const OWNER_OF: Selector = Selector::from_bytes([0x63, 0x52, 0x21, 0x1e]); // ownerOf(uint256)
+
+    /// Signature of every selector above, for display
+    pub static NAMES: &[(Selector, &str)] = &[
+        (TOKEN0, "token0()"),
+        (TOKEN1, "token1()"),
+        (FACTORY, "factory()"),
+        (TRANSFER, "transfer(address,uint256)"),
+        (BALANCE_OF, "balanceOf(address)"),
+        (TOTAL_SUPPLY, "totalSupply()"),
+        (SWAP_V2, "swap(uint256,uint256,address,bytes)"),
+        (SWAP_V3, "swap(address,bool,int256,uint160,bytes)"),
+        (GET_RESERVES, "getReserves()"),
+        (K_LAST, "kLast()"),
+        (PRICE0_CUMULATIVE_LAST, "price0CumulativeLast()"),
+        (PRICE1_CUMULATIVE_LAST, "price1CumulativeLast()"),
+        (TOKEN0_FEE_PERCENT, "token0FeePercent()"),
+        (SLOT0, "slot0()"),
+        (FEE, "fee()"),
+        (TICK_SPACING, "tickSpacing()"),
+        (LIQUIDITY, "liquidity()"),
+        (TICKS, "ticks(int24)"),
+        (POSITIONS, "positions(bytes32)"),
+        (STABLE, "stable()"),
+        (GET_AMOUNT_OUT, "getAmountOut(uint256,address)"),
+        (CLAIM_FEES, "claimFees()"),
+        (CURRENT_CUMULATIVE_PRICES, "currentCumulativePrices()"),
+        (GLOBAL_STATE, "globalState()"),
+        (DATA_STORAGE_OPERATOR, "dataStorageOperator()"),
+        (GET_INNER_CUMULATIVES, "getInnerCumulatives(int24,int24)"),
+        (PLUGIN, "plugin()"),
+        (COMMUNITY_VAULT, "communityVault()"),
+        (SAFELY_GET_STATE_OF_AMM, "safelyGetStateOfAMM()"),
+        (
+            SWAP_WITH_PAYMENT_IN_ADVANCE,
+            "swapWithPaymentInAdvance(address,address,bool,int256,uint160,bytes)",
+        ),
+        (GET_PLUGIN_FEE_PENDING, "getPluginFeePending()"),
+        (GET_COMMUNITY_FEE_PENDING, "getCommunityFeePending()"),
+        (PLUGIN_CONFIG, "pluginConfig()"),
+        (IS_UNLOCKED, "isUnlocked()"),
+        (GET_POOL_ID, "getPoolId()"),
+        (GET_VAULT, "getVault()"),
+        (GET_SWAP_FEE_PERCENTAGE, "getSwapFeePercentage()"),
+        (GET_NORMALIZED_WEIGHTS, "getNormalizedWeights()"),
+        (GET_AMPLIFICATION_PARAMETER, "getAmplificationParameter()"),
+        (GET_BPT_INDEX, "getBptIndex()"),
+        (GET_MIN_PRICE, "getMinPrice(address)"),
+        (GET_MAX_PRICE, "getMaxPrice(address)"),
+        (USDG, "usdg()"),
+        (ALL_PAIRS_LENGTH, "allPairsLength()"),
+        (QUERY_SWAP, "querySwap(address,address,uint256)"),
+        (TRY_QUERY, "tryQuery(address,address,uint256)"),
+        (
+            SELL_BASE,
+            "sellBase(address,uint256,uint256,address,address)",
+        ),
+        (
+            SELL_QUOTE,
+            "sellQuote(address,uint256,uint256,address,address)",
+        ),
+        (GET_THRESHOLD, "getThreshold()"),
+        (GET_OWNERS, "getOwners()"),
+        (IS_OWNER, "isOwner(address)"),
+        (MASTER_COPY, "masterCopy()"),
+        (GET_PAIR, "getPair(address,address)"),
+        (CREATE_PAIR, "createPair(address,address)"),
+        (GET_POOL_V3, "getPool(address,address,uint24)"),
+        (CREATE_POOL_V3, "createPool(address,address,uint24)"),
+        (GET_POOL_SOLIDLY, "getPool(address,address,bool)"),
+        (CREATE_POOL_SOLIDLY, "createPool(address,address,bool)"),
+        (BLACKLIST, "blacklist(address)"),
+        (ADD_TO_BLACKLIST, "addToBlacklist(address)"),
+        (IS_BLACKLISTED, "isBlacklisted(address)"),
+        (WHITELIST, "whitelist(address)"),
+        (SET_WHITELIST, "setWhitelist(address,bool)"),
+        (SET_MAX_TX_AMOUNT, "setMaxTxAmount(uint256)"),
+        (ALLOWANCE, "allowance(address,address)"),
+        (DEPOSIT, "deposit()"),
+        (WITHDRAW, "withdraw(uint256)"),
+        (
+            PERMIT_TRANSFER_FROM,
+            "permitTransferFrom(((address,uint256),uint256,uint256),(address,uint256),address,bytes)",
+        ),
+        (PERMIT2_ALLOWANCE, "allowance(address,address,address)"),
+        (POSITIONS_NFT, "positions(uint256)"),
+        (OWNER_OF, "ownerOf(uint256)"),
+    ];
 }
 
 /// Protocol fingerprint definition
-struct ProtocolFingerprint {
-    protocol: DexProtocol,
+#[derive(Debug)]
+pub(crate) struct ProtocolFingerprint {
+    pub(crate) protocol: DexProtocol,
     /// Selectors that MUST be present
-    required: &'static [Selector],
+    pub(crate) required: &'static [Selector],
     /// Selectors that MUST NOT be present
-    forbidden: &'static [Selector],
+    pub(crate) forbidden: &'static [Selector],
     /// Selectors that add confidence if present
-    optional: &'static [Selector],
+    pub(crate) optional: &'static [Selector],
+    /// Confidence an optional selector adds when present; unlisted ones add 1
+    ///
+    /// A selector only one protocol family uses is a stronger signal than one shared
+    /// by many (e.g. `factory()`).
+    pub(crate) weights: &'static [(Selector, u32)],
 }
 
 impl ProtocolFingerprint {
-    fn matches(&self, bytecode: &[u8]) -> bool {
-        let has_all_required = self.required.iter().all(|s| s.exists_in(bytecode));
-        let has_no_forbidden = !self.forbidden.iter().any(|s| s.exists_in(bytecode));
+    /// Highest confidence this fingerprint can report: every required and optional
+    /// selector present
+    #[cfg(feature = "analysis")]
+    pub(crate) const fn max_confidence(&self) -> u32 {
+        let mut total = self.required.len() as u32;
+        let mut i = 0;
+        while i < self.optional.len() {
+            total += self.weight(&self.optional[i]);
+            i += 1;
+        }
+        total
+    }
+
+    /// Confidence `selector` adds as an optional match (1 unless listed in `weights`)
+    pub(crate) const fn weight(&self, selector: &Selector) -> u32 {
+        let mut i = 0;
+        while i < self.weights.len() {
+            let (s, weight) = self.weights[i];
+            if u32::from_be_bytes(s.0) == u32::from_be_bytes(selector.0) {
+                return weight;
+            }
+            i += 1;
+        }
+        1
+    }
+
+    /// Owned, named copy of this fingerprint's selectors
+    pub(crate) fn spec(&self) -> FingerprintSpec {
+        let group = |selectors: &[Selector], weight: &dyn Fn(&Selector) -> u32| {
+            selectors
+                .iter()
+                .map(|s| SpecSelector {
+                    selector: *s,
+                    name: selector_name(s),
+                    weight: weight(s),
+                })
+                .collect()
+        };
+        FingerprintSpec {
+            protocol: self.protocol,
+            required: group(self.required, &|_| 1),
+            forbidden: group(self.forbidden, &|_| 0),
+            optional: group(self.optional, &|s| self.weight(s)),
+        }
+    }
+
+    /// `confidence` as a whole percentage of [`Self::max_confidence`], rounded down
+    #[cfg(feature = "analysis")]
+    pub(crate) fn confidence_percent(&self, confidence: u32) -> u32 {
+        (confidence.min(self.max_confidence()) * 100)
+            .checked_div(self.max_confidence())
+            .unwrap_or(0)
+    }
+
+    fn matches(&self, haystack: &Haystack) -> bool {
+        let has_all_required = self.required.iter().all(|s| haystack.contains_required(s));
+        let has_no_forbidden = !self.forbidden.iter().any(|s| haystack.contains(s));
         has_all_required && has_no_forbidden
     }
 
-    fn confidence(&self, bytecode: &[u8]) -> u32 {
-        if !self.matches(bytecode) {
+    fn confidence(&self, haystack: &Haystack) -> u32 {
+        if !self.matches(haystack) {
             return 0;
         }
 
-        let optional_matches = self
+        let optional_weight: u32 = self
             .optional
             .iter()
-            .filter(|s| s.exists_in(bytecode))
-            .count();
-        (self.required.len() + optional_matches) as u32
+            .filter(|s| haystack.contains(s))
+            .map(|s| self.weight(s))
+            .sum();
+        self.required.len() as u32 + optional_weight
+    }
+}
+
+/// How selectors are located in bytecode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectorScan {
+    /// Any 4-byte window (solc/vyper EVM code; tolerant of unusual dispatchers)
+    #[default]
+    Anywhere,
+    /// 32-byte aligned words holding a selector in their low 4 bytes
+    ///
+    /// zksolc (EraVM) code has no PUSH4 dispatcher: selectors live in the constant
+    /// pool as zero-padded 32-byte cells, while 8-byte instructions can contain any
+    /// byte sequence. Unaligned matches are ignored.
+    AlignedWords,
+    /// Required selectors must be PUSH1..PUSH4 operands (dispatcher comparisons),
+    /// so coincidental byte matches in data sections can't satisfy a fingerprint
+    ///
+    /// Forbidden and optional selectors are still matched anywhere.
+    Push4,
+}
+
+/// Bytecode prepared for selector lookups under a [`SelectorScan`], scanned once
+struct Haystack {
+    /// Where forbidden and optional selectors are looked up
+    selectors: SelectorSet,
+    /// Where required selectors are looked up, if not in `selectors` (`Push4`)
+    required: Option<SelectorSet>,
+}
+
+impl Haystack {
+    fn new(bytecode: &[u8], scan: SelectorScan) -> Self {
+        match scan {
+            SelectorScan::Anywhere => Self {
+                selectors: SelectorSet::from_bytecode(bytecode),
+                required: None,
+            },
+            SelectorScan::AlignedWords => Self {
+                selectors: bytecode
+                    .chunks_exact(32)
+                    .filter(|word| word[..28].iter().all(|b| *b == 0))
+                    .map(|word| Selector([word[28], word[29], word[30], word[31]]))
+                    .collect(),
+                required: None,
+            },
+            SelectorScan::Push4 => Self {
+                selectors: SelectorSet::from_bytecode(bytecode),
+                required: Some(push_operands(bytecode).into_iter().collect()),
+            },
+        }
+    }
+
+    fn contains(&self, selector: &Selector) -> bool {
+        self.selectors.contains(selector)
+    }
+
+    fn contains_required(&self, selector: &Selector) -> bool {
+        self.required
+            .as_ref()
+            .unwrap_or(&self.selectors)
+            .contains(selector)
+    }
+}
+
+/// PUSH1..PUSH4 operands, zero-extended to 4 bytes
+///
+/// solc pushes constants with the smallest PUSH that fits, so a selector with a
+/// leading zero byte is compared via PUSH3.
+fn push_operands(bytecode: &[u8]) -> Vec<Selector> {
+    let mut operands = Vec::new();
+    let mut i = 0;
+
+    while i < bytecode.len() {
+        let size = push_data_len(bytecode[i]);
+        if (1..=4).contains(&size) && i + size < bytecode.len() {
+            let mut bytes = [0u8; 4];
+            bytes[4 - size..].copy_from_slice(&bytecode[i + 1..i + 1 + size]);
+            operands.push(Selector(bytes));
+        }
+        i += size + 1;
     }
+
+    operands
 }
 
 /// All known protocol fingerprints, ordered by specificity (most specific first)
@@ -182,6 +633,10 @@ static FINGERPRINTS: &[ProtocolFingerprint] = &[
             selectors::IS_UNLOCKED,
             selectors::FEE,
         ],
+        weights: &[
+            (selectors::SWAP_WITH_PAYMENT_IN_ADVANCE, 2),
+            (selectors::PLUGIN_CONFIG, 2),
+        ],
     },
     // Algebra V1.9
     ProtocolFingerprint {
@@ -196,6 +651,7 @@ static FINGERPRINTS: &[ProtocolFingerprint] = &[
         ],
         forbidden: &[selectors::SLOT0, selectors::SAFELY_GET_STATE_OF_AMM],
         optional: &[selectors::DATA_STORAGE_OPERATOR],
+        weights: &[],
     },
     // Algebra legacy v1.x (pre-plugin)
     ProtocolFingerprint {
@@ -210,6 +666,23 @@ static FINGERPRINTS: &[ProtocolFingerprint] = &[
         ],
         forbidden: &[selectors::SLOT0, selectors::PLUGIN],
         optional: &[selectors::GET_INNER_CUMULATIVES],
+        weights: &[(selectors::GET_INNER_CUMULATIVES, 2)],
+    },
+    // Solidly-derived CL: slot0() together with stable(). UniswapV3 forbids stable() and
+    // Solidly forbids slot0(), so neither can match these pools; this entry does.
+    ProtocolFingerprint {
+        protocol: DexProtocol::SolidlyCL,
+        required: &[
+            selectors::TOKEN0,
+            selectors::TOKEN1,
+            selectors::SLOT0,
+            selectors::TICK_SPACING,
+            selectors::LIQUIDITY,
+            selectors::STABLE,
+        ],
+        forbidden: &[selectors::GLOBAL_STATE],
+        optional: &[selectors::FEE, selectors::TICKS, selectors::CLAIM_FEES],
+        weights: &[(selectors::CLAIM_FEES, 2)],
     },
     // Uniswap V3
     ProtocolFingerprint {
@@ -224,8 +697,10 @@ static FINGERPRINTS: &[ProtocolFingerprint] = &[
         ],
         forbidden: &[selectors::GLOBAL_STATE, selectors::STABLE],
         optional: &[selectors::TICKS, selectors::POSITIONS],
+        weights: &[],
     },
-    // Solidly / Velodrome / Aerodrome
+    // Solidly / Velodrome / Aerodrome. Pairs quote on-chain with
+    // getAmountOut(amountIn, tokenIn); stable() alone also turns up in other pools.
     ProtocolFingerprint {
         protocol: DexProtocol::Solidly,
         required: &[
@@ -233,9 +708,51 @@ static FINGERPRINTS: &[ProtocolFingerprint] = &[
             selectors::TOKEN1,
             selectors::GET_RESERVES,
             selectors::STABLE,
+            selectors::GET_AMOUNT_OUT,
         ],
         forbidden: &[selectors::SLOT0, selectors::K_LAST],
         optional: &[selectors::CLAIM_FEES, selectors::CURRENT_CUMULATIVE_PRICES],
+        weights: &[
+            (selectors::CLAIM_FEES, 2),
+            (selectors::CURRENT_CUMULATIVE_PRICES, 2),
+        ],
+    },
+    // Balancer V2 (any pool type; the math type is refined by `balancer_pool_subtype`)
+    ProtocolFingerprint {
+        protocol: DexProtocol::BalancerV2Pool,
+        required: &[
+            selectors::GET_POOL_ID,
+            selectors::GET_VAULT,
+            selectors::GET_SWAP_FEE_PERCENTAGE,
+        ],
+        forbidden: &[
+            selectors::GET_RESERVES,
+            selectors::SLOT0,
+            selectors::GLOBAL_STATE,
+        ],
+        optional: &[
+            selectors::GET_NORMALIZED_WEIGHTS,
+            selectors::GET_AMPLIFICATION_PARAMETER,
+            selectors::GET_BPT_INDEX,
+        ],
+        weights: &[
+            (selectors::GET_NORMALIZED_WEIGHTS, 2),
+            (selectors::GET_AMPLIFICATION_PARAMETER, 2),
+            (selectors::GET_BPT_INDEX, 2),
+        ],
+    },
+    // WOOFi PMM: quote functions instead of reserves
+    ProtocolFingerprint {
+        protocol: DexProtocol::WooFi,
+        required: &[selectors::QUERY_SWAP, selectors::TRY_QUERY],
+        forbidden: &[
+            selectors::GET_RESERVES,
+            selectors::SLOT0,
+            selectors::GLOBAL_STATE,
+        ],
+        // Legacy WooPP V1 entry points: suggestive, not required of WooPPV2
+        optional: &[selectors::SELL_BASE, selectors::SELL_QUOTE],
+        weights: &[],
     },
     // Uniswap V2 (most generic V2; many forks share the exact same selectors)
     ProtocolFingerprint {
@@ -252,17 +769,98 @@ static FINGERPRINTS: &[ProtocolFingerprint] = &[
             selectors::PRICE1_CUMULATIVE_LAST,
             selectors::FACTORY,
         ],
+        weights: &[],
     },
 ];
 
+/// Every protocol fingerprint, in matching order
+#[cfg(feature = "analysis")]
+pub(crate) fn fingerprints() -> &'static [ProtocolFingerprint] {
+    FINGERPRINTS
+}
+
+/// Every protocol this build can detect, in matching order, as [`fingerprint_for`]
+/// describes it
+pub fn fingerprint_specs() -> impl Iterator<Item = FingerprintSpec> {
+    FINGERPRINTS.iter().map(ProtocolFingerprint::spec)
+}
+
+/// The fingerprint that identifies `protocol` (`None` for [`DexProtocol::Unknown`])
+pub(crate) fn protocol_fingerprint(protocol: DexProtocol) -> Option<&'static ProtocolFingerprint> {
+    FINGERPRINTS.iter().find(|fp| fp.protocol == protocol)
+}
+
+/// Owned description of the fingerprint that identifies `protocol`, selector names
+/// included (`None` for [`DexProtocol::Unknown`])
+///
+/// The knowledge matching uses, shaped for display (e.g. a detection UI
+/// or the `protocols` command) rather than matching.
+pub fn fingerprint_for(protocol: DexProtocol) -> Option<FingerprintSpec> {
+    protocol_fingerprint(protocol).map(ProtocolFingerprint::spec)
+}
+
+/// Selectors defining one protocol, from [`fingerprint_for`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FingerprintSpec {
+    pub protocol: DexProtocol,
+    pub required: Vec<SpecSelector>,
+    pub forbidden: Vec<SpecSelector>,
+    pub optional: Vec<SpecSelector>,
+}
+
+impl FingerprintSpec {
+    /// Highest confidence the protocol can report: every required and optional
+    /// selector present
+    pub fn max_confidence(&self) -> u32 {
+        self.required
+            .iter()
+            .chain(&self.optional)
+            .map(|s| s.weight)
+            .sum()
+    }
+
+    /// `confidence` as a whole percentage of [`Self::max_confidence`], rounded down
+    pub fn confidence_percent(&self, confidence: u32) -> u32 {
+        (confidence.min(self.max_confidence()) * 100)
+            .checked_div(self.max_confidence())
+            .unwrap_or(0)
+    }
+}
+
+/// One selector of a [`FingerprintSpec`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpecSelector {
+    pub selector: Selector,
+    /// Signature, when the selector is a well-known one (see [`selector_name`])
+    pub name: Option<&'static str>,
+    /// Confidence the selector adds when present: 1 for required ones, the
+    /// fingerprint's weight for optional ones, 0 for forbidden ones
+    pub weight: u32,
+}
+
+/// Signature of a well-known selector (e.g. `token0()`)
+pub fn selector_name(selector: &Selector) -> Option<&'static str> {
+    selectors::NAMES
+        .iter()
+        .find(|(s, _)| s == selector)
+        .map(|(_, name)| *name)
+}
+
 /// Identify DEX protocol from bytecode using selector analysis
 pub fn identify_protocol(bytecode: &[u8]) -> DexProtocol {
+    identify_protocol_with(bytecode, SelectorScan::Anywhere)
+}
+
+/// [`identify_protocol`] with an explicit selector-scan strategy
+pub fn identify_protocol_with(bytecode: &[u8], scan: SelectorScan) -> DexProtocol {
+    let haystack = Haystack::new(bytecode, scan);
+
     // Find the fingerprint with highest confidence
     let mut best_match = None;
     let mut best_confidence = 0u32;
 
     for fp in FINGERPRINTS {
-        let confidence = fp.confidence(bytecode);
+        let confidence = fp.confidence(&haystack);
         if confidence > best_confidence {
             best_confidence = confidence;
             best_match = Some(fp.protocol);
@@ -273,20 +871,355 @@ pub fn identify_protocol(bytecode: &[u8]) -> DexProtocol {
 }
 
 /// Get all matching protocols (for ambiguous cases)
+///
+/// Matches come highest confidence first; equal confidences are ordered by protocol
+/// name ([`DexProtocol::name`]), so ties between forks always list the same way.
 pub fn identify_protocols(bytecode: &[u8]) -> Vec<(DexProtocol, u32)> {
-    FINGERPRINTS
+    identify_protocols_with(bytecode, SelectorScan::Anywhere)
+}
+
+/// [`identify_protocols`] with an explicit selector-scan strategy (same ordering)
+pub fn identify_protocols_with(bytecode: &[u8], scan: SelectorScan) -> Vec<(DexProtocol, u32)> {
+    let haystack = Haystack::new(bytecode, scan);
+    let mut matches: Vec<_> = FINGERPRINTS
         .iter()
         .filter_map(|fp| {
-            let confidence = fp.confidence(bytecode);
+            let confidence = fp.confidence(&haystack);
             if confidence > 0 {
                 Some((fp.protocol, confidence))
             } else {
                 None
             }
         })
+        .collect();
+    matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.name().cmp(b.0.name())));
+    matches
+}
+
+/// How one fingerprint fared against a bytecode, from [`identify_protocols_verbose`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolMatchDetail {
+    pub protocol: DexProtocol,
+    /// Confidence as [`identify_protocols`] reports it (0 when not matched)
+    pub confidence: u32,
+    pub matched_required: Vec<Selector>,
+    pub missing_required: Vec<Selector>,
+    /// Forbidden selectors found in the bytecode (any one rules the protocol out)
+    pub forbidden_present: Vec<Selector>,
+    pub optional_present: Vec<Selector>,
+}
+
+impl ProtocolMatchDetail {
+    /// Every required selector but one is present and nothing forbidden is
+    pub fn is_near_miss(&self) -> bool {
+        self.missing_required.len() == 1 && self.forbidden_present.is_empty()
+    }
+}
+
+/// Per-fingerprint breakdown of required / forbidden / optional selectors, for every
+/// known protocol (matched or not), in matching order
+///
+/// A diagnostic aid for pools that don't match: near-misses show which selector a
+/// new fork renamed or dropped.
+pub fn identify_protocols_verbose(bytecode: &[u8]) -> Vec<ProtocolMatchDetail> {
+    identify_protocols_verbose_with(bytecode, SelectorScan::Anywhere)
+}
+
+/// [`identify_protocols_verbose`] with an explicit selector-scan strategy
+pub fn identify_protocols_verbose_with(
+    bytecode: &[u8],
+    scan: SelectorScan,
+) -> Vec<ProtocolMatchDetail> {
+    let haystack = Haystack::new(bytecode, scan);
+    FINGERPRINTS
+        .iter()
+        .map(|fp| {
+            let (matched_required, missing_required) = fp
+                .required
+                .iter()
+                .partition(|s| haystack.contains_required(s));
+            ProtocolMatchDetail {
+                protocol: fp.protocol,
+                confidence: fp.confidence(&haystack),
+                matched_required,
+                missing_required,
+                forbidden_present: present(fp.forbidden, &haystack),
+                optional_present: present(fp.optional, &haystack),
+            }
+        })
+        .collect()
+}
+
+fn present(selectors: &[Selector], haystack: &Haystack) -> Vec<Selector> {
+    selectors
+        .iter()
+        .copied()
+        .filter(|s| haystack.contains(s))
+        .collect()
+}
+
+/// Balancer V2 pool math type: `Weighted`, `Stable` or `ComposableStable`
+///
+/// Composable stable pools also expose `getAmplificationParameter()`, so `getBptIndex()`
+/// is checked first.
+pub fn balancer_pool_subtype(bytecode: &[u8]) -> Option<&'static str> {
+    if selectors::GET_BPT_INDEX.exists_in(bytecode) {
+        Some("ComposableStable")
+    } else if selectors::GET_AMPLIFICATION_PARAMETER.exists_in(bytecode) {
+        Some("Stable")
+    } else if selectors::GET_NORMALIZED_WEIGHTS.exists_in(bytecode) {
+        Some("Weighted")
+    } else {
+        None
+    }
+}
+
+/// Non-AMM contract category, reported so such contracts aren't mistaken for pools
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContractKind {
+    /// GMX/GLP-style perp vault: prices come from oracles (`getMinPrice`/`getMaxPrice`),
+    /// so there are no reserves to read
+    PerpVault,
+    /// Pool factory; factories carry pool selectors in their creation logic and can
+    /// match pool fingerprints, so the protocol of the pools they deploy is kept
+    Factory { pool_protocol: DexProtocol },
+    /// Proactive market maker (WOOFi): swaps are quoted from an oracle (`querySwap` /
+    /// `tryQuery`), so there are no reserves for a router to read
+    Pmm,
+    /// Precompiled contract (0x01..=0x0a): built into the client, no bytecode
+    Precompile { name: &'static str },
+    /// The zero address: burn / unset address, never has code
+    ZeroAddress,
+    /// Safe (Gnosis Safe) multisig: a Safe singleton, or a Safe proxy delegating to one
+    Safe,
+    /// Account whose code is an EIP-7702 delegation designator (`0xef0100 || target`):
+    /// an EOA that runs `target`'s code, never a pool itself
+    Eip7702Delegation { target: [u8; 20] },
+    /// Wrapped native token (WETH9 and its copies: WBNB, WMATIC, ...): an ERC-20 minted
+    /// by `deposit()` and burned by `withdraw(uint256)`, traded through pools but not one
+    WrappedNative,
+    /// Uniswap's Permit2 signature-based approval contract, used by routers to move tokens
+    Permit2,
+    /// UniswapV3-style NonfungiblePositionManager: holds LP positions as ERC-721 tokens
+    /// and calls pools (so embeds `slot0()` and friends), but isn't one
+    PositionManager,
+    /// ERC-20 with `token0()`/`token1()` but no `swap`: an LP token deployed apart from
+    /// its pool, or a locker wrapping one
+    LpToken,
+    /// ERC-20 with `token0()`/`token1()` and `swap`: a V2-style pair, which is both the
+    /// pool and its own LP token. Only set to tell such a pair from an [`LpToken`](Self::LpToken);
+    /// pools that aren't ERC-20s (V3-style and most others) get no kind at all
+    Pool,
+}
+
+impl ContractKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PerpVault => "PerpVault",
+            Self::Factory { .. } => "Factory",
+            Self::Pmm => "PMM",
+            Self::Precompile { .. } => "Precompile",
+            Self::ZeroAddress => "ZeroAddress",
+            Self::Safe => "Safe",
+            Self::Eip7702Delegation { .. } => "Eip7702Delegation",
+            Self::WrappedNative => "WrappedNative",
+            Self::Permit2 => "Permit2",
+            Self::PositionManager => "PositionManager",
+            Self::LpToken => "LpToken",
+            Self::Pool => "Pool",
+        }
+    }
+
+    /// Whether the kind is still a spot pool (only [`ContractKind::Pool`])
+    pub fn is_pool(&self) -> bool {
+        matches!(self, Self::Pool)
+    }
+}
+
+/// Factory interfaces: (getter, creator, protocol of the deployed pools)
+const FACTORY_INTERFACES: &[(Selector, Selector, DexProtocol)] = &[
+    (
+        selectors::GET_POOL_V3,
+        selectors::CREATE_POOL_V3,
+        DexProtocol::UniswapV3,
+    ),
+    (
+        selectors::GET_POOL_SOLIDLY,
+        selectors::CREATE_POOL_SOLIDLY,
+        DexProtocol::Solidly,
+    ),
+    (
+        selectors::GET_PAIR,
+        selectors::CREATE_PAIR,
+        DexProtocol::UniswapV2,
+    ),
+];
+
+/// Classify contracts that look DeFi-like but are not spot pools
+pub fn identify_contract_kind(bytecode: &[u8]) -> Option<ContractKind> {
+    if let Some(target) = eip7702_delegation_target(bytecode) {
+        return Some(ContractKind::Eip7702Delegation { target });
+    }
+
+    for (getter, creator, pool_protocol) in FACTORY_INTERFACES {
+        if getter.exists_in(bytecode) && creator.exists_in(bytecode) {
+            return Some(ContractKind::Factory {
+                pool_protocol: *pool_protocol,
+            });
+        }
+    }
+
+    // GMX V1 Vault and forks: min/max oracle prices per token; usdg() is common but not required.
+    if selectors::GET_MIN_PRICE.exists_in(bytecode) && selectors::GET_MAX_PRICE.exists_in(bytecode)
+    {
+        return Some(ContractKind::PerpVault);
+    }
+
+    if selectors::QUERY_SWAP.exists_in(bytecode) && selectors::TRY_QUERY.exists_in(bytecode) {
+        return Some(ContractKind::Pmm);
+    }
+
+    let safe_singleton = [
+        selectors::GET_THRESHOLD,
+        selectors::GET_OWNERS,
+        selectors::IS_OWNER,
+    ]
+    .iter()
+    .all(|s| s.exists_in(bytecode));
+    if safe_singleton || is_safe_proxy(bytecode) {
+        return Some(ContractKind::Safe);
+    }
+
+    // Position NFTs: pools key positions by bytes32 and aren't ERC-721s.
+    if selectors::POSITIONS_NFT.exists_in(bytecode) && selectors::OWNER_OF.exists_in(bytecode) {
+        return Some(ContractKind::PositionManager);
+    }
+
+    // Routers call deposit/withdraw/transfer on WETH too, but don't embed the read-only
+    // half of the ERC-20 surface.
+    let wrapped_native = [
+        selectors::DEPOSIT,
+        selectors::WITHDRAW,
+        selectors::TRANSFER,
+        selectors::BALANCE_OF,
+        selectors::TOTAL_SUPPLY,
+        selectors::ALLOWANCE,
+    ]
+    .iter()
+    .all(|s| s.exists_in(bytecode));
+    if wrapped_native {
+        return Some(ContractKind::WrappedNative);
+    }
+
+    // Contracts pulling tokens through Permit2 embed permitTransferFrom as well; only
+    // Permit2 itself serves the three-address allowance getter next to it.
+    if selectors::PERMIT_TRANSFER_FROM.exists_in(bytecode)
+        && selectors::PERMIT2_ALLOWANCE.exists_in(bytecode)
+    {
+        return Some(ContractKind::Permit2);
+    }
+
+    // Only ERC-20s need telling apart from their LP token: a non-ERC-20 pool (V3-style)
+    // falls through to `None`, which callers read as "may be a pool" as well.
+    let pair_token = [
+        selectors::TRANSFER,
+        selectors::BALANCE_OF,
+        selectors::TOTAL_SUPPLY,
+        selectors::TOKEN0,
+        selectors::TOKEN1,
+    ]
+    .iter()
+    .all(|s| s.exists_in(bytecode));
+    if pair_token {
+        let swaps =
+            selectors::SWAP_V2.exists_in(bytecode) || selectors::SWAP_V3.exists_in(bytecode);
+        return Some(if swaps {
+            ContractKind::Pool
+        } else {
+            ContractKind::LpToken
+        });
+    }
+    None
+}
+
+/// Token selectors that let an owner block or cap transfers, as checked by [`token_risk_flags`]
+pub const TOKEN_RISK_SELECTORS: &[Selector] = &[
+    selectors::BLACKLIST,
+    selectors::ADD_TO_BLACKLIST,
+    selectors::IS_BLACKLISTED,
+    selectors::WHITELIST,
+    selectors::SET_WHITELIST,
+    selectors::SET_MAX_TX_AMOUNT,
+];
+
+/// Signatures of the [`TOKEN_RISK_SELECTORS`] a token's code dispatches
+///
+/// A rug-screening heuristic, unrelated to protocol detection: legitimate tokens
+/// (e.g. USDC's `isBlacklisted`) have some of these too, so a flag is a reason to look
+/// closer, not a verdict.
+pub fn token_risk_flags(bytecode: &[u8]) -> Vec<&'static str> {
+    // Only dispatched selectors count: the same 4 bytes in other PUSH data or in the
+    // metadata trailer are not a function.
+    let dispatched = push_operands(bytecode);
+    TOKEN_RISK_SELECTORS
+        .iter()
+        .filter(|s| dispatched.contains(s))
+        .filter_map(selector_name)
         .collect()
 }
 
+/// Delegation target of an EIP-7702 designator: exactly `0xef0100` followed by the
+/// 20-byte address (`0xef` can't start deployed contract code, per EIP-3541)
+pub fn eip7702_delegation_target(bytecode: &[u8]) -> Option<[u8; 20]> {
+    match bytecode {
+        [0xef, 0x01, 0x00, target @ ..] => target.try_into().ok(),
+        _ => None,
+    }
+}
+
+/// Safe proxy layout: `PUSH32 masterCopy()` (the proxy answers it from storage slot 0)
+/// plus a `DELEGATECALL` to the singleton
+///
+/// The proxy has none of the Safe selectors itself; resolve the singleton through
+/// `masterCopy()` to confirm it is a Safe.
+pub fn is_safe_proxy(bytecode: &[u8]) -> bool {
+    const PUSH32: u8 = 0x7f;
+    const DELEGATECALL: u8 = 0xf4;
+
+    let mut push_master_copy = false;
+    let mut delegatecall = false;
+    let mut i = 0;
+    while i < bytecode.len() {
+        let op = bytecode[i];
+        if op == PUSH32 {
+            if let Some(word) = bytecode.get(i + 1..i + 33) {
+                push_master_copy |= word[..4] == *selectors::MASTER_COPY.as_bytes()
+                    && word[4..].iter().all(|b| *b == 0);
+            }
+        }
+        delegatecall |= op == DELEGATECALL;
+        i += push_data_len(op) + 1;
+    }
+    push_master_copy && delegatecall
+}
+
+/// Immediate data bytes following `op`: 1..=32 for PUSH1..PUSH32, 0 for every other
+/// opcode (`PUSH0` included)
+///
+/// Every opcode walk in the crate steps with this, so PUSH data is never read as code.
+pub(crate) const fn push_data_len(op: u8) -> usize {
+    const PUSH0: u8 = 0x5f;
+    const PUSH1: u8 = 0x60;
+    const PUSH32: u8 = 0x7f;
+
+    match op {
+        // Shanghai's PUSH0 pushes a constant zero: no immediate to skip
+        PUSH0 => 0,
+        PUSH1..=PUSH32 => (op - PUSH0) as usize,
+        _ => 0,
+    }
+}
+
 /// Extract all function selectors from bytecode
 pub fn extract_selectors(bytecode: &[u8]) -> Vec<Selector> {
     let mut selectors = Vec::new();
@@ -296,16 +1229,14 @@ pub fn extract_selectors(bytecode: &[u8]) -> Vec<Selector> {
         let op = bytecode[i];
 
         // PUSH4 (0x63) followed by 4 bytes - likely a selector
-        if op == 0x63 && i + 4 < bytecode.len() {
+        if op == 0x63 && i + 5 <= bytecode.len() {
             let mut bytes = [0u8; 4];
             bytes.copy_from_slice(&bytecode[i + 1..i + 5]);
             selectors.push(Selector::from_bytes(bytes));
             i += 5;
-        } else if (0x60..=0x7f).contains(&op) {
-            // Skip other PUSH opcodes
-            i += (op - 0x5f) as usize + 1;
         } else {
-            i += 1;
+            // Skip other PUSH opcodes' data
+            i += push_data_len(op) + 1;
         }
     }
 
@@ -314,6 +1245,37 @@ pub fn extract_selectors(bytecode: &[u8]) -> Vec<Selector> {
     selectors
 }
 
+/// Byte range of the function dispatcher: from the start of the code through the last
+/// `PUSH4 <selector> [DUPn] EQ` comparison
+///
+/// solc (legacy and via-IR) compares the calldata selector this way, including in
+/// split binary-search dispatchers. Empty when the code has no such comparison
+/// (EIP-1167 proxies, fallback-only contracts).
+pub fn dispatcher_region(bytecode: &[u8]) -> Range<usize> {
+    const PUSH4: u8 = 0x63;
+    const EQ: u8 = 0x14;
+    const DUP1: u8 = 0x80;
+    const DUP16: u8 = 0x8f;
+
+    let mut end = 0;
+    let mut i = 0;
+    while i < bytecode.len() {
+        let op = bytecode[i];
+        if op == PUSH4 {
+            let mut next = i + 5;
+            if matches!(bytecode.get(next), Some(DUP1..=DUP16)) {
+                next += 1;
+            }
+            if bytecode.get(next) == Some(&EQ) {
+                end = next + 1;
+            }
+        }
+        i += push_data_len(op) + 1;
+    }
+
+    0..end
+}
+
 /// Check if bytecode contains a specific function signature
 pub fn has_function(bytecode: &[u8], signature: &str) -> bool {
     Selector::from_signature(signature).exists_in(bytecode)
@@ -323,6 +1285,25 @@ pub fn has_function(bytecode: &[u8], signature: &str) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_dispatcher_region() {
+        // DUP1 PUSH4 token0() EQ PUSH2 JUMPI, then DUP1 PUSH4 x DUP2 EQ (via-IR shape),
+        // then a PUSH4 that is not compared.
+        let code = [
+            0x80, 0x63, 0x0d, 0xfe, 0x16, 0x81, 0x14, 0x61, 0x00, 0x40, 0x57, //
+            0x63, 0x12, 0x34, 0x56, 0x78, 0x81, 0x14, //
+            0x63, 0x0d, 0xfe, 0x16, 0x81, 0x50,
+        ];
+        assert_eq!(dispatcher_region(&code), 0..18);
+        assert_eq!(selectors::TOKEN0.positions_in(&code), [2, 19]);
+
+        // A PUSH32 operand hiding `PUSH4 .. EQ` bytes is not a comparison.
+        let mut hidden = vec![0x7f];
+        hidden.extend([0x63, 0, 0, 0, 1, 0x14]);
+        hidden.resize(33, 0);
+        assert_eq!(dispatcher_region(&hidden), 0..0);
+    }
+
     #[test]
     fn test_selector_from_signature() {
         // Known selectors
@@ -348,6 +1329,213 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_builtin_selectors_match_their_signatures() {
+        // Written out by hand rather than read from NAMES, so a typo in a constant and
+        // the same typo in its NAMES entry can't cancel out.
+        let builtin = [
+            (selectors::TOKEN0, "token0()"),
+            (selectors::TOKEN1, "token1()"),
+            (selectors::FACTORY, "factory()"),
+            (selectors::TRANSFER, "transfer(address,uint256)"),
+            (selectors::BALANCE_OF, "balanceOf(address)"),
+            (selectors::TOTAL_SUPPLY, "totalSupply()"),
+            (selectors::SWAP_V2, "swap(uint256,uint256,address,bytes)"),
+            (
+                selectors::SWAP_V3,
+                "swap(address,bool,int256,uint160,bytes)",
+            ),
+            (selectors::GET_RESERVES, "getReserves()"),
+            (selectors::K_LAST, "kLast()"),
+            (selectors::PRICE0_CUMULATIVE_LAST, "price0CumulativeLast()"),
+            (selectors::PRICE1_CUMULATIVE_LAST, "price1CumulativeLast()"),
+            (selectors::TOKEN0_FEE_PERCENT, "token0FeePercent()"),
+            (selectors::SLOT0, "slot0()"),
+            (selectors::FEE, "fee()"),
+            (selectors::TICK_SPACING, "tickSpacing()"),
+            (selectors::LIQUIDITY, "liquidity()"),
+            (selectors::TICKS, "ticks(int24)"),
+            (selectors::POSITIONS, "positions(bytes32)"),
+            (selectors::STABLE, "stable()"),
+            (selectors::GET_AMOUNT_OUT, "getAmountOut(uint256,address)"),
+            (selectors::CLAIM_FEES, "claimFees()"),
+            (
+                selectors::CURRENT_CUMULATIVE_PRICES,
+                "currentCumulativePrices()",
+            ),
+            (selectors::GLOBAL_STATE, "globalState()"),
+            (selectors::DATA_STORAGE_OPERATOR, "dataStorageOperator()"),
+            (
+                selectors::GET_INNER_CUMULATIVES,
+                "getInnerCumulatives(int24,int24)",
+            ),
+            (selectors::PLUGIN, "plugin()"),
+            (selectors::COMMUNITY_VAULT, "communityVault()"),
+            (selectors::SAFELY_GET_STATE_OF_AMM, "safelyGetStateOfAMM()"),
+            (
+                selectors::SWAP_WITH_PAYMENT_IN_ADVANCE,
+                "swapWithPaymentInAdvance(address,address,bool,int256,uint160,bytes)",
+            ),
+            (selectors::GET_PLUGIN_FEE_PENDING, "getPluginFeePending()"),
+            (
+                selectors::GET_COMMUNITY_FEE_PENDING,
+                "getCommunityFeePending()",
+            ),
+            (selectors::PLUGIN_CONFIG, "pluginConfig()"),
+            (selectors::IS_UNLOCKED, "isUnlocked()"),
+            (selectors::GET_POOL_ID, "getPoolId()"),
+            (selectors::GET_VAULT, "getVault()"),
+            (selectors::GET_SWAP_FEE_PERCENTAGE, "getSwapFeePercentage()"),
+            (selectors::GET_NORMALIZED_WEIGHTS, "getNormalizedWeights()"),
+            (
+                selectors::GET_AMPLIFICATION_PARAMETER,
+                "getAmplificationParameter()",
+            ),
+            (selectors::GET_BPT_INDEX, "getBptIndex()"),
+            (selectors::GET_MIN_PRICE, "getMinPrice(address)"),
+            (selectors::GET_MAX_PRICE, "getMaxPrice(address)"),
+            (selectors::USDG, "usdg()"),
+            (selectors::QUERY_SWAP, "querySwap(address,address,uint256)"),
+            (selectors::TRY_QUERY, "tryQuery(address,address,uint256)"),
+            (
+                selectors::SELL_BASE,
+                "sellBase(address,uint256,uint256,address,address)",
+            ),
+            (
+                selectors::SELL_QUOTE,
+                "sellQuote(address,uint256,uint256,address,address)",
+            ),
+            (selectors::GET_THRESHOLD, "getThreshold()"),
+            (selectors::GET_OWNERS, "getOwners()"),
+            (selectors::IS_OWNER, "isOwner(address)"),
+            (selectors::MASTER_COPY, "masterCopy()"),
+            (selectors::ALL_PAIRS_LENGTH, "allPairsLength()"),
+            (selectors::GET_PAIR, "getPair(address,address)"),
+            (selectors::CREATE_PAIR, "createPair(address,address)"),
+            (selectors::GET_POOL_V3, "getPool(address,address,uint24)"),
+            (
+                selectors::CREATE_POOL_V3,
+                "createPool(address,address,uint24)",
+            ),
+            (selectors::GET_POOL_SOLIDLY, "getPool(address,address,bool)"),
+            (
+                selectors::CREATE_POOL_SOLIDLY,
+                "createPool(address,address,bool)",
+            ),
+            (selectors::BLACKLIST, "blacklist(address)"),
+            (selectors::ADD_TO_BLACKLIST, "addToBlacklist(address)"),
+            (selectors::IS_BLACKLISTED, "isBlacklisted(address)"),
+            (selectors::WHITELIST, "whitelist(address)"),
+            (selectors::SET_WHITELIST, "setWhitelist(address,bool)"),
+            (selectors::SET_MAX_TX_AMOUNT, "setMaxTxAmount(uint256)"),
+            (selectors::ALLOWANCE, "allowance(address,address)"),
+            (selectors::DEPOSIT, "deposit()"),
+            (selectors::WITHDRAW, "withdraw(uint256)"),
+            (
+                selectors::PERMIT_TRANSFER_FROM,
+                "permitTransferFrom(((address,uint256),uint256,uint256),(address,uint256),address,bytes)",
+            ),
+            (
+                selectors::PERMIT2_ALLOWANCE,
+                "allowance(address,address,address)",
+            ),
+            (selectors::POSITIONS_NFT, "positions(uint256)"),
+            (selectors::OWNER_OF, "ownerOf(uint256)"),
+        ];
+        for (selector, signature) in builtin {
+            assert_eq!(Selector::from_signature(signature), selector, "{signature}");
+            assert_eq!(selector_name(&selector), Some(signature));
+        }
+        assert_eq!(builtin.len(), selectors::NAMES.len());
+    }
+
+    #[test]
+    fn test_every_fingerprint_selector_is_named() {
+        for fp in FINGERPRINTS {
+            for s in fp.required.iter().chain(fp.forbidden).chain(fp.optional) {
+                let name = selector_name(s).unwrap_or_else(|| panic!("{s} has no name"));
+                assert_eq!(Selector::from_signature(name), *s, "{name}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_aligned_words_scan_ignores_unaligned_bytes() {
+        let mut bytecode = vec![0u8; 64];
+        bytecode[28..32].copy_from_slice(selectors::TOKEN0.as_bytes());
+        bytecode[40..44].copy_from_slice(selectors::TOKEN1.as_bytes());
+
+        let haystack = Haystack::new(&bytecode, SelectorScan::AlignedWords);
+        assert!(haystack.contains(&selectors::TOKEN0));
+        assert!(!haystack.contains(&selectors::TOKEN1));
+    }
+
+    #[test]
+    fn test_push_operands_zero_extend_short_pushes() {
+        // PUSH3 0xfdd58e (selector 0x00fdd58e), PUSH4 token0()
+        let bytecode = [0x62, 0xfd, 0xd5, 0x8e, 0x63, 0x0d, 0xfe, 0x16, 0x81, 0x00];
+        let operands = push_operands(&bytecode);
+        assert_eq!(
+            operands,
+            [
+                Selector::from_bytes([0x00, 0xfd, 0xd5, 0x8e]),
+                selectors::TOKEN0
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identify_protocols_verbose_near_miss() {
+        // UniswapV2 minus kLast(), plus the optional factory()
+        let bytecode: Vec<u8> = [
+            selectors::TOKEN0,
+            selectors::TOKEN1,
+            selectors::GET_RESERVES,
+            selectors::FACTORY,
+        ]
+        .iter()
+        .flat_map(|s| s.0)
+        .collect();
+
+        let details = identify_protocols_verbose(&bytecode);
+        assert_eq!(details.len(), FINGERPRINTS.len());
+        let v2 = details
+            .iter()
+            .find(|d| d.protocol == DexProtocol::UniswapV2)
+            .unwrap();
+        assert_eq!(v2.confidence, 0);
+        assert_eq!(v2.missing_required, [selectors::K_LAST]);
+        assert_eq!(
+            v2.matched_required,
+            [
+                selectors::TOKEN0,
+                selectors::TOKEN1,
+                selectors::GET_RESERVES
+            ]
+        );
+        assert_eq!(v2.optional_present, [selectors::FACTORY]);
+        assert!(v2.is_near_miss());
+
+        let mut with_slot0 = bytecode.clone();
+        with_slot0.extend(selectors::SLOT0.0);
+        let v2 = identify_protocols_verbose(&with_slot0)
+            .into_iter()
+            .find(|d| d.protocol == DexProtocol::UniswapV2)
+            .unwrap();
+        assert_eq!(v2.forbidden_present, [selectors::SLOT0]);
+        assert!(!v2.is_near_miss());
+
+        // Matched entries agree with identify_protocols.
+        let mut full = bytecode;
+        full.extend(selectors::K_LAST.0);
+        let matched: Vec<_> = identify_protocols_verbose(&full)
+            .into_iter()
+            .filter(|d| d.confidence > 0)
+            .map(|d| (d.protocol, d.confidence))
+            .collect();
+        assert_eq!(matched, identify_protocols(&full));
+    }
+
     #[test]
     fn test_selector_exists_in() {
         let bytecode = vec![0x00, 0x0d, 0xfe, 0x16, 0x81, 0x00];
@@ -364,6 +1552,50 @@ mod tests {
         assert_eq!(extracted[0], selectors::TOKEN0);
     }
 
+    #[test]
+    fn test_extract_selectors_push4_ending_at_buffer_end() {
+        // STOP, then PUSH4 token0() whose last operand byte is the last byte of the code
+        let bytecode = vec![0x00, 0x63, 0x0d, 0xfe, 0x16, 0x81];
+        assert_eq!(extract_selectors(&bytecode), vec![selectors::TOKEN0]);
+        // A truncated PUSH4 yields nothing
+        assert!(extract_selectors(&bytecode[..5]).is_empty());
+    }
+
+    #[test]
+    fn test_push0_does_not_shift_selector_extraction() {
+        const PUSH0: u8 = 0x5f;
+        // Post-Shanghai dispatcher shape: PUSH0 right before and after each PUSH4
+        // comparison, so a PUSH0 read as PUSH1 would swallow the next PUSH4 opcode.
+        let mut bytecode = Vec::new();
+        for s in [
+            selectors::TOKEN0,
+            selectors::TOKEN1,
+            selectors::GET_RESERVES,
+            selectors::K_LAST,
+        ] {
+            bytecode.extend([PUSH0, 0x80, 0x63]);
+            bytecode.extend(s.0);
+            bytecode.extend([0x14, PUSH0, 0x57]);
+        }
+        bytecode.push(PUSH0);
+
+        assert_eq!(push_data_len(PUSH0), 0);
+        let mut expected = vec![
+            selectors::TOKEN0,
+            selectors::TOKEN1,
+            selectors::GET_RESERVES,
+            selectors::K_LAST,
+        ];
+        expected.sort_unstable_by_key(|s| s.0);
+        assert_eq!(extract_selectors(&bytecode), expected);
+        // Through the last EQ, before its PUSH0 JUMPI and the trailing PUSH0
+        assert_eq!(dispatcher_region(&bytecode), 0..bytecode.len() - 3);
+        assert_eq!(
+            identify_protocols_with(&bytecode, SelectorScan::Push4),
+            [(DexProtocol::UniswapV2, 4)]
+        );
+    }
+
     #[test]
     fn test_has_function() {
         let bytecode = vec![0x00, 0x0d, 0xfe, 0x16, 0x81, 0x00];
@@ -414,4 +1646,65 @@ mod tests {
         let protocol = identify_protocol(&bytecode);
         assert_eq!(protocol, DexProtocol::AlgebraIntegral);
     }
+
+    #[test]
+    fn test_safely_get_state_of_amm_rules_out_legacy_algebra() {
+        // A plugin-era pool with safelyGetStateOfAMM() must not also match the
+        // legacy v1.9+ fingerprint, or the verdict would be an ambiguous Unknown.
+        let mut bytecode = Vec::new();
+        for selector in [
+            selectors::TOKEN0,
+            selectors::TOKEN1,
+            selectors::GLOBAL_STATE,
+            selectors::TICK_SPACING,
+            selectors::LIQUIDITY,
+            selectors::PLUGIN,
+            selectors::SAFELY_GET_STATE_OF_AMM,
+            selectors::COMMUNITY_VAULT,
+        ] {
+            bytecode.extend_from_slice(selector.as_bytes());
+        }
+
+        assert_eq!(
+            identify_protocols(&bytecode),
+            [(DexProtocol::AlgebraIntegral, 8)]
+        );
+    }
+
+    #[test]
+    fn test_weighted_optional_selector_flips_the_winner() {
+        let common = ProtocolFingerprint {
+            protocol: DexProtocol::UniswapV2,
+            required: &[selectors::TOKEN0],
+            forbidden: &[],
+            optional: &[selectors::FACTORY, selectors::K_LAST],
+            weights: &[],
+        };
+        let specific = |weights| ProtocolFingerprint {
+            protocol: DexProtocol::AlgebraLegacyV1,
+            required: &[selectors::TOKEN0],
+            forbidden: &[],
+            optional: &[selectors::GLOBAL_STATE],
+            weights,
+        };
+        let bytecode: Vec<u8> = [
+            selectors::TOKEN0,
+            selectors::FACTORY,
+            selectors::K_LAST,
+            selectors::GLOBAL_STATE,
+        ]
+        .iter()
+        .flat_map(|s| s.0)
+        .collect();
+        let haystack = Haystack::new(&bytecode, SelectorScan::Anywhere);
+
+        // Counted, two weak selectors beat one strong one.
+        assert_eq!(common.confidence(&haystack), 3);
+        assert_eq!(specific(&[]).confidence(&haystack), 2);
+
+        let weighted = specific(&[(selectors::GLOBAL_STATE, 3)]);
+        assert_eq!(weighted.confidence(&haystack), 4);
+        assert_eq!(weighted.spec().max_confidence(), 4);
+        assert_eq!(weighted.spec().confidence_percent(4), 100);
+    }
 }
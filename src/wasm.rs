@@ -0,0 +1,73 @@
+//! wasm-bindgen entrypoints for in-browser classification (no networking)
+//!
+//! The page fetches bytecode with its own provider and passes it in as hex.
+//! Exported JS functions (both throw an `Error` on invalid input):
+//!
+//! ```ts
+//! analyzeBytecode(address: string, bytecodeHex: string): string  // JSON BytecodeAnalysis
+//! identifyProtocol(bytecodeHex: string): string                  // JSON {"protocol": "UniswapV3"}
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::analyze::{analyze_bytecode, dex_protocol_name, parse_address_hex};
+use crate::selector_fingerprint::identify_protocol;
+
+/// JS: `analyzeBytecode(address: string, bytecodeHex: string): string`
+#[wasm_bindgen(js_name = analyzeBytecode)]
+pub fn analyze_bytecode_js(address: &str, bytecode_hex: &str) -> Result<String, JsError> {
+    analyze_bytecode_json(address, bytecode_hex).map_err(|e| JsError::new(&e))
+}
+
+/// JS: `identifyProtocol(bytecodeHex: string): string`
+#[wasm_bindgen(js_name = identifyProtocol)]
+pub fn identify_protocol_js(bytecode_hex: &str) -> Result<String, JsError> {
+    identify_protocol_json(bytecode_hex).map_err(|e| JsError::new(&e))
+}
+
+/// [`analyze_bytecode`] over hex input, serialized as JSON
+pub fn analyze_bytecode_json(address: &str, bytecode_hex: &str) -> Result<String, String> {
+    let address = parse_address_hex(address).map_err(|e| e.to_string())?;
+    let bytecode = decode_hex(bytecode_hex)?;
+    serde_json::to_string(&analyze_bytecode(address, &bytecode)).map_err(|e| e.to_string())
+}
+
+/// [`identify_protocol`] over hex input, as `{"protocol": "<name>"}`
+pub fn identify_protocol_json(bytecode_hex: &str) -> Result<String, String> {
+    let bytecode = decode_hex(bytecode_hex)?;
+    let protocol = dex_protocol_name(identify_protocol(&bytecode));
+    Ok(serde_json::json!({ "protocol": protocol }).to_string())
+}
+
+fn decode_hex(bytecode_hex: &str) -> Result<Vec<u8>, String> {
+    let trimmed = bytecode_hex.trim();
+    hex::decode(trimmed.strip_prefix("0x").unwrap_or(trimmed))
+        .map_err(|e| format!("invalid bytecode hex: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identify_protocol_json() {
+        // token0 + token1 + getReserves + kLast
+        let json = identify_protocol_json("0x0dfe1681d21220a70902f1ac7464fc3d").unwrap();
+        assert_eq!(json, r#"{"protocol":"UniswapV2"}"#);
+        assert!(identify_protocol_json("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_analyze_bytecode_json() {
+        let json = analyze_bytecode_json(
+            "0x0000000000000000000000000000000000000001",
+            "0x0dfe1681d21220a70902f1ac7464fc3d",
+        )
+        .unwrap();
+        let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(v["protocol"], "UniswapV2");
+        assert_eq!(v["code_size"], 16);
+
+        assert!(analyze_bytecode_json("vitalik.eth", "0x00").is_err());
+    }
+}
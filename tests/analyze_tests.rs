@@ -0,0 +1,1586 @@
+//! Integration tests for report assembly and single-address analysis
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use alloy::eips::BlockId;
+use alloy::primitives::{Address, B256};
+use alloy::rpc::types::state::{AccountOverride, StateOverride};
+use common::{eip1167_proxy, eip7702_designator, load_fixture, MockRpc};
+use which_dex::analyze::{
+    analyze_address, analyze_address_with_deadline, analyze_address_with_options,
+    created_contract_address, parse_block_id, parse_tx_hash,
+};
+use which_dex::selector_fingerprint::{fingerprint_for, selectors};
+use which_dex::{
+    analyze_bytecode, analyze_bytecode_with_db, analyze_bytecode_with_options, AnalyzeError,
+    AnalyzeOptions, BytecodeFingerprint, Chain, ChainProfile, DexProtocol, EffectiveVerdict,
+    FactoryAllowlist, FingerprintDb, ProxyKind, RpcError,
+};
+
+const V3_POOL: &str = "0x8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
+const V2_POOL: &str = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+const PROXY: &str = "0x00000000000000000000000000000000000000aa";
+
+#[test]
+fn test_eip1167_proxy_has_no_fingerprint_error() {
+    let proxy = load_fixture("eip1167_proxy.hex");
+    let analysis = analyze_bytecode(Address::ZERO, &proxy);
+
+    assert_eq!(analysis.code_size, 45);
+    assert!(analysis.fingerprint.is_none());
+    assert!(analysis.fingerprint_error.is_none());
+
+    let json = serde_json::to_value(&analysis).unwrap();
+    assert!(json.get("fingerprint_error").is_none());
+}
+
+#[test]
+fn test_small_non_proxy_still_reports_fingerprint_error() {
+    let analysis = analyze_bytecode(Address::ZERO, &[0x60, 0x80, 0x60, 0x40, 0x52]);
+
+    assert!(analysis.fingerprint.is_none());
+    assert!(analysis.fingerprint_error.is_some());
+}
+
+#[test]
+fn test_forwarding_wrapper_is_flagged_but_still_matched() {
+    // Synthetic: UniswapV2 selectors whose functions only STATICCALL/DELEGATECALL a pool
+    let wrapper = analyze_bytecode(
+        Address::ZERO,
+        &load_fixture("forwarding_wrapper_synthetic.hex"),
+    );
+    assert_eq!(wrapper.protocol, "UniswapV2");
+    assert!(wrapper.forwarding_suspected);
+    // Advisory only: the verdict is left alone.
+    assert!(wrapper.is_pool_likely);
+
+    for fixture in [
+        "univ2_usdc_eth.hex",
+        "sushi_usdc_eth.hex",
+        "univ3_usdc_eth.hex",
+        "velo_impl.hex",
+        "algebra_matic_usdc.hex",
+        "eip1167_proxy.hex",
+    ] {
+        let analysis = analyze_bytecode(Address::ZERO, &load_fixture(fixture));
+        assert!(!analysis.forwarding_suspected, "{fixture}");
+    }
+    let json = serde_json::to_value(&wrapper).unwrap();
+    assert_eq!(json["forwarding_suspected"], true);
+}
+
+#[test]
+fn test_balancer_pool_subtype_in_report() {
+    let weighted = load_fixture("balancer_weighted_synthetic.hex");
+    let analysis = analyze_bytecode(Address::ZERO, &weighted);
+
+    assert_eq!(analysis.protocol, "BalancerV2Pool");
+    assert_eq!(analysis.pool_subtype.as_deref(), Some("Weighted"));
+
+    let v2 = analyze_bytecode(Address::ZERO, &load_fixture("univ2_usdc_eth.hex"));
+    assert!(v2.pool_subtype.is_none());
+    assert!(serde_json::to_value(&v2)
+        .unwrap()
+        .get("pool_subtype")
+        .is_none());
+}
+
+#[test]
+fn test_zksync_profile_scans_constant_pool_words() {
+    // Synthetic EraVM-shaped code: V2 selectors as zero-padded constant-pool cells,
+    // plus an instruction word that happens to contain slot0()'s bytes unaligned.
+    let bytecode = load_fixture("zksync_univ2_synthetic.hex");
+    let address = Address::ZERO;
+
+    let evm = analyze_bytecode(address, &bytecode);
+    assert_eq!(evm.protocol, "Unknown");
+
+    let options = AnalyzeOptions {
+        chain_profile: ChainProfile::ZkSync,
+        ..AnalyzeOptions::default()
+    };
+    let zksync = analyze_bytecode_with_options(address, &bytecode, &options);
+    assert_eq!(zksync.protocol, "UniswapV2");
+    assert!(zksync.is_pool_likely);
+}
+
+#[test]
+fn test_chain_priors_break_a_tie_per_chain() {
+    // Balancer's three required views against WOOFi's two plus an (unweighted) optional one
+    let code: Vec<u8> = [
+        selectors::GET_POOL_ID,
+        selectors::GET_VAULT,
+        selectors::GET_SWAP_FEE_PERCENTAGE,
+        selectors::QUERY_SWAP,
+        selectors::TRY_QUERY,
+        selectors::SELL_BASE,
+    ]
+    .iter()
+    .flat_map(|s| *s.as_bytes())
+    .collect();
+    let analyze = |chain_hint: Option<Chain>| {
+        let options = AnalyzeOptions {
+            chain_hint,
+            ..AnalyzeOptions::default()
+        };
+        analyze_bytecode_with_options(Address::ZERO, &code, &options)
+    };
+
+    let none = analyze(None);
+    assert_eq!(none.protocol, "Unknown");
+    assert_eq!(none.protocol_candidates.as_ref().unwrap().len(), 2);
+
+    // Balancer is the more common of the two on Ethereum, WOOFi on BSC.
+    let ethereum = analyze(Some(Chain::Ethereum));
+    assert_eq!(ethereum.protocol, "BalancerV2Pool");
+    assert_eq!(ethereum.protocol_candidates.unwrap().len(), 2);
+    let bsc = analyze(Some(Chain::Bsc));
+    assert_eq!(bsc.protocol, "WooFi");
+    assert_eq!(bsc.pool_confidence, ethereum.pool_confidence);
+}
+
+#[test]
+fn test_protocol_candidates_carry_the_enum() {
+    let code: Vec<u8> = [
+        selectors::GET_POOL_ID,
+        selectors::GET_VAULT,
+        selectors::GET_SWAP_FEE_PERCENTAGE,
+        selectors::QUERY_SWAP,
+        selectors::TRY_QUERY,
+        selectors::SELL_BASE,
+    ]
+    .iter()
+    .flat_map(|s| *s.as_bytes())
+    .collect();
+    let analysis = analyze_bytecode(Address::ZERO, &code);
+    let candidates = analysis.protocol_candidates.as_ref().unwrap();
+
+    let protocols: Vec<DexProtocol> = candidates.iter().map(|c| c.protocol_enum).collect();
+    assert_eq!(protocols, [DexProtocol::BalancerV2Pool, DexProtocol::WooFi]);
+    let json = serde_json::to_value(&analysis).unwrap();
+    for (candidate, value) in candidates
+        .iter()
+        .zip(json["protocol_candidates"].as_array().unwrap())
+    {
+        assert_eq!(candidate.protocol.parse(), Ok(candidate.protocol_enum));
+        assert_eq!(value["protocol_enum"], value["protocol"]);
+    }
+}
+
+#[test]
+fn test_runtime_only_ignores_selectors_in_constructor() {
+    let runtime = load_fixture("univ2_usdc_eth.hex");
+    // Constructor touching slot0() (which UniswapV2 forbids), then the solc deployer tail
+    let mut creation = vec![0x60, 0x80, 0x60, 0x40, 0x52, 0x63];
+    creation.extend_from_slice(selectors::SLOT0.as_bytes());
+    creation.extend_from_slice(&[0x50, 0x61]);
+    creation.extend_from_slice(&(runtime.len() as u16).to_be_bytes());
+    creation.extend_from_slice(&[
+        0x80, 0x61, 0x00, 25, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3, 0xfe,
+    ]);
+    creation.extend_from_slice(&runtime);
+
+    let full = analyze_bytecode(Address::ZERO, &creation);
+    assert_ne!(full.protocol, "UniswapV2");
+
+    let options = AnalyzeOptions {
+        runtime_only: true,
+        ..AnalyzeOptions::default()
+    };
+    let runtime_only = analyze_bytecode_with_options(Address::ZERO, &creation, &options);
+    assert_eq!(runtime_only.protocol, "UniswapV2");
+    assert_eq!(runtime_only.code_size, runtime.len());
+    // Runtime input is analyzed whole.
+    let plain = analyze_bytecode_with_options(Address::ZERO, &runtime, &options);
+    assert_eq!(plain.code_size, runtime.len());
+}
+
+#[test]
+fn test_opcode_histogram_only_when_requested() {
+    let bytecode = load_fixture("univ2_uni_eth.hex");
+    assert!(analyze_bytecode(Address::ZERO, &bytecode)
+        .opcode_histogram
+        .is_none());
+
+    let options = AnalyzeOptions {
+        opcode_histogram: true,
+        ..AnalyzeOptions::default()
+    };
+    let analysis = analyze_bytecode_with_options(Address::ZERO, &bytecode, &options);
+    let histogram = analysis.opcode_histogram.unwrap();
+    assert_eq!(histogram.len(), 256);
+    assert!(histogram[0x63] > 0, "PUSH4 dispatcher comparisons");
+}
+
+#[test]
+fn test_known_contract_only_when_requested() {
+    let bytecode = load_fixture("univ3_usdc_eth.hex");
+    assert!(analyze_bytecode(Address::ZERO, &bytecode)
+        .known_contract
+        .is_none());
+
+    let options = AnalyzeOptions {
+        known_contracts: true,
+        ..AnalyzeOptions::default()
+    };
+    let analysis = analyze_bytecode_with_options(Address::ZERO, &bytecode, &options);
+    assert_eq!(analysis.known_contract.as_deref(), Some("UniswapV3Pool"));
+}
+
+#[test]
+fn test_pool_min_confidence_excludes_borderline_match() {
+    // UniswapV2 with only its four required selectors: confidence 4, no optional ones.
+    let bytecode: Vec<u8> = [
+        selectors::TOKEN0,
+        selectors::TOKEN1,
+        selectors::GET_RESERVES,
+        selectors::K_LAST,
+    ]
+    .iter()
+    .flat_map(|s| s.as_bytes().to_vec())
+    .collect();
+    let address = Address::ZERO;
+
+    let default = analyze_bytecode(address, &bytecode);
+    assert_eq!(default.protocol, "UniswapV2");
+    assert_eq!(default.pool_confidence, 4);
+    assert!(default.is_pool_likely);
+
+    let options = AnalyzeOptions {
+        pool_min_confidence: 5,
+        ..AnalyzeOptions::default()
+    };
+    let strict = analyze_bytecode_with_options(address, &bytecode, &options);
+    assert_eq!(strict.protocol, "UniswapV2");
+    assert!(!strict.is_pool_likely);
+
+    let real =
+        analyze_bytecode_with_options(address, &load_fixture("univ2_usdc_eth.hex"), &options);
+    assert!(real.pool_confidence >= 5);
+    assert!(real.is_pool_likely);
+}
+
+#[test]
+fn test_metadata_ipfs_cid_from_real_trailer() {
+    // Velodrome pool implementation, solc 0.8.19 with an `ipfs` metadata hash
+    let velo = analyze_bytecode(Address::ZERO, &load_fixture("velo_impl.hex"));
+    assert_eq!(
+        velo.metadata_ipfs.as_deref(),
+        Some("QmWLkeQeobHkwM4JomB3Eh9JkFDKcWSCMcApTXpMX2yfjC")
+    );
+    assert!(velo.metadata_swarm.is_none());
+
+    // Uniswap V2 pair, solc 0.5.16 with a `bzzr1` Swarm hash
+    let univ2 = analyze_bytecode(Address::ZERO, &load_fixture("univ2_usdc_eth.hex"));
+    assert!(univ2.metadata_ipfs.is_none());
+    assert_eq!(
+        univ2.metadata_swarm.as_deref(),
+        Some("7dca18479e58487606bf70c79e44d8dee62353c9ee6d01f9a9d70885b8765f22")
+    );
+
+    // Uniswap V3 pool was deployed with only the `solc` key
+    let univ3 = analyze_bytecode(Address::ZERO, &load_fixture("univ3_usdc_eth.hex"));
+    assert!(univ3.metadata_ipfs.is_none() && univ3.metadata_swarm.is_none());
+}
+
+#[tokio::test]
+async fn test_deadline_bounds_proxy_hops() {
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .latency(Duration::from_millis(150))
+        .start();
+    let proxy = PROXY.parse().unwrap();
+
+    // Each hop fits comfortably, but proxy + implementation does not.
+    let err = analyze_address_with_deadline(&rpc.url, proxy, Duration::from_millis(200))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AnalyzeError::Deadline(200)));
+
+    let report = analyze_address_with_deadline(&rpc.url, proxy, Duration::from_secs(10))
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+}
+
+#[tokio::test]
+async fn test_merge_proxy_flattens_proxied_and_direct_reports() {
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .start();
+    let pool_code = load_fixture("univ3_usdc_eth.hex");
+
+    let direct = analyze_address(&rpc.url, V3_POOL.parse().unwrap())
+        .await
+        .unwrap();
+    let expected = EffectiveVerdict {
+        address: V3_POOL.to_string(),
+        protocol: "UniswapV3".to_string(),
+        code_size: pool_code.len(),
+        is_pool_likely: true,
+        via_proxy: false,
+    };
+    assert_eq!(direct.merge_proxy(), expected);
+
+    // The proxy's verdict is the implementation's, at the implementation's address.
+    let proxied = analyze_address(&rpc.url, PROXY.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        proxied.merge_proxy(),
+        EffectiveVerdict {
+            via_proxy: true,
+            ..expected.clone()
+        }
+    );
+
+    // An unresolved proxy stands for itself.
+    let proxy = PROXY.parse().unwrap();
+    let options = AnalyzeOptions {
+        skip_proxy_resolution: [proxy].into(),
+        ..AnalyzeOptions::default()
+    };
+    let unresolved = analyze_address_with_options(&rpc.url, proxy, &options)
+        .await
+        .unwrap();
+    let verdict = unresolved.merge_proxy();
+    assert_eq!(verdict.address, PROXY);
+    assert!(!verdict.via_proxy && !verdict.is_pool_likely);
+}
+
+#[tokio::test]
+async fn test_include_bytecode_covers_proxy_and_implementation() {
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .start();
+    let proxy = PROXY.parse().unwrap();
+
+    let options = AnalyzeOptions {
+        include_bytecode: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, proxy, &options)
+        .await
+        .unwrap();
+    let json = serde_json::to_value(&report).unwrap();
+
+    for layer in ["analysis", "proxy_analysis"] {
+        let hex_code = json[layer]["bytecode_hex"]
+            .as_str()
+            .unwrap_or_else(|| panic!("{layer}.bytecode_hex missing"));
+        let decoded = hex::decode(hex_code.trim_start_matches("0x")).unwrap();
+        assert_eq!(
+            decoded.len() as u64,
+            json[layer]["code_size"].as_u64().unwrap()
+        );
+    }
+
+    let default = analyze_address_with_options(&rpc.url, proxy, &AnalyzeOptions::default())
+        .await
+        .unwrap();
+    assert!(default.analysis.bytecode_hex.is_none());
+}
+
+#[tokio::test]
+async fn test_proxy_report_exposes_implementation_fingerprint_hash() {
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .start();
+
+    let report = analyze_address(&rpc.url, PROXY.parse().unwrap())
+        .await
+        .unwrap();
+    let json = serde_json::to_value(&report).unwrap();
+
+    let hash = json["analysis"]["fingerprint"]["hash_hex"]
+        .as_str()
+        .unwrap();
+    assert_eq!(json["implementation_fingerprint_hash"], hash);
+    // 45 bytes is below the TLSH minimum: the proxy layer has no fingerprint of its own.
+    assert!(json["proxy_analysis"].get("fingerprint").is_none());
+
+    let direct = analyze_address(&rpc.url, V3_POOL.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        direct.implementation_fingerprint_hash.as_deref(),
+        Some(hash)
+    );
+}
+
+#[tokio::test]
+async fn test_system_addresses_are_reported_without_fetching_code() {
+    let fetched = Arc::new(Mutex::new(Vec::new()));
+    let log = fetched.clone();
+    let rpc = MockRpc::builder()
+        .handler(move |method, params| {
+            if method == "eth_getCode" {
+                log.lock().unwrap().push(params[0].clone());
+            }
+            None
+        })
+        .start();
+
+    let zero = analyze_address(&rpc.url, Address::ZERO).await.unwrap();
+    assert_eq!(zero.analysis.contract_kind.as_deref(), Some("ZeroAddress"));
+    assert!(zero.analysis.fingerprint_error.is_none());
+    assert!(!zero.analysis.is_pool_likely);
+
+    let sha256 = analyze_address(&rpc.url, Address::with_last_byte(0x02))
+        .await
+        .unwrap();
+    assert_eq!(sha256.analysis.contract_kind.as_deref(), Some("Precompile"));
+    assert_eq!(
+        sha256.analysis.contract_hint.as_deref(),
+        Some("sha256 precompile (no bytecode)")
+    );
+    assert_eq!(rpc.http_requests(), 0);
+
+    // Batch mode reports them alongside regular addresses.
+    let results = which_dex::analyze_addresses(
+        &rpc.url,
+        &[Address::with_last_byte(0x09), Address::with_last_byte(0xee)],
+        &AnalyzeOptions::default(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        results[0]
+            .1
+            .as_ref()
+            .unwrap()
+            .analysis
+            .contract_kind
+            .as_deref(),
+        Some("Precompile")
+    );
+    assert!(matches!(
+        results[1].1,
+        Err(AnalyzeError::NoDeployedBytecode)
+    ));
+    // Only the regular address goes into the eth_getCode batch.
+    assert_eq!(
+        *fetched.lock().unwrap(),
+        [format!("{:#x}", Address::with_last_byte(0xee))]
+    );
+}
+
+#[tokio::test]
+async fn test_curve_type_of_v2_shaped_pools() {
+    let v2 = analyze_bytecode(Address::ZERO, &load_fixture("univ2_usdc_eth.hex"));
+    assert_eq!(v2.curve_type.as_deref(), Some("constant_product"));
+
+    // A V2 pair that also serves stable() runs the stable invariant; no fingerprint
+    // claims it (UniswapV2 forbids stable(), Solidly forbids kLast()).
+    let mut stable_v2 = load_fixture("univ2_usdc_eth.hex");
+    stable_v2.push(0x63);
+    stable_v2.extend(selectors::STABLE.as_bytes());
+    let stable = analyze_bytecode(Address::ZERO, &stable_v2);
+    assert_eq!(stable.protocol, "Unknown");
+    assert_eq!(stable.curve_type.as_deref(), Some("stable"));
+
+    // Solidly's stable() is a storage flag: bytecode alone can't tell the curve.
+    let velo_code = load_fixture("velo_impl.hex");
+    let velo = analyze_bytecode(Address::ZERO, &velo_code);
+    assert_eq!(velo.protocol, "Solidly");
+    assert!(velo.curve_type.is_none());
+
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &velo_code)
+        .handler(|method, params| {
+            (method == "eth_call" && params[0]["input"] == "0x22be3de1")
+                .then(|| Ok(serde_json::json!(format!("0x{:0>64}", 1))))
+        })
+        .start();
+    let options = AnalyzeOptions {
+        resolve_onchain: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.curve_type.as_deref(), Some("stable"));
+}
+
+#[tokio::test]
+async fn test_trader_joe_v1_pair_is_identified_by_factory() {
+    const JOE_FACTORY: &str = "9ad6c38be94206ca50bb0d90783181662f0cfa10";
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("joe_v1_pair_synthetic.hex"))
+        .handler(|method, params| {
+            (method == "eth_call" && params[0]["input"] == "0xc45a0155")
+                .then(|| Ok(serde_json::json!(format!("0x{JOE_FACTORY:0>64}"))))
+        })
+        .start();
+    let pool = V2_POOL.parse().unwrap();
+
+    // JoePair has no selectors of its own: bytecode alone says UniswapV2.
+    let default = analyze_address(&rpc.url, pool).await.unwrap();
+    assert_eq!(default.analysis.protocol, "UniswapV2");
+    assert!(default.analysis.fork_family.is_none());
+
+    let options = AnalyzeOptions {
+        resolve_onchain: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, pool, &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "UniswapV2");
+    assert_eq!(report.analysis.fork_family.as_deref(), Some("TraderJoeV1"));
+    assert_eq!(
+        report.analysis.factory.as_deref(),
+        Some(format!("0x{JOE_FACTORY}").as_str())
+    );
+}
+
+#[tokio::test]
+async fn test_safe_proxy_resolves_singleton() {
+    const SINGLETON: &str = "d9db270c1b5e3bd161e8c8503c55ceabee709552";
+    let rpc = MockRpc::builder()
+        .code(PROXY, &load_fixture("safe_proxy.hex"))
+        .code(
+            &format!("0x{SINGLETON}"),
+            &load_fixture("safe_singleton_synthetic.hex"),
+        )
+        .handler(|method, params| {
+            (method == "eth_call" && params[0]["input"] == "0xa619486e")
+                .then(|| Ok(serde_json::json!(format!("0x{SINGLETON:0>64}"))))
+        })
+        .start();
+    let proxy = PROXY.parse().unwrap();
+
+    let report = analyze_address(&rpc.url, proxy).await.unwrap();
+    assert!(!report.is_eip1167_proxy);
+    assert_eq!(
+        report.implementation_address.as_deref(),
+        Some(format!("0x{SINGLETON}").as_str())
+    );
+    assert_eq!(report.analysis.contract_kind.as_deref(), Some("Safe"));
+    assert!(!report.analysis.is_pool_likely);
+    let proxy_analysis = report.proxy_analysis.as_ref().unwrap();
+    assert_eq!(proxy_analysis.contract_kind.as_deref(), Some("Safe"));
+
+    // Batch mode resolves the singleton too.
+    let results = which_dex::analyze_addresses(&rpc.url, &[proxy], &AnalyzeOptions::default())
+        .await
+        .unwrap();
+    let batch = results[0].1.as_ref().unwrap();
+    assert_eq!(batch.implementation_address, report.implementation_address);
+    assert_eq!(batch.analysis.contract_kind.as_deref(), Some("Safe"));
+}
+
+/// USDC and WETH appended to a clones-with-immutable-args proxy, plus the length suffix
+const CWIA_ARGS: &str =
+    "0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48c02aaa39b223fe8d0a0e5c4f27ead9083c756cc2002a";
+
+#[tokio::test]
+async fn test_clones_with_immutable_args_resolves_implementation() {
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &load_fixture("cwia_proxy.hex"))
+        .start();
+    let proxy = PROXY.parse().unwrap();
+
+    let report = analyze_address(&rpc.url, proxy).await.unwrap();
+    assert!(!report.is_eip1167_proxy);
+    assert_eq!(report.proxy_kind, Some(ProxyKind::ClonesWithImmutableArgs));
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    // Two token addresses plus the 2-byte length suffix
+    assert_eq!(report.immutable_args_len, Some(42));
+    assert_eq!(report.immutable_args.as_deref(), Some(CWIA_ARGS));
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+    let proxy_analysis = report.proxy_analysis.as_ref().unwrap();
+    assert!(proxy_analysis.fingerprint_error.is_none());
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["proxy_kind"], "clones_with_immutable_args");
+
+    let results = which_dex::analyze_addresses(&rpc.url, &[proxy], &AnalyzeOptions::default())
+        .await
+        .unwrap();
+    let batch = results[0].1.as_ref().unwrap();
+    assert_eq!(batch.implementation_address, report.implementation_address);
+    assert_eq!(batch.immutable_args_len, Some(42));
+    assert_eq!(batch.immutable_args, report.immutable_args);
+}
+
+#[tokio::test]
+async fn test_solady_clone_with_args_resolves_implementation() {
+    // LibClone's clone-with-args runtime: ETH receive hook, then CODECOPY of the
+    // calldata and args, DELEGATECALL to the implementation
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &load_fixture("solady_cwia_proxy.hex"))
+        .start();
+
+    let report = analyze_address(&rpc.url, PROXY.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(report.proxy_kind, Some(ProxyKind::ClonesWithImmutableArgs));
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    assert_eq!(report.immutable_args_len, Some(42));
+    assert_eq!(report.immutable_args.as_deref(), Some(CWIA_ARGS));
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["immutable_args"], CWIA_ARGS);
+}
+
+#[tokio::test]
+async fn test_impl_slot_resolves_proxy_with_custom_storage_layout() {
+    const SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+    let slot_reads = Arc::new(AtomicUsize::new(0));
+    let reads = slot_reads.clone();
+    // CALLDATACOPY, SLOAD <slot>, DELEGATECALL, bubble the result up
+    let proxy_code = hex::decode(format!(
+        "363d3d373d3d3d363d7f{}545af43d82803e903d91602b57fd5bf3",
+        &SLOT[2..]
+    ))
+    .unwrap();
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(PROXY, &proxy_code)
+        .handler(move |method, params| {
+            (method == "eth_getStorageAt").then(|| {
+                reads.fetch_add(1, Ordering::SeqCst);
+                match params[1].as_str() {
+                    Some(SLOT) => Ok(serde_json::json!(format!("0x{:0>64}", &V3_POOL[2..]))),
+                    _ => Ok(serde_json::json!("0x0")),
+                }
+            })
+        })
+        .start();
+    let proxy = PROXY.parse().unwrap();
+    let with_slot = |slot: &str| AnalyzeOptions {
+        impl_slot: Some(slot.parse().unwrap()),
+        ..AnalyzeOptions::default()
+    };
+
+    let report = analyze_address_with_options(&rpc.url, proxy, &with_slot(SLOT))
+        .await
+        .unwrap();
+    assert_eq!(report.proxy_kind, Some(ProxyKind::CustomSlot));
+    assert_eq!(report.impl_slot.as_deref(), Some(SLOT));
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["proxy_kind"], "custom_slot");
+
+    // Without the slot, or with an empty one, the proxy is analyzed by itself.
+    let plain = analyze_address(&rpc.url, proxy).await.unwrap();
+    assert!(plain.implementation_address.is_none());
+    assert!(plain.proxy_kind.is_none());
+    let empty_slot = format!("0x{:064x}", 1);
+    let empty = analyze_address_with_options(&rpc.url, proxy, &with_slot(&empty_slot))
+        .await
+        .unwrap();
+    assert!(empty.implementation_address.is_none());
+    assert!(empty.impl_slot.is_none());
+    assert_eq!(slot_reads.load(Ordering::SeqCst), 2);
+
+    // Code without DELEGATECALL never reads the slot.
+    let pool = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &with_slot(SLOT))
+        .await
+        .unwrap();
+    assert_eq!(pool.analysis.protocol, "UniswapV2");
+    assert!(pool.proxy_kind.is_none());
+    assert_eq!(slot_reads.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_proxy_that_is_also_a_pool_keeps_both_analyses() {
+    const SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+    // Synthetic diamond-ish proxy: a full V2 pair that also forwards to the slot's target
+    let mut proxy_code = load_fixture("univ2_usdc_eth.hex");
+    proxy_code.extend(
+        hex::decode(format!(
+            "363d3d373d3d3d363d7f{}545af43d82803e903d91602b57fd5bf3",
+            &SLOT[2..]
+        ))
+        .unwrap(),
+    );
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &proxy_code)
+        .handler(|method, _| {
+            (method == "eth_getStorageAt")
+                .then(|| Ok(serde_json::json!(format!("0x{:0>64}", &V3_POOL[2..]))))
+        })
+        .start();
+    let options = AnalyzeOptions {
+        impl_slot: Some(SLOT.parse().unwrap()),
+        ..AnalyzeOptions::default()
+    };
+
+    let report = analyze_address_with_options(&rpc.url, PROXY.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+    assert!(report.proxy_is_also_pool);
+    let proxy = report.proxy_analysis.as_ref().unwrap();
+    assert_eq!(proxy.protocol, "UniswapV2");
+    assert!(proxy.is_pool_likely);
+    assert!(proxy.fingerprint.is_some());
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["proxy_is_also_pool"], true);
+    assert_eq!(json["proxy_analysis"]["protocol"], "UniswapV2");
+
+    // A plain forwarding stub is not.
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .start();
+    let stub = analyze_address(&rpc.url, PROXY.parse().unwrap())
+        .await
+        .unwrap();
+    assert!(!stub.proxy_is_also_pool);
+    assert_eq!(
+        serde_json::to_value(&stub).unwrap()["proxy_is_also_pool"],
+        false
+    );
+}
+
+#[tokio::test]
+async fn test_scan_tokens_flags_owner_controls_of_pool_tokens() {
+    const RISKY: &str = "00000000000000000000000000000000000000d0";
+    const CLEAN: &str = "00000000000000000000000000000000000000d1";
+    // Dispatcher comparisons against `blacklist(address)` and `setMaxTxAmount(uint256)`
+    let risky: Vec<u8> = [selectors::BLACKLIST, selectors::SET_MAX_TX_AMOUNT]
+        .iter()
+        .flat_map(|s| [&[0x63][..], s.as_bytes()].concat())
+        .collect();
+    // `isBlacklisted(address)` only inside PUSH32 data: not a function
+    let clean: Vec<u8> = [0x63]
+        .into_iter()
+        .chain(*selectors::TRANSFER.as_bytes())
+        .chain([0x7f])
+        .chain(*selectors::IS_BLACKLISTED.as_bytes())
+        .chain([0; 28])
+        .collect();
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_uni_eth.hex"))
+        .code(&format!("0x{RISKY}"), &risky)
+        .code(&format!("0x{CLEAN}"), &clean)
+        .handler(|method, params| {
+            if method != "eth_call" {
+                return None;
+            }
+            let token = match params[0]["input"].as_str()? {
+                "0x0dfe1681" => RISKY,
+                "0xd21220a7" => CLEAN,
+                _ => return None,
+            };
+            Some(Ok(serde_json::json!(format!("0x{token:0>64}"))))
+        })
+        .start();
+    let pool = V2_POOL.parse().unwrap();
+
+    let default = analyze_address(&rpc.url, pool).await.unwrap();
+    assert!(default.token_risk_flags.is_empty());
+    assert!(serde_json::to_value(&default)
+        .unwrap()
+        .get("token_risk_flags")
+        .is_none());
+
+    let options = AnalyzeOptions {
+        scan_tokens: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, pool, &options)
+        .await
+        .unwrap();
+    // Protocol detection is untouched.
+    assert_eq!(report.analysis.protocol, "UniswapV2");
+    assert_eq!(
+        report.token_risk_flags,
+        [
+            format!("token0 0x{RISKY}: blacklist(address)"),
+            format!("token0 0x{RISKY}: setMaxTxAmount(uint256)"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_scan_tokens_looks_behind_token_proxies() {
+    const TOKEN: &str = "00000000000000000000000000000000000000d0";
+    const TOKEN_IMPL: &str = "00000000000000000000000000000000000000d1";
+    let implementation: Vec<u8> = [0x63]
+        .into_iter()
+        .chain(*selectors::IS_BLACKLISTED.as_bytes())
+        .collect();
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_uni_eth.hex"))
+        .code(&format!("0x{TOKEN}"), &eip1167_proxy(TOKEN_IMPL))
+        .code(&format!("0x{TOKEN_IMPL}"), &implementation)
+        .handler(|method, params| {
+            (method == "eth_call" && params[0]["input"] == "0x0dfe1681")
+                .then(|| Ok(serde_json::json!(format!("0x{TOKEN:0>64}"))))
+        })
+        .start();
+    let mut options = AnalyzeOptions {
+        scan_tokens: true,
+        ..AnalyzeOptions::default()
+    };
+
+    let report = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(
+        report.token_risk_flags,
+        [format!("token0 0x{TOKEN}: isBlacklisted(address)")]
+    );
+
+    // Under a state override the overridden implementation is scanned.
+    let mut overrides = StateOverride::default();
+    overrides.insert(
+        format!("0x{TOKEN_IMPL}").parse().unwrap(),
+        AccountOverride {
+            code: Some(hex::decode("6080604052600080fd").unwrap().into()),
+            ..AccountOverride::default()
+        },
+    );
+    let overridden = AnalyzeOptions {
+        state_override: Some(Arc::new(overrides)),
+        ..options.clone()
+    };
+    let report = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &overridden)
+        .await
+        .unwrap();
+    assert!(report.token_risk_flags.is_empty());
+
+    // Skipping the token's resolution scans the stub itself.
+    options.skip_proxy_resolution = [format!("0x{TOKEN}").parse().unwrap()].into();
+    let report = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert!(report.token_risk_flags.is_empty());
+}
+
+#[tokio::test]
+async fn test_safe_proxy_without_singleton_is_still_safe() {
+    let rpc = MockRpc::builder()
+        .code(PROXY, &load_fixture("safe_proxy.hex"))
+        .start();
+
+    let report = analyze_address(&rpc.url, PROXY.parse().unwrap())
+        .await
+        .unwrap();
+    assert!(report.implementation_address.is_none());
+    assert_eq!(report.analysis.contract_kind.as_deref(), Some("Safe"));
+}
+
+#[tokio::test]
+async fn test_state_override_analyzes_hypothetical_implementation() {
+    const SINGLETON: &str = "00000000000000000000000000000000000000dd";
+    const SAFE: &str = "0x00000000000000000000000000000000000000ab";
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .code(SAFE, &load_fixture("safe_proxy.hex"))
+        // Only answers `masterCopy()` when the override reaches the endpoint.
+        .handler(|method, params| {
+            (method == "eth_call"
+                && params[0]["input"] == "0xa619486e"
+                && params[2][SAFE]["stateDiff"].is_object())
+            .then(|| Ok(serde_json::json!(format!("0x{SINGLETON:0>64}"))))
+        })
+        .start();
+
+    let mut overrides = StateOverride::default();
+    overrides.insert(
+        V3_POOL.parse().unwrap(),
+        AccountOverride {
+            code: Some(load_fixture("univ2_usdc_eth.hex").into()),
+            ..AccountOverride::default()
+        },
+    );
+    overrides.insert(
+        SAFE.parse().unwrap(),
+        AccountOverride {
+            state_diff: Some(
+                [(B256::ZERO, format!("0x{SINGLETON:0>64}").parse().unwrap())]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..AccountOverride::default()
+        },
+    );
+    overrides.insert(
+        format!("0x{SINGLETON}").parse().unwrap(),
+        AccountOverride {
+            code: Some(load_fixture("safe_singleton_synthetic.hex").into()),
+            ..AccountOverride::default()
+        },
+    );
+    let options = AnalyzeOptions {
+        state_override: Some(Arc::new(overrides)),
+        ..AnalyzeOptions::default()
+    };
+
+    // The overridden implementation code replaces the deployed one.
+    let report = analyze_address_with_options(&rpc.url, PROXY.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    assert_eq!(report.analysis.protocol, "UniswapV2");
+
+    // Without it the chain's code is analyzed.
+    let report = analyze_address(&rpc.url, PROXY.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+
+    // The map is forwarded with `eth_call`: a storage override repoints the Safe.
+    let report = analyze_address_with_options(&rpc.url, SAFE.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(
+        report.implementation_address.as_deref(),
+        Some(format!("0x{SINGLETON}").as_str())
+    );
+    assert_eq!(report.analysis.contract_kind.as_deref(), Some("Safe"));
+}
+
+#[tokio::test]
+async fn test_eip7702_delegation_reports_account_and_analyzes_target() {
+    const UNDEPLOYED: &str = "00000000000000000000000000000000000000bb";
+    const DELEGATED_NOWHERE: &str = "0x00000000000000000000000000000000000000cc";
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip7702_designator(&V3_POOL[2..]))
+        .code(DELEGATED_NOWHERE, &eip7702_designator(UNDEPLOYED))
+        .start();
+    let account = PROXY.parse().unwrap();
+
+    let report = analyze_address(&rpc.url, account).await.unwrap();
+    assert!(!report.is_eip1167_proxy);
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    // The target's code is analyzed, but the account itself is not a pool.
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+    assert!(!report.analysis.is_pool_likely);
+    assert_eq!(
+        report.analysis.contract_kind.as_deref(),
+        Some("Eip7702Delegation")
+    );
+    let expected_hint = format!("EIP-7702 delegation to {V3_POOL}");
+    assert_eq!(
+        report.analysis.contract_hint.as_deref(),
+        Some(expected_hint.as_str())
+    );
+    let designator = report.proxy_analysis.as_ref().unwrap();
+    assert_eq!(designator.code_size, 23);
+    assert!(designator.fingerprint.is_none());
+    assert!(designator.fingerprint_error.is_none());
+
+    // Delegating to an address without code: the designator alone is reported.
+    let nowhere = analyze_address(&rpc.url, DELEGATED_NOWHERE.parse().unwrap())
+        .await
+        .unwrap();
+    assert!(nowhere.implementation_address.is_none());
+    assert_eq!(nowhere.analysis.protocol, "Unknown");
+    assert_eq!(
+        nowhere.analysis.contract_kind.as_deref(),
+        Some("Eip7702Delegation")
+    );
+    assert!(nowhere.analysis.fingerprint_error.is_none());
+
+    let results = which_dex::analyze_addresses(
+        &rpc.url,
+        &[account, DELEGATED_NOWHERE.parse().unwrap()],
+        &AnalyzeOptions::default(),
+    )
+    .await
+    .unwrap();
+    let batch = results[0].1.as_ref().unwrap();
+    assert_eq!(batch.implementation_address, report.implementation_address);
+    assert!(!batch.analysis.is_pool_likely);
+    assert!(results[1].1.is_ok());
+}
+
+#[tokio::test]
+async fn test_resolve_onchain_identifies_v2_fork_by_factory() {
+    const SUSHI_FACTORY: &str = "c0aee478e3658e2610c5f7a4a2e1777ce9e4f2ac";
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("sushi_usdc_eth.hex"))
+        .handler(|method, params| {
+            (method == "eth_call" && params[0]["input"] == "0xc45a0155")
+                .then(|| Ok(serde_json::json!(format!("0x{SUSHI_FACTORY:0>64}"))))
+        })
+        .start();
+    let pool = V2_POOL.parse().unwrap();
+
+    let default = analyze_address_with_options(&rpc.url, pool, &AnalyzeOptions::default())
+        .await
+        .unwrap();
+    assert_eq!(default.analysis.protocol, "UniswapV2");
+    assert!(default.analysis.fork_family.is_none());
+    assert!(default.analysis.factory.is_none());
+
+    let options = AnalyzeOptions {
+        resolve_onchain: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, pool, &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "UniswapV2");
+    assert_eq!(report.analysis.fork_family.as_deref(), Some("SushiSwap"));
+    assert_eq!(
+        report.analysis.factory.as_deref(),
+        Some(format!("0x{SUSHI_FACTORY}").as_str())
+    );
+}
+
+#[tokio::test]
+async fn test_resolve_onchain_names_base_v3_fork_by_factory() {
+    const BASESWAP_V3_FACTORY: &str = "38015d05f4fec8afe15d7cc0386a126574e8077b";
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .handler(|method, params| {
+            (method == "eth_call" && params[0]["input"] == "0xc45a0155")
+                .then(|| Ok(serde_json::json!(format!("0x{BASESWAP_V3_FACTORY:0>64}"))))
+        })
+        .start();
+    let options = AnalyzeOptions {
+        resolve_onchain: true,
+        ..AnalyzeOptions::default()
+    };
+
+    let report = analyze_address_with_options(&rpc.url, V3_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "UniswapV3");
+    assert_eq!(report.analysis.fork_family.as_deref(), Some("BaseSwapV3"));
+}
+
+#[tokio::test]
+async fn test_factory_allowlist_verifies_resolved_factory() {
+    const SUSHI_FACTORY: &str = "c0aee478e3658e2610c5f7a4a2e1777ce9e4f2ac";
+    const SPOOF_FACTORY: &str = "00000000000000000000000000000000000000ff";
+    // A pair without price0CumulativeLast(): a V2 match short of full confidence
+    let mut code = load_fixture("sushi_usdc_eth.hex");
+    let price0 = selectors::PRICE0_CUMULATIVE_LAST.as_bytes();
+    let at = code
+        .windows(4)
+        .position(|w| w == price0)
+        .expect("fixture dispatches price0CumulativeLast()");
+    code[at + 3] ^= 0xff;
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &code)
+        .code(PROXY, &code)
+        .handler(|method, params| match method {
+            "eth_chainId" => Some(Ok(serde_json::json!("0x1"))),
+            "eth_call" if params[0]["input"] == "0xc45a0155" => {
+                let factory = if params[0]["to"] == V2_POOL {
+                    SUSHI_FACTORY
+                } else {
+                    SPOOF_FACTORY
+                };
+                Some(Ok(serde_json::json!(format!("0x{factory:0>64}"))))
+            }
+            _ => None,
+        })
+        .start();
+    let allowlist = FactoryAllowlist::parse(&format!(
+        "chain_id,factory,protocol\n1,0x{SUSHI_FACTORY},UniswapV2"
+    ))
+    .unwrap();
+    let options = AnalyzeOptions {
+        resolve_onchain: true,
+        factory_allowlist: Some(Arc::new(allowlist)),
+        ..AnalyzeOptions::default()
+    };
+
+    let listed = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap()
+        .analysis;
+    assert_eq!(listed.factory_verified, Some(true));
+    let max = fingerprint_for(DexProtocol::UniswapV2)
+        .unwrap()
+        .max_confidence();
+    assert_eq!(listed.pool_confidence, max);
+
+    // Same pool code, unknown factory: a look-alike.
+    let spoofed = analyze_address_with_options(&rpc.url, PROXY.parse().unwrap(), &options)
+        .await
+        .unwrap()
+        .analysis;
+    assert_eq!(spoofed.protocol, "UniswapV2");
+    assert_eq!(spoofed.factory_verified, Some(false));
+    assert!(spoofed.pool_confidence < max);
+
+    // Without the allowlist nothing is verified.
+    let options = AnalyzeOptions {
+        factory_allowlist: None,
+        ..options
+    };
+    let unchecked = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap()
+        .analysis;
+    assert_eq!(unchecked.factory_verified, None);
+}
+
+#[tokio::test]
+async fn test_every_empty_code_shape_is_no_deployed_bytecode() {
+    let rpc = MockRpc::builder()
+        .handler(|method, params| {
+            if method != "eth_getCode" {
+                return None;
+            }
+            match params[0].as_str()? {
+                "0x00000000000000000000000000000000000000e1" => Some(Ok(serde_json::Value::Null)),
+                "0x00000000000000000000000000000000000000e2" => Some(Ok(serde_json::json!(""))),
+                "0x00000000000000000000000000000000000000e3" => Some(Ok(serde_json::json!("0x"))),
+                "0x00000000000000000000000000000000000000e4" => Some(Ok(serde_json::json!("0x0"))),
+                "0x00000000000000000000000000000000000000e7" => Some(Ok(serde_json::json!("0x00"))),
+                "0x00000000000000000000000000000000000000e5" => Some(Err(
+                    serde_json::json!({ "code": -32000, "message": "upstream unavailable" }),
+                )),
+                "0x00000000000000000000000000000000000000e6" => Some(Err(
+                    serde_json::json!({ "code": -32005, "message": "daily request count exceeded, request rate limited" }),
+                )),
+                _ => None,
+            }
+        })
+        .start();
+
+    for last in ["e1", "e2", "e3", "e4"] {
+        let address = format!("0x{last:0>40}").parse().unwrap();
+        let result = analyze_address(&rpc.url, address).await;
+        assert!(
+            matches!(result, Err(AnalyzeError::NoDeployedBytecode)),
+            "{last}: {result:?}"
+        );
+    }
+
+    let address = format!("0x{:0>40}", "e5").parse().unwrap();
+    let result = analyze_address(&rpc.url, address).await;
+    assert!(
+        matches!(
+            result,
+            Err(AnalyzeError::Rpc(RpcError::Server { code: -32000, .. }))
+        ),
+        "{result:?}"
+    );
+
+    let address = format!("0x{:0>40}", "e6").parse().unwrap();
+    let result = analyze_address(&rpc.url, address).await;
+    assert!(
+        matches!(result, Err(AnalyzeError::Rpc(RpcError::RateLimited(_)))),
+        "{result:?}"
+    );
+
+    // A single STOP is deployed code, not an empty account.
+    let address = format!("0x{:0>40}", "e7").parse().unwrap();
+    let report = analyze_address(&rpc.url, address).await.unwrap();
+    assert_eq!(report.analysis.code_size, 1);
+}
+
+fn reference_db() -> FingerprintDb {
+    let mut db = FingerprintDb::new();
+    for (protocol, fixture) in [
+        (DexProtocol::UniswapV2, "univ2_usdc_eth.hex"),
+        (DexProtocol::UniswapV3, "univ3_usdc_eth.hex"),
+    ] {
+        let fp = BytecodeFingerprint::from_bytecode(&load_fixture(fixture)).unwrap();
+        db.insert(protocol, fp);
+    }
+    db
+}
+
+#[test]
+fn test_detection_method_selector_and_tlsh_agree() {
+    let code = load_fixture("univ3_usdc_eth_005.hex");
+    assert_eq!(
+        analyze_bytecode(Address::ZERO, &code).detection_method,
+        "selector"
+    );
+
+    let options = AnalyzeOptions::default();
+    let analysis = analyze_bytecode_with_db(Address::ZERO, &code, &options, &reference_db());
+    assert_eq!(analysis.protocol, "UniswapV3");
+    assert_eq!(analysis.detection_method, "selector+tlsh_agree");
+    assert!(analysis.pool_confidence > 0);
+}
+
+#[test]
+fn test_detection_method_tlsh_fallback_on_unknown() {
+    // A V2 pair whose getReserves() dispatch was mangled: selectors give up, the code
+    // shape still matches the UniswapV2 reference.
+    let mut code = load_fixture("univ2_uni_eth.hex");
+    let get_reserves = selectors::GET_RESERVES.as_bytes();
+    let at = code
+        .windows(4)
+        .position(|w| w == get_reserves)
+        .expect("fixture dispatches getReserves()");
+    code[at + 3] ^= 0xff;
+
+    let options = AnalyzeOptions::default();
+    assert_eq!(analyze_bytecode(Address::ZERO, &code).protocol, "Unknown");
+    let analysis = analyze_bytecode_with_db(Address::ZERO, &code, &options, &reference_db());
+    assert_eq!(analysis.protocol, "UniswapV2");
+    assert_eq!(analysis.detection_method, "tlsh_fallback");
+    assert_eq!(analysis.pool_confidence, 0);
+    assert!(analysis.is_pool_likely);
+
+    // Nothing in the same family: the selector verdict stands.
+    let mut db = FingerprintDb::new();
+    db.insert(
+        DexProtocol::UniswapV3,
+        BytecodeFingerprint::from_bytecode(&load_fixture("univ3_usdc_eth.hex")).unwrap(),
+    );
+    let analysis = analyze_bytecode_with_db(Address::ZERO, &code, &options, &db);
+    assert_eq!(analysis.protocol, "Unknown");
+    assert_eq!(analysis.detection_method, "selector");
+}
+
+#[tokio::test]
+async fn test_created_contract_address_from_receipt() {
+    const CREATION: &str = "0x1111111111111111111111111111111111111111111111111111111111111111";
+    const TRANSFER: &str = "0x2222222222222222222222222222222222222222222222222222222222222222";
+    const PENDING: &str = "0x3333333333333333333333333333333333333333333333333333333333333333";
+    let rpc = MockRpc::builder()
+        .handler(|method, params| {
+            (method == "eth_getTransactionReceipt").then(|| {
+                Ok(match params[0].as_str().unwrap() {
+                    CREATION => serde_json::json!({ "status": "0x1", "contractAddress": V2_POOL }),
+                    TRANSFER => serde_json::json!({ "status": "0x1", "contractAddress": null }),
+                    _ => serde_json::Value::Null,
+                })
+            })
+        })
+        .start();
+
+    let created = created_contract_address(&rpc.url, parse_tx_hash(CREATION).unwrap())
+        .await
+        .unwrap();
+    assert_eq!(created, V2_POOL.parse::<Address>().unwrap());
+
+    let err = created_contract_address(&rpc.url, parse_tx_hash(TRANSFER).unwrap())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(err, AnalyzeError::NotContractCreation(ref h) if h == TRANSFER),
+        "{err}"
+    );
+
+    let err = created_contract_address(&rpc.url, parse_tx_hash(PENDING).unwrap())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AnalyzeError::TransactionNotFound(_)), "{err}");
+
+    assert!(matches!(
+        parse_tx_hash(V2_POOL),
+        Err(AnalyzeError::InvalidTxHash)
+    ));
+}
+
+#[test]
+fn test_parse_block_id_accepts_tags_numbers_and_hashes() {
+    assert_eq!(parse_block_id("latest").unwrap(), BlockId::latest());
+    assert_eq!(parse_block_id("Finalized").unwrap(), BlockId::finalized());
+    assert_eq!(parse_block_id("safe").unwrap(), BlockId::safe());
+    assert_eq!(parse_block_id("earliest").unwrap(), BlockId::earliest());
+    assert_eq!(parse_block_id("pending").unwrap(), BlockId::pending());
+    assert_eq!(
+        parse_block_id("19000000").unwrap(),
+        BlockId::number(19_000_000)
+    );
+    assert_eq!(
+        parse_block_id("0x121eac0").unwrap(),
+        BlockId::number(19_000_000)
+    );
+    let hash = "0x4444444444444444444444444444444444444444444444444444444444444444";
+    assert_eq!(
+        parse_block_id(hash).unwrap(),
+        BlockId::from(hash.parse::<B256>().unwrap())
+    );
+
+    for invalid in ["", "final", "-1", "0x", "0xzz", V2_POOL, "1.5"] {
+        let err = parse_block_id(invalid).unwrap_err();
+        assert!(
+            matches!(&err, AnalyzeError::InvalidBlock(b) if b == invalid),
+            "{err}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_block_applies_to_every_read() {
+    const SLOT: &str = "0x360894a13ba1a3210667c828492db98dca3e2076cc3735a920a3ca505d382bbc";
+    // CALLDATACOPY, SLOAD <slot>, DELEGATECALL, bubble the result up
+    let proxy_code = hex::decode(format!(
+        "363d3d373d3d3d363d7f{}545af43d82803e903d91602b57fd5bf3",
+        &SLOT[2..]
+    ))
+    .unwrap();
+    let blocks = Arc::new(Mutex::new(Vec::new()));
+    let seen = blocks.clone();
+    let rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &proxy_code)
+        .handler(move |method, params| {
+            let block = match method {
+                "eth_getCode" | "eth_call" => &params[1],
+                "eth_getStorageAt" => &params[2],
+                _ => return None,
+            };
+            seen.lock()
+                .unwrap()
+                .push((method.to_string(), block.clone()));
+            match method {
+                "eth_getStorageAt" => {
+                    Some(Ok(serde_json::json!(format!("0x{:0>64}", &V3_POOL[2..]))))
+                }
+                "eth_call" => Some(Ok(serde_json::json!("0x"))),
+                _ => None,
+            }
+        })
+        .start();
+    let options = AnalyzeOptions {
+        block: parse_block_id("finalized").unwrap(),
+        impl_slot: Some(SLOT.parse().unwrap()),
+        resolve_onchain: true,
+        ..AnalyzeOptions::default()
+    };
+
+    let report = analyze_address_with_options(&rpc.url, PROXY.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+    let reads = std::mem::take(&mut *blocks.lock().unwrap());
+    for method in ["eth_getCode", "eth_getStorageAt", "eth_call"] {
+        assert!(
+            reads.iter().any(|(m, _)| m == method),
+            "{method}: {reads:?}"
+        );
+    }
+    assert!(
+        reads.iter().all(|(_, block)| block == "finalized"),
+        "{reads:?}"
+    );
+
+    // Batch fetches too
+    let options = AnalyzeOptions {
+        block: BlockId::number(19_000_000),
+        ..AnalyzeOptions::default()
+    };
+    which_dex::analyze_addresses(&rpc.url, &[V3_POOL.parse().unwrap()], &options)
+        .await
+        .unwrap();
+    let reads = blocks.lock().unwrap();
+    assert!(!reads.is_empty());
+    assert!(
+        reads.iter().all(|(_, block)| block == "0x121eac0"),
+        "{reads:?}"
+    );
+}
+
+#[test]
+fn test_explain_near_miss_names_closest_family_and_missing_selector() {
+    // A UniswapV3 pool whose fee() dispatch was renamed.
+    let mut code = load_fixture("univ3_usdc_eth_005.hex");
+    let fee = selectors::FEE.as_bytes();
+    while let Some(at) = code.windows(4).position(|w| w == fee) {
+        code[at + 3] ^= 0xff;
+    }
+
+    let options = AnalyzeOptions {
+        explain: true,
+        ..AnalyzeOptions::default()
+    };
+    let analysis = analyze_bytecode_with_options(Address::ZERO, &code, &options);
+    assert_eq!(analysis.protocol, "Unknown");
+    assert_eq!(
+        analysis.explanation.as_deref(),
+        Some(
+            "SolidlyCL is missing required selector stable(); \
+             UniswapV3 is missing required selector fee()."
+        )
+    );
+
+    let mut db = FingerprintDb::new();
+    db.insert(
+        DexProtocol::UniswapV3,
+        BytecodeFingerprint::from_bytecode(&load_fixture("univ3_usdc_eth.hex")).unwrap(),
+    );
+    let options = AnalyzeOptions {
+        fingerprint_db: Some(std::sync::Arc::new(db)),
+        ..options
+    };
+    let analysis = analyze_bytecode_with_options(Address::ZERO, &code, &options);
+    // The database only explains; it doesn't decide without `combined`.
+    assert_eq!(analysis.protocol, "Unknown");
+    let explanation = analysis.explanation.unwrap();
+    assert!(
+        explanation.starts_with("Closest family: UniswapV3 (TLSH diff "),
+        "{explanation}"
+    );
+    assert!(
+        explanation.ends_with(
+            "); missing required selector fee(); \
+             SolidlyCL is missing required selector stable()."
+        ),
+        "{explanation}"
+    );
+
+    // Identified protocols need no explanation.
+    let v3 =
+        analyze_bytecode_with_options(Address::ZERO, &load_fixture("univ3_usdc_eth.hex"), &options);
+    assert_eq!(v3.explanation, None);
+}
+
+#[tokio::test]
+async fn test_dynamic_probe_classifies_inconclusive_bytecode() {
+    // Code with no recognizable dispatcher; the calls still answer like a V2 pair.
+    let answering = [
+        selectors::TOKEN0,
+        selectors::TOKEN1,
+        selectors::GET_RESERVES,
+        selectors::K_LAST,
+        selectors::FACTORY,
+    ];
+    let handler = move |method: &str, params: &serde_json::Value| {
+        if method != "eth_call" {
+            return None;
+        }
+        let input = params[0]["input"].as_str().unwrap();
+        Some(
+            if answering
+                .iter()
+                .any(|s| input == format!("0x{}", hex::encode(s.as_bytes())))
+            {
+                Ok(serde_json::json!(format!("0x{:0>64}", 1)))
+            } else {
+                Err(serde_json::json!({ "code": 3, "message": "execution reverted" }))
+            },
+        )
+    };
+    let rpc = MockRpc::builder()
+        .code(
+            V2_POOL,
+            &[0x60, 0x80, 0x60, 0x40, 0x52, 0x36, 0x60, 0x00, 0xf3],
+        )
+        .handler(handler)
+        .start();
+    let address = V2_POOL.parse().unwrap();
+
+    let static_only = analyze_address(&rpc.url, address).await.unwrap();
+    assert_eq!(static_only.analysis.protocol, "Unknown");
+    assert_eq!(static_only.analysis.dynamic_probes, None);
+
+    let options = AnalyzeOptions {
+        dynamic: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, address, &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "UniswapV2");
+    assert_eq!(report.analysis.detection_method, "dynamic_probe");
+    assert_eq!(report.analysis.pool_confidence, 5);
+    assert!(report.analysis.is_pool_likely);
+    assert_eq!(
+        report.analysis.dynamic_probes.unwrap(),
+        [
+            "token0()",
+            "token1()",
+            "getReserves()",
+            "kLast()",
+            "factory()"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_dynamic_probe_follows_the_reported_verdict() {
+    let rpc = MockRpc::builder()
+        .code(
+            V2_POOL,
+            &[0x60, 0x80, 0x60, 0x40, 0x52, 0x36, 0x60, 0x00, 0xf3],
+        )
+        .code(PROXY, &eip1167_proxy(&V2_POOL[2..]))
+        .handler(|method, params| {
+            let answers = [
+                selectors::TOKEN0,
+                selectors::TOKEN1,
+                selectors::GET_RESERVES,
+                selectors::K_LAST,
+                selectors::FACTORY,
+            ]
+            .map(|s| format!("0x{}", hex::encode(s.as_bytes())));
+            (method == "eth_call").then(|| {
+                if answers.iter().any(|a| params[0]["input"] == *a) {
+                    Ok(serde_json::json!(format!("0x{:0>64}", 1)))
+                } else {
+                    Err(serde_json::json!({ "code": 3, "message": "execution reverted" }))
+                }
+            })
+        })
+        .start();
+    let proxy = PROXY.parse().unwrap();
+    let mut options = AnalyzeOptions {
+        dynamic: true,
+        ..AnalyzeOptions::default()
+    };
+
+    // The verdict is the implementation's, which is inconclusive: probe.
+    let report = analyze_address_with_options(&rpc.url, proxy, &options)
+        .await
+        .unwrap();
+    assert_eq!(report.implementation_address.as_deref(), Some(V2_POOL));
+    assert_eq!(report.analysis.detection_method, "dynamic_probe");
+
+    // An unresolved proxy is reported as itself; probing would answer for its
+    // implementation instead.
+    options.skip_proxy_resolution = [proxy].into();
+    let report = analyze_address_with_options(&rpc.url, proxy, &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "Unknown");
+    assert_eq!(report.analysis.dynamic_probes, None);
+    let batch = which_dex::analyze_addresses(&rpc.url, &[proxy], &options)
+        .await
+        .unwrap();
+    let report = batch[0].1.as_ref().unwrap();
+    assert_eq!(report.analysis.protocol, "Unknown");
+    assert_eq!(report.analysis.dynamic_probes, None);
+}
+
+#[tokio::test]
+async fn test_dynamic_probe_ignores_catch_all_fallback() {
+    // Answers every call, as a fallback returning a word would.
+    let rpc = MockRpc::builder()
+        .code(
+            V2_POOL,
+            &[0x60, 0x80, 0x60, 0x40, 0x52, 0x36, 0x60, 0x00, 0xf3],
+        )
+        .handler(|method, _| {
+            (method == "eth_call").then(|| Ok(serde_json::json!(format!("0x{:0>64}", 1))))
+        })
+        .start();
+    let options = AnalyzeOptions {
+        dynamic: true,
+        ..AnalyzeOptions::default()
+    };
+    let report = analyze_address_with_options(&rpc.url, V2_POOL.parse().unwrap(), &options)
+        .await
+        .unwrap();
+    assert_eq!(report.analysis.protocol, "Unknown");
+    assert_eq!(report.analysis.detection_method, "selector");
+    assert_eq!(report.analysis.dynamic_probes, None);
+}
@@ -0,0 +1,556 @@
+//! Batch analysis against a local mock JSON-RPC endpoint
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use alloy::primitives::Address;
+use common::{eip1167_proxy, load_fixture, MockRpc};
+use which_dex::{
+    analyze_addresses, analyze_addresses_grouped_by_implementation, analyze_addresses_with_metrics,
+    compare_implementations, compare_many, group_by_fingerprint, parse_address_list, AnalyzeError,
+    AnalyzeOptions, AnalyzerMetrics, Concurrency, InMemoryMetrics, RpcError, Similarity,
+};
+
+const V2_POOL: &str = "0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc";
+const V3_POOL: &str = "0x8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
+const PROXY: &str = "0x00000000000000000000000000000000000000aa";
+const EOA: &str = "0x00000000000000000000000000000000000000bb";
+const FAILING: &str = "0x00000000000000000000000000000000000000cc";
+
+fn mock(batch_support: bool) -> MockRpc {
+    let builder = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]));
+    if batch_support {
+        builder.start()
+    } else {
+        builder.without_batch_support().start()
+    }
+}
+
+fn addresses() -> Vec<alloy::primitives::Address> {
+    parse_address_list(&[V2_POOL, V3_POOL, PROXY, EOA].join("\n")).unwrap()
+}
+
+#[tokio::test]
+async fn test_batch_run_reuses_one_connection() {
+    const FACTORY: &str = "c0aee478e3658e2610c5f7a4a2e1777ce9e4f2ac";
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .handler(|method, _| {
+            (method == "eth_call").then(|| Ok(serde_json::json!(format!("0x{FACTORY:0>64}"))))
+        })
+        .start();
+    // Several batches, proxy hops and one `factory()` call per pool
+    let options = AnalyzeOptions {
+        batch_size: 2,
+        resolve_onchain: true,
+        ..AnalyzeOptions::default()
+    };
+
+    let results = analyze_addresses(&rpc.url, &addresses(), &options)
+        .await
+        .unwrap();
+    assert!(results[0].1.as_ref().unwrap().analysis.factory.is_some());
+    assert!(rpc.http_requests() > 4);
+    assert_eq!(rpc.connections(), 1);
+
+    analyze_addresses_grouped_by_implementation(&rpc.url, &addresses(), &options)
+        .await
+        .unwrap();
+    assert_eq!(rpc.connections(), 2);
+}
+
+#[tokio::test]
+async fn test_batch_results_in_input_order() {
+    let rpc = mock(true);
+    let results = analyze_addresses(&rpc.url, &addresses(), &AnalyzeOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(results.len(), 4);
+    let protocol = |i: usize| results[i].1.as_ref().unwrap().analysis.protocol.clone();
+    assert_eq!(protocol(0), "UniswapV2");
+    assert_eq!(protocol(1), "UniswapV3");
+
+    let proxied = results[2].1.as_ref().unwrap();
+    assert!(proxied.is_eip1167_proxy);
+    assert_eq!(proxied.implementation_address.as_deref(), Some(V3_POOL));
+    assert_eq!(proxied.analysis.protocol, "UniswapV3");
+
+    assert!(matches!(
+        results[3].1,
+        Err(AnalyzeError::NoDeployedBytecode)
+    ));
+}
+
+#[tokio::test]
+async fn test_excluded_proxies_are_analyzed_as_they_are() {
+    let rpc = mock(true);
+    let options = AnalyzeOptions {
+        skip_proxy_resolution: [PROXY.parse().unwrap()].into(),
+        ..AnalyzeOptions::default()
+    };
+
+    let results = analyze_addresses(&rpc.url, &addresses(), &options)
+        .await
+        .unwrap();
+    // The implementation is never fetched: one round trip for the four codes.
+    assert_eq!(rpc.http_requests(), 1);
+    let wrapper = results[2].1.as_ref().unwrap();
+    assert!(wrapper.is_eip1167_proxy);
+    assert!(wrapper.implementation_address.is_none());
+    assert!(wrapper.proxy_analysis.is_none());
+    assert_eq!(wrapper.analysis.address, PROXY);
+    assert_eq!(wrapper.analysis.code_size, 45);
+    assert_eq!(wrapper.analysis.protocol, "Unknown");
+
+    let grouped = analyze_addresses_grouped_by_implementation(&rpc.url, &addresses(), &options)
+        .await
+        .unwrap();
+    let wrapper = grouped[2].1.as_ref().unwrap();
+    assert!(wrapper.implementation_address.is_none());
+    assert_eq!(wrapper.analysis.code_size, 45);
+
+    let single = which_dex::analyze::analyze_address_with_options(
+        &rpc.url,
+        PROXY.parse().unwrap(),
+        &options,
+    )
+    .await
+    .unwrap();
+    assert!(single.implementation_address.is_none());
+    assert_eq!(single.analysis.code_size, 45);
+}
+
+#[tokio::test]
+async fn test_batch_groups_calls_into_round_trips() {
+    let rpc = mock(true);
+    analyze_addresses(&rpc.url, &addresses(), &AnalyzeOptions::default())
+        .await
+        .unwrap();
+
+    // One batch for the four addresses, one for the single proxy implementation.
+    assert_eq!(rpc.http_requests(), 2);
+}
+
+#[tokio::test]
+async fn test_batch_falls_back_when_endpoint_rejects_batches() {
+    let rpc = mock(false);
+    let results = analyze_addresses(&rpc.url, &addresses(), &AnalyzeOptions::default())
+        .await
+        .unwrap();
+
+    assert_eq!(
+        results[0].1.as_ref().unwrap().analysis.protocol,
+        "UniswapV2"
+    );
+    assert_eq!(
+        results[2].1.as_ref().unwrap().analysis.protocol,
+        "UniswapV3"
+    );
+    assert!(matches!(
+        results[3].1,
+        Err(AnalyzeError::NoDeployedBytecode)
+    ));
+}
+
+#[tokio::test]
+async fn test_batch_metrics_count_outcomes() {
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .handler(|method, params| {
+            (method == "eth_getCode" && params[0] == FAILING)
+                .then(|| Err(serde_json::json!({ "code": -32000, "message": "boom" })))
+        })
+        .start();
+    let addresses =
+        parse_address_list(&[V2_POOL, V3_POOL, PROXY, EOA, FAILING].join("\n")).unwrap();
+
+    let metrics = InMemoryMetrics::new();
+    let options = AnalyzeOptions {
+        batch_size: 2,
+        ..AnalyzeOptions::default()
+    };
+    analyze_addresses_with_metrics(&rpc.url, &addresses, &options, &metrics)
+        .await
+        .unwrap();
+
+    let snapshot = metrics.snapshot();
+    assert_eq!(snapshot.analyzed_total, 3);
+    assert_eq!(snapshot.protocols.get("UniswapV2"), Some(&1));
+    assert_eq!(snapshot.protocols.get("UniswapV3"), Some(&2));
+    assert_eq!(snapshot.proxies_resolved, 1);
+    assert_eq!(snapshot.rpc_errors, 1);
+    assert_eq!(snapshot.failed_total, 2);
+    assert_eq!(snapshot.latency_ms.count, 3);
+
+    let json: serde_json::Value = serde_json::from_str(&metrics.to_json()).unwrap();
+    assert_eq!(json["analyzed_total"], 3);
+}
+
+#[tokio::test]
+async fn test_identical_pools_collapse_into_one_group() {
+    const TWIN_A: &str = "0x00000000000000000000000000000000000000a1";
+    const TWIN_B: &str = "0x00000000000000000000000000000000000000a2";
+    let v2 = load_fixture("univ2_usdc_eth.hex");
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &v2)
+        .code(TWIN_A, &v2)
+        .code(TWIN_B, &v2)
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .start();
+    let addresses =
+        parse_address_list(&[V2_POOL, V3_POOL, TWIN_A, TWIN_B, EOA].join("\n")).unwrap();
+
+    let results = analyze_addresses(&rpc.url, &addresses, &AnalyzeOptions::default())
+        .await
+        .unwrap();
+    let groups = group_by_fingerprint(results.iter().filter_map(|(_, r)| r.as_ref().ok()));
+
+    assert_eq!(groups.len(), 2);
+    assert_eq!(groups[0].report.analysis.protocol, "UniswapV2");
+    assert_eq!(groups[0].report.address, V2_POOL);
+    assert_eq!(groups[0].addresses, [V2_POOL, TWIN_A, TWIN_B]);
+    assert_eq!(groups[1].addresses, [V3_POOL]);
+
+    let json = serde_json::to_value(&groups[0]).unwrap();
+    assert_eq!(json["analysis"]["protocol"], "UniswapV2");
+    assert_eq!(json["addresses"].as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_batch_maps_null_code_to_no_deployed_bytecode() {
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .handler(|method, params| {
+            (method == "eth_getCode" && params[0] == EOA).then_some(Ok(serde_json::Value::Null))
+        })
+        .start();
+    let addresses = parse_address_list(&[V2_POOL, EOA].join("\n")).unwrap();
+
+    let results = analyze_addresses(&rpc.url, &addresses, &AnalyzeOptions::default())
+        .await
+        .unwrap();
+    assert!(results[0].1.is_ok());
+    assert!(matches!(
+        results[1].1,
+        Err(AnalyzeError::NoDeployedBytecode)
+    ));
+}
+
+/// Records every `Concurrency::Auto` limit change
+#[derive(Default)]
+struct LimitLog(Mutex<Vec<usize>>);
+
+impl AnalyzerMetrics for LimitLog {
+    fn on_concurrency_changed(&self, limit: usize) {
+        self.0.lock().unwrap().push(limit);
+    }
+}
+
+/// Twelve copies of a V2 pool behind an endpoint throttling more than two concurrent requests
+fn throttled_pools() -> (MockRpc, Vec<Address>) {
+    let code = load_fixture("univ2_usdc_eth.hex");
+    // Past the precompiles, which are reported without a fetch
+    let addresses: Vec<Address> = (0x20..0x2c).map(Address::with_last_byte).collect();
+    let rpc = addresses
+        .iter()
+        .fold(MockRpc::builder(), |builder, address| {
+            builder.code(&format!("{address:#x}"), &code)
+        })
+        .latency(Duration::from_millis(30))
+        .max_in_flight(2)
+        .start();
+    (rpc, addresses)
+}
+
+#[tokio::test]
+async fn test_auto_concurrency_backs_off_and_retries_rate_limits() {
+    let (rpc, addresses) = throttled_pools();
+    let options = AnalyzeOptions {
+        batch_size: 1,
+        concurrency: Concurrency::Auto,
+        ..AnalyzeOptions::default()
+    };
+    let log = LimitLog::default();
+
+    let results = analyze_addresses_with_metrics(&rpc.url, &addresses, &options, &log)
+        .await
+        .unwrap();
+
+    for ((address, result), expected) in results.iter().zip(&addresses) {
+        assert_eq!(address, expected);
+        assert_eq!(result.as_ref().unwrap().analysis.protocol, "UniswapV2");
+    }
+    // Ramped past what the endpoint allows, then halved on its rate limits.
+    let limits = log.0.into_inner().unwrap();
+    assert!(limits.iter().any(|limit| *limit > 2), "{limits:?}");
+    assert!(limits.windows(2).any(|w| w[1] < w[0]), "{limits:?}");
+}
+
+#[tokio::test]
+async fn test_fixed_concurrency_reports_rate_limits() {
+    let (rpc, addresses) = throttled_pools();
+    let options = AnalyzeOptions {
+        batch_size: 1,
+        concurrency: Concurrency::Fixed(4),
+        ..AnalyzeOptions::default()
+    };
+
+    let results = analyze_addresses(&rpc.url, &addresses[..4], &options)
+        .await
+        .unwrap();
+    let rate_limited = results
+        .iter()
+        .filter(|(_, r)| matches!(r, Err(AnalyzeError::Rpc(RpcError::RateLimited(_)))))
+        .count();
+    assert_eq!(rate_limited, 2);
+}
+
+#[tokio::test]
+async fn test_compare_many_ranks_candidates_by_diff() {
+    const UNI_V2_POOL: &str = "0xd3d2e2692501a5c9ca623199d38826e513033a17";
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(UNI_V2_POOL, &load_fixture("univ2_uni_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .start();
+    let candidates = parse_address_list(&[EOA, V3_POOL, PROXY, UNI_V2_POOL].join("\n")).unwrap();
+
+    let matches = compare_many(&rpc.url, V2_POOL.parse().unwrap(), &candidates, 1, 2)
+        .await
+        .unwrap();
+    let ranked: Vec<&str> = matches.iter().map(|m| m.address.as_str()).collect();
+    assert_eq!(ranked, [UNI_V2_POOL, V3_POOL, PROXY, EOA]);
+
+    assert!(matches[0].diff < matches[1].diff);
+    assert!(matches[0].similarity.unwrap().is_same_family());
+    // The proxy is compared by its implementation's code.
+    assert_eq!(matches[2].diff, matches[1].diff);
+    assert_eq!(matches[3].diff, None);
+    assert!(matches[3]
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("no deployed bytecode"));
+
+    let json = serde_json::to_value(&matches).unwrap();
+    assert_eq!(
+        json[0]["similarity"],
+        serde_json::to_value(matches[0].similarity.unwrap()).unwrap()
+    );
+    assert!(json[3].get("diff").is_none());
+
+    // The suspect itself has to be comparable.
+    let small = MockRpc::builder().code(EOA, &[0x60, 0x00]).start();
+    let err = compare_many(&small.url, EOA.parse().unwrap(), &candidates, 1, 2)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AnalyzeError::Fingerprint { .. }), "{err}");
+}
+
+#[tokio::test]
+async fn test_compare_many_keeps_candidates_of_a_failed_batch() {
+    const UNI_V2_POOL: &str = "0xd3d2e2692501a5c9ca623199d38826e513033a17";
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(UNI_V2_POOL, &load_fixture("univ2_uni_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .handler(|method, params| {
+            let address = params[0].as_str()?;
+            (method == "eth_getCode" && [FAILING, UNI_V2_POOL].contains(&address))
+                .then(|| Err(serde_json::json!({ "code": -32000, "message": "upstream down" })))
+        })
+        .start();
+    // Batches of two: the second one fails as a whole.
+    let candidates =
+        parse_address_list(&[V3_POOL, PROXY, FAILING, UNI_V2_POOL].join("\n")).unwrap();
+
+    let matches = compare_many(&rpc.url, V2_POOL.parse().unwrap(), &candidates, 2, 2)
+        .await
+        .unwrap();
+    let ranked: Vec<&str> = matches.iter().map(|m| m.address.as_str()).collect();
+    assert_eq!(ranked, [V3_POOL, PROXY, FAILING, UNI_V2_POOL]);
+    assert!(matches[..2].iter().all(|m| m.diff.is_some()));
+    for failed in &matches[2..] {
+        assert_eq!(failed.diff, None);
+        assert!(failed.error.as_deref().unwrap().contains("upstream down"));
+    }
+}
+
+#[tokio::test]
+async fn test_compare_implementations_of_two_proxies() {
+    const OTHER_PROXY: &str = "0x00000000000000000000000000000000000000ab";
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .code(OTHER_PROXY, &eip1167_proxy(&V3_POOL[2..]))
+        .start();
+
+    let same = compare_implementations(
+        &rpc.url,
+        PROXY.parse().unwrap(),
+        OTHER_PROXY.parse().unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(same.address_a, PROXY);
+    assert_eq!(same.implementation_a, V3_POOL);
+    assert_eq!(same.address_b, OTHER_PROXY);
+    assert_eq!(same.implementation_b, V3_POOL);
+    assert!(same.exact_match);
+    assert_eq!(same.diff, 0);
+    assert_eq!(same.similarity, Similarity::Identical);
+
+    // A contract that isn't a proxy stands for itself.
+    let different =
+        compare_implementations(&rpc.url, PROXY.parse().unwrap(), V2_POOL.parse().unwrap())
+            .await
+            .unwrap();
+    assert_eq!(different.implementation_b, V2_POOL);
+    assert!(!different.exact_match);
+    assert!(different.diff > 0);
+
+    // Other PUSH data (e.g. immutables) is the same code to TLSH, but not an exact match.
+    let mut patched = load_fixture("univ3_usdc_eth.hex");
+    let mut pc = 0;
+    while patched[pc] != 0x7f {
+        pc += match patched[pc] {
+            op @ 0x60..=0x7f => usize::from(op - 0x5f) + 1,
+            _ => 1,
+        };
+    }
+    patched[pc + 32] ^= 0xff;
+    let patched_rpc = MockRpc::builder()
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .code(OTHER_PROXY, &patched)
+        .start();
+    let immutables = compare_implementations(
+        &patched_rpc.url,
+        V3_POOL.parse().unwrap(),
+        OTHER_PROXY.parse().unwrap(),
+    )
+    .await
+    .unwrap();
+    assert!(!immutables.exact_match);
+    assert_eq!(immutables.similarity, Similarity::Identical);
+
+    let json = serde_json::to_value(&same).unwrap();
+    assert_eq!(json["exact_match"], true);
+    assert_eq!(json["similarity"], "identical");
+
+    let err = compare_implementations(&rpc.url, PROXY.parse().unwrap(), EOA.parse().unwrap())
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AnalyzeError::NoDeployedBytecode), "{err}");
+}
+
+#[tokio::test]
+async fn test_grouped_by_implementation_fetches_shared_implementation_once() {
+    let impl_fetches = Arc::new(AtomicUsize::new(0));
+    let counter = impl_fetches.clone();
+    let clones: Vec<String> = (0xa0..0xa5).map(|i| format!("0x{i:040x}")).collect();
+    let mut builder = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .handler(move |method, params| {
+            if method == "eth_getCode" && params[0] == V3_POOL {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+            None
+        });
+    for clone in &clones {
+        builder = builder.code(clone, &eip1167_proxy(&V3_POOL[2..]));
+    }
+    let rpc = builder.start();
+    let addresses =
+        parse_address_list(&[clones.join("\n"), V2_POOL.into(), EOA.into()].join("\n")).unwrap();
+    let options = AnalyzeOptions {
+        batch_size: 2,
+        concurrency: Concurrency::Fixed(2),
+        ..AnalyzeOptions::default()
+    };
+
+    let results = analyze_addresses_grouped_by_implementation(&rpc.url, &addresses, &options)
+        .await
+        .unwrap();
+    assert_eq!(impl_fetches.load(Ordering::SeqCst), 1);
+    assert_eq!(results.len(), 7);
+    for ((address, result), clone) in results.iter().zip(&clones) {
+        let report = result.as_ref().unwrap();
+        assert_eq!(format!("{address:#x}"), *clone);
+        assert_eq!(report.address, *clone);
+        assert!(report.is_eip1167_proxy);
+        assert_eq!(report.implementation_address.as_deref(), Some(V3_POOL));
+        assert_eq!(report.analysis.protocol, "UniswapV3");
+        assert!(report.proxy_analysis.is_some());
+    }
+    assert_eq!(
+        results[5].1.as_ref().unwrap().analysis.protocol,
+        "UniswapV2"
+    );
+    assert!(matches!(
+        results[6].1,
+        Err(AnalyzeError::NoDeployedBytecode)
+    ));
+
+    // Same reports as the per-pool pipeline, which fetches the implementation per clone.
+    impl_fetches.store(0, Ordering::SeqCst);
+    let per_pool = analyze_addresses(&rpc.url, &addresses, &options)
+        .await
+        .unwrap();
+    assert_eq!(impl_fetches.load(Ordering::SeqCst), clones.len());
+    for ((_, grouped), (_, single)) in results.iter().zip(&per_pool) {
+        assert_eq!(
+            serde_json::to_value(grouped.as_ref().ok()).unwrap(),
+            serde_json::to_value(single.as_ref().ok()).unwrap()
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_deadline_fails_only_the_addresses_that_overrun() {
+    let rpc = MockRpc::builder()
+        .code(V2_POOL, &load_fixture("univ2_usdc_eth.hex"))
+        .code(V3_POOL, &load_fixture("univ3_usdc_eth.hex"))
+        .handler(|method, params| {
+            // The V3 pool's factory() read hangs.
+            if method == "eth_call" && params[0]["to"] == V3_POOL {
+                std::thread::sleep(Duration::from_millis(1000));
+            }
+            None
+        })
+        .start();
+    let options = AnalyzeOptions {
+        batch_size: 1,
+        resolve_onchain: true,
+        deadline: Some(Duration::from_millis(300)),
+        ..AnalyzeOptions::default()
+    };
+    let addresses = parse_address_list(&[V2_POOL, V3_POOL].join("\n")).unwrap();
+
+    let results = analyze_addresses(&rpc.url, &addresses, &options)
+        .await
+        .unwrap();
+    assert_eq!(
+        results[0].1.as_ref().unwrap().analysis.protocol,
+        "UniswapV2"
+    );
+    assert!(
+        matches!(results[1].1, Err(AnalyzeError::Deadline(300))),
+        "{:?}",
+        results[1].1
+    );
+}
@@ -3,7 +3,13 @@
 //! These tests use real DEX pool bytecodes fetched from mainnet to verify
 //! that TLSH fingerprinting correctly identifies protocol families.
 
-use which_dex::{BytecodeFingerprint, Similarity};
+use which_dex::bytecode_fingerprint::{
+    cluster, cosine_distance, fingerprint_hex_dir, known_contract, opcode_histogram,
+    similarity_matrix,
+};
+use which_dex::{
+    BytecodeFingerprint, Fingerprint, FingerprintOptions, Similarity, SimilarityThresholds,
+};
 
 fn load_fixture(name: &str) -> Vec<u8> {
     let path = format!("tests/fixtures/{}", name);
@@ -13,6 +19,19 @@ fn load_fixture(name: &str) -> Vec<u8> {
         .unwrap_or_else(|_| panic!("Invalid hex in fixture: {}", path))
 }
 
+/// Both UniV3 fee tiers share one normalized hash that names the contract
+#[test]
+fn test_univ3_fixture_is_a_known_contract() {
+    for name in ["univ3_usdc_eth.hex", "univ3_usdc_eth_005.hex"] {
+        let fp = BytecodeFingerprint::from_bytecode(&load_fixture(name)).unwrap();
+        assert_eq!(fp.known_contract(), Some("UniswapV3Pool"), "{name}");
+    }
+
+    let v2 = BytecodeFingerprint::from_bytecode(&load_fixture("univ2_uni_eth.hex")).unwrap();
+    assert_eq!(known_contract(&v2.exact_hash()), Some("UniswapV2Pair"));
+    assert_eq!(known_contract(&[0u8; 32]), None);
+}
+
 /// Two Uniswap V2 pools should be identical after normalization
 #[test]
 fn test_univ2_pools_identical() {
@@ -33,6 +52,77 @@ fn test_univ2_pools_identical() {
     assert!(similarity.is_same_family());
 }
 
+/// Without PUSH normalization, pools that differ only in immutables are told apart
+#[test]
+fn test_strict_fingerprint_keeps_immutables() {
+    let strict = FingerprintOptions {
+        normalize_push: false,
+        ..FingerprintOptions::default()
+    };
+    let exact = |code: &[u8], options: &FingerprintOptions| {
+        BytecodeFingerprint::from_bytecode_with_options(code, options)
+            .unwrap()
+            .exact_hash()
+    };
+
+    // A UniV2 pair whose first PUSH20+ operand (an address-sized constant) differs
+    let pool = load_fixture("univ2_uni_eth.hex");
+    let mut other = pool.clone();
+    let mut i = 0;
+    loop {
+        let op = other[i];
+        if (0x73..=0x7f).contains(&op) {
+            break;
+        }
+        let push_len = if (0x60..=0x7f).contains(&op) {
+            op - 0x5f
+        } else {
+            0
+        };
+        i += 1 + usize::from(push_len);
+    }
+    other[i + 1] ^= 0xff;
+
+    let default = FingerprintOptions::default();
+    assert_eq!(exact(&pool, &default), exact(&other, &default));
+    assert_ne!(exact(&pool, &strict), exact(&other, &strict));
+    assert_eq!(
+        BytecodeFingerprint::from_bytecode(&pool)
+            .unwrap()
+            .exact_hash(),
+        exact(&pool, &default)
+    );
+
+    // Real V3 pools of two fee tiers: same family, different deployments
+    let fee_03 = load_fixture("univ3_usdc_eth.hex");
+    let fee_005 = load_fixture("univ3_usdc_eth_005.hex");
+    assert_eq!(exact(&fee_03, &default), exact(&fee_005, &default));
+    assert_ne!(exact(&fee_03, &strict), exact(&fee_005, &strict));
+}
+
+/// `strip_constructor` fingerprints creation code like the runtime it deploys
+#[test]
+fn test_strip_constructor_fingerprints_the_runtime() {
+    let runtime = load_fixture("univ2_usdc_eth.hex");
+    // PUSH2 <size> DUP1 PUSH2 <offset> PUSH1 0 CODECOPY PUSH1 0 RETURN INVALID
+    let mut creation = vec![0x61];
+    creation.extend_from_slice(&(runtime.len() as u16).to_be_bytes());
+    creation.extend_from_slice(&[
+        0x80, 0x61, 0x00, 14, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3, 0xfe,
+    ]);
+    creation.extend_from_slice(&runtime);
+
+    let options = FingerprintOptions {
+        strip_constructor: true,
+        ..FingerprintOptions::default()
+    };
+    let deployed = BytecodeFingerprint::from_bytecode(&runtime).unwrap();
+    let stripped = BytecodeFingerprint::from_bytecode_with_options(&creation, &options).unwrap();
+    let whole = BytecodeFingerprint::from_bytecode(&creation).unwrap();
+    assert_eq!(stripped.exact_hash(), deployed.exact_hash());
+    assert_ne!(whole.exact_hash(), deployed.exact_hash());
+}
+
 /// Two Uniswap V3 pools should be identical after normalization
 #[test]
 fn test_univ3_pools_identical() {
@@ -180,3 +270,225 @@ fn test_story_chain_univ3_forks_same_family() {
         distance
     );
 }
+
+/// Size-normalized distance: same template across chains, and a length-bucket edge
+#[test]
+fn test_distance_normalized() {
+    // Same UniV3 template, different immutables (tokens, fee, tick spacing)
+    let usdc_eth_030 =
+        BytecodeFingerprint::from_bytecode(&load_fixture("univ3_usdc_eth.hex")).unwrap();
+    let usdc_eth_005 =
+        BytecodeFingerprint::from_bytecode(&load_fixture("univ3_usdc_eth_005.hex")).unwrap();
+    assert_eq!(usdc_eth_030.distance_normalized(&usdc_eth_005), 0);
+
+    // Mainnet UniV3 vs a Story-chain fork of it
+    let story =
+        BytecodeFingerprint::from_bytecode(&load_fixture("story_univ3_fork_pool.hex")).unwrap();
+    let normalized = usdc_eth_030.distance_normalized(&story);
+    assert!(normalized <= usdc_eth_030.distance(&story));
+    assert_eq!(normalized, story.distance_normalized(&usdc_eth_030));
+    assert!(usdc_eth_030.compare_normalized(&story).is_same_family());
+
+    // Dropping 900 bytes (~4%) crosses a TLSH length bucket; that costs less when normalized.
+    let code = load_fixture("univ3_usdc_eth.hex");
+    let shorter = BytecodeFingerprint::from_bytecode(&code[..code.len() - 900]).unwrap();
+    assert!(usdc_eth_030.distance_normalized(&shorter) < usdc_eth_030.distance(&shorter));
+
+    // Large size gaps keep the full length penalty.
+    let v2 = BytecodeFingerprint::from_bytecode(&load_fixture("univ2_uni_eth.hex")).unwrap();
+    assert_eq!(
+        usdc_eth_030.distance_normalized(&v2),
+        usdc_eth_030.distance(&v2)
+    );
+}
+
+/// The deprecated `Fingerprint`/`diff`/`similarity` names keep working during the deprecation window
+#[test]
+#[allow(deprecated)]
+fn test_deprecated_method_names_still_work() {
+    let v2 = load_fixture("univ2_usdc_eth.hex");
+    let v3 = load_fixture("univ3_usdc_eth.hex");
+
+    let fp_v2 = Fingerprint::from_bytecode(&v2).unwrap();
+    let fp_v3 = BytecodeFingerprint::from_bytecode(&v3).unwrap();
+
+    assert_eq!(fp_v2.diff(&fp_v3), fp_v2.distance(&fp_v3));
+    assert_eq!(fp_v2.similarity(&fp_v3), fp_v2.compare(&fp_v3));
+    assert_eq!(fp_v2.similarity(&fp_v2), Similarity::Identical);
+}
+
+/// Pairwise comparison over the fixtures directory groups pools of one contract
+#[test]
+fn test_fixture_dir_clusters() {
+    let dir = fingerprint_hex_dir(std::path::Path::new("tests/fixtures")).unwrap();
+
+    // The 45-byte EIP-1167 proxy is too small for TLSH: skipped, not fatal.
+    assert!(dir
+        .skipped
+        .iter()
+        .any(|(name, _)| name == "eip1167_proxy.hex"));
+
+    let (names, fps): (Vec<String>, Vec<BytecodeFingerprint>) =
+        dir.fingerprints.into_iter().unzip();
+    let matrix = similarity_matrix(&fps);
+    assert_eq!(matrix.len(), fps.len());
+    for (i, row) in matrix.iter().enumerate() {
+        assert_eq!(row[i], 0);
+        for (j, d) in row.iter().enumerate() {
+            assert_eq!(*d, matrix[j][i]);
+        }
+    }
+
+    let clusters = cluster(&matrix, 30);
+    let cluster_of = |name: &str| {
+        let i = names.iter().position(|n| n == name).unwrap();
+        clusters.iter().position(|c| c.contains(&i)).unwrap()
+    };
+
+    assert!(clusters.len() > 1 && clusters.len() < fps.len());
+    assert_eq!(
+        cluster_of("univ2_uni_eth.hex"),
+        cluster_of("univ2_usdc_eth.hex")
+    );
+    assert_eq!(
+        cluster_of("univ3_usdc_eth.hex"),
+        cluster_of("univ3_usdc_eth_005.hex")
+    );
+    assert_ne!(
+        cluster_of("univ2_usdc_eth.hex"),
+        cluster_of("univ3_usdc_eth.hex")
+    );
+    assert_ne!(
+        cluster_of("univ2_usdc_eth.hex"),
+        cluster_of("velo_impl.hex")
+    );
+
+    // Every file lands in exactly one cluster.
+    assert_eq!(clusters.iter().map(Vec::len).sum::<usize>(), fps.len());
+}
+
+/// Two deployments of one vyper (Curve) template differ only in the immutables
+/// stored after the signature
+#[test]
+fn test_vyper_pools_same_family_after_stripping_immutables() {
+    let pool_a = load_fixture("curve_vyper_pool_a.hex");
+    let pool_b = load_fixture("curve_vyper_pool_b.hex");
+    let fp_a = BytecodeFingerprint::from_bytecode(&pool_a).unwrap();
+    let fp_b = BytecodeFingerprint::from_bytecode(&pool_b).unwrap();
+    assert_eq!(fp_a.distance(&fp_b), 0);
+    assert_eq!(fp_a.exact_hash(), fp_b.exact_hash());
+
+    // With the signature unrecognized, the immutables stay in and the two pools no
+    // longer look like one family.
+    let unmarked = |code: &[u8]| {
+        let mut code = code.to_vec();
+        let at = code.windows(5).position(|w| w == b"vyper").unwrap();
+        code[at] = b'V';
+        BytecodeFingerprint::from_bytecode(&code).unwrap()
+    };
+    let before = unmarked(&pool_a).distance(&unmarked(&pool_b));
+    assert!(!Similarity::from_diff(before).is_same_family(), "{before}");
+}
+
+/// A vyper signature inside the code, a whole number of words from the end, is taken
+/// for the trailer and everything after it is cut
+#[test]
+fn test_metadata_strip_suspect_flags_over_stripping() {
+    let v2 = load_fixture("univ2_uni_eth.hex");
+    assert!(!BytecodeFingerprint::from_bytecode(&v2)
+        .unwrap()
+        .metadata_strip_suspect());
+
+    // {"vyper": [0, 3, 7]} and its length, then 64 words of code after it
+    let mut code = v2[..3000].to_vec();
+    code.extend_from_slice(b"\xa1\x65vyper\x83\x00\x03\x07\x00\x0b");
+    code.extend_from_slice(&v2[3000..3000 + 64 * 32]);
+    let fp = BytecodeFingerprint::from_bytecode(&code).unwrap();
+    assert_eq!(fp.normalized_size(), 3000);
+    assert!(fp.metadata_strip_suspect());
+
+    let analysis = which_dex::analyze_bytecode(Default::default(), &code);
+    assert!(analysis.fingerprint.unwrap().metadata_strip_suspect);
+}
+
+#[test]
+fn test_opcode_histogram_skips_push_data() {
+    // PUSH1 0x80 PUSH1 0x40 MSTORE PUSH4 0x60606060 PUSH32 <0x5b * 32> JUMPDEST STOP
+    let mut code = vec![
+        0x60, 0x80, 0x60, 0x40, 0x52, 0x63, 0x60, 0x60, 0x60, 0x60, 0x7f,
+    ];
+    code.extend([0x5b; 32]);
+    code.extend([0x5b, 0x00]);
+
+    let histogram = opcode_histogram(&code);
+    assert_eq!(histogram[0x60], 2);
+    assert_eq!(histogram[0x63], 1);
+    assert_eq!(histogram[0x7f], 1);
+    assert_eq!(histogram[0x5b], 1);
+    assert_eq!(histogram[0x80], 0);
+    assert_eq!(histogram.iter().sum::<u32>(), 7);
+
+    // Truncated PUSH data at the end of the code is not counted either.
+    assert_eq!(opcode_histogram(&[0x00, 0x62, 0x01]).iter().sum::<u32>(), 2);
+}
+
+#[test]
+fn test_opcode_histogram_cosine_distance() {
+    let v2 = opcode_histogram(&load_fixture("univ2_uni_eth.hex"));
+    let sushi = opcode_histogram(&load_fixture("sushi_usdc_eth.hex"));
+    let v3 = opcode_histogram(&load_fixture("univ3_usdc_eth.hex"));
+
+    assert_eq!(cosine_distance(&v2, &v2), 0.0);
+    assert!(cosine_distance(&v2, &sushi) < cosine_distance(&v2, &v3));
+    assert_eq!(cosine_distance(&v2, &[0; 256]), 1.0);
+}
+
+#[test]
+fn test_similarity_serde_round_trip() {
+    for similarity in [
+        Similarity::Identical,
+        Similarity::SameContract,
+        Similarity::SameFamily,
+        Similarity::PossiblyRelated,
+        Similarity::Different,
+    ] {
+        let json = serde_json::to_string(&similarity).unwrap();
+        assert_eq!(json, format!("\"{}\"", similarity.as_str()));
+        assert_eq!(
+            serde_json::from_str::<Similarity>(&json).unwrap(),
+            similarity
+        );
+    }
+    assert!(serde_json::from_str::<Similarity>("\"SameFamily\"").is_err());
+}
+
+#[test]
+fn test_similarity_thresholds_serde_round_trip() {
+    let tuned = SimilarityThresholds {
+        same_contract: 20,
+        same_family: 80,
+        possibly_related: 120,
+    };
+    let json = serde_json::to_string(&tuned).unwrap();
+    assert_eq!(
+        json,
+        r#"{"same_contract":20,"same_family":80,"possibly_related":120}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<SimilarityThresholds>(&json).unwrap(),
+        tuned
+    );
+
+    // A partial config keeps the other defaults.
+    let partial: SimilarityThresholds = serde_json::from_str(r#"{"same_family":80}"#).unwrap();
+    assert_eq!(partial.same_family, 80);
+    assert_eq!(
+        partial.same_contract,
+        SimilarityThresholds::default().same_contract
+    );
+
+    assert_eq!(tuned.classify(0), Similarity::Identical);
+    assert_eq!(tuned.classify(90), Similarity::PossiblyRelated);
+    assert_eq!(Similarity::from_diff(90), Similarity::SameFamily);
+    assert_eq!(tuned.classify(-1), Similarity::Different);
+}
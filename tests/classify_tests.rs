@@ -0,0 +1,102 @@
+//! Integration tests for the combined selector + TLSH classifier
+
+mod common;
+
+use common::load_fixture;
+use which_dex::{
+    analyze_bytecode, classify, load_fingerprint_db, BytecodeFingerprint, DetectionMethod,
+    DexProtocol, FingerprintDb,
+};
+
+fn fingerprint(fixture: &str) -> BytecodeFingerprint {
+    BytecodeFingerprint::from_bytecode(&load_fixture(fixture)).unwrap()
+}
+
+fn reference_db() -> FingerprintDb {
+    let mut db = FingerprintDb::new();
+    db.insert(
+        DexProtocol::BalancerV2Pool,
+        fingerprint("balancer_weighted_synthetic.hex"),
+    );
+    db.insert(DexProtocol::WooFi, fingerprint("woofi_synthetic.hex"));
+    // Same dispatcher shape as the tie fixture, different selectors: TLSH, which
+    // zeroes PUSH data, can't tell the two apart.
+    db.insert(
+        DexProtocol::UniswapV2,
+        fingerprint("univ2_dispatcher_synthetic.hex"),
+    );
+    db
+}
+
+/// Balancer pool code that also carries WooFi's query selectors
+#[test]
+fn test_combined_beats_selectors_and_tlsh_alone_on_tie() {
+    let code = load_fixture("balancer_woofi_tie_synthetic.hex");
+    let db = reference_db();
+
+    // Selectors alone: two protocols match, so no verdict.
+    let selector_only = analyze_bytecode(Default::default(), &code);
+    assert_eq!(selector_only.protocol, "Unknown");
+    assert_eq!(selector_only.protocol_candidates.unwrap().len(), 2);
+
+    // TLSH alone: the nearest reference is a protocol the selectors rule out.
+    let fp = BytecodeFingerprint::from_bytecode(&code).unwrap();
+    assert_eq!(db.nearest(&fp).unwrap().0, DexProtocol::UniswapV2);
+
+    let combined = classify(&code, &db);
+    assert_eq!(combined.protocol, DexProtocol::BalancerV2Pool);
+    assert_eq!(combined.method, DetectionMethod::TlshNearest);
+    assert_eq!(combined.selector_confidence, 5);
+    assert!(combined.distance.unwrap() < db.nearest_to(&fp, DexProtocol::WooFi).unwrap());
+    assert!(combined.confidence > 0.0 && combined.confidence <= 1.0);
+}
+
+#[test]
+fn test_combined_confirms_single_candidate() {
+    let mut db = reference_db();
+    db.insert(DexProtocol::UniswapV3, fingerprint("univ3_usdc_eth.hex"));
+
+    let confirmed = classify(&load_fixture("univ3_usdc_eth_005.hex"), &db);
+    assert_eq!(confirmed.protocol, DexProtocol::UniswapV3);
+    assert_eq!(confirmed.method, DetectionMethod::SelectorTlshAgree);
+
+    // No reference for the protocol: scored on selectors alone.
+    let unreferenced = classify(&load_fixture("velo_impl.hex"), &db);
+    assert_eq!(unreferenced.protocol, DexProtocol::Solidly);
+    assert_eq!(unreferenced.method, DetectionMethod::Selector);
+    assert_eq!(unreferenced.distance, None);
+    assert!(unreferenced.confidence > 0.0);
+
+    // Neither signal (no selectors, too small for TLSH): Unknown.
+    let unknown = classify(&load_fixture("eip1167_proxy.hex"), &db);
+    assert_eq!(unknown.protocol, DexProtocol::Unknown);
+    assert_eq!(unknown.confidence, 0.0);
+}
+
+#[test]
+fn test_load_fingerprint_db_from_protocol_dirs() {
+    let dir = std::env::temp_dir().join(format!("which-dex-fpdb-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    for (subdir, fixture) in [
+        ("uniswap-v3", "univ3_usdc_eth.hex"),
+        ("UniswapV2", "univ2_usdc_eth.hex"),
+        ("NotADex", "univ2_uni_eth.hex"),
+    ] {
+        std::fs::create_dir_all(dir.join(subdir)).unwrap();
+        std::fs::copy(
+            format!("tests/fixtures/{fixture}"),
+            dir.join(subdir).join(fixture),
+        )
+        .unwrap();
+    }
+    std::fs::write(dir.join("UniswapV2/short.hex"), "0x6080").unwrap();
+
+    let (db, skipped) = load_fingerprint_db(&dir).unwrap();
+    assert_eq!(db.len(), 2);
+    let skipped: Vec<&str> = skipped.iter().map(|(entry, _)| entry.as_str()).collect();
+    assert_eq!(skipped, ["NotADex", "UniswapV2/short.hex"]);
+
+    let fp = fingerprint("univ3_usdc_eth_005.hex");
+    assert_eq!(db.nearest(&fp).unwrap().0, DexProtocol::UniswapV3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
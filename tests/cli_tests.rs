@@ -0,0 +1,916 @@
+//! Offline CLI tests (no RPC, or a local mock endpoint)
+
+mod common;
+
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use common::{eip1167_proxy, load_fixture, run_cli, temp_dir, MockRpc};
+
+#[test]
+fn test_protocols_json_lists_fingerprints() {
+    let output = run_cli(&["protocols", "--json"], None);
+    assert!(output.status.success());
+
+    let v: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("stdout is valid JSON");
+    assert_eq!(v["version"], env!("CARGO_PKG_VERSION"));
+
+    let protocols = v["protocols"].as_array().unwrap();
+    let v2 = protocols
+        .iter()
+        .find(|p| p["protocol"] == "UniswapV2")
+        .expect("UniswapV2 listed");
+    assert!(v2["required"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|s| s["selector"] == "0x0902f1ac" && s["name"] == "getReserves()"));
+    assert!(v2["forbidden"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|s| s["name"] == "slot0()"));
+    assert!(v2["optional"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|s| s["weight"] == 1));
+    let solidly = protocols
+        .iter()
+        .find(|p| p["protocol"] == "Solidly")
+        .expect("Solidly listed");
+    assert!(solidly["optional"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|s| s["name"] == "claimFees()" && s["weight"] == 2));
+    assert!(protocols.iter().all(|p| p["protocol"] != "Unknown"));
+}
+
+#[test]
+fn test_hidden_fingerprints_command_dumps_the_rules() {
+    let run = |args: &[&str]| {
+        let output = run_cli(args, None);
+        assert!(output.status.success(), "{output:?}");
+        output.stdout
+    };
+
+    let dump = run(&["fingerprints"]);
+    assert_eq!(dump, run(&["protocols", "--json"]));
+    let v: serde_json::Value = serde_json::from_slice(&dump).unwrap();
+    let v2 = v["protocols"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|p| p["protocol"] == "UniswapV2")
+        .expect("UniswapV2 listed");
+    let hex = |group: &str| -> Vec<String> {
+        v2[group]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|s| s["selector"].as_str().unwrap().to_string())
+            .collect()
+    };
+    // token0, token1, getReserves, kLast
+    assert_eq!(
+        hex("required"),
+        ["0x0dfe1681", "0xd21220a7", "0x0902f1ac", "0x7464fc3d"]
+    );
+    // slot0, stable, globalState
+    assert_eq!(hex("forbidden"), ["0x3850c7bd", "0x22be3de1", "0xe76c01e4"]);
+    assert_eq!(hex("optional").len(), 3);
+
+    let help = String::from_utf8(run(&["--help"])).unwrap();
+    assert!(!help.contains("fingerprints"), "{help}");
+}
+
+#[test]
+fn test_fail_on_unknown_reports_every_unknown_address() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .start();
+
+    let dir = temp_dir("cli");
+    let list = dir.join("pools.txt");
+    std::fs::write(&list, format!("{v2_pool}\n{unknown}\n")).unwrap();
+
+    let run = |extra: &[&str]| {
+        let args = [
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--addresses-file",
+            list.to_str().unwrap(),
+        ];
+        run_cli(&[&args[..], extra].concat(), None)
+    };
+
+    let output = run(&["--fail-on-unknown"]);
+    assert!(!output.status.success());
+    // Every address is still analyzed and reported before failing.
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 2);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Progress lines name every address; only the error lists the unknown ones.
+    let error = &stderr[stderr.find("error:").expect("error on stderr")..];
+    assert!(
+        error.contains("1 address(es) resolved to Unknown"),
+        "{stderr}"
+    );
+    assert!(error.contains(unknown), "{stderr}");
+    assert!(!error.contains(v2_pool), "{stderr}");
+
+    assert!(run(&[]).status.success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_ndjson_single_address_is_one_line_and_quiet() {
+    let pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let rpc = MockRpc::builder()
+        .code(pool, &load_fixture("sushi_usdc_eth.hex"))
+        .start();
+
+    let output = Command::new(assert_cmd::cargo::cargo_bin!("which-dex"))
+        .args([
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--address",
+            pool,
+            "--ndjson",
+        ])
+        .env("RUST_LOG", "debug")
+        .output()
+        .expect("run which-dex");
+    assert!(output.status.success());
+    assert!(
+        output.stderr.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.ends_with('\n'));
+    assert_eq!(stdout.lines().count(), 1);
+    let report: serde_json::Value = serde_json::from_str(&stdout).unwrap();
+    assert_eq!(report["analysis"]["protocol"], "UniswapV2");
+
+    let failed = run_cli(
+        &[
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--address",
+            "0x1234",
+            "--ndjson",
+        ],
+        None,
+    );
+    assert!(!failed.status.success());
+    assert!(failed.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&failed.stderr).starts_with("error: invalid address"));
+}
+
+#[test]
+fn test_output_writes_batch_reports_to_file() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let missing = "0x00000000000000000000000000000000000000cc";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .start();
+
+    let dir = temp_dir("cli-output");
+    let list = dir.join("pools.txt");
+    std::fs::write(&list, format!("{v2_pool}\n{unknown}\n{missing}\n")).unwrap();
+    let report_file = dir.join("reports.jsonl");
+
+    let output = run_cli(
+        &[
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--addresses-file",
+            list.to_str().unwrap(),
+            "--output",
+            report_file.to_str().unwrap(),
+        ],
+        None,
+    );
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("wrote 3 report(s) to"), "{stderr}");
+
+    // One JSON line per address, the failed one included.
+    let contents = std::fs::read_to_string(&report_file).unwrap();
+    let lines: Vec<serde_json::Value> = contents
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["analysis"]["protocol"], "UniswapV2");
+    assert!(lines[2]["error"].is_string());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_analyze_code_reads_each_input_encoding_from_stdin() {
+    use base64::Engine;
+
+    let code = load_fixture("sushi_usdc_eth.hex");
+    let run = |encoding: &str, input: Vec<u8>| {
+        run_cli(
+            &["analyze-code", "--json", "--input-encoding", encoding],
+            Some(&input),
+        )
+    };
+
+    let inputs = [
+        ("hex", format!("0x{}\n", hex::encode(&code)).into_bytes()),
+        (
+            "base64",
+            base64::engine::general_purpose::STANDARD
+                .encode(&code)
+                .into_bytes(),
+        ),
+        ("raw", code.clone()),
+    ];
+    for (encoding, input) in inputs {
+        let output = run(encoding, input);
+        assert!(output.status.success(), "{encoding}: {output:?}");
+        let analysis: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(analysis["protocol"], "UniswapV2", "{encoding}");
+        assert_eq!(analysis["code_size"], code.len(), "{encoding}");
+    }
+
+    let malformed = run("base64", b"not base64!".to_vec());
+    assert!(!malformed.status.success());
+    assert!(String::from_utf8_lossy(&malformed.stderr)
+        .starts_with("error: invalid bytecode input: invalid base64"));
+
+    // A file that can't be read is an IO failure, not malformed bytecode.
+    let unreadable = run_cli(
+        &["analyze-code", "--file", "tests/fixtures/missing.hex"],
+        None,
+    );
+    assert!(!unreadable.status.success());
+    assert!(String::from_utf8_lossy(&unreadable.stderr)
+        .starts_with("error: cannot read bytecode input: tests/fixtures/missing.hex"));
+}
+
+#[test]
+fn test_batch_progress_goes_to_stderr_only() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let missing = "0x00000000000000000000000000000000000000cc";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .start();
+
+    let dir = temp_dir("cli-progress");
+    let list = dir.join("pools.txt");
+    std::fs::write(&list, format!("{v2_pool}\n{unknown}\n{missing}\n")).unwrap();
+
+    let run = |extra: &[&str]| {
+        let args = [
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--addresses-file",
+            list.to_str().unwrap(),
+        ];
+        run_cli(&[&args[..], extra].concat(), None)
+    };
+
+    let output = run(&[]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let progress: Vec<&str> = stderr.lines().collect();
+    assert_eq!(
+        progress,
+        [
+            format!("[1/3] {v2_pool} (errors: 0)"),
+            format!("[2/3] {unknown} (errors: 0)"),
+            format!("[3/3] {missing} (errors: 1)"),
+        ]
+    );
+    // stdout is untouched: nothing but the JSON Lines.
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 3);
+    for line in stdout.lines() {
+        serde_json::from_str::<serde_json::Value>(line).unwrap();
+    }
+
+    let quiet = run(&["--quiet"]);
+    assert!(quiet.status.success());
+    assert!(quiet.stderr.is_empty());
+    assert_eq!(quiet.stdout, stdout.as_bytes());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_only_protocol_filters_batch_output() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let missing = "0x00000000000000000000000000000000000000cc";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .start();
+
+    let dir = temp_dir("cli-only");
+    let list = dir.join("pools.txt");
+    std::fs::write(&list, format!("{v2_pool}\n{unknown}\n{missing}\n")).unwrap();
+
+    let run = |extra: &[&str]| {
+        let args = [
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--addresses-file",
+            list.to_str().unwrap(),
+        ];
+        run_cli(&[&args[..], extra].concat(), None)
+    };
+
+    let output = run(&["--only-protocol", "uniswapv2", "--quiet"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.contains(v2_pool));
+
+    let output = run(&["--only-protocol", "UniswapV2", "--only-protocol", "Unknown"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 2);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("skipped 1 address(es) not matching --only-protocol"),
+        "{stderr}"
+    );
+
+    let invalid = run(&["--only-protocol", "Curve"]);
+    assert!(!invalid.status.success());
+    assert!(invalid.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("unknown protocol `Curve`"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_sort_by_protocol_orders_batch_output() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let v3_pool = "0x8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let missing = "0x00000000000000000000000000000000000000cc";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(v3_pool, &load_fixture("univ3_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .start();
+
+    let dir = temp_dir("cli-sort");
+    let list = dir.join("pools.txt");
+    std::fs::write(
+        &list,
+        format!("{missing}\n{v3_pool}\n{unknown}\n{v2_pool}\n"),
+    )
+    .unwrap();
+
+    let output = run_cli(
+        &[
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--quiet",
+            "--sort",
+            "protocol",
+            "--addresses-file",
+            list.to_str().unwrap(),
+        ],
+        None,
+    );
+    assert!(output.status.success());
+
+    let lines: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let protocols: Vec<&str> = lines
+        .iter()
+        .map(|line| line["analysis"]["protocol"].as_str().unwrap_or("<error>"))
+        .collect();
+    assert_eq!(protocols, ["UniswapV2", "UniswapV3", "Unknown", "<error>"]);
+    assert_eq!(lines[3]["address"], missing);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_limit_analyzes_only_the_first_addresses() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let v3_pool = "0x8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let code_fetches = Arc::new(AtomicUsize::new(0));
+    let counter = code_fetches.clone();
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(v3_pool, &load_fixture("univ3_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .handler(move |method, _| {
+            if method == "eth_getCode" {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+            None
+        })
+        .start();
+
+    let dir = temp_dir("cli-limit");
+    let list = dir.join("pools.txt");
+    std::fs::write(&list, format!("# pools\n{v2_pool}\n{v3_pool}\n{unknown}\n")).unwrap();
+
+    let run = |limit: &[&str]| {
+        let args = [
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--quiet",
+            "--addresses-file",
+            list.to_str().unwrap(),
+        ];
+        run_cli(&[&args[..], limit].concat(), None)
+    };
+
+    let output = run(&["--limit", "2"]);
+    assert!(output.status.success());
+    let addresses: Vec<String> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["address"].to_string())
+        .collect();
+    assert_eq!(
+        addresses,
+        [format!("\"{v2_pool}\""), format!("\"{v3_pool}\"")]
+    );
+    // Only the kept addresses are fetched.
+    assert_eq!(code_fetches.load(Ordering::SeqCst), 2);
+
+    let output = run(&["--head", "10"]);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 3);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stdin_reads_the_address_list() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let v3_pool = "0x8ad599c3a0ff1de082011efddc58f1908eb6e6d8";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(v3_pool, &load_fixture("univ3_usdc_eth.hex"))
+        .start();
+    let run = |extra: &[&str], input: &str| {
+        let args = [
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--json",
+            "--quiet",
+            "--stdin",
+        ];
+        run_cli(&[&args[..], extra].concat(), Some(input.as_bytes()))
+    };
+
+    // Blank lines and comments are skipped, surrounding whitespace trimmed.
+    let output = run(&[], &format!("# pools\n\n  {v2_pool}  \n{v3_pool}\n"));
+    assert!(output.status.success(), "{output:?}");
+    let protocols: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| {
+            serde_json::from_str::<serde_json::Value>(line).unwrap()["analysis"]["protocol"].clone()
+        })
+        .collect();
+    assert_eq!(protocols, ["UniswapV2", "UniswapV3"]);
+
+    // Batch flags apply as with --addresses-file.
+    let output = run(&["--limit", "1"], &format!("{v2_pool}\n{v3_pool}\n"));
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().count(), 1);
+
+    let output = run(&["--addresses-file", "pools.txt"], v2_pool);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("cannot be used with"));
+}
+
+#[test]
+fn test_summary_tallies_batch_run() {
+    let v2_pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let unknown = "0x00000000000000000000000000000000000000bb";
+    let missing = "0x00000000000000000000000000000000000000cc";
+    let rpc = MockRpc::builder()
+        .code(v2_pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(unknown, &hex::decode("6080604052600080fd").unwrap())
+        .start();
+
+    let dir = temp_dir("cli-summary");
+    let list = dir.join("pools.txt");
+    std::fs::write(&list, format!("{v2_pool}\n{unknown}\n{missing}\n")).unwrap();
+
+    let run = |extra: &[&str]| {
+        let args = [
+            "analyze",
+            "--rpc-url",
+            &rpc.url,
+            "--summary",
+            "--quiet",
+            "--addresses-file",
+            list.to_str().unwrap(),
+        ];
+        run_cli(&[&args[..], extra].concat(), None)
+    };
+
+    let json = run(&["--json"]);
+    assert!(json.status.success());
+    let stdout = String::from_utf8(json.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 4);
+    let summary: serde_json::Value = serde_json::from_str(stdout.lines().last().unwrap()).unwrap();
+    assert_eq!(
+        summary,
+        serde_json::json!({
+            "summary": {
+                "total": 3,
+                "protocols": { "UniswapV2": 1, "Unknown": 1 },
+                "proxies": 0,
+                "errors": 1,
+                "unknown": 1,
+            }
+        })
+    );
+    assert!(json.stderr.is_empty());
+
+    let human = run(&[]);
+    assert!(human.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&human.stderr),
+        "summary: 3 address(es) | UniswapV2 1, Unknown 1 | proxies 0 | errors 1 | unknown 1\n"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_name_style_renames_protocols_in_output() {
+    let pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let rpc = MockRpc::builder()
+        .code(pool, &load_fixture("sushi_usdc_eth.hex"))
+        .start();
+
+    let run = |extra: &[&str]| {
+        let args = ["analyze", "--rpc-url", &rpc.url, "--address", pool];
+        run_cli(&[&args[..], extra].concat(), None)
+    };
+
+    let json = run(&["--json", "--name-style", "kebab"]);
+    assert!(json.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    assert_eq!(report["analysis"]["protocol"], "uniswap-v2");
+
+    let human = run(&["--name-style", "lowercase"]);
+    assert!(human.status.success());
+    assert!(String::from_utf8_lossy(&human.stdout).contains("protocol: uniswapv2\n"));
+
+    let canonical = run(&["--json"]);
+    let report: serde_json::Value = serde_json::from_slice(&canonical.stdout).unwrap();
+    assert_eq!(report["analysis"]["protocol"], "UniswapV2");
+}
+
+#[test]
+fn test_config_file_supplies_defaults_that_flags_override() {
+    let pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let rpc = MockRpc::builder()
+        .code(pool, &load_fixture("sushi_usdc_eth.hex"))
+        .start();
+    let dir = temp_dir("cli-config");
+    let write_config = |name: &str, contents: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let config = write_config(
+        "which-dex.toml",
+        &format!(
+            "rpc-url = \"{}\"\nname-style = \"kebab\"\nconcurrency = 4\n",
+            rpc.url
+        ),
+    );
+    let run = |args: &[&str]| run_cli(args, None);
+
+    // --rpc-url and --name-style come from the file.
+    let output = run(&["analyze", "--config", &config, "--address", pool, "--json"]);
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["analysis"]["protocol"], "uniswap-v2");
+
+    // A flag on the command line wins over the file.
+    let config_arg = format!("--config={config}");
+    let output = run(&[
+        "analyze",
+        "--address",
+        pool,
+        "--json",
+        &config_arg,
+        "--name-style",
+        "lowercase",
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["analysis"]["protocol"], "uniswapv2");
+
+    let unknown_key = write_config("unknown.toml", "rpc_url = \"http://localhost\"\n");
+    let output = run(&["analyze", "--config", &unknown_key, "--address", pool]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("error: cannot load config"), "{stderr}");
+    assert!(stderr.contains("unknown field `rpc_url`"), "{stderr}");
+    // The parser's location survives, so the offending key can be found.
+    assert!(stderr.contains("line 1, column 1"), "{stderr}");
+
+    // Values go through the flags' own parsers.
+    let bad_value = write_config("bad.toml", "name-style = \"shouty\"\n");
+    let output = run(&["analyze", "--config", &bad_value, "--rpc-url", &rpc.url]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("shouty"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_code_prints_fetched_bytecode_following_proxies() {
+    let pool = "0x88e6a0c2ddd26feeb64f039a2c41296fcb3f5640";
+    let proxy = "0x1111111111111111111111111111111111111111";
+    let pool_code = load_fixture("univ3_usdc_eth.hex");
+    let proxy_code = eip1167_proxy(&pool[2..]);
+    let rpc = MockRpc::builder()
+        .code(pool, &pool_code)
+        .code(proxy, &proxy_code)
+        .start();
+
+    let run = |extra: &[&str]| {
+        let args = ["code", "--rpc-url", &rpc.url, "--address", proxy];
+        let output = run_cli(&[&args[..], extra].concat(), None);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let hex_code = stdout.trim_end().strip_prefix("0x").expect("0x-prefixed");
+        hex::decode(hex_code).expect("valid hex")
+    };
+
+    assert_eq!(run(&[]), proxy_code);
+    let resolved = run(&["--resolve-proxy"]);
+    assert_eq!(resolved.len(), pool_code.len());
+    assert_eq!(resolved, pool_code);
+}
+
+#[test]
+fn test_include_disassembly_lists_dispatcher_of_unknown_code() {
+    let run = |fixture: &str, json: bool| {
+        let file = format!("tests/fixtures/{fixture}");
+        let args = ["analyze-code", "--include-disassembly", "--file", &file];
+        let output = run_cli(
+            &[&args[..], json.then_some("--json").as_slice()].concat(),
+            None,
+        );
+        assert!(output.status.success(), "{output:?}");
+        output.stdout
+    };
+
+    let unknown: serde_json::Value =
+        serde_json::from_slice(&run("balancer_woofi_tie_synthetic.hex", true)).unwrap();
+    assert_eq!(unknown["protocol"], "Unknown");
+    let lines = unknown["disassembly"].as_array().unwrap();
+    assert_eq!(lines[0], "0000: PUSH1 0x80");
+    assert!(lines.len() <= which_dex::analyze::DISASSEMBLY_MAX_BYTES);
+    assert!(lines
+        .iter()
+        .any(|line| line.as_str().unwrap().contains("PUSH4 0x")));
+
+    let human = String::from_utf8(run("balancer_woofi_tie_synthetic.hex", false)).unwrap();
+    assert!(
+        human.contains("disassembly:\n  0000: PUSH1 0x80\n"),
+        "{human}"
+    );
+
+    // Identified code needs no triage.
+    let known: serde_json::Value =
+        serde_json::from_slice(&run("sushi_usdc_eth.hex", true)).unwrap();
+    assert!(known.get("disassembly").is_none());
+}
+
+#[test]
+fn test_dump_normalized_writes_the_hashed_bytes() {
+    let dir = temp_dir("cli-dump");
+    let dump = |fixture: &str| {
+        let path = dir.join(fixture);
+        let output = run_cli(
+            &[
+                "analyze-code",
+                "--json",
+                "--file",
+                &format!("tests/fixtures/{fixture}"),
+                "--dump-normalized",
+                path.to_str().unwrap(),
+            ],
+            None,
+        );
+        assert!(output.status.success(), "{output:?}");
+        let analysis: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let hex = std::fs::read_to_string(&path).unwrap();
+        let normalized = hex::decode(hex.trim().trim_start_matches("0x")).unwrap();
+        (analysis, normalized)
+    };
+
+    let (analysis, normalized) = dump("univ3_usdc_eth.hex");
+    assert_eq!(
+        analysis["fingerprint"]["exact_hash"],
+        hex::encode(alloy::primitives::keccak256(&normalized))
+    );
+    assert_eq!(
+        normalized,
+        which_dex::normalize_for_fingerprint(
+            &load_fixture("univ3_usdc_eth.hex"),
+            &Default::default()
+        )
+    );
+
+    // Fee tiers differ only in immutables, which normalization zeroes.
+    assert_eq!(dump("univ3_usdc_eth_005.hex").1, normalized);
+    assert_ne!(dump("univ2_uni_eth.hex").1, normalized);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_combined_classifier_breaks_selector_tie() {
+    let dir = temp_dir("cli-classifier");
+    for (protocol, fixture) in [
+        ("BalancerV2Pool", "balancer_weighted_synthetic.hex"),
+        ("WooFi", "woofi_synthetic.hex"),
+    ] {
+        std::fs::create_dir_all(dir.join(protocol)).unwrap();
+        std::fs::copy(
+            format!("tests/fixtures/{fixture}"),
+            dir.join(protocol).join(fixture),
+        )
+        .unwrap();
+    }
+    let db = dir.to_str().unwrap();
+    let run = |extra: &[&str]| {
+        let args = [
+            "analyze-code",
+            "--json",
+            "--file",
+            "tests/fixtures/balancer_woofi_tie_synthetic.hex",
+        ];
+        run_cli(&[&args[..], extra].concat(), None)
+    };
+
+    let selector: serde_json::Value = serde_json::from_slice(&run(&[]).stdout).unwrap();
+    assert_eq!(selector["protocol"], "Unknown");
+    assert_eq!(selector["detection_method"], "selector");
+    assert!(selector.get("combined_confidence").is_none());
+    assert!(selector.get("protocol_candidates").is_some());
+
+    let output = run(&["--classifier", "combined", "--fingerprint-db", db]);
+    assert!(output.status.success(), "{output:?}");
+    let combined: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(combined["protocol"], "BalancerV2Pool");
+    assert_eq!(combined["detection_method"], "tlsh_nearest");
+    assert!(combined.get("protocol_candidates").is_none());
+    assert!(combined["combined_confidence"].as_f64().unwrap() > 0.0);
+
+    assert!(!run(&["--classifier", "combined"]).status.success());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_analyze_tx_analyzes_created_contract() {
+    let pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let tx_hash = "0x4444444444444444444444444444444444444444444444444444444444444444";
+    let rpc = MockRpc::builder()
+        .code(pool, &load_fixture("sushi_usdc_eth.hex"))
+        .handler(move |method, params| {
+            (method == "eth_getTransactionReceipt").then(|| {
+                Ok(if params[0] == tx_hash {
+                    serde_json::json!({ "contractAddress": pool })
+                } else {
+                    serde_json::Value::Null
+                })
+            })
+        })
+        .start();
+
+    let output = run_cli(
+        &[
+            "analyze-tx",
+            "--rpc-url",
+            &rpc.url,
+            "--tx-hash",
+            tx_hash,
+            "--json",
+        ],
+        None,
+    );
+    assert!(output.status.success(), "{output:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["address"], pool);
+    assert_eq!(report["analysis"]["protocol"], "UniswapV2");
+
+    let missing = run_cli(
+        &[
+            "analyze-tx",
+            "--rpc-url",
+            &rpc.url,
+            "--tx-hash",
+            "0x5555555555555555555555555555555555555555555555555555555555555555",
+        ],
+        None,
+    );
+    assert!(!missing.status.success());
+    assert!(String::from_utf8_lossy(&missing.stderr).starts_with("error: no receipt"));
+}
+
+#[test]
+fn test_compare_implementations_prints_both_implementations() {
+    let pool = "0x397ff1542f962076d0bfe58ea045ffa2d347aca0";
+    let (proxy_a, proxy_b) = (
+        "0x00000000000000000000000000000000000000aa",
+        "0x00000000000000000000000000000000000000ab",
+    );
+    let rpc = MockRpc::builder()
+        .code(pool, &load_fixture("sushi_usdc_eth.hex"))
+        .code(proxy_a, &eip1167_proxy(&pool[2..]))
+        .code(proxy_b, &eip1167_proxy(&pool[2..]))
+        .start();
+
+    let output = run_cli(
+        &[
+            "compare-many",
+            "--rpc-url",
+            &rpc.url,
+            "--address",
+            proxy_a,
+            "--compare-implementations",
+            proxy_b,
+        ],
+        None,
+    );
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "{proxy_a} -> {pool}\n{proxy_b} -> {pool}\nexact_match: true\ndiff: 0 (identical)\n"
+        )
+    );
+}
+
+#[test]
+fn test_exclude_proxy_resolution_for_validates_addresses() {
+    let output = run_cli(
+        &[
+            "analyze",
+            "--rpc-url",
+            "http://127.0.0.1:1",
+            "--address",
+            "0x00000000000000000000000000000000000000aa",
+            "--exclude-proxy-resolution-for",
+            "0x00000000000000000000000000000000000000aa,0x1234",
+        ],
+        None,
+    );
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("invalid value '0x1234' for '--exclude-proxy-resolution-for"),
+        "{stderr}"
+    );
+}
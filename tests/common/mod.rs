@@ -0,0 +1,276 @@
+//! Shared helpers for integration tests: fixtures, a CLI runner and a local mock JSON-RPC endpoint.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+pub fn load_fixture(name: &str) -> Vec<u8> {
+    let path = format!("tests/fixtures/{}", name);
+    let hex_content = std::fs::read_to_string(&path)
+        .unwrap_or_else(|_| panic!("Failed to read fixture: {}", path));
+    hex::decode(hex_content.trim().trim_start_matches("0x"))
+        .unwrap_or_else(|_| panic!("Invalid hex in fixture: {}", path))
+}
+
+/// EIP-1167 runtime code delegating to `implementation` (20-byte hex, no 0x)
+pub fn eip1167_proxy(implementation: &str) -> Vec<u8> {
+    hex::decode(format!(
+        "363d3d373d3d3d363d73{implementation}5af43d82803e903d91602b57fd5bf3"
+    ))
+    .unwrap()
+}
+
+/// EIP-7702 delegation designator (`0xef0100 || target`; 20-byte hex, no 0x)
+pub fn eip7702_designator(target: &str) -> Vec<u8> {
+    hex::decode(format!("ef0100{target}")).unwrap()
+}
+
+/// Run the `which-dex` binary with `args`, feeding `stdin` (closed when `None`)
+pub fn run_cli(args: &[&str], stdin: Option<&[u8]>) -> Output {
+    let mut child = Command::new(assert_cmd::cargo::cargo_bin!("which-dex"))
+        .args(args)
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run which-dex");
+    if let Some(input) = stdin {
+        // The child may exit on a usage error before reading stdin (BrokenPipe).
+        let _ = child.stdin.take().unwrap().write_all(input);
+    }
+    child.wait_with_output().expect("run which-dex")
+}
+
+/// Fresh, empty scratch directory for one test; the caller removes it when done
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("which-dex-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Custom method handler: `None` means "not handled", `Some(Err(..))` is a JSON-RPC error object
+type Handler = dyn Fn(&str, &Value) -> Option<Result<Value, Value>> + Send + Sync;
+
+/// Minimal HTTP JSON-RPC server answering from an in-memory handler.
+///
+/// `eth_getCode` is served from the `code` map (unknown addresses get `0x`);
+/// other methods go to the optional custom handler.
+pub struct MockRpc {
+    pub url: String,
+    http_requests: Arc<AtomicUsize>,
+    connections: Arc<AtomicUsize>,
+}
+
+pub struct MockRpcBuilder {
+    code: HashMap<String, String>,
+    batch_support: bool,
+    latency: Duration,
+    max_in_flight: Option<usize>,
+    in_flight: AtomicUsize,
+    handler: Option<Arc<Handler>>,
+}
+
+impl MockRpc {
+    pub fn builder() -> MockRpcBuilder {
+        MockRpcBuilder {
+            code: HashMap::new(),
+            batch_support: true,
+            latency: Duration::ZERO,
+            max_in_flight: None,
+            in_flight: AtomicUsize::new(0),
+            handler: None,
+        }
+    }
+
+    /// Number of HTTP requests (round trips) served so far
+    pub fn http_requests(&self) -> usize {
+        self.http_requests.load(Ordering::SeqCst)
+    }
+
+    /// Number of TCP connections accepted so far (keep-alive reuses one for many requests)
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+impl MockRpcBuilder {
+    pub fn code(mut self, address: &str, bytecode: &[u8]) -> Self {
+        self.code.insert(
+            address.to_lowercase(),
+            format!("0x{}", hex::encode(bytecode)),
+        );
+        self
+    }
+
+    /// Reject JSON-RPC batches the way endpoints without batch support do
+    pub fn without_batch_support(mut self) -> Self {
+        self.batch_support = false;
+        self
+    }
+
+    /// Delay every HTTP response, simulating a remote endpoint
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    /// Answer every call with a rate-limit error while more than `max` HTTP requests
+    /// are being served at once
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    pub fn handler(
+        mut self,
+        handler: impl Fn(&str, &Value) -> Option<Result<Value, Value>> + Send + Sync + 'static,
+    ) -> Self {
+        self.handler = Some(Arc::new(handler));
+        self
+    }
+
+    pub fn start(self) -> MockRpc {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let http_requests = Arc::new(AtomicUsize::new(0));
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let state = Arc::new(self);
+        let counter = http_requests.clone();
+        let accepted = connections.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let state = state.clone();
+                let counter = counter.clone();
+                std::thread::spawn(move || serve_connection(stream, &state, &counter));
+            }
+        });
+
+        MockRpc {
+            url,
+            http_requests,
+            connections,
+        }
+    }
+}
+
+fn serve_connection(stream: TcpStream, state: &MockRpcBuilder, counter: &AtomicUsize) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut stream = stream;
+
+    loop {
+        let mut content_length = 0usize;
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let mut body = vec![0u8; content_length];
+        if reader.read_exact(&mut body).is_err() {
+            return;
+        }
+        counter.fetch_add(1, Ordering::SeqCst);
+        let in_flight = state.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        std::thread::sleep(state.latency);
+
+        let request: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        let payload = if state.max_in_flight.is_some_and(|max| in_flight > max) {
+            rate_limited(&request)
+        } else {
+            respond(state, &request)
+        }
+        .to_string();
+        state.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let http = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+            payload.len(),
+            payload
+        );
+        if stream.write_all(http.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+fn respond(state: &MockRpcBuilder, request: &Value) -> Value {
+    match request {
+        Value::Array(_) if !state.batch_support => {
+            json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": { "code": -32600, "message": "batch requests are not supported" }
+            })
+        }
+        Value::Array(calls) => Value::Array(calls.iter().map(|c| respond_one(state, c)).collect()),
+        call => respond_one(state, call),
+    }
+}
+
+fn rate_limited(request: &Value) -> Value {
+    let error = |call: &Value| {
+        json!({
+            "jsonrpc": "2.0",
+            "id": call["id"],
+            "error": { "code": -32005, "message": "rate limit exceeded" }
+        })
+    };
+    match request {
+        Value::Array(calls) => Value::Array(calls.iter().map(error).collect()),
+        call => error(call),
+    }
+}
+
+fn respond_one(state: &MockRpcBuilder, call: &Value) -> Value {
+    let id = call["id"].clone();
+    let method = call["method"].as_str().unwrap_or_default();
+    let params = &call["params"];
+
+    let handled = state.handler.as_ref().and_then(|h| h(method, params));
+    let result = match (handled, method) {
+        (Some(result), _) => result,
+        (None, "eth_getCode") => {
+            let address = params[0].as_str().unwrap_or_default().to_lowercase();
+            Ok(json!(state
+                .code
+                .get(&address)
+                .cloned()
+                .unwrap_or_else(|| "0x".to_string())))
+        }
+        (None, _) => Err(json!({ "code": -32601, "message": "method not found" })),
+    };
+
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+    }
+}
@@ -2,7 +2,12 @@
 //!
 //! These tests use real DEX pool bytecodes to verify protocol detection.
 
-use which_dex::{identify_protocol, DexProtocol};
+use which_dex::selector_fingerprint::{
+    balancer_pool_subtype, dispatcher_region, eip7702_delegation_target, fingerprint_for,
+    fingerprint_specs, identify_contract_kind, identify_protocol_with, identify_protocols,
+    is_safe_proxy, selectors, ContractKind, SelectorScan,
+};
+use which_dex::{identify_protocol, DexProtocol, Selector, SelectorParseError, SelectorSet};
 
 fn load_fixture(name: &str) -> Vec<u8> {
     let path = format!("tests/fixtures/{}", name);
@@ -65,6 +70,38 @@ fn test_identify_solidly() {
     assert_eq!(protocol, DexProtocol::Solidly);
 }
 
+#[test]
+fn test_solidly_requires_get_amount_out() {
+    // A pair with stable() but no Solidly quoting function is not Solidly.
+    let mut bytecode = load_fixture("velo_impl.hex");
+    let at = bytecode
+        .windows(4)
+        .position(|w| w == selectors::GET_AMOUNT_OUT.as_bytes())
+        .unwrap();
+    bytecode[at..at + 4].copy_from_slice(&[0xff; 4]);
+
+    assert!(!identify_protocols(&bytecode)
+        .iter()
+        .any(|(protocol, _)| *protocol == DexProtocol::Solidly));
+}
+
+#[test]
+fn test_identify_solidly_cl_with_slot0_and_stable() {
+    // Synthetic: V3 pool interface plus stable(), getReserves() and claimFees()
+    let bytecode = load_fixture("solidly_cl_synthetic.hex");
+
+    assert_eq!(identify_protocol(&bytecode), DexProtocol::SolidlyCL);
+    assert_eq!(
+        which_dex::identify_protocols(&bytecode),
+        [(DexProtocol::SolidlyCL, 10)]
+    );
+    assert!(DexProtocol::SolidlyCL.is_v3_style());
+
+    let analysis = which_dex::analyze_bytecode(Default::default(), &bytecode);
+    assert_eq!(analysis.protocol, "SolidlyCL");
+    assert!(analysis.is_pool_likely);
+}
+
 #[test]
 fn test_sushiswap_detected_as_v2_compatible() {
     // SushiSwap has same interface as UniV2, so detected as UniswapV2
@@ -102,3 +139,498 @@ fn test_identify_story_chain_univ3_fork_pool() {
     let protocol = identify_protocol(&bytecode);
     assert_eq!(protocol, DexProtocol::UniswapV3);
 }
+
+#[test]
+fn test_identify_balancer_v2_weighted_and_stable() {
+    // Synthetic dispatchers (getPoolId/getVault/getSwapFeePercentage + one math selector).
+    let weighted = load_fixture("balancer_weighted_synthetic.hex");
+    let stable = load_fixture("balancer_stable_synthetic.hex");
+
+    assert_eq!(identify_protocol(&weighted), DexProtocol::BalancerV2Pool);
+    assert_eq!(identify_protocol(&stable), DexProtocol::BalancerV2Pool);
+
+    assert_eq!(balancer_pool_subtype(&weighted), Some("Weighted"));
+    assert_eq!(balancer_pool_subtype(&stable), Some("Stable"));
+}
+
+#[test]
+fn test_balancer_composable_stable_wins_over_stable() {
+    let mut bytecode = load_fixture("balancer_stable_synthetic.hex");
+    bytecode.extend_from_slice(selectors::GET_BPT_INDEX.as_bytes());
+
+    assert_eq!(balancer_pool_subtype(&bytecode), Some("ComposableStable"));
+}
+
+#[test]
+fn test_selector_set_agrees_with_exists_in() {
+    for entry in std::fs::read_dir("tests/fixtures").unwrap() {
+        let name = entry.unwrap().file_name().into_string().unwrap();
+        let bytecode = load_fixture(&name);
+        let set = SelectorSet::from_bytecode(&bytecode);
+        for (selector, signature) in selectors::NAMES {
+            assert_eq!(
+                set.contains(selector),
+                selector.exists_in(&bytecode),
+                "{signature} in {name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_selector_from_hex_round_trips_display() {
+    assert_eq!(Selector::from_hex("0x0dfe1681"), Ok(selectors::TOKEN0));
+    assert_eq!(Selector::from_hex("0DFE1681"), Ok(selectors::TOKEN0));
+    assert_eq!("0xd21220a7".parse::<Selector>(), Ok(selectors::TOKEN1));
+    assert_eq!(
+        selectors::FACTORY.to_string().parse::<Selector>(),
+        Ok(selectors::FACTORY)
+    );
+}
+
+#[test]
+fn test_selector_byte_conversions() {
+    let token0 = Selector::from([0x0d, 0xfe, 0x16, 0x81]);
+    assert_eq!(token0, selectors::TOKEN0);
+    assert_eq!(<[u8; 4]>::from(token0), [0x0d, 0xfe, 0x16, 0x81]);
+
+    let calldata = [selectors::GET_RESERVES.as_ref(), &[0u8; 4][..]].concat();
+    assert_eq!(&calldata[..4], &[0x09, 0x02, 0xf1, 0xac]);
+}
+
+#[test]
+fn test_selector_from_hex_rejects_wrong_length() {
+    assert_eq!(
+        Selector::from_hex("0x0dfe16"),
+        Err(SelectorParseError::InvalidLength(6))
+    );
+    assert_eq!(
+        Selector::from_hex("0x0dfe168100"),
+        Err(SelectorParseError::InvalidLength(10))
+    );
+    assert_eq!(
+        Selector::from_hex(""),
+        Err(SelectorParseError::InvalidLength(0))
+    );
+}
+
+#[test]
+fn test_selector_from_hex_rejects_non_hex() {
+    assert_eq!(
+        Selector::from_hex("0x0dfe16zz"),
+        Err(SelectorParseError::InvalidHex)
+    );
+    assert!("token0()".parse::<Selector>().is_err());
+}
+
+#[test]
+fn test_gmx_style_vault_is_perp_vault_not_pool() {
+    let mut bytecode = Vec::new();
+    for s in [
+        selectors::GET_MIN_PRICE,
+        selectors::GET_MAX_PRICE,
+        selectors::USDG,
+        selectors::TOKEN0,
+    ] {
+        bytecode.push(0x63); // PUSH4
+        bytecode.extend_from_slice(s.as_bytes());
+    }
+
+    assert_eq!(
+        identify_contract_kind(&bytecode),
+        Some(ContractKind::PerpVault)
+    );
+    assert_eq!(identify_protocol(&bytecode), DexProtocol::Unknown);
+
+    let analysis = which_dex::analyze_bytecode(Default::default(), &bytecode);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("PerpVault"));
+    assert!(!analysis.is_pool_likely);
+
+    assert_eq!(
+        identify_contract_kind(&load_fixture("univ2_usdc_eth.hex")),
+        Some(ContractKind::Pool)
+    );
+}
+
+#[test]
+fn test_identify_protocols_orders_by_confidence_then_name() {
+    let push4s = |selectors: &[Selector]| {
+        let mut bytecode = Vec::new();
+        for s in selectors {
+            bytecode.push(0x63); // PUSH4
+            bytecode.extend_from_slice(s.as_bytes());
+        }
+        bytecode
+    };
+    let balancer = [
+        selectors::GET_POOL_ID,
+        selectors::GET_VAULT,
+        selectors::GET_SWAP_FEE_PERCENTAGE,
+    ];
+
+    // WOOFi is matched after Balancer, but its legacy entry points make it the better match.
+    let woofi_ahead = push4s(
+        &[
+            &balancer[..],
+            &[
+                selectors::QUERY_SWAP,
+                selectors::TRY_QUERY,
+                selectors::SELL_BASE,
+                selectors::SELL_QUOTE,
+            ],
+        ]
+        .concat(),
+    );
+    assert_eq!(
+        identify_protocols(&woofi_ahead),
+        [(DexProtocol::WooFi, 4), (DexProtocol::BalancerV2Pool, 3)]
+    );
+
+    // Equal confidences list by name.
+    let tie = push4s(
+        &[
+            &balancer[..],
+            &[
+                selectors::QUERY_SWAP,
+                selectors::TRY_QUERY,
+                selectors::SELL_BASE,
+            ],
+        ]
+        .concat(),
+    );
+    assert_eq!(
+        identify_protocols(&tie),
+        [(DexProtocol::BalancerV2Pool, 3), (DexProtocol::WooFi, 3)]
+    );
+    assert!(DexProtocol::BalancerV2Pool.name() < DexProtocol::WooFi.name());
+}
+
+#[test]
+fn test_woofi_is_pmm_not_pool() {
+    // WooPPV2-shaped dispatcher: quote functions, no reserves
+    let mut bytecode = Vec::new();
+    for s in [
+        selectors::QUERY_SWAP,
+        selectors::TRY_QUERY,
+        selectors::SELL_BASE,
+        selectors::SELL_QUOTE,
+    ] {
+        bytecode.push(0x63); // PUSH4
+        bytecode.extend_from_slice(s.as_bytes());
+    }
+
+    assert_eq!(identify_protocol(&bytecode), DexProtocol::WooFi);
+    assert!(DexProtocol::WooFi.is_pmm());
+    assert_eq!(identify_contract_kind(&bytecode), Some(ContractKind::Pmm));
+
+    let analysis = which_dex::analyze_bytecode(Default::default(), &bytecode);
+    assert_eq!(analysis.protocol, "WooFi");
+    assert_eq!(analysis.pool_confidence, 4);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("PMM"));
+    assert!(!analysis.is_pool_likely);
+
+    // querySwap alone (e.g. a router quoting through the pool) is not a PMM.
+    let router = [&[0x63][..], selectors::QUERY_SWAP.as_bytes()].concat();
+    assert_eq!(identify_contract_kind(&router), None);
+    assert_eq!(identify_protocol(&router), DexProtocol::Unknown);
+}
+
+#[test]
+fn test_strict_push4_ignores_selector_in_data_region() {
+    // Dispatcher compares three of UniswapV2's required selectors; token0()'s bytes
+    // only appear after the code, in a data region.
+    let mut bytecode = Vec::new();
+    for s in [
+        selectors::TOKEN1,
+        selectors::GET_RESERVES,
+        selectors::K_LAST,
+    ] {
+        bytecode.extend_from_slice(&[0x80, 0x63]); // DUP1 PUSH4
+        bytecode.extend_from_slice(s.as_bytes());
+        bytecode.push(0x14); // EQ
+    }
+    bytecode.push(0xfe); // INVALID: end of code
+    bytecode.extend_from_slice(&[0x12, 0x34]);
+    bytecode.extend_from_slice(selectors::TOKEN0.as_bytes());
+
+    assert_eq!(identify_protocol(&bytecode), DexProtocol::UniswapV2);
+    assert_eq!(
+        identify_protocol_with(&bytecode, SelectorScan::Push4),
+        DexProtocol::Unknown
+    );
+}
+
+#[test]
+fn test_strict_push4_keeps_real_pools() {
+    for (fixture, expected) in [
+        ("univ2_usdc_eth.hex", DexProtocol::UniswapV2),
+        ("univ3_usdc_eth.hex", DexProtocol::UniswapV3),
+        ("velo_impl.hex", DexProtocol::Solidly),
+    ] {
+        let bytecode = load_fixture(fixture);
+        assert_eq!(
+            identify_protocol_with(&bytecode, SelectorScan::Push4),
+            expected,
+            "{fixture}"
+        );
+    }
+}
+
+#[test]
+fn test_univ3_factory_is_factory_not_pool() {
+    // Synthetic: factory dispatcher followed by the embedded pool creation code,
+    // the way UniswapV3PoolDeployer carries the full pool bytecode.
+    let bytecode = load_fixture("univ3_factory_synthetic.hex");
+
+    assert_eq!(
+        identify_contract_kind(&bytecode),
+        Some(ContractKind::Factory {
+            pool_protocol: DexProtocol::UniswapV3
+        })
+    );
+
+    let analysis = which_dex::analyze_bytecode(Default::default(), &bytecode);
+    assert_eq!(analysis.protocol, "UniswapV3");
+    assert_eq!(analysis.contract_kind.as_deref(), Some("Factory"));
+    assert_eq!(analysis.contract_hint.as_deref(), Some("UniswapV3 factory"));
+    assert!(!analysis.is_pool_likely);
+
+    let pool = load_fixture("univ3_usdc_eth.hex");
+    assert_eq!(identify_contract_kind(&pool), None);
+}
+
+#[test]
+fn test_eip7702_designator_is_delegation() {
+    let target = [0x11; 20];
+    let mut designator = vec![0xef, 0x01, 0x00];
+    designator.extend(target);
+
+    assert_eq!(eip7702_delegation_target(&designator), Some(target));
+    assert_eq!(
+        identify_contract_kind(&designator),
+        Some(ContractKind::Eip7702Delegation { target })
+    );
+
+    // Truncated, padded or a different version byte: not a designator.
+    assert_eq!(eip7702_delegation_target(&designator[..22]), None);
+    designator.push(0x00);
+    assert_eq!(eip7702_delegation_target(&designator), None);
+    designator.pop();
+    designator[2] = 0x01;
+    assert_eq!(eip7702_delegation_target(&designator), None);
+}
+
+#[test]
+fn test_selector_positions_in_dispatcher() {
+    let bytecode = load_fixture("univ2_uni_eth.hex");
+
+    let positions = selectors::GET_RESERVES.positions_in(&bytecode);
+    assert_eq!(positions, [432]);
+    assert_eq!(&bytecode[432..436], selectors::GET_RESERVES.as_bytes());
+    assert_eq!(bytecode[431], 0x63, "operand of a PUSH4");
+
+    let dispatcher = dispatcher_region(&bytecode);
+    assert!(dispatcher.contains(&432));
+    assert!(dispatcher.contains(&selectors::TOKEN0.positions_in(&bytecode)[0]));
+    // The dispatcher sits at the start of the runtime code.
+    assert!(dispatcher.end < bytecode.len() / 4, "{dispatcher:?}");
+
+    assert!(selectors::SLOT0.positions_in(&bytecode).is_empty());
+}
+
+#[test]
+fn test_confidence_percent() {
+    let v2 = fingerprint_for(DexProtocol::UniswapV2).unwrap();
+    assert_eq!(v2.max_confidence(), 7);
+    assert_eq!(v2.confidence_percent(6), 85);
+    assert_eq!(v2.confidence_percent(0), 0);
+    assert_eq!(v2.confidence_percent(99), 100);
+    assert!(fingerprint_for(DexProtocol::Unknown).is_none());
+
+    // A canonical V2 pair exposes every optional selector.
+    let bytecode = load_fixture("univ2_uni_eth.hex");
+    let matches = identify_protocols(&bytecode);
+    assert_eq!(matches, [(DexProtocol::UniswapV2, 7)]);
+    assert_eq!(v2.confidence_percent(matches[0].1), 100);
+}
+
+#[test]
+fn test_safe_singleton_and_proxy_are_safe() {
+    let singleton = load_fixture("safe_singleton_synthetic.hex");
+    assert_eq!(identify_contract_kind(&singleton), Some(ContractKind::Safe));
+    assert_eq!(identify_protocol(&singleton), DexProtocol::Unknown);
+    assert!(!is_safe_proxy(&singleton));
+
+    // The proxy carries no Safe selectors, only its own masterCopy() answer.
+    let proxy = load_fixture("safe_proxy.hex");
+    assert!(!selectors::GET_THRESHOLD.exists_in(&proxy));
+    assert!(is_safe_proxy(&proxy));
+    assert_eq!(identify_contract_kind(&proxy), Some(ContractKind::Safe));
+
+    let analysis = which_dex::analyze_bytecode(Default::default(), &singleton);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("Safe"));
+    assert!(!analysis.is_pool_likely);
+
+    let pool = load_fixture("univ2_uni_eth.hex");
+    assert!(!is_safe_proxy(&pool));
+    assert_eq!(identify_contract_kind(&pool), Some(ContractKind::Pool));
+}
+
+#[test]
+fn test_weth_and_permit2_have_their_own_kinds() {
+    let push4s = |selectors: &[Selector]| {
+        let mut bytecode = Vec::new();
+        for s in selectors {
+            bytecode.push(0x63); // PUSH4
+            bytecode.extend_from_slice(s.as_bytes());
+        }
+        bytecode
+    };
+
+    let weth = load_fixture("weth9_synthetic.hex");
+    assert_eq!(
+        identify_contract_kind(&weth),
+        Some(ContractKind::WrappedNative)
+    );
+    let analysis = which_dex::analyze_bytecode(Default::default(), &weth);
+    assert_eq!(analysis.protocol, "Unknown");
+    assert_eq!(analysis.contract_kind.as_deref(), Some("WrappedNative"));
+    assert!(!analysis.is_pool_likely);
+
+    // A router wraps and unwraps through WETH without being an ERC-20 itself.
+    let router = push4s(&[
+        selectors::DEPOSIT,
+        selectors::WITHDRAW,
+        selectors::TRANSFER,
+        selectors::BALANCE_OF,
+    ]);
+    assert_eq!(identify_contract_kind(&router), None);
+
+    let permit2 = load_fixture("permit2_synthetic.hex");
+    assert_eq!(
+        identify_contract_kind(&permit2),
+        Some(ContractKind::Permit2)
+    );
+    let analysis = which_dex::analyze_bytecode(Default::default(), &permit2);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("Permit2"));
+    assert!(!analysis.is_pool_likely);
+
+    // Neither does a contract that only pulls tokens through Permit2.
+    let spender = push4s(&[selectors::PERMIT_TRANSFER_FROM, selectors::TRANSFER]);
+    assert_eq!(identify_contract_kind(&spender), None);
+
+    let pool = load_fixture("univ2_uni_eth.hex");
+    assert_eq!(identify_contract_kind(&pool), Some(ContractKind::Pool));
+}
+
+#[test]
+fn test_v3_position_manager_is_not_pool() {
+    let mut bytecode = Vec::new();
+    for s in [
+        // NonfungiblePositionManager's own interface (ERC-721 plus position management)
+        "positions(uint256)",
+        "mint((address,address,uint24,int24,int24,uint256,uint256,uint256,uint256,address,uint256))",
+        "increaseLiquidity((uint256,uint256,uint256,uint256,uint256,uint256))",
+        "decreaseLiquidity((uint256,uint128,uint256,uint256,uint256))",
+        "collect((uint256,address,uint128,uint128))",
+        "burn(uint256)",
+        "ownerOf(uint256)",
+        "balanceOf(address)",
+        "safeTransferFrom(address,address,uint256)",
+        "uniswapV3MintCallback(uint256,uint256,bytes)",
+        // Calls into pools
+        "slot0()",
+        "mint(address,int24,int24,uint128,bytes)",
+        "burn(int24,int24,uint128)",
+        "positions(bytes32)",
+    ] {
+        bytecode.push(0x63); // PUSH4
+        bytecode.extend_from_slice(Selector::from_signature(s).as_bytes());
+    }
+
+    assert_eq!(
+        identify_contract_kind(&bytecode),
+        Some(ContractKind::PositionManager)
+    );
+    let analysis = which_dex::analyze_bytecode(Default::default(), &bytecode);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("PositionManager"));
+    assert!(!analysis.is_pool_likely);
+
+    // A pool's positions(bytes32) and slot0() alone don't make a position manager.
+    let v3 = load_fixture("univ3_usdc_eth.hex");
+    assert_eq!(identify_contract_kind(&v3), None);
+    assert!(which_dex::analyze_bytecode(Default::default(), &v3).is_pool_likely);
+}
+
+#[test]
+fn test_lp_token_without_swap_is_not_pool() {
+    let lp_token_selectors = [
+        selectors::TRANSFER,
+        selectors::BALANCE_OF,
+        selectors::TOTAL_SUPPLY,
+        selectors::TOKEN0,
+        selectors::TOKEN1,
+    ];
+    let dispatcher = |extra: &[Selector]| {
+        let mut bytecode = Vec::new();
+        for s in lp_token_selectors.iter().chain(extra) {
+            bytecode.push(0x63); // PUSH4
+            bytecode.extend_from_slice(s.as_bytes());
+        }
+        bytecode
+    };
+
+    let lp_token = dispatcher(&[]);
+    assert_eq!(
+        identify_contract_kind(&lp_token),
+        Some(ContractKind::LpToken)
+    );
+    let analysis = which_dex::analyze_bytecode(Default::default(), &lp_token);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("LpToken"));
+    assert!(!analysis.is_pool_likely);
+
+    // The same surface plus swap() is a V2-style pair: pool and LP token at once.
+    let pair = dispatcher(&[
+        selectors::SWAP_V2,
+        selectors::GET_RESERVES,
+        selectors::FACTORY,
+        selectors::K_LAST,
+    ]);
+    assert_eq!(identify_contract_kind(&pair), Some(ContractKind::Pool));
+    let analysis = which_dex::analyze_bytecode(Default::default(), &pair);
+    assert_eq!(analysis.contract_kind.as_deref(), Some("Pool"));
+    assert_eq!(analysis.protocol, "UniswapV2");
+    assert!(analysis.is_pool_likely);
+
+    // V3 pools aren't ERC-20s, so they get no kind either way.
+    let v3 = load_fixture("univ3_usdc_eth.hex");
+    assert_eq!(identify_contract_kind(&v3), None);
+}
+
+#[test]
+fn test_fingerprint_for_matches_the_listing() {
+    for spec in fingerprint_specs() {
+        assert_eq!(fingerprint_for(spec.protocol).as_ref(), Some(&spec));
+        // Every built-in selector has a name.
+        assert!(spec
+            .required
+            .iter()
+            .chain(&spec.forbidden)
+            .chain(&spec.optional)
+            .all(|s| s.name.is_some()));
+    }
+
+    let v2 = fingerprint_for(DexProtocol::UniswapV2).unwrap();
+    assert_eq!(v2.required[2].name, Some("getReserves()"));
+    assert!(v2.required.iter().all(|s| s.weight == 1));
+    assert!(v2.forbidden.iter().all(|s| s.weight == 0));
+    let solidly = fingerprint_for(DexProtocol::Solidly).unwrap();
+    let claim_fees = solidly
+        .optional
+        .iter()
+        .find(|s| s.selector == selectors::CLAIM_FEES)
+        .unwrap();
+    assert_eq!(claim_fees.weight, 2);
+
+    assert_eq!(fingerprint_for(DexProtocol::Unknown), None);
+}